@@ -21,7 +21,7 @@ mod serve;
 
 pub use self::counter::Counter;
 pub use self::gauge::Gauge;
-pub use self::histogram::Histogram;
+pub use self::histogram::{Bounds, Bucket, Histogram};
 pub use self::prom::{FmtMetrics, FmtLabels, FmtMetric, Metric};
 pub use self::scopes::Scopes;
 pub use self::serve::Serve;
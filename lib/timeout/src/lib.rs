@@ -11,10 +11,20 @@ use std::{error, fmt};
 use std::time::Duration;
 
 /// A timeout that wraps an underlying operation.
+///
+/// When wrapping a `Service`, a `D`-typed `Deadline` may shorten the timeout
+/// for each request.
 #[derive(Debug, Clone)]
-pub struct Timeout<T> {
+pub struct Timeout<T, D = ()> {
     inner: T,
     duration: Duration,
+    deadline: D,
+}
+
+/// Determines how long a request may take, if it is shorter than the
+/// `Timeout`'s duration.
+pub trait Deadline<Req> {
+    fn deadline(&self, req: &Req) -> Option<Duration>;
 }
 
 
@@ -47,6 +57,19 @@ impl<T> Timeout<T> {
         Timeout {
             inner,
             duration,
+            deadline: (),
+        }
+    }
+}
+
+impl<T, D> Timeout<T, D> {
+    /// Shortens the timeout of each request to the duration returned by
+    /// `deadline`, if any.
+    pub fn with_deadline<E>(self, deadline: E) -> Timeout<T, E> {
+        Timeout {
+            inner: self.inner,
+            duration: self.duration,
+            deadline,
         }
     }
 
@@ -70,9 +93,10 @@ impl<T> Timeout<T> {
     }
 }
 
-impl<S, T, E> Service for Timeout<S>
+impl<S, D, T, E> Service for Timeout<S, D>
 where
     S: Service<Response=T, Error=E>,
+    D: Deadline<S::Request>,
 {
     type Request = S::Request;
     type Response = T;
@@ -84,10 +108,15 @@ where
     }
 
     fn call(&mut self, req: Self::Request) -> Self::Future {
-        let inner = timer::Timeout::new(self.inner.call(req), self.duration);
+        let duration = match self.deadline.deadline(&req) {
+            Some(d) if d < self.duration => d,
+            _ => self.duration,
+        };
+        let inner = timer::Timeout::new(self.inner.call(req), duration);
         Timeout {
             inner,
-            duration: self.duration,
+            duration,
+            deadline: (),
         }
     }
 }
@@ -106,6 +135,7 @@ where
         Timeout {
             inner,
             duration: self.duration,
+            deadline: (),
         }
     }
 }
@@ -122,6 +152,14 @@ where
     }
 }
 
+//===== impl Deadline =====
+
+impl<Req> Deadline<Req> for () {
+    fn deadline(&self, _: &Req) -> Option<Duration> {
+        None
+    }
+}
+
 //===== impl Error =====
 
 impl<E> fmt::Display for Error<E>
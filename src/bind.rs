@@ -11,7 +11,7 @@ use tower_h2;
 
 use control::destination::Endpoint;
 use ctx;
-use svc::{MakeClient, Reconnect};
+use svc::{MakeClient, Reconnect, reconnect};
 use telemetry;
 use proxy;
 use transport;
@@ -22,7 +22,9 @@ use watch_service::{WatchService, Rebind};
 /// An HTTP `Service` that is created for each `Endpoint` and `Protocol`.
 pub type Stack<B> = proxy::http::orig_proto::Upgrade<
     proxy::http::normalize_uri::Service<
-        WatchTls<B>
+        proxy::http::deadline::Propagate<
+            WatchTls<B>
+        >
     >
 >;
 
@@ -52,6 +54,8 @@ pub struct Bind<C, B> {
     ctx: C,
     sensors: telemetry::Sensors,
     transport_registry: transport::metrics::Registry,
+    reconnect_backoff: reconnect::Backoff,
+    reconnect_registry: telemetry::reconnect::Registry,
     tls_client_config: tls::ClientConfigWatch,
     _p: PhantomData<fn() -> B>,
 }
@@ -171,12 +175,16 @@ impl<B> Bind<(), B> {
     pub fn new(
         sensors: telemetry::Sensors,
         transport_registry: transport::metrics::Registry,
+        reconnect_backoff: reconnect::Backoff,
+        reconnect_registry: telemetry::reconnect::Registry,
         tls_client_config: tls::ClientConfigWatch
     ) -> Self {
         Self {
             ctx: (),
             sensors,
             transport_registry,
+            reconnect_backoff,
+            reconnect_registry,
             tls_client_config,
             _p: PhantomData,
         }
@@ -187,6 +195,8 @@ impl<B> Bind<(), B> {
             ctx,
             sensors: self.sensors,
             transport_registry: self.transport_registry,
+            reconnect_backoff: self.reconnect_backoff,
+            reconnect_registry: self.reconnect_registry,
             tls_client_config: self.tls_client_config,
             _p: PhantomData,
         }
@@ -199,6 +209,8 @@ impl<C: Clone, B> Clone for Bind<C, B> {
             ctx: self.ctx.clone(),
             sensors: self.sensors.clone(),
             transport_registry: self.transport_registry.clone(),
+            reconnect_backoff: self.reconnect_backoff,
+            reconnect_registry: self.reconnect_registry.clone(),
             tls_client_config: self.tls_client_config.clone(),
             _p: PhantomData,
         }
@@ -215,7 +227,8 @@ where
     /// A reconnecting HTTP client is established with the given endpont,
     /// protocol, and TLS configuration.
     ///
-    /// This client is instrumented with metrics.
+    /// This client is instrumented with metrics. Failed connection attempts are
+    /// retried after backing off.
     fn bind_with_tls(
         &self,
         ep: &Endpoint,
@@ -228,11 +241,17 @@ where
         let log = ::logging::Client::proxy(self.ctx, addr)
             .with_protocol(protocol.clone());
 
+        // Tell the server which protocol will be spoken, so that it needn't
+        // detect it.
+        let alpn_protocol = match protocol {
+            Protocol::Http1 { .. } => tls::AlpnProtocol::Http1,
+            Protocol::Http2 => tls::AlpnProtocol::Http2,
+        };
         let tls = ep.tls_identity().and_then(|identity| {
             tls_client_config.as_ref().map(|config| {
                 tls::ConnectionConfig {
                     server_identity: identity.clone(),
-                    config: config.clone(),
+                    config: config.with_alpn_protocol(alpn_protocol),
                 }
             })
         });
@@ -248,12 +267,13 @@ where
         let connect = self.transport_registry
             .new_connect(client_ctx.as_ref(), transport::Connect::new(addr, tls));
 
-        // TODO: Add some sort of backoff logic between reconnects.
         self.sensors.http(
             client_ctx.clone(),
             Reconnect::new(
                 client_ctx.clone(),
-                proxy::http::Client::new(protocol, connect, log.executor())
+                proxy::http::Client::new(protocol, connect, log.executor()),
+                self.reconnect_backoff,
+                self.reconnect_registry.sensor(self.ctx),
             )
         )
    }
@@ -274,6 +294,11 @@ where
         };
         let watch_tls = WatchService::new(self.tls_client_config.clone(), rebind);
 
+        // Rewrite timeout headers to reflect the time remaining before the
+        // request's deadline, so that time spent in the proxy is accounted
+        // for by the endpoint.
+        let propagate = proxy::http::deadline::Propagate::new(watch_tls);
+
         // HTTP/1.1 middlewares
         //
         // TODO make this conditional based on `protocol`
@@ -284,7 +309,7 @@ where
         //
         // TODO move this into proxy::Client?
         let normalize_uri = proxy::http::normalize_uri::Service::new(
-            watch_tls,
+            propagate,
             protocol.was_absolute_form()
         );
 
@@ -322,6 +347,25 @@ where
     }
 }
 
+impl<C, B> Bind<C, B> {
+    /// Changes the type of request body accepted by bound services.
+    pub fn with_body<A>(self) -> Bind<C, A> {
+        Bind {
+            ctx: self.ctx,
+            sensors: self.sensors,
+            transport_registry: self.transport_registry,
+            reconnect_backoff: self.reconnect_backoff,
+            reconnect_registry: self.reconnect_registry,
+            tls_client_config: self.tls_client_config,
+            _p: PhantomData,
+        }
+    }
+
+    pub fn sensors(&self) -> &telemetry::Sensors {
+        &self.sensors
+    }
+}
+
 // ===== impl BindProtocol =====
 
 
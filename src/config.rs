@@ -12,7 +12,8 @@ use trust_dns_resolver::config::ResolverOpts;
 
 use conditional::Conditional;
 use convert::TryFrom;
-use transport::{Host, HostAndPort, HostAndPortError, tls};
+use dns;
+use transport::{DnsNameAndPort, Host, HostAndPort, HostAndPortError, tls};
 
 // TODO:
 //
@@ -58,8 +59,26 @@ pub struct Config {
     /// active concurrently.
     pub destination_concurrency_limit: usize,
 
+    /// The path to a file describing the endpoints of authorities that are
+    /// resolved locally rather than by the Destination service or DNS.
+    pub destination_static_endpoints_path: Option<PathBuf>,
+
+    /// The maximum number of endpoints that a single resolution may have.
+    pub destination_max_endpoints_per_resolution: usize,
+
+    /// The maximum number of endpoints across all resolutions.
+    pub destination_max_endpoints: usize,
+
     pub tls_settings: Conditional<tls::CommonSettings, tls::ReasonForNoTls>,
 
+    /// The maximum number of TLS sessions that are cached so that outbound
+    /// connections may resume them.
+    pub outbound_tls_session_cache_capacity: usize,
+
+    /// The identity that clients of the tap server must authenticate with,
+    /// if any.
+    pub tap_client_identity: Option<tls::Identity>,
+
     /// The path to "/etc/resolv.conf"
     pub resolv_conf_path: PathBuf,
 
@@ -83,13 +102,84 @@ pub struct Config {
     /// Timeout after which to cancel binding a request.
     pub bind_timeout: Duration,
 
+    /// The initial time to wait before reconnecting to an endpoint after a
+    /// connection failure.
+    pub reconnect_backoff_base: Duration,
+
+    /// The maximum time to wait before reconnecting to an endpoint after
+    /// consecutive connection failures.
+    pub reconnect_backoff_max: Duration,
+
     pub namespaces: Namespaces,
 
+    /// The path to a file describing how requests for logical authorities are
+    /// split across backing authorities.
+    pub traffic_split_path: Option<PathBuf>,
+
+    /// The window over which a newly discovered endpoint's share of requests
+    /// is ramped up, if any.
+    pub slow_start_window: Option<Duration>,
+
+    /// The path to a file describing which load balancing algorithm is used
+    /// for each destination.
+    pub balance_algorithms_path: Option<PathBuf>,
+
+    /// The path to a file describing the limits applied to inbound requests.
+    pub inbound_admission_path: Option<PathBuf>,
+
+    /// The maximum number of requests in flight for each inbound route.
+    pub inbound_max_in_flight: usize,
+
+    /// Inbound ports whose connections are closed unless the client
+    /// authenticated with a TLS certificate.
+    pub inbound_ports_require_identity: IndexSet<u16>,
+
+    /// The path to a file describing which clients are authorized to use
+    /// each inbound port and path.
+    pub inbound_authorization_path: Option<PathBuf>,
+
+    /// The proxy's region, used to prefer endpoints in the same locality.
+    pub locality_region: Option<String>,
+
+    /// The proxy's zone, used to prefer endpoints in the same locality.
+    pub locality_zone: Option<String>,
+
+    /// The fraction of local endpoints that must be ready for requests not to
+    /// spill over to other localities.
+    pub locality_min_ready: f32,
+
+    /// The authorities of outbound destinations whose slow requests are
+    /// hedged.
+    pub hedge_authorities: IndexSet<DnsNameAndPort>,
+
+    /// The fraction of a destination's recent responses that are received
+    /// before its requests are hedged.
+    pub hedge_percentile: f32,
+
     /// Optional minimum TTL for DNS lookups.
     pub dns_min_ttl: Option<Duration>,
 
     /// Optional maximum TTL for DNS lookups.
     pub dns_max_ttl: Option<Duration>,
+
+    /// Names ending in these suffixes are resolved by their SRV records.
+    pub dns_srv_suffixes: Vec<dns::Name>,
+
+    /// How long an authority's last DNS answer is still used after NXDOMAIN
+    /// responses.
+    pub dns_nxdomain_grace_period: Duration,
+
+    /// How long an authority's last DNS answer may be used after its TTL has
+    /// elapsed, while DNS queries fail. If `None`, it's used until a query
+    /// succeeds.
+    pub dns_max_staleness: Option<Duration>,
+
+    /// The initial time to wait before querying DNS again after an error.
+    pub dns_error_backoff_base: Duration,
+
+    /// The maximum time to wait before querying DNS again after consecutive
+    /// errors.
+    pub dns_error_backoff_max: Duration,
 }
 
 #[derive(Clone, Debug)]
@@ -125,6 +215,12 @@ pub enum ParseError {
     EnvironmentUnsupported,
     NotADuration,
     NotANumber,
+    NotARatio,
+    NotABool,
+    NotATlsVersion,
+    NotACipherSuite,
+    NotAnAuthority,
+    NotADnsSuffix,
     HostIsNotAnIpAddress,
     NotUnicode,
     UrlError(UrlError),
@@ -174,6 +270,78 @@ const ENV_INBOUND_CONNECT_TIMEOUT: &str = "LINKERD2_PROXY_INBOUND_CONNECT_TIMEOU
 const ENV_OUTBOUND_CONNECT_TIMEOUT: &str = "LINKERD2_PROXY_OUTBOUND_CONNECT_TIMEOUT";
 pub const ENV_BIND_TIMEOUT: &str = "LINKERD2_PROXY_BIND_TIMEOUT";
 
+/// Configures the backoff between attempts to reconnect to an endpoint.
+///
+/// The backoff starts at the base value and doubles (with jitter) after each
+/// consecutive failure, up to the maximum value.
+pub const ENV_RECONNECT_BACKOFF_BASE: &str = "LINKERD2_PROXY_RECONNECT_BACKOFF_BASE";
+pub const ENV_RECONNECT_BACKOFF_MAX: &str = "LINKERD2_PROXY_RECONNECT_BACKOFF_MAX";
+
+/// Configures a file that splits outbound traffic for logical authorities
+/// across backing authorities by weight.
+///
+/// The file is reloaded as it changes. Its splits take precedence over those
+/// that the Destination service describes. See `control::split`.
+pub const ENV_TRAFFIC_SPLIT_PATH: &str = "LINKERD2_PROXY_TRAFFIC_SPLIT_PATH";
+
+/// Configures a window over which newly discovered endpoints' share of
+/// requests is ramped up linearly, so that endpoints which are warming up are
+/// not sent their full share of requests.
+///
+/// Applies to destinations that are balanced by load, i.e. with a P2C
+/// algorithm. See `proxy::http::balance::slow_start`.
+pub const ENV_SLOW_START_WINDOW: &str = "LINKERD2_PROXY_SLOW_START_WINDOW";
+
+/// Configures a file that chooses the load balancing algorithm for each
+/// outbound destination.
+///
+/// The file is reloaded as it changes. Its algorithms take precedence over
+/// those that the Destination service chooses. See `control::algorithm`.
+pub const ENV_BALANCE_ALGORITHMS_PATH: &str = "LINKERD2_PROXY_BALANCE_ALGORITHMS_PATH";
+
+/// Configures a file that limits the concurrency and rate of inbound
+/// requests, by their source, authority or path.
+///
+/// The file is reloaded as it changes. See `proxy::http::admission`.
+pub const ENV_INBOUND_ADMISSION_PATH: &str = "LINKERD2_PROXY_INBOUND_ADMISSION_PATH";
+
+/// Limits the number of requests in flight for each inbound route.
+pub const ENV_INBOUND_MAX_IN_FLIGHT: &str = "LINKERD2_PROXY_INBOUND_MAX_IN_FLIGHT";
+
+/// Configures inbound ports that require clients to authenticate with TLS
+/// certificates. Connections from other clients to these ports are closed.
+///
+/// The port is that of the connection's SO_ORIGINAL_DST, if it has one.
+pub const ENV_INBOUND_PORTS_REQUIRE_IDENTITY: &str = "LINKERD2_PROXY_INBOUND_PORTS_REQUIRE_IDENTITY";
+
+/// Configures a file that allows or denies inbound connections and requests
+/// by port, path, and client identity.
+///
+/// The file is reloaded as it changes. See `proxy::authorize`.
+pub const ENV_INBOUND_AUTHORIZATION_PATH: &str = "LINKERD2_PROXY_INBOUND_AUTHORIZATION_PATH";
+
+/// Configures the proxy's locality, so that outbound requests prefer
+/// endpoints whose `region` and `zone` labels match.
+///
+/// If fewer than `LINKERD2_PROXY_LOCALITY_MIN_READY` (a ratio between 0 and
+/// 1) of the local endpoints are ready, a proportional share of requests
+/// spills over to endpoints in other localities.
+pub const ENV_LOCALITY_REGION: &str = "LINKERD2_PROXY_LOCALITY_REGION";
+pub const ENV_LOCALITY_ZONE: &str = "LINKERD2_PROXY_LOCALITY_ZONE";
+pub const ENV_LOCALITY_MIN_READY: &str = "LINKERD2_PROXY_LOCALITY_MIN_READY";
+
+/// Configures a comma-separated list of outbound authorities whose requests
+/// are hedged: if a request is slower than `LINKERD2_PROXY_HEDGE_PERCENTILE`
+/// (a ratio between 0 and 1) of the authority's recent responses, a copy of
+/// it is sent to another endpoint and whichever response is received first is
+/// used.
+///
+/// Listing an authority declares that its requests are safe to send twice, so
+/// its requests are hedged (and retried) whatever their method, including
+/// gRPC requests. HTTP/1.1 upgrades are never hedged.
+pub const ENV_HEDGE_AUTHORITIES: &str = "LINKERD2_PROXY_HEDGE_AUTHORITIES";
+pub const ENV_HEDGE_PERCENTILE: &str = "LINKERD2_PROXY_HEDGE_PERCENTILE";
+
 pub const DEPRECATED_ENV_PRIVATE_LISTENER: &str = "LINKERD2_PROXY_PRIVATE_LISTENER";
 pub const DEPRECATED_ENV_PRIVATE_FORWARD: &str = "LINKERD2_PROXY_PRIVATE_FORWARD";
 const DEPRECATED_ENV_PUBLIC_LISTENER: &str = "LINKERD2_PROXY_PUBLIC_LISTENER";
@@ -198,6 +366,30 @@ pub const ENV_OUTBOUND_ROUTER_MAX_IDLE_AGE: &str = "LINKERD2_PROXY_OUTBOUND_ROUT
 pub const ENV_DESTINATION_CLIENT_CONCURRENCY_LIMIT: &str =
     "LINKERD2_PROXY_DESTINATION_CLIENT_CONCURRENCY_LIMIT";
 
+/// Configures a YAML or JSON file that describes the endpoints of authorities,
+/// which are then resolved from the file instead of the Destination service or
+/// DNS.
+///
+/// The file is reloaded as it changes. See `control::destination::static_endpoints`.
+pub const ENV_DESTINATION_STATIC_ENDPOINTS_PATH: &str =
+    "LINKERD2_PROXY_DESTINATION_STATIC_ENDPOINTS_PATH";
+
+/// Limits the number of endpoints that a single resolution may have.
+///
+/// When an authority has more endpoints than this, a subset of them is chosen
+/// by rendezvous hashing on the pod's identity, so that each proxy uses a
+/// stable subset and the proxies of different pods use different subsets.
+pub const ENV_DESTINATION_MAX_ENDPOINTS_PER_RESOLUTION: &str =
+    "LINKERD2_PROXY_DESTINATION_MAX_ENDPOINTS_PER_RESOLUTION";
+
+/// Limits the number of endpoints across all resolutions.
+///
+/// When resolutions have more endpoints than this in total, the limit is
+/// shared evenly among them, and each is subset as it is when it exceeds
+/// `ENV_DESTINATION_MAX_ENDPOINTS_PER_RESOLUTION`. Every resolution that has
+/// endpoints uses at least one, even if that exceeds this limit.
+pub const ENV_DESTINATION_MAX_ENDPOINTS: &str = "LINKERD2_PROXY_DESTINATION_MAX_ENDPOINTS";
+
 // These *disable* our protocol detection for connections whose SO_ORIGINAL_DST
 // has a port in the provided list.
 pub const ENV_INBOUND_PORTS_DISABLE_PROTOCOL_DETECTION: &str = "LINKERD2_PROXY_INBOUND_PORTS_DISABLE_PROTOCOL_DETECTION";
@@ -209,6 +401,37 @@ pub const ENV_TLS_PRIVATE_KEY: &str = "LINKERD2_PROXY_TLS_PRIVATE_KEY";
 pub const ENV_TLS_POD_IDENTITY: &str = "LINKERD2_PROXY_TLS_POD_IDENTITY";
 pub const ENV_TLS_CONTROLLER_IDENTITY: &str = "LINKERD2_PROXY_TLS_CONTROLLER_IDENTITY";
 
+/// The path to a file of concatenated DER-encoded certificate revocation
+/// lists. Peers' end-entity certificates that have been revoked are rejected;
+/// intermediate certificates are not checked. A warning is logged while a
+/// list is past its `nextUpdate`.
+pub const ENV_TLS_REVOCATION_LIST: &str = "LINKERD2_PROXY_TLS_REVOCATION_LIST";
+
+/// The oldest version of TLS that may be negotiated, either `1.2` or `1.3`.
+/// Newer versions may also be negotiated.
+pub const ENV_TLS_MIN_VERSION: &str = "LINKERD2_PROXY_TLS_MIN_VERSION";
+
+/// The maximum number of TLS sessions that are cached so that outbound
+/// connections may resume them.
+pub const ENV_OUTBOUND_TLS_SESSION_CACHE_CAPACITY: &str =
+    "LINKERD2_PROXY_OUTBOUND_TLS_SESSION_CACHE_CAPACITY";
+
+/// A comma-separated list of the cipher suites that may be negotiated, in
+/// order of preference, by their IANA names. Each suite must be usable with
+/// the minimum TLS version or a newer one.
+pub const ENV_TLS_CIPHER_SUITES: &str = "LINKERD2_PROXY_TLS_CIPHER_SUITES";
+
+/// Configures how long before a certificate in use expires to begin logging
+/// warnings about it.
+pub const ENV_TLS_CERT_EXPIRY_WARNING: &str = "LINKERD2_PROXY_TLS_CERT_EXPIRY_WARNING";
+
+/// If true, connections to the control (tap) listener are closed unless the
+/// client authenticates with a certificate for the controller's identity, so
+/// that only the control plane may tap traffic.
+///
+/// Requires that `ENV_TLS_CONTROLLER_IDENTITY` is set.
+pub const ENV_TAP_REQUIRE_CONTROLLER_IDENTITY: &str = "LINKERD2_PROXY_TAP_REQUIRE_CONTROLLER_IDENTITY";
+
 pub const ENV_CONTROLLER_NAMESPACE: &str = "LINKERD2_PROXY_CONTROLLER_NAMESPACE";
 pub const ENV_POD_NAMESPACE: &str = "LINKERD2_PROXY_POD_NAMESPACE";
 pub const VAR_POD_NAMESPACE: &str = "$LINKERD2_PROXY_POD_NAMESPACE";
@@ -225,6 +448,28 @@ const ENV_DNS_MIN_TTL: &str = "LINKERD2_PROXY_DNS_MIN_TTL";
 ///
 /// Lookups with TTLs above this value will use this value instead.
 const ENV_DNS_MAX_TTL: &str = "LINKERD2_PROXY_DNS_MAX_TTL";
+/// Configures a comma-separated list of DNS suffixes, such as
+/// `service.consul`, of names that are resolved by their SRV records.
+///
+/// The SRV records' ports are used instead of the port of the authority, and
+/// their weights are used as the endpoints' weights. Names of the form
+/// `_service._proto.name` are always resolved by their SRV records.
+const ENV_DNS_SRV_SUFFIXES: &str = "LINKERD2_PROXY_DNS_SRV_SUFFIXES";
+/// Configures how long the last DNS answer for an authority continues to be
+/// used after NXDOMAIN responses, so that a spurious NXDOMAIN does not remove
+/// all of its endpoints.
+const ENV_DNS_NXDOMAIN_GRACE_PERIOD: &str = "LINKERD2_PROXY_DNS_NXDOMAIN_GRACE_PERIOD";
+/// Configures how long the last DNS answer for an authority may be used after
+/// its TTL has elapsed, while queries fail.
+///
+/// If this is not set, the last answer is used until a query succeeds.
+const ENV_DNS_MAX_STALENESS: &str = "LINKERD2_PROXY_DNS_MAX_STALENESS";
+/// Configures the backoff between DNS queries after errors.
+///
+/// The backoff starts at the base value and doubles (with jitter) after each
+/// consecutive error, up to the maximum value.
+const ENV_DNS_ERROR_BACKOFF_BASE: &str = "LINKERD2_PROXY_DNS_ERROR_BACKOFF_BASE";
+const ENV_DNS_ERROR_BACKOFF_MAX: &str = "LINKERD2_PROXY_DNS_ERROR_BACKOFF_MAX";
 
 // Default values for various configuration fields
 const DEFAULT_OUTBOUND_LISTENER: &str = "tcp://127.0.0.1:4140";
@@ -236,7 +481,17 @@ const DEFAULT_INBOUND_CONNECT_TIMEOUT: Duration = Duration::from_millis(20);
 const DEFAULT_OUTBOUND_CONNECT_TIMEOUT: Duration = Duration::from_millis(300);
 const DEFAULT_BIND_TIMEOUT: Duration = Duration::from_secs(10); // same as in Linkerd
 const DEFAULT_CONTROL_BACKOFF_DELAY: Duration = Duration::from_secs(5);
+const DEFAULT_RECONNECT_BACKOFF_BASE: Duration = Duration::from_millis(100);
+const DEFAULT_RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(10);
+const DEFAULT_DNS_NXDOMAIN_GRACE_PERIOD: Duration = Duration::from_secs(0);
+const DEFAULT_DNS_ERROR_BACKOFF_BASE: Duration = Duration::from_secs(5);
+const DEFAULT_DNS_ERROR_BACKOFF_MAX: Duration = Duration::from_secs(60);
 const DEFAULT_RESOLV_CONF: &str = "/etc/resolv.conf";
+const DEFAULT_LOCALITY_MIN_READY: f32 = 0.7;
+const DEFAULT_HEDGE_PERCENTILE: f32 = 0.95;
+const DEFAULT_INBOUND_MAX_IN_FLIGHT: usize = 10_000;
+const DEFAULT_OUTBOUND_TLS_SESSION_CACHE_CAPACITY: usize = 1_000;
+const DEFAULT_TLS_CERT_EXPIRY_WARNING: Duration = Duration::from_secs(24 * 60 * 60);
 
 /// It's assumed that a typical proxy can serve inbound traffic for up to 100 pod-local
 /// HTTP services and may communicate with up to 10K external HTTP domains.
@@ -248,6 +503,9 @@ const DEFAULT_OUTBOUND_ROUTER_MAX_IDLE_AGE: Duration = Duration::from_secs(60);
 
 const DEFAULT_DESTINATION_CLIENT_CONCURRENCY_LIMIT: usize = 100;
 
+const DEFAULT_DESTINATION_MAX_ENDPOINTS_PER_RESOLUTION: usize = 1_000;
+const DEFAULT_DESTINATION_MAX_ENDPOINTS: usize = 10_000;
+
 // By default, we keep a list of known assigned ports of server-first protocols.
 //
 // https://www.iana.org/assignments/service-names-port-numbers/service-names-port-numbers.txt
@@ -299,16 +557,50 @@ impl<'a> TryFrom<&'a Strings> for Config {
         let outbound_router_max_idle_age = parse(strings, ENV_OUTBOUND_ROUTER_MAX_IDLE_AGE, parse_duration);
         let destination_concurrency_limit =
             parse(strings, ENV_DESTINATION_CLIENT_CONCURRENCY_LIMIT, parse_number);
+        let destination_static_endpoints_path =
+            parse(strings, ENV_DESTINATION_STATIC_ENDPOINTS_PATH, parse_path);
+        let destination_max_endpoints_per_resolution =
+            parse(strings, ENV_DESTINATION_MAX_ENDPOINTS_PER_RESOLUTION, parse_number);
+        let destination_max_endpoints =
+            parse(strings, ENV_DESTINATION_MAX_ENDPOINTS, parse_number);
         let tls_trust_anchors = parse(strings, ENV_TLS_TRUST_ANCHORS, parse_path);
         let tls_end_entity_cert = parse(strings, ENV_TLS_CERT, parse_path);
         let tls_private_key = parse(strings, ENV_TLS_PRIVATE_KEY, parse_path);
         let tls_pod_identity_template = strings.get(ENV_TLS_POD_IDENTITY);
         let tls_controller_identity = strings.get(ENV_TLS_CONTROLLER_IDENTITY);
+        let tls_revocation_list = parse(strings, ENV_TLS_REVOCATION_LIST, parse_path);
+        let tls_min_version = parse(strings, ENV_TLS_MIN_VERSION, parse_tls_version);
+        let outbound_tls_session_cache_capacity =
+            parse(strings, ENV_OUTBOUND_TLS_SESSION_CACHE_CAPACITY, parse_number);
+        let tls_cipher_suites = parse(strings, ENV_TLS_CIPHER_SUITES, parse_cipher_suites);
+        let tls_cert_expiry_warning = parse(strings, ENV_TLS_CERT_EXPIRY_WARNING, parse_duration);
+        let tap_require_controller_identity =
+            parse(strings, ENV_TAP_REQUIRE_CONTROLLER_IDENTITY, parse_bool);
         let bind_timeout = parse(strings, ENV_BIND_TIMEOUT, parse_duration);
+        let reconnect_backoff_base = parse(strings, ENV_RECONNECT_BACKOFF_BASE, parse_duration);
+        let reconnect_backoff_max = parse(strings, ENV_RECONNECT_BACKOFF_MAX, parse_duration);
+        let traffic_split_path = parse(strings, ENV_TRAFFIC_SPLIT_PATH, parse_path);
+        let slow_start_window = parse(strings, ENV_SLOW_START_WINDOW, parse_duration);
+        let balance_algorithms_path = parse(strings, ENV_BALANCE_ALGORITHMS_PATH, parse_path);
+        let inbound_admission_path = parse(strings, ENV_INBOUND_ADMISSION_PATH, parse_path);
+        let inbound_max_in_flight = parse(strings, ENV_INBOUND_MAX_IN_FLIGHT, parse_number);
+        let inbound_ports_require_identity = parse(strings, ENV_INBOUND_PORTS_REQUIRE_IDENTITY, parse_port_set);
+        let inbound_authorization_path = parse(strings, ENV_INBOUND_AUTHORIZATION_PATH, parse_path);
+        let locality_region = strings.get(ENV_LOCALITY_REGION);
+        let locality_zone = strings.get(ENV_LOCALITY_ZONE);
+        let locality_min_ready = parse(strings, ENV_LOCALITY_MIN_READY, parse_ratio);
+        let hedge_authorities = parse(strings, ENV_HEDGE_AUTHORITIES, parse_authority_set);
+        let hedge_percentile = parse(strings, ENV_HEDGE_PERCENTILE, parse_ratio);
         let resolv_conf_path = strings.get(ENV_RESOLV_CONF);
         let metrics_retain_idle = parse(strings, ENV_METRICS_RETAIN_IDLE, parse_duration);
         let dns_min_ttl = parse(strings, ENV_DNS_MIN_TTL, parse_duration);
         let dns_max_ttl = parse(strings, ENV_DNS_MAX_TTL, parse_duration);
+        let dns_srv_suffixes = parse(strings, ENV_DNS_SRV_SUFFIXES, parse_dns_suffixes);
+        let dns_nxdomain_grace_period =
+            parse(strings, ENV_DNS_NXDOMAIN_GRACE_PERIOD, parse_duration);
+        let dns_max_staleness = parse(strings, ENV_DNS_MAX_STALENESS, parse_duration);
+        let dns_error_backoff_base = parse(strings, ENV_DNS_ERROR_BACKOFF_BASE, parse_duration);
+        let dns_error_backoff_max = parse(strings, ENV_DNS_ERROR_BACKOFF_MAX, parse_duration);
         let pod_namespace = strings.get(ENV_POD_NAMESPACE).and_then(|maybe_value| {
             // There cannot be a default pod namespace, and the pod namespace is required.
             maybe_value.ok_or_else(|| {
@@ -324,6 +616,18 @@ impl<'a> TryFrom<&'a Strings> for Config {
 
         let control_backoff_delay = parse(strings, ENV_CONTROL_BACKOFF_DELAY, parse_duration)?
             .unwrap_or(DEFAULT_CONTROL_BACKOFF_DELAY);
+        let tls_revocation_list = tls_revocation_list?;
+        let tls_min_version = tls_min_version?.unwrap_or(tls::TlsVersion::Tls12);
+        let tls_cipher_suites = tls_cipher_suites?;
+        if let Some(ref suites) = tls_cipher_suites {
+            if !suites.supports(tls_min_version) {
+                error!("{} includes cipher suites that may not be negotiated",
+                       ENV_TLS_CIPHER_SUITES);
+                return Err(Error::InvalidEnvVar);
+            }
+        }
+        let tls_cert_expiry_warning = tls_cert_expiry_warning?
+            .unwrap_or(DEFAULT_TLS_CERT_EXPIRY_WARNING);
 
         let namespaces = Namespaces {
             pod: pod_namespace?,
@@ -344,7 +648,7 @@ impl<'a> TryFrom<&'a Strings> for Config {
              Some(tls_pod_identity_template)) => {
                 let pod_identity =
                     tls_pod_identity_template.replace(VAR_POD_NAMESPACE, &namespaces.pod);
-                let pod_identity = tls::Identity::from_sni_hostname(pod_identity.as_bytes())
+                let pod_identity = tls::Identity::parse(&pod_identity)
                     .map_err(|_| Error::InvalidEnvVar)?; // Already logged.
 
                 // Avoid setting the controller identity if it is going to be
@@ -355,7 +659,7 @@ impl<'a> TryFrom<&'a Strings> for Config {
                         Some(hp) if hp.is_loopback() =>
                             Conditional::None(tls::ReasonForNoIdentity::Loopback),
                         Some(_) => {
-                            let identity = tls::Identity::from_sni_hostname(identity.as_bytes())
+                            let identity = tls::Identity::parse(identity)
                                 .map_err(|_| Error::InvalidEnvVar)?; // Already logged.
                             Conditional::Some(identity)
                         },
@@ -369,8 +673,12 @@ impl<'a> TryFrom<&'a Strings> for Config {
                     trust_anchors,
                     end_entity_cert,
                     private_key,
+                    revocation_list: tls_revocation_list,
                     pod_identity,
                     controller_identity,
+                    expiry_warning: tls_cert_expiry_warning,
+                    min_version: tls_min_version,
+                    cipher_suites: tls_cipher_suites,
                 }))
             },
             (None, None, None, _) => Ok(Conditional::None(tls::ReasonForNoTls::Disabled)),
@@ -395,6 +703,22 @@ impl<'a> TryFrom<&'a Strings> for Config {
             },
         }?;
 
+        let tap_client_identity = if tap_require_controller_identity?.unwrap_or(false) {
+            match tls_settings {
+                Conditional::Some(tls::CommonSettings {
+                    controller_identity: Conditional::Some(ref identity),
+                    ..
+                }) => Some(identity.clone()),
+                _ => {
+                    error!("{} requires a controller identity, but {} is not configured.",
+                           ENV_TAP_REQUIRE_CONTROLLER_IDENTITY, ENV_TLS_CONTROLLER_IDENTITY);
+                    return Err(Error::InvalidEnvVar);
+                },
+            }
+        } else {
+            None
+        };
+
         Ok(Config {
             outbound_listener: Listener {
                 addr: outbound_listener_addr?
@@ -436,8 +760,17 @@ impl<'a> TryFrom<&'a Strings> for Config {
 
             destination_concurrency_limit: destination_concurrency_limit?
                 .unwrap_or(DEFAULT_DESTINATION_CLIENT_CONCURRENCY_LIMIT),
+            destination_static_endpoints_path: destination_static_endpoints_path?,
+            destination_max_endpoints_per_resolution: destination_max_endpoints_per_resolution?
+                .unwrap_or(DEFAULT_DESTINATION_MAX_ENDPOINTS_PER_RESOLUTION),
+            destination_max_endpoints: destination_max_endpoints?
+                .unwrap_or(DEFAULT_DESTINATION_MAX_ENDPOINTS),
 
             tls_settings,
+            outbound_tls_session_cache_capacity: outbound_tls_session_cache_capacity?
+                .unwrap_or(DEFAULT_OUTBOUND_TLS_SESSION_CACHE_CAPACITY),
+
+            tap_client_identity,
 
             resolv_conf_path: resolv_conf_path?
                 .unwrap_or(DEFAULT_RESOLV_CONF.into())
@@ -449,11 +782,49 @@ impl<'a> TryFrom<&'a Strings> for Config {
 
             bind_timeout: bind_timeout?.unwrap_or(DEFAULT_BIND_TIMEOUT),
 
+            reconnect_backoff_base: reconnect_backoff_base?
+                .unwrap_or(DEFAULT_RECONNECT_BACKOFF_BASE),
+            reconnect_backoff_max: reconnect_backoff_max?
+                .unwrap_or(DEFAULT_RECONNECT_BACKOFF_MAX),
+
             namespaces,
 
+            traffic_split_path: traffic_split_path?,
+
+            slow_start_window: slow_start_window?,
+
+            balance_algorithms_path: balance_algorithms_path?,
+
+            inbound_admission_path: inbound_admission_path?,
+            inbound_max_in_flight: inbound_max_in_flight?
+                .unwrap_or(DEFAULT_INBOUND_MAX_IN_FLIGHT),
+
+            inbound_ports_require_identity: inbound_ports_require_identity?
+                .unwrap_or_default(),
+            inbound_authorization_path: inbound_authorization_path?,
+
+            locality_region: locality_region?,
+            locality_zone: locality_zone?,
+            locality_min_ready: locality_min_ready?.unwrap_or(DEFAULT_LOCALITY_MIN_READY),
+
+            hedge_authorities: hedge_authorities?.unwrap_or_default(),
+            hedge_percentile: hedge_percentile?.unwrap_or(DEFAULT_HEDGE_PERCENTILE),
+
             dns_min_ttl: dns_min_ttl?,
 
             dns_max_ttl: dns_max_ttl?,
+
+            dns_srv_suffixes: dns_srv_suffixes?.unwrap_or_default(),
+
+            dns_nxdomain_grace_period: dns_nxdomain_grace_period?
+                .unwrap_or(DEFAULT_DNS_NXDOMAIN_GRACE_PERIOD),
+
+            dns_max_staleness: dns_max_staleness?,
+
+            dns_error_backoff_base: dns_error_backoff_base?
+                .unwrap_or(DEFAULT_DNS_ERROR_BACKOFF_BASE),
+            dns_error_backoff_max: dns_error_backoff_max?
+                .unwrap_or(DEFAULT_DNS_ERROR_BACKOFF_MAX),
         })
     }
 }
@@ -529,7 +900,28 @@ fn parse_number<T>(s: &str) -> Result<T, ParseError> where T: FromStr {
     s.parse().map_err(|_| ParseError::NotANumber)
 }
 
-fn parse_duration(s: &str) -> Result<Duration, ParseError> {
+fn parse_bool(s: &str) -> Result<bool, ParseError> {
+    s.parse().map_err(|_| ParseError::NotABool)
+}
+
+fn parse_tls_version(s: &str) -> Result<tls::TlsVersion, ParseError> {
+    s.parse().map_err(|()| ParseError::NotATlsVersion)
+}
+
+fn parse_cipher_suites(s: &str) -> Result<tls::CipherSuites, ParseError> {
+    s.parse().map_err(|()| ParseError::NotACipherSuite)
+}
+
+fn parse_ratio(s: &str) -> Result<f32, ParseError> {
+    let r: f32 = parse_number(s)?;
+    if r > 0.0 && r <= 1.0 {
+        Ok(r)
+    } else {
+        Err(ParseError::NotARatio)
+    }
+}
+
+pub(crate) fn parse_duration(s: &str) -> Result<Duration, ParseError> {
     use regex::Regex;
 
     let re = Regex::new(r"^\s*(\d+)(ms|s|m|h|d)?\s*$")
@@ -581,6 +973,26 @@ fn parse_port_set(s: &str) -> Result<IndexSet<u16>, ParseError> {
     Ok(set)
 }
 
+fn parse_authority_set(s: &str) -> Result<IndexSet<DnsNameAndPort>, ParseError> {
+    let mut set = IndexSet::new();
+    for authority in s.split(',') {
+        let authority = ::control::split::parse_authority(authority.trim())
+            .ok_or(ParseError::NotAnAuthority)?;
+        set.insert(authority);
+    }
+    Ok(set)
+}
+
+fn parse_dns_suffixes(s: &str) -> Result<Vec<dns::Name>, ParseError> {
+    let mut suffixes = Vec::new();
+    for suffix in s.split(',') {
+        let suffix = dns::Name::try_from(suffix.trim().as_bytes())
+            .map_err(|_| ParseError::NotADnsSuffix)?;
+        suffixes.push(suffix);
+    }
+    Ok(suffixes)
+}
+
 fn parse<T, Parse>(strings: &Strings, name: &str, parse: Parse) -> Result<Option<T>, Error>
     where Parse: FnOnce(&str) -> Result<T, ParseError> {
     match strings.get(name)? {
@@ -676,6 +1088,19 @@ mod tests {
         assert_eq!(parse_duration("12y"), Err(ParseError::NotADuration));
     }
 
+    #[test]
+    fn parse_bool_invalid() {
+        assert_eq!(parse_bool("true"), Ok(true));
+        assert_eq!(parse_bool("yes"), Err(ParseError::NotABool));
+    }
+
+    #[test]
+    fn parse_tls_version_invalid() {
+        assert_eq!(parse_tls_version("1.3"), Ok(tls::TlsVersion::Tls13));
+        assert_eq!(parse_tls_version("1.1"), Err(ParseError::NotATlsVersion));
+        assert_eq!(parse_tls_version("TLSv1.2"), Err(ParseError::NotATlsVersion));
+    }
+
     #[test]
     fn parse_duration_zero_without_unit() {
         assert_eq!(parse_duration("0"), Ok(Duration::from_secs(0)));
@@ -685,4 +1110,30 @@ mod tests {
     fn parse_duration_number_without_unit_is_invalid() {
         assert_eq!(parse_duration("1"), Err(ParseError::NotADuration));
     }
+
+    #[test]
+    fn parse_ratio_in_range() {
+        assert_eq!(parse_ratio("0.7"), Ok(0.7));
+        assert_eq!(parse_ratio("1"), Ok(1.0));
+        assert_eq!(parse_ratio("0"), Err(ParseError::NotARatio));
+        assert_eq!(parse_ratio("1.5"), Err(ParseError::NotARatio));
+        assert_eq!(parse_ratio("half"), Err(ParseError::NotANumber));
+    }
+
+    #[test]
+    fn parse_authority_set_uses_default_port() {
+        let set = parse_authority_set("web.svc.cluster.local:8080, api.svc.cluster.local").unwrap();
+        let ports = set.iter().map(|a| a.port).collect::<Vec<_>>();
+        assert_eq!(ports, vec![8080, 80]);
+        assert_eq!(parse_authority_set("10.1.1.1:8080"), Err(ParseError::NotAnAuthority));
+        assert_eq!(parse_authority_set("web:8080,"), Err(ParseError::NotAnAuthority));
+    }
+
+    #[test]
+    fn parse_dns_suffixes_invalid() {
+        let suffixes = parse_dns_suffixes("service.consul, srv.example.com.").unwrap();
+        assert_eq!(suffixes.len(), 2);
+        assert_eq!(parse_dns_suffixes("service.consul,").map(|_| ()), Err(ParseError::NotADnsSuffix));
+        assert_eq!(parse_dns_suffixes("1.2.3.4").map(|_| ()), Err(ParseError::NotADnsSuffix));
+    }
 }
@@ -0,0 +1,217 @@
+//! Load balancing algorithms, configured per destination.
+//!
+//! Algorithms are read from a local file, which is watched for changes. Each
+//! line that is not empty or a `#` comment names an authority, followed by the
+//! algorithm that balances its endpoints and the algorithm's options:
+//!
+//! ```text
+//! web.prod.svc.cluster.local:8080 p2c-peak-ewma decay=30s
+//! api.prod.svc.cluster.local:8080 p2c-pending-requests
+//! cache.prod.svc.cluster.local:8080 round-robin
+//! cart.prod.svc.cluster.local:8080 hash header=x-session-id
+//! shop.prod.svc.cluster.local:8080 hash cookie=session
+//! ```
+//!
+//! If no port is specified, port 80 is assumed. Destinations that are not
+//! configured use `p2c-peak-ewma` with a decay of 10s. A change applies to a
+//! destination's requests as soon as the file is reloaded: they are routed to
+//! a new balancer that uses the new algorithm.
+//!
+//! The Destination service may also choose a destination's algorithm, by
+//! labeling its endpoints (see `control::destination::routes`). An algorithm
+//! in the file takes precedence over one from the Destination service. Since
+//! the Destination service's algorithm is only known once the destination is
+//! resolved, its first requests may be balanced by the default algorithm.
+
+use futures_watch;
+use http::header::HeaderName;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use fs_watch::{parse_lines, LineError};
+use super::split::parse_authority;
+use transport::DnsNameAndPort;
+
+/// This default is used by Finagle.
+const DEFAULT_DECAY: Duration = Duration::from_secs(10);
+
+/// Watches the current `Algorithms`.
+pub type Watch = futures_watch::Watch<Algorithms>;
+
+/// Maps authorities to the algorithms that balance their endpoints.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Algorithms(HashMap<DnsNameAndPort, Algorithm>);
+
+/// Describes how requests are distributed across a destination's endpoints.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    /// Chooses the less loaded of two random endpoints, by a peak EWMA of
+    /// their response latencies.
+    PeakEwma { decay: Duration },
+
+    /// Chooses the less loaded of two random endpoints, by their numbers of
+    /// pending requests.
+    PendingRequests,
+
+    /// Chooses each endpoint in turn.
+    RoundRobin,
+
+    /// Chooses endpoints by a consistent hash of a request's key, so that
+    /// requests with the same key are sent to the same endpoint.
+    Hash(HashKey),
+}
+
+/// The part of a request that is hashed to choose its endpoint.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    Header(HeaderName),
+    Cookie(String),
+}
+
+// ===== impl Algorithms =====
+
+impl Algorithms {
+    /// Returns the algorithm configured for the given authority, if there is
+    /// one.
+    pub fn get(&self, authority: &DnsNameAndPort) -> Option<&Algorithm> {
+        self.0.get(authority)
+    }
+}
+
+impl FromStr for Algorithms {
+    type Err = LineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut algorithms = HashMap::new();
+
+        parse_lines(s, |line| {
+            let mut words = line.splitn(2, char::is_whitespace);
+            let authority = words.next()
+                .and_then(parse_authority)
+                .ok_or("invalid authority")?;
+            let algorithm = words.next().unwrap_or("").parse::<Algorithm>()?;
+
+            if algorithms.insert(authority, algorithm).is_some() {
+                return Err("authority is configured more than once");
+            }
+            Ok(())
+        })?;
+
+        Ok(Algorithms(algorithms))
+    }
+}
+
+// ===== impl Algorithm =====
+
+impl FromStr for Algorithm {
+    type Err = &'static str;
+
+    /// Parses an algorithm's name, followed by its option, if it has one.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let name = words.next()
+            .ok_or("missing algorithm")?;
+        let option = words.next().map(|word| {
+            let mut parts = word.splitn(2, '=');
+            (parts.next().unwrap_or(""), parts.next())
+        });
+        if words.next().is_some() {
+            return Err("too many options");
+        }
+
+        let algorithm = match (name, option) {
+            ("p2c-peak-ewma", None) => Algorithm::default(),
+            ("p2c-peak-ewma", Some(("decay", Some(decay)))) => {
+                let decay = ::config::parse_duration(decay)
+                    .map_err(|_| "invalid decay")?;
+                Algorithm::PeakEwma { decay }
+            }
+            ("p2c-pending-requests", None) => Algorithm::PendingRequests,
+            ("round-robin", None) => Algorithm::RoundRobin,
+            ("hash", Some(("header", Some(header)))) => {
+                let header = HeaderName::from_str(header)
+                    .map_err(|_| "invalid header name")?;
+                Algorithm::Hash(HashKey::Header(header))
+            }
+            ("hash", Some(("cookie", Some(cookie)))) if !cookie.is_empty() => {
+                Algorithm::Hash(HashKey::Cookie(cookie.to_owned()))
+            }
+            ("hash", _) => return Err("hash requires a header or cookie"),
+            ("p2c-peak-ewma", _) |
+            ("p2c-pending-requests", _) |
+            ("round-robin", _) => return Err("invalid option"),
+            _ => return Err("unknown algorithm"),
+        };
+        Ok(algorithm)
+    }
+}
+
+impl Default for Algorithm {
+    fn default() -> Self {
+        Algorithm::PeakEwma { decay: DEFAULT_DECAY }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use convert::TryFrom;
+    use dns;
+
+    fn authority(host: &str, port: u16) -> DnsNameAndPort {
+        DnsNameAndPort {
+            host: dns::Name::try_from(host.as_bytes()).unwrap(),
+            port,
+        }
+    }
+
+    #[test]
+    fn parses_algorithms() {
+        let algorithms = "
+            # sticky sessions
+            cart.prod.svc.cluster.local:8080 hash header=x-session-id
+            shop.prod.svc.cluster.local hash cookie=session
+
+            web.prod.svc.cluster.local:8080 p2c-peak-ewma decay=30s
+            api.prod.svc.cluster.local:8080 p2c-pending-requests
+            cache.prod.svc.cluster.local:8080 round-robin
+        ".parse::<Algorithms>().unwrap();
+
+        let get = |host, port| algorithms.get(&authority(host, port)).cloned();
+        assert_eq!(
+            get("cart.prod.svc.cluster.local", 8080),
+            Some(Algorithm::Hash(HashKey::Header(HeaderName::from_static("x-session-id")))),
+        );
+        assert_eq!(
+            get("shop.prod.svc.cluster.local", 80),
+            Some(Algorithm::Hash(HashKey::Cookie("session".into()))),
+        );
+        assert_eq!(
+            get("web.prod.svc.cluster.local", 8080),
+            Some(Algorithm::PeakEwma { decay: Duration::from_secs(30) }),
+        );
+        assert_eq!(get("api.prod.svc.cluster.local", 8080), Some(Algorithm::PendingRequests));
+        assert_eq!(get("cache.prod.svc.cluster.local", 8080), Some(Algorithm::RoundRobin));
+        assert_eq!(get("cache.prod.svc.cluster.local", 80), None);
+    }
+
+    #[test]
+    fn rejects_invalid_algorithms() {
+        for s in &[
+            "web:8080",
+            "web:8080 random",
+            "web:8080 hash",
+            "web:8080 hash header=",
+            "web:8080 hash cookie=",
+            "web:8080 hash query=id",
+            "web:8080 round-robin decay=10s",
+            "web:8080 p2c-peak-ewma decay=soon",
+            "web:8080 p2c-peak-ewma decay=10s header=x-id",
+            "10.1.1.1:8080 round-robin",
+            "web:8080 round-robin\nweb:8080 p2c-pending-requests",
+        ] {
+            assert!(s.parse::<Algorithms>().is_err(), "{:?} must not parse", s);
+        }
+    }
+}
@@ -63,6 +63,14 @@ where
         self.reset_on_next_modification = true;
     }
 
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.inner.get(key)
+    }
+
     /// Update the cache to contain the union of its current contents and the
     /// key-value pairs in `iter`. Pairs not present in the cache will be
     /// inserted, and keys present in both the cache and the iterator will be
@@ -1,5 +1,7 @@
 use indexmap::IndexMap;
+use rand;
 use std::{
+    cmp,
     collections::HashMap,
     fmt,
     iter::IntoIterator,
@@ -21,21 +23,73 @@ use linkerd2_proxy_api::{
 
 use control::{
     cache::{Cache, CacheChange, Exists},
-    destination::{Metadata, Responder, ProtocolHint, Update},
+    destination::{DnsConfig, Metadata, Responder, ProtocolHint, Routes, Update},
     remote_stream::Remote,
 };
 use dns::{self, IpAddrListFuture};
+use svc::reconnect::Backoff;
+use telemetry::{dns::{ResolutionState, Sensor}, resolution};
 use transport::{tls, DnsNameAndPort};
 use conditional::Conditional;
 
-use super::{ActiveQuery, DestinationServiceQuery, UpdateRx};
+use super::{limits, ActiveQuery, DestinationServiceQuery, UpdateRx};
 
 /// Holds the state of a single resolution.
 pub(super) struct DestinationSet<T: HttpService<ResponseBody = RecvBody>> {
+    /// All of the endpoints that have been discovered.
     pub addrs: Exists<Cache<SocketAddr, Metadata>>,
+    /// The endpoints that are sent to responders.
+    pub subset: Subset,
     pub query: DestinationServiceQuery<T>,
     pub dns_query: Option<IpAddrListFuture>,
+    pub dns_state: DnsState,
     pub responders: Vec<Responder>,
+    /// Whether the endpoints are read from the static endpoints file, rather
+    /// than the Destination service or DNS.
+    pub is_static: bool,
+}
+
+/// Tracks how current the last DNS answer is, so that it may continue to be
+/// used for a while after DNS queries start to fail.
+pub(super) struct DnsState {
+    /// When the last positive answer's TTL elapses, while it's being used.
+    valid_until: Option<Instant>,
+
+    /// When NXDOMAIN was first returned since the last positive answer.
+    nxdomain_since: Option<Instant>,
+
+    /// The number of consecutive failed queries.
+    failures: u32,
+
+    sensor: Sensor,
+}
+
+/// What a resolution does with its endpoints after a DNS query fails, and
+/// when it queries DNS again.
+#[derive(Debug, PartialEq)]
+enum Fallback {
+    /// The endpoints are unchanged.
+    Keep(Instant),
+    /// The last answer has been stale for too long, so its endpoints are
+    /// removed.
+    Discard(Instant),
+    /// The authority does not exist, so its endpoints are removed.
+    DoesNotExist(Instant),
+}
+
+/// The endpoints that a resolution uses: all of its discovered endpoints, or
+/// as many of them as the endpoint limits allow.
+pub(super) struct Subset {
+    endpoints: Cache<SocketAddr, Metadata>,
+
+    /// The maximum number of endpoints that may be used.
+    limit: usize,
+
+    /// Whether the discovered endpoints have changed since the subset was
+    /// last chosen.
+    is_stale: bool,
+
+    sensor: resolution::Sensor,
 }
 
 // ===== impl DestinationSet =====
@@ -57,7 +111,12 @@ where
             deadline
         );
         self.reset_on_next_modification();
-        self.dns_query = Some(dns_resolver.resolve_all_ips(deadline, &authority.host));
+        let query = if dns_resolver.resolves_srv(&authority.host) {
+            dns_resolver.resolve_srv(deadline, &authority.host)
+        } else {
+            dns_resolver.resolve_all_ips(deadline, &authority.host)
+        };
+        self.dns_query = Some(query);
     }
 
     // Processes Destination service updates from `request_rx`, returning the new query
@@ -69,6 +128,7 @@ where
         auth: &DnsNameAndPort,
         mut rx: UpdateRx<T>,
         tls_controller_namespace: Option<&str>,
+        routes: &Routes,
     ) -> (ActiveQuery<T>, Exists<()>) {
         let mut exists = Exists::Unknown;
 
@@ -76,18 +136,18 @@ where
             match rx.poll() {
                 Ok(Async::Ready(Some(update))) => match update.update {
                     Some(PbUpdate2::Add(a_set)) => {
-                        let set_labels = a_set.metric_labels;
+                        let mut set_labels = a_set.metric_labels;
+                        routes.update(auth, &mut set_labels);
                         let addrs = a_set
                             .addrs
                             .into_iter()
                             .filter_map(|pb|
                                 pb_to_addr_meta(pb, &set_labels, tls_controller_namespace));
-                        self.add(auth, addrs)
+                        self.add(addrs)
                     },
                     Some(PbUpdate2::Remove(r_set)) => {
                         exists = Exists::Yes(());
                         self.remove(
-                            auth,
                             r_set
                                 .addrs
                                 .iter()
@@ -122,11 +182,12 @@ where
         }
     }
 
-    pub(super) fn poll_dns(&mut self, dns_resolver: &dns::Resolver, authority: &DnsNameAndPort) {
-        // Duration to wait before polling DNS again after an error
-        // (or a NXDOMAIN response with no TTL).
-        const DNS_ERROR_TTL: Duration = Duration::from_secs(5);
-
+    pub(super) fn poll_dns(
+        &mut self,
+        dns_resolver: &dns::Resolver,
+        authority: &DnsNameAndPort,
+        config: &DnsConfig,
+    ) {
         trace!("checking DNS for {:?}", authority);
         while let Some(mut query) = self.dns_query.take() {
             trace!("polling DNS for {:?}", authority);
@@ -143,7 +204,6 @@ where
                         ips
                     );
                     self.add(
-                        authority,
                         ips.iter().map(|ip| {
                             (
                                 SocketAddr::from((ip, authority.port)),
@@ -151,32 +211,77 @@ where
                             )
                         }),
                     );
+                    self.dns_state.answered(ips.valid_until());
 
                     // Poll again after the deadline on the DNS response.
                     ips.valid_until()
                 },
+                Ok(Async::Ready(dns::Response::SrvExists(srv))) => {
+                    trace!(
+                        "positive result of DNS SRV query for {:?}: {:?}",
+                        authority,
+                        srv
+                    );
+                    // The SRV records' ports and weights are used instead of
+                    // the authority's port and the default weight.
+                    self.add(
+                        srv.addrs().iter().map(|srv| {
+                            let meta = Metadata::new(
+                                IndexMap::default(),
+                                ProtocolHint::Unknown,
+                                Conditional::None(
+                                    tls::ReasonForNoIdentity::NotProvidedByServiceDiscovery,
+                                ),
+                                srv.weight,
+                            );
+                            (srv.addr, meta)
+                        }),
+                    );
+                    self.dns_state.answered(srv.valid_until());
+
+                    // Poll again after the earliest deadline of the SRV
+                    // records and their targets' addresses.
+                    srv.valid_until()
+                },
                 Ok(Async::Ready(dns::Response::DoesNotExist { retry_after })) => {
                     trace!(
                         "negative result (NXDOMAIN) of DNS query for {:?}",
                         authority
                     );
-                    self.no_endpoints(authority, false);
-                    // Poll again after the deadline on the DNS response, if
-                    // there is one.
-                    retry_after.unwrap_or_else(|| Instant::now() + DNS_ERROR_TTL)
+                    let fallback = self.dns_state.nxdomain(Instant::now(), retry_after, config);
+                    self.fall_back(authority, fallback)
                 },
                 Err(e) => {
                     // Do nothing so that the most recent non-error response is used until a
-                    // non-error response is received
+                    // non-error response is received, or it becomes too stale.
                     trace!("DNS resolution failed for {}: {}", &authority.host, e);
-
-                    // Poll again after the default wait time.
-                    Instant::now() + DNS_ERROR_TTL
+                    let fallback = self.dns_state.failed_query(Instant::now(), config);
+                    self.fall_back(authority, fallback)
                 },
             };
             self.reset_dns_query(dns_resolver, deadline, &authority)
         }
     }
+
+    /// Applies the outcome of a failed DNS query to the endpoints, returning
+    /// the deadline of the next query.
+    fn fall_back(&mut self, authority: &DnsNameAndPort, fallback: Fallback) -> Instant {
+        match fallback {
+            Fallback::Keep(deadline) => {
+                trace!("querying DNS for {:?} again at {:?}", authority, deadline);
+                deadline
+            },
+            Fallback::Discard(deadline) => {
+                debug!("discarding stale DNS answer for {:?}", authority);
+                self.clear();
+                deadline
+            },
+            Fallback::DoesNotExist(deadline) => {
+                self.no_endpoints(authority, false);
+                deadline
+            },
+        }
+    }
 }
 
 impl<T: HttpService<ResponseBody = RecvBody>> DestinationSet<T> {
@@ -187,6 +292,52 @@ impl<T: HttpService<ResponseBody = RecvBody>> DestinationSet<T> {
         self.query.needs_query_capacity()
     }
 
+    /// Replaces the endpoints with those read from the static endpoints file,
+    /// and stops querying the Destination service and DNS.
+    pub(super) fn set_static_endpoints(
+        &mut self,
+        authority: &DnsNameAndPort,
+        endpoints: &IndexMap<SocketAddr, Metadata>,
+    ) {
+        trace!("using static endpoints for {:?}", authority);
+        self.is_static = true;
+        self.query.take();
+        self.dns_query = None;
+        self.dns_state.reset();
+        self.reset_on_next_modification();
+        self.add(endpoints.iter().map(|(&addr, meta)| (addr, meta.clone())));
+    }
+
+    /// Returns the number of discovered endpoints that the resolution would
+    /// use, if it weren't for the limit on the total number of endpoints.
+    pub(super) fn wanted_endpoints(&self, max_per_resolution: usize) -> usize {
+        match self.addrs {
+            Exists::Yes(ref cache) => cmp::min(cache.len(), max_per_resolution),
+            Exists::No | Exists::Unknown => 0,
+        }
+    }
+
+    /// Chooses the endpoints that are sent to responders, if the discovered
+    /// endpoints or the limit have changed since they were last chosen.
+    ///
+    /// If there are more than `limit` endpoints, they are subset by
+    /// rendezvous hashing on `key`.
+    pub(super) fn update_subset(
+        &mut self,
+        authority_for_logging: &DnsNameAndPort,
+        limit: usize,
+        key: &str,
+    ) {
+        let discovered = match self.addrs {
+            Exists::Yes(ref cache) => Some(cache),
+            Exists::No | Exists::Unknown => None,
+        };
+        let responders = &mut self.responders;
+        self.subset.update(discovered, limit, key, authority_for_logging, &mut |change| {
+            Self::on_change(responders, authority_for_logging, change)
+        });
+    }
+
     pub(super) fn reset_on_next_modification(&mut self) {
         match self.addrs {
             Exists::Yes(ref mut cache) => {
@@ -196,7 +347,11 @@ impl<T: HttpService<ResponseBody = RecvBody>> DestinationSet<T> {
         }
     }
 
-    fn add<A>(&mut self, authority_for_logging: &DnsNameAndPort, addrs_to_add: A)
+    /// Adds discovered endpoints, or updates their metadata.
+    ///
+    /// The changes are sent to responders when the subset is next chosen by
+    /// `update_subset`, rather than as they are made.
+    fn add<A>(&mut self, addrs_to_add: A)
     where
         A: Iterator<Item = (SocketAddr, Metadata)>,
     {
@@ -204,26 +359,39 @@ impl<T: HttpService<ResponseBody = RecvBody>> DestinationSet<T> {
             Exists::Yes(mut cache) => cache,
             Exists::Unknown | Exists::No => Cache::new(),
         };
-        cache.update_union(addrs_to_add, &mut |change| {
-            Self::on_change(&mut self.responders, authority_for_logging, change)
-        });
+        cache.update_union(addrs_to_add, &mut |_| ());
         self.addrs = Exists::Yes(cache);
+        self.subset.is_stale = true;
     }
 
-    fn remove<A>(&mut self, authority_for_logging: &DnsNameAndPort, addrs_to_remove: A)
+    /// Removes discovered endpoints.
+    ///
+    /// The removals are sent to responders when the subset is next chosen by
+    /// `update_subset`.
+    fn remove<A>(&mut self, addrs_to_remove: A)
     where
         A: Iterator<Item = SocketAddr>,
     {
         let cache = match self.addrs.take() {
             Exists::Yes(mut cache) => {
-                cache.remove(addrs_to_remove, &mut |change| {
-                    Self::on_change(&mut self.responders, authority_for_logging, change)
-                });
+                cache.remove(addrs_to_remove, &mut |_| ());
                 cache
             },
             Exists::Unknown | Exists::No => Cache::new(),
         };
         self.addrs = Exists::Yes(cache);
+        self.subset.is_stale = true;
+    }
+
+    /// Removes all endpoints, without knowing whether the authority exists.
+    ///
+    /// The removals are sent to responders when the subset is next chosen by
+    /// `update_subset`.
+    fn clear(&mut self) {
+        if let Exists::Yes(mut cache) = self.addrs.take() {
+            cache.clear(&mut |_| ());
+        }
+        self.subset.is_stale = true;
     }
 
     fn no_endpoints(&mut self, authority_for_logging: &DnsNameAndPort, exists: bool) {
@@ -232,19 +400,12 @@ impl<T: HttpService<ResponseBody = RecvBody>> DestinationSet<T> {
             authority_for_logging,
             if exists { "exist" } else { "not exist" }
         );
-        match self.addrs.take() {
-            Exists::Yes(mut cache) => {
-                cache.clear(&mut |change| {
-                    Self::on_change(&mut self.responders, authority_for_logging, change)
-                });
-            },
-            Exists::Unknown | Exists::No => (),
-        };
         self.addrs = if exists {
             Exists::Yes(Cache::new())
         } else {
             Exists::No
         };
+        self.subset.is_stale = true;
     }
 
     fn on_change(
@@ -273,6 +434,215 @@ impl<T: HttpService<ResponseBody = RecvBody>> DestinationSet<T> {
 }
 
 
+// ===== impl Subset =====
+
+impl Subset {
+    pub(super) fn new(limit: usize, sensor: resolution::Sensor) -> Self {
+        Self {
+            endpoints: Cache::new(),
+            limit,
+            is_stale: true,
+            sensor,
+        }
+    }
+
+    pub(super) fn endpoints(&self) -> &Cache<SocketAddr, Metadata> {
+        &self.endpoints
+    }
+
+    /// Chooses the subset of the `discovered` endpoints, if they or the limit
+    /// have changed since the subset was last chosen.
+    ///
+    /// Only the endpoints that are added to or removed from the subset, or
+    /// whose metadata has changed, are passed to `on_change`.
+    fn update<F>(
+        &mut self,
+        discovered: Option<&Cache<SocketAddr, Metadata>>,
+        limit: usize,
+        key: &str,
+        authority_for_logging: &DnsNameAndPort,
+        on_change: &mut F,
+    )
+    where
+        F: for<'value> FnMut(CacheChange<'value, SocketAddr, Metadata>),
+    {
+        if !self.is_stale && self.limit == limit {
+            return;
+        }
+        self.is_stale = false;
+        self.limit = limit;
+
+        let empty = Cache::new();
+        let discovered = discovered.unwrap_or(&empty);
+
+        // If there are too many endpoints, only those chosen are used.
+        let chosen = if discovered.len() > limit {
+            let addrs = discovered.into_iter().map(|(&addr, _)| addr);
+            Some(limits::rendezvous(key, addrs, limit))
+        } else {
+            None
+        };
+        let is_used = |addr: &SocketAddr| {
+            discovered.get(addr).is_some() &&
+                chosen.as_ref().map_or(true, |chosen| chosen.contains(addr))
+        };
+
+        // Removals are made first, so that the remaining endpoints may be
+        // compared to those discovered. The subset's endpoints are never
+        // reset: a new `Cache` is reset on its first modification, but the
+        // subset is empty then.
+        let removed = self.endpoints.into_iter()
+            .map(|(&addr, _)| addr)
+            .filter(|addr| !is_used(addr))
+            .collect::<Vec<_>>();
+        self.endpoints.remove(removed.into_iter(), on_change);
+
+        // Only the metadata of endpoints that are new or changed is cloned.
+        let updated = {
+            let endpoints = &self.endpoints;
+            discovered.into_iter()
+                .filter(|&(addr, meta)| is_used(addr) && endpoints.get(addr) != Some(meta))
+                .map(|(&addr, meta)| (addr, meta.clone()))
+                .collect::<Vec<_>>()
+        };
+        self.endpoints.update_union(updated.into_iter(), on_change);
+
+        let truncated = discovered.len() - self.endpoints.len();
+        if truncated > 0 {
+            debug!(
+                "using {} of {} endpoints for {:?}",
+                self.endpoints.len(),
+                discovered.len(),
+                authority_for_logging,
+            );
+        }
+        self.sensor.set_endpoints(self.endpoints.len(), truncated);
+    }
+}
+
+// ===== impl DnsState =====
+
+impl DnsState {
+    pub(super) fn new(sensor: Sensor) -> Self {
+        Self {
+            valid_until: None,
+            nxdomain_since: None,
+            failures: 0,
+            sensor,
+        }
+    }
+
+    /// Forgets the last answer, once the authority is no longer resolved by
+    /// DNS.
+    pub(super) fn reset(&mut self) {
+        self.valid_until = None;
+        self.nxdomain_since = None;
+        self.failures = 0;
+        self.sensor.clear();
+    }
+
+    fn is_answered(&self) -> bool {
+        self.valid_until.is_some()
+    }
+
+    /// Records a positive answer, whose TTL elapses at `valid_until`.
+    fn answered(&mut self, valid_until: Instant) {
+        self.valid_until = Some(valid_until);
+        self.nxdomain_since = None;
+        self.failures = 0;
+        self.sensor.set_state(ResolutionState::Fresh);
+    }
+
+    /// Records a NXDOMAIN response at `now`, which may be cached until
+    /// `retry_after`.
+    ///
+    /// A spurious NXDOMAIN shouldn't remove all of the endpoints, so the last
+    /// answer is used until NXDOMAIN has been returned for the grace period.
+    fn nxdomain(
+        &mut self,
+        now: Instant,
+        retry_after: Option<Instant>,
+        config: &DnsConfig,
+    ) -> Fallback {
+        // Query again once the NXDOMAIN may no longer be cached, if it has a
+        // TTL, or else after backing off.
+        let retry_after = retry_after.unwrap_or_else(|| {
+            now + self.failed(&config.error_backoff)
+        });
+
+        let grace_until = *self.nxdomain_since.get_or_insert(now) + config.nxdomain_grace_period;
+        if self.is_answered() && now < grace_until {
+            self.use_stale_answer(now, cmp::min(retry_after, grace_until), config)
+        } else {
+            self.expired();
+            Fallback::DoesNotExist(retry_after)
+        }
+    }
+
+    /// Records a query that failed at `now` with an error.
+    fn failed_query(&mut self, now: Instant, config: &DnsConfig) -> Fallback {
+        let backoff = self.failed(&config.error_backoff);
+        self.use_stale_answer(now, now + backoff, config)
+    }
+
+    /// Continues to use the last answer after a failed query, unless it has
+    /// been stale for longer than the configured maximum.
+    ///
+    /// The next query is no later than `deadline`, nor than when the answer
+    /// must be discarded.
+    fn use_stale_answer(
+        &mut self,
+        now: Instant,
+        deadline: Instant,
+        config: &DnsConfig,
+    ) -> Fallback {
+        let valid_until = match self.valid_until {
+            Some(valid_until) => valid_until,
+            None => {
+                self.expired();
+                return Fallback::Keep(deadline);
+            },
+        };
+
+        if now < valid_until {
+            // The answer's TTL hasn't elapsed, so it's still fresh.
+            return Fallback::Keep(deadline);
+        }
+
+        match config.max_staleness.map(|max| valid_until + max) {
+            Some(expiry) if expiry <= now => {
+                self.expired();
+                Fallback::Discard(deadline)
+            },
+            Some(expiry) => {
+                self.stale();
+                Fallback::Keep(cmp::min(deadline, expiry))
+            },
+            None => {
+                self.stale();
+                Fallback::Keep(deadline)
+            },
+        }
+    }
+
+    /// Records a failed query, returning how long to wait before querying
+    /// again.
+    fn failed(&mut self, backoff: &Backoff) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+        backoff.duration(self.failures, &mut rand::thread_rng())
+    }
+
+    fn stale(&mut self) {
+        self.sensor.set_state(ResolutionState::Stale);
+    }
+
+    /// Records that no answer is used.
+    fn expired(&mut self) {
+        self.valid_until = None;
+        self.sensor.set_state(ResolutionState::Expired);
+    }
+}
+
 /// Construct a new labeled `SocketAddr `from a protobuf `WeightedAddr`.
 fn pb_to_addr_meta(
     pb: WeightedAddr,
@@ -324,7 +694,7 @@ fn pb_to_addr_meta(
         }
     }
 
-    let meta = Metadata::new(meta, proto_hint, tls_identity);
+    let meta = Metadata::new(meta, proto_hint, tls_identity, pb.weight);
     Some((addr, meta))
 }
 
@@ -379,3 +749,300 @@ fn pb_to_sock_addr(pb: TcpAddress) -> Option<SocketAddr> {
         None => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use convert::TryFrom;
+    use std::collections::HashSet;
+    use telemetry;
+
+    const KEY: &str = "web.ns.serviceaccount.identity";
+
+    fn authority() -> DnsNameAndPort {
+        DnsNameAndPort {
+            host: dns::Name::try_from("web.ns.svc.cluster.local".as_bytes()).unwrap(),
+            port: 8080,
+        }
+    }
+
+    fn addr(n: u8) -> SocketAddr {
+        SocketAddr::from(([10, 1, 1, n], 8080))
+    }
+
+    fn meta(weight: u32) -> Metadata {
+        Metadata::new(
+            IndexMap::default(),
+            ProtocolHint::Unknown,
+            Conditional::None(tls::ReasonForNoIdentity::NotProvidedByServiceDiscovery),
+            weight,
+        )
+    }
+
+    /// Returns a cache of the endpoints `addr(n)` with `meta(weight)`.
+    fn discovered(endpoints: &[(u8, u32)]) -> Cache<SocketAddr, Metadata> {
+        let mut cache = Cache::new();
+        let endpoints = endpoints.iter().map(|&(n, weight)| (addr(n), meta(weight)));
+        cache.update_union(endpoints, &mut |_| ());
+        cache
+    }
+
+    fn subset() -> Subset {
+        let sensor = resolution::Registry::default().sensor(authority());
+        Subset::new(0, sensor)
+    }
+
+    /// Updates `subset`, returning the changes that are sent to responders.
+    fn update(
+        subset: &mut Subset,
+        discovered: Option<&Cache<SocketAddr, Metadata>>,
+        limit: usize,
+    ) -> Vec<(&'static str, SocketAddr)> {
+        let mut changes = Vec::new();
+        subset.update(discovered, limit, KEY, &authority(), &mut |change| {
+            changes.push(match change {
+                CacheChange::Insertion { key, .. } => ("insert", key),
+                CacheChange::Removal { key } => ("remove", key),
+                CacheChange::Modification { key, .. } => ("modify", key),
+            })
+        });
+        changes
+    }
+
+    fn dns_state() -> DnsState {
+        DnsState::new(telemetry::dns::Registry::default().sensor(authority()))
+    }
+
+    fn dns_config(nxdomain_grace_period: u64, max_staleness: Option<u64>) -> DnsConfig {
+        // The backoff is always 1ms, since it isn't jittered when it's so
+        // short.
+        let ms = Duration::from_millis(1);
+        DnsConfig {
+            nxdomain_grace_period: Duration::from_secs(nxdomain_grace_period),
+            max_staleness: max_staleness.map(Duration::from_secs),
+            error_backoff: Backoff { base: ms, max: ms },
+        }
+    }
+
+    fn addrs(subset: &Subset) -> HashSet<SocketAddr> {
+        subset.endpoints().into_iter().map(|(&addr, _)| addr).collect()
+    }
+
+    #[test]
+    fn sends_changes_to_discovered_endpoints() {
+        let mut subset = subset();
+
+        let cache = discovered(&[(1, 1), (2, 1)]);
+        assert_eq!(
+            update(&mut subset, Some(&cache), 10),
+            vec![("insert", addr(1)), ("insert", addr(2))],
+        );
+
+        // Nothing is sent until the discovered endpoints change.
+        assert_eq!(update(&mut subset, Some(&cache), 10), vec![]);
+
+        subset.is_stale = true;
+        let cache = discovered(&[(2, 1), (3, 1)]);
+        assert_eq!(
+            update(&mut subset, Some(&cache), 10),
+            vec![("remove", addr(1)), ("insert", addr(3))],
+        );
+
+        subset.is_stale = true;
+        let cache = discovered(&[(2, 5), (3, 1)]);
+        assert_eq!(update(&mut subset, Some(&cache), 10), vec![("modify", addr(2))]);
+        assert_eq!(subset.endpoints().get(&addr(2)), Some(&meta(5)));
+
+        subset.is_stale = true;
+        assert_eq!(
+            update(&mut subset, None, 10),
+            vec![("remove", addr(2)), ("remove", addr(3))],
+        );
+        assert!(addrs(&subset).is_empty());
+    }
+
+    #[test]
+    fn subsets_endpoints_when_the_limit_shrinks() {
+        let mut subset = subset();
+        let all = (1..11).map(|n| (n, 1)).collect::<Vec<_>>();
+        let cache = discovered(&all);
+
+        assert_eq!(update(&mut subset, Some(&cache), 10).len(), 10);
+
+        let changes = update(&mut subset, Some(&cache), 4);
+        assert_eq!(changes.len(), 6);
+        assert!(changes.iter().all(|&(change, _)| change == "remove"));
+        let chosen = limits::rendezvous(KEY, (1..11).map(addr), 4);
+        assert_eq!(addrs(&subset), chosen);
+
+        let changes = update(&mut subset, Some(&cache), 10);
+        assert_eq!(changes.len(), 6);
+        assert!(changes.iter().all(|&(change, _)| change == "insert"));
+        assert_eq!(addrs(&subset).len(), 10);
+    }
+
+    #[test]
+    fn subset_changes_only_when_chosen_endpoints_change() {
+        let mut subset = subset();
+        let all = (1..11).map(|n| (n, 1)).collect::<Vec<_>>();
+        let cache = discovered(&all);
+        assert_eq!(update(&mut subset, Some(&cache), 4).len(), 4);
+        let chosen = addrs(&subset);
+
+        // Removing an endpoint that isn't used, or changing the metadata of
+        // one, changes nothing.
+        let unchosen = (1..11)
+            .filter(|&n| !chosen.contains(&addr(n)))
+            .collect::<Vec<_>>();
+        let remaining = all.iter()
+            .filter(|&&(n, _)| n != unchosen[0])
+            .map(|&(n, weight)| if n == unchosen[1] { (n, 5) } else { (n, weight) })
+            .collect::<Vec<_>>();
+        subset.is_stale = true;
+        assert_eq!(update(&mut subset, Some(&discovered(&remaining)), 4), vec![]);
+        assert_eq!(addrs(&subset), chosen);
+
+        // Removing a used endpoint replaces it with another.
+        let used = (1..11).find(|&n| chosen.contains(&addr(n))).unwrap();
+        let remaining = remaining.into_iter()
+            .filter(|&(n, _)| n != used)
+            .collect::<Vec<_>>();
+        subset.is_stale = true;
+        let changes = update(&mut subset, Some(&discovered(&remaining)), 4);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0], ("remove", addr(used)));
+        assert_eq!(changes[1].0, "insert");
+        assert_eq!(addrs(&subset).len(), 4);
+        assert_eq!(addrs(&subset).intersection(&chosen).count(), 3);
+    }
+
+    #[test]
+    fn nxdomain_with_a_ttl_uses_the_last_answer_for_the_grace_period() {
+        let config = dns_config(10, Some(60));
+        let secs = Duration::from_secs;
+        let t0 = Instant::now();
+        let mut state = dns_state();
+        state.answered(t0 + secs(30));
+
+        // The answer is used until NXDOMAIN has been returned for the grace
+        // period, and the authority is queried again by then.
+        assert_eq!(state.nxdomain(t0, Some(t0 + secs(5)), &config), Fallback::Keep(t0 + secs(5)));
+        assert_eq!(
+            state.nxdomain(t0 + secs(5), Some(t0 + secs(20)), &config),
+            Fallback::Keep(t0 + secs(10)),
+        );
+        assert!(state.is_answered());
+
+        assert_eq!(
+            state.nxdomain(t0 + secs(10), Some(t0 + secs(20)), &config),
+            Fallback::DoesNotExist(t0 + secs(20)),
+        );
+        assert!(!state.is_answered());
+    }
+
+    #[test]
+    fn nxdomain_without_a_ttl_backs_off() {
+        let config = dns_config(10, Some(60));
+        let (secs, ms) = (Duration::from_secs, Duration::from_millis);
+        let t0 = Instant::now();
+
+        // Without an answer, the authority doesn't exist.
+        let mut state = dns_state();
+        assert_eq!(state.nxdomain(t0, None, &config), Fallback::DoesNotExist(t0 + ms(1)));
+
+        state.answered(t0 + secs(30));
+        assert_eq!(state.nxdomain(t0, None, &config), Fallback::Keep(t0 + ms(1)));
+        assert_eq!(state.failures, 1);
+    }
+
+    #[test]
+    fn answers_restart_the_grace_period() {
+        let config = dns_config(10, None);
+        let secs = Duration::from_secs;
+        let t0 = Instant::now();
+        let mut state = dns_state();
+        state.answered(t0 + secs(1));
+
+        assert_eq!(
+            state.nxdomain(t0 + secs(5), None, &config),
+            Fallback::Keep(t0 + secs(5) + Duration::from_millis(1)),
+        );
+        state.answered(t0 + secs(12));
+
+        // NXDOMAIN was first returned after the last answer at 12s, not 5s.
+        assert_eq!(
+            state.nxdomain(t0 + secs(12), Some(t0 + secs(30)), &config),
+            Fallback::Keep(t0 + secs(22)),
+        );
+        assert_eq!(
+            state.nxdomain(t0 + secs(22), Some(t0 + secs(30)), &config),
+            Fallback::DoesNotExist(t0 + secs(30)),
+        );
+    }
+
+    #[test]
+    fn stale_answers_are_discarded_after_the_max_staleness() {
+        let config = dns_config(10, Some(10));
+        let (secs, ms) = (Duration::from_secs, Duration::from_millis);
+        let t0 = Instant::now();
+        let mut state = dns_state();
+        state.answered(t0 + secs(1));
+
+        // While the answer is fresh, errors don't change it.
+        assert_eq!(state.failed_query(t0, &config), Fallback::Keep(t0 + ms(1)));
+
+        // Once it's stale, it's used until it has been stale for 10s, and the
+        // next query is no later than that.
+        assert_eq!(
+            state.failed_query(t0 + secs(2), &config),
+            Fallback::Keep(t0 + secs(2) + ms(1)),
+        );
+        let t1 = t0 + secs(11) - Duration::from_micros(500);
+        assert_eq!(state.failed_query(t1, &config), Fallback::Keep(t0 + secs(11)));
+        assert!(state.is_answered());
+
+        assert_eq!(
+            state.failed_query(t0 + secs(11), &config),
+            Fallback::Discard(t0 + secs(11) + ms(1)),
+        );
+        assert!(!state.is_answered());
+
+        // Without an answer, there is nothing more to discard.
+        assert_eq!(
+            state.failed_query(t0 + secs(12), &config),
+            Fallback::Keep(t0 + secs(12) + ms(1)),
+        );
+    }
+
+    #[test]
+    fn stale_answers_are_discarded_during_the_grace_period() {
+        let config = dns_config(30, Some(5));
+        let (secs, ms) = (Duration::from_secs, Duration::from_millis);
+        let t0 = Instant::now();
+        let mut state = dns_state();
+        state.answered(t0 + secs(1));
+
+        assert_eq!(
+            state.nxdomain(t0 + secs(2), None, &config),
+            Fallback::Keep(t0 + secs(2) + ms(1)),
+        );
+        assert_eq!(
+            state.nxdomain(t0 + secs(6), None, &config),
+            Fallback::Discard(t0 + secs(6) + ms(1)),
+        );
+        assert!(!state.is_answered());
+    }
+
+    #[test]
+    fn stale_answers_are_used_without_a_max_staleness() {
+        let config = dns_config(10, None);
+        let (secs, ms) = (Duration::from_secs, Duration::from_millis);
+        let t0 = Instant::now();
+        let mut state = dns_state();
+        state.answered(t0 + secs(1));
+
+        let t1 = t0 + secs(86_400);
+        assert_eq!(state.failed_query(t1, &config), Fallback::Keep(t1 + ms(1)));
+        assert!(state.is_answered());
+    }
+}
@@ -0,0 +1,156 @@
+//! Chooses which endpoints resolutions use when they exceed the endpoint
+//! limits.
+
+use fnv::FnvHasher;
+use std::{
+    cmp,
+    collections::HashSet,
+    hash::Hasher,
+    net::{IpAddr, SocketAddr},
+};
+
+/// Chooses `limit` of `addrs` by rendezvous hashing on `key`.
+///
+/// Each address is scored by hashing it with `key`, and those with the
+/// highest scores are chosen. The same addresses are chosen for the same
+/// `key` regardless of the order of `addrs`, and adding or removing an
+/// address changes at most one of the chosen addresses, so resolutions don't
+/// churn as their endpoints change. Proxies with different keys choose
+/// different addresses, so the load on the endpoints is spread evenly.
+pub(super) fn rendezvous<I>(key: &str, addrs: I, limit: usize) -> HashSet<SocketAddr>
+where
+    I: IntoIterator<Item = SocketAddr>,
+{
+    let mut scored = addrs.into_iter()
+        .map(|addr| (score(key, &addr), addr))
+        .collect::<Vec<_>>();
+    scored.sort_by(|(a, _), (b, _)| b.cmp(a));
+    scored.into_iter()
+        .take(limit)
+        .map(|(_, addr)| addr)
+        .collect()
+}
+
+/// Shares `total` among resolutions that each want `wants[i]` endpoints.
+///
+/// Every resolution that wants endpoints is given at least one, even if that
+/// exceeds `total`, so that no resolution is left without endpoints while it
+/// has some. The remainder is shared fairly: resolutions that want less than
+/// an even share are given all that they want, and what's left is shared
+/// evenly among the others.
+pub(super) fn share(wants: &[usize], total: usize) -> Vec<usize> {
+    let reserved = wants.iter().filter(|&&want| want > 0).count();
+    let extra = wants.iter()
+        .map(|&want| want.saturating_sub(1))
+        .collect::<Vec<_>>();
+
+    let mut order = (0..wants.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| extra[i]);
+
+    let mut shares = vec![0; wants.len()];
+    let mut remaining = total.saturating_sub(reserved);
+    for (n, &i) in order.iter().enumerate() {
+        let share = cmp::min(extra[i], remaining / (wants.len() - n));
+        shares[i] = cmp::min(wants[i], 1) + share;
+        remaining -= share;
+    }
+    shares
+}
+
+/// Scores `addr` for `key`.
+///
+/// The score must be the same in every proxy and across releases, so that
+/// proxies with the same key choose the same subset. The standard library's
+/// hashers don't guarantee that, so FNV-1a is used over the bytes of the key,
+/// IP address, and port, with a final mix so that addresses that differ only
+/// in their last bytes are ordered independently for each key.
+fn score(key: &str, addr: &SocketAddr) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(key.as_bytes());
+    match addr.ip() {
+        IpAddr::V4(ip) => hasher.write(&ip.octets()),
+        IpAddr::V6(ip) => hasher.write(&ip.octets()),
+    }
+    let port = addr.port();
+    hasher.write(&[(port >> 8) as u8, port as u8]);
+    mix(hasher.finish())
+}
+
+/// The SplitMix64 finalizer.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs(n: u16) -> Vec<SocketAddr> {
+        (0..n).map(|port| SocketAddr::from(([10, 1, 1, 1], 8000 + port))).collect()
+    }
+
+    #[test]
+    fn rendezvous_is_stable() {
+        let chosen = rendezvous("web.ns.serviceaccount.identity", addrs(100), 10);
+        assert_eq!(chosen.len(), 10);
+
+        let mut reversed = addrs(100);
+        reversed.reverse();
+        assert_eq!(rendezvous("web.ns.serviceaccount.identity", reversed, 10), chosen);
+
+        // Removing an address that isn't chosen doesn't change the subset.
+        let unchosen = addrs(100).into_iter().find(|a| !chosen.contains(a)).unwrap();
+        let remaining = addrs(100).into_iter().filter(|a| *a != unchosen);
+        assert_eq!(rendezvous("web.ns.serviceaccount.identity", remaining, 10), chosen);
+
+        // Removing a chosen address only replaces that address.
+        let removed = *chosen.iter().next().unwrap();
+        let remaining = addrs(100).into_iter().filter(|a| *a != removed);
+        let replaced = rendezvous("web.ns.serviceaccount.identity", remaining, 10);
+        assert_eq!(replaced.len(), 10);
+        assert_eq!(replaced.intersection(&chosen).count(), 9);
+
+        assert_ne!(rendezvous("api.ns.serviceaccount.identity", addrs(100), 10), chosen);
+    }
+
+    #[test]
+    fn rendezvous_is_the_same_in_every_proxy() {
+        let ports = |ports: &[u16]| ports.iter()
+            .map(|&port| SocketAddr::from(([10, 1, 1, 1], port)))
+            .collect::<HashSet<_>>();
+        assert_eq!(
+            rendezvous("web.ns.serviceaccount.identity", addrs(10), 3),
+            ports(&[8001, 8003, 8005]),
+        );
+        assert_eq!(
+            rendezvous("api.ns.serviceaccount.identity", addrs(10), 3),
+            ports(&[8002, 8003, 8006]),
+        );
+    }
+
+    #[test]
+    fn rendezvous_chooses_all_within_limit() {
+        let chosen = rendezvous("web.ns.serviceaccount.identity", addrs(5), 10);
+        assert_eq!(chosen, addrs(5).into_iter().collect());
+    }
+
+    #[test]
+    fn share_is_fair() {
+        assert_eq!(share(&[], 10), Vec::<usize>::new());
+        assert_eq!(share(&[3, 4], 10), vec![3, 4]);
+        assert_eq!(share(&[10, 10], 10), vec![5, 5]);
+        assert_eq!(share(&[20, 2, 20], 10), vec![4, 2, 4]);
+        assert_eq!(share(&[20, 2, 3], 10), vec![5, 2, 3]);
+        assert_eq!(share(&[0, 5], 10), vec![0, 5]);
+    }
+
+    #[test]
+    fn share_gives_each_resolution_an_endpoint() {
+        assert_eq!(share(&[5, 5, 5], 2), vec![1, 1, 1]);
+        assert_eq!(share(&[5, 5, 5], 0), vec![1, 1, 1]);
+        assert_eq!(share(&[5, 0, 5], 2), vec![1, 0, 1]);
+        assert_eq!(share(&[5, 1, 5], 4), vec![1, 1, 2]);
+    }
+}
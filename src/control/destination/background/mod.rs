@@ -22,7 +22,7 @@ use linkerd2_proxy_api::destination::{
     Update as PbUpdate,
 };
 
-use super::{ResolveRequest, Update};
+use super::{static_endpoints, DnsConfig, EndpointLimits, ResolveRequest, Routes, Update};
 use config::Namespaces;
 use control::{
     cache::Exists,
@@ -30,6 +30,7 @@ use control::{
     remote_stream::{Receiver, Remote},
 };
 use dns;
+use telemetry;
 use transport::{tls, DnsNameAndPort, HostAndPort};
 use conditional::Conditional;
 use watch_service::WatchService;
@@ -37,10 +38,11 @@ use futures_watch::Watch;
 
 mod client;
 mod destination_set;
+mod limits;
 
 use self::{
     client::BindClient,
-    destination_set::DestinationSet,
+    destination_set::{DestinationSet, DnsState, Subset},
 };
 
 type ActiveQuery<T> = Remote<PbUpdate, T>;
@@ -55,12 +57,21 @@ type UpdateRx<T> = Receiver<PbUpdate, T>;
 struct Background<T: HttpService<ResponseBody = RecvBody>> {
     new_query: NewQuery,
     dns_resolver: dns::Resolver,
+    dns_config: DnsConfig,
+    dns_metrics: telemetry::dns::Registry,
+    endpoint_limits: EndpointLimits,
+    resolution_metrics: telemetry::resolution::Registry,
     dsts: DestinationCache<T>,
     /// The Destination.Get RPC client service.
     /// Each poll, records whether the rpc service was till ready.
     rpc_ready: bool,
     /// A receiver of new watch requests.
     request_rx: mpsc::UnboundedReceiver<ResolveRequest>,
+    /// Authorities that are resolved from a local file rather than by the
+    /// Destination service or DNS.
+    static_endpoints: static_endpoints::Watch,
+    /// The routes that the Destination service describes for authorities.
+    routes: Routes,
 }
 
 /// Holds the currently active `DestinationSet`s and a list of any destinations
@@ -101,6 +112,12 @@ pub(super) fn task(
     controller_tls: tls::ConditionalConnectionConfig<tls::ClientConfigWatch>,
     control_backoff_delay: Duration,
     concurrency_limit: usize,
+    static_endpoints: static_endpoints::Watch,
+    routes: Routes,
+    dns_config: DnsConfig,
+    dns_metrics: telemetry::dns::Registry,
+    endpoint_limits: EndpointLimits,
+    resolution_metrics: telemetry::resolution::Registry,
 ) -> impl Future<Item = (), Error = ()>
 {
     // Build up the Controller Client Stack
@@ -131,6 +148,12 @@ pub(super) fn task(
         dns_resolver,
         namespaces,
         concurrency_limit,
+        static_endpoints,
+        routes,
+        dns_config,
+        dns_metrics,
+        endpoint_limits,
+        resolution_metrics,
     );
 
     future::poll_fn(move || {
@@ -150,13 +173,25 @@ where
         dns_resolver: dns::Resolver,
         namespaces: Namespaces,
         concurrency_limit: usize,
+        static_endpoints: static_endpoints::Watch,
+        routes: Routes,
+        dns_config: DnsConfig,
+        dns_metrics: telemetry::dns::Registry,
+        endpoint_limits: EndpointLimits,
+        resolution_metrics: telemetry::resolution::Registry,
     ) -> Self {
         Self {
             new_query: NewQuery::new(namespaces, concurrency_limit),
             dns_resolver,
+            dns_config,
+            dns_metrics,
+            endpoint_limits,
+            resolution_metrics,
             dsts: DestinationCache::new(),
             rpc_ready: false,
             request_rx,
+            static_endpoints,
+            routes,
         }
     }
 
@@ -165,12 +200,14 @@ where
         // in `poll_destinations` while the `rpc` service is ready should
         // be reconnected now, otherwise the task would just sleep...
         loop {
+            self.poll_static_endpoints(client);
             if let Async::Ready(()) = self.poll_resolve_requests(client) {
                 // request_rx has closed, meaning the main thread is terminating.
                 return Ok(Async::Ready(()));
             }
-            self.dsts.retain_active();
+            self.dsts.retain_active(&self.routes);
             self.poll_destinations();
+            self.dsts.update_subsets(&self.endpoint_limits);
 
             if self.dsts.reconnects.is_empty() || !self.rpc_ready {
                 return Ok(Async::NotReady);
@@ -210,6 +247,7 @@ where
 
                     let new_query = &self.new_query;
                     let dsts = &mut self.dsts;
+                    let static_endpoints = self.static_endpoints.borrow();
 
                     // If the requested authority currently needs more
                     // query capacity to query the destination service, go
@@ -217,21 +255,18 @@ where
                     // inactive DestinationSets).
                     if dsts.needs_query_for(&resolve.authority) {
                         trace!("--> no query capacity, try retain_active...", );
-                        dsts.retain_active();
+                        dsts.retain_active(&self.routes);
                     };
 
                     match dsts.destinations.entry(resolve.authority) {
                         Entry::Occupied(mut occ) => {
                             // we may already know of some addresses here, so push
                             // them onto the new watch first
-                            match occ.get().addrs {
-                                Exists::Yes(ref cache) => for (&addr, meta) in cache {
-                                    let update = Update::NewClient(addr, meta.clone());
-                                    resolve.responder.update_tx
-                                        .unbounded_send(update)
-                                        .expect("unbounded_send does not fail");
-                                },
-                                Exists::No | Exists::Unknown => (),
+                            for (&addr, meta) in occ.get().subset.endpoints() {
+                                let update = Update::NewClient(addr, meta.clone());
+                                resolve.responder.update_tx
+                                    .unbounded_send(update)
+                                    .expect("unbounded_send does not fail");
                             }
 
                             if occ.get().needs_query_capacity() {
@@ -248,6 +283,26 @@ where
                             occ.get_mut().responders.push(resolve.responder);
                         },
                         Entry::Vacant(vac) => {
+                            if let Some(endpoints) = static_endpoints.get(vac.key()) {
+                                let mut set = DestinationSet {
+                                    addrs: Exists::Unknown,
+                                    subset: Subset::new(
+                                        self.endpoint_limits.per_resolution,
+                                        self.resolution_metrics.sensor(vac.key().clone()),
+                                    ),
+                                    query: DestinationServiceQuery::Inactive,
+                                    dns_query: None,
+                                    dns_state: DnsState::new(
+                                        self.dns_metrics.sensor(vac.key().clone()),
+                                    ),
+                                    responders: vec![resolve.responder],
+                                    is_static: false,
+                                };
+                                set.set_static_endpoints(vac.key(), endpoints);
+                                vac.insert(set);
+                                continue;
+                            }
+
                             let query = new_query
                                 .query_destination_service_if_relevant(
                                     client.as_mut(),
@@ -256,9 +311,17 @@ where
                                 );
                             let mut set = DestinationSet {
                                 addrs: Exists::Unknown,
+                                subset: Subset::new(
+                                    self.endpoint_limits.per_resolution,
+                                    self.resolution_metrics.sensor(vac.key().clone()),
+                                ),
                                 query,
                                 dns_query: None,
+                                dns_state: DnsState::new(
+                                    self.dns_metrics.sensor(vac.key().clone()),
+                                ),
                                 responders: vec![resolve.responder],
+                                is_static: false,
                             };
                             // If the authority is one for which the Destination service is never
                             // relevant (e.g. an absolute name that doesn't end in ".svc.$zone." in
@@ -291,6 +354,10 @@ where
 
         while let Some(auth) = self.dsts.reconnects.pop_front() {
             if let Some(set) = self.dsts.destinations.get_mut(&auth) {
+                if set.is_static {
+                    trace!("reconnect no longer needed: {:?} is static", auth);
+                    continue;
+                }
                 set.query = self.new_query
                     .query_destination_service_if_relevant(
                         Some(client),
@@ -305,6 +372,37 @@ where
         false
     }
 
+    /// Applies any changes to the static endpoints to the active resolutions.
+    ///
+    /// Authorities that are added to the file stop querying the Destination
+    /// service and DNS, and those that are removed from it start to.
+    fn poll_static_endpoints(&mut self, client: &mut Option<T>) {
+        // `watch.poll()` can't actually fail; so errors are not considered.
+        while let Ok(Async::Ready(Some(()))) = self.static_endpoints.poll() {
+            let static_endpoints = self.static_endpoints.borrow();
+            for (auth, set) in &mut self.dsts.destinations {
+                if let Some(endpoints) = static_endpoints.get(auth) {
+                    set.set_static_endpoints(auth, endpoints);
+                } else if set.is_static {
+                    trace!("{:?} is no longer static", auth);
+                    // Keep the static endpoints until they're replaced by
+                    // the first update from the Destination service or DNS.
+                    set.is_static = false;
+                    set.reset_on_next_modification();
+                    set.query = self.new_query
+                        .query_destination_service_if_relevant(
+                            client.as_mut(),
+                            auth,
+                            "connect (no longer static)",
+                        );
+                    if !set.query.is_active() {
+                        set.reset_dns_query(&self.dns_resolver, Instant::now(), auth);
+                    }
+                }
+            }
+        }
+    }
+
     fn poll_destinations(&mut self) {
         for (auth, set) in &mut self.dsts.destinations {
             // Query the Destination service first.
@@ -315,6 +413,7 @@ where
                             auth,
                             rx,
                             self.new_query.tls_controller_ns(),
+                            &self.routes,
                         );
                     if let Remote::NeedsReconnect = new_query {
                         set.reset_on_next_modification();
@@ -338,6 +437,7 @@ where
                 Exists::Yes(()) => {
                     // Stop polling DNS on any active update from the Destination service.
                     set.dns_query = None;
+                    set.dns_state.reset();
                 },
                 Exists::No => {
                     // Fall back to DNS.
@@ -348,7 +448,7 @@ where
 
             // Poll DNS after polling the Destination service. This may reset the DNS query but it
             // won't affect the Destination Service query.
-            set.poll_dns(&self.dns_resolver, auth);
+            set.poll_dns(&self.dns_resolver, auth, &self.dns_config);
         }
     }
 
@@ -472,13 +572,32 @@ where
             .unwrap_or(false)
     }
 
+    /// Chooses the endpoints that each resolution uses, so that no resolution
+    /// has more endpoints than the per-resolution limit and there are no more
+    /// than the total limit across all resolutions.
+    fn update_subsets(&mut self, endpoint_limits: &EndpointLimits) {
+        let wanted = self.destinations.values()
+            .map(|set| set.wanted_endpoints(endpoint_limits.per_resolution))
+            .collect::<Vec<_>>();
+        let shares = limits::share(&wanted, endpoint_limits.total);
+        for ((auth, set), limit) in self.destinations.iter_mut().zip(shares) {
+            set.update_subset(auth, limit, &endpoint_limits.subset_key);
+        }
+    }
+
     /// Ensures that `destinations` is updated to only maintain active resolutions.
     ///
-    /// If there are no active resolutions for a destination, the destination is removed.
-    fn retain_active(&mut self) {
-        self.destinations.retain(|_, ref mut dst| {
+    /// If there are no active resolutions for a destination, the destination is removed,
+    /// unless its route is still used.
+    fn retain_active(&mut self, routes: &Routes) {
+        let now = Instant::now();
+        self.destinations.retain(|auth, ref mut dst| {
             dst.responders.retain(|r| r.is_active());
-            dst.responders.len() > 0
+            if dst.responders.len() > 0 || routes.is_used(auth, now) {
+                return true;
+            }
+            routes.remove(auth);
+            false
         });
     }
 }
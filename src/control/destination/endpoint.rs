@@ -1,7 +1,7 @@
 use indexmap::IndexMap;
 use std::net::SocketAddr;
 
-use super::{Metadata, ProtocolHint};
+use super::{Locality, Metadata, ProtocolHint};
 use tls;
 use conditional::Conditional;
 
@@ -36,6 +36,15 @@ impl Endpoint {
         self.metadata.labels()
     }
 
+    /// Adds a label, replacing any existing value for `key`.
+    pub fn add_label<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.metadata.add_label(key, value);
+    }
+
     pub fn can_use_orig_proto(&self) -> bool {
         match self.metadata.protocol_hint() {
             ProtocolHint::Unknown => false,
@@ -46,6 +55,18 @@ impl Endpoint {
     pub fn tls_identity(&self) -> Conditional<&tls::Identity, tls::ReasonForNoIdentity> {
         self.metadata.tls_identity()
     }
+
+    pub fn weight(&self) -> u32 {
+        self.metadata.weight()
+    }
+
+    pub fn locality(&self) -> Option<Locality> {
+        self.metadata.locality()
+    }
+
+    pub fn set_locality(&mut self, locality: Locality) {
+        self.metadata.set_locality(locality);
+    }
 }
 
 impl From<SocketAddr> for Endpoint {
@@ -6,23 +6,36 @@
 //! that the thread responsible for proxying data need not also do this administrative
 //! work of communicating with the control plane.
 //!
+//! Authorities may instead be resolved from a local file, in environments without a
+//! Destination service; see `static_endpoints`. Their endpoints are sent to resolutions
+//! in the same way as the Destination service's.
+//!
+//! The Destination service may also describe how requests for an authority are routed,
+//! e.g. by splitting them across backing authorities; see `routes`.
+//!
 //! The number of active resolutions is not currently bounded by this module. Instead, we
 //! trust that callers of `Resolver` enforce such a constraint (for example, via
 //! `linkerd2_proxy_router`'s LRU cache). Additionally, users of this module must ensure
 //! they consume resolutions as they are sent so that the response channels don't grow
 //! without bounds.
 //!
-//! Furthermore, there are not currently any bounds on the number of endpoints that may be
-//! returned for a single resolution. It is expected that the Destination service enforce
-//! some reasonable upper bounds.
+//! The number of endpoints that resolutions use is bounded by `EndpointLimits`, both for
+//! each resolution and across all of them. A resolution with more endpoints than it may
+//! use is sent a subset of them, chosen by rendezvous hashing on the proxy's identity, so
+//! that the subset is stable as endpoints change and different proxies use different
+//! subsets. Every resolution with endpoints uses at least one of them, even if there are
+//! more such resolutions than the total limit.
 //!
 //! ## TODO
 //!
 //! - Given that the underlying gRPC client has some max number of concurrent streams, we
 //!   actually do have an upper bound on concurrent resolutions. This needs to be made
 //!   more explicit.
-//! - We need some means to limit the number of endpoints that can be returned for a
-//!   single resolution so that `control::Cache` is not effectively unbounded.
+//! - `EndpointLimits` only bound the endpoints that are sent to balancers. All of a
+//!   resolution's discovered endpoints are still held by the background task, so that
+//!   the subset may be chosen again as they change, so `control::Cache` is not bounded.
+//!   We need some means to limit the number of endpoints that the Destination service
+//!   or DNS may return for a single resolution.
 
 use indexmap::IndexMap;
 use std::net::SocketAddr;
@@ -40,14 +53,18 @@ use tower_discover::{Change, Discover};
 use tower_service::Service;
 
 use dns;
-use svc::MakeClient;
+use svc::{reconnect::Backoff, MakeClient};
+use telemetry;
 use tls;
 use transport::{DnsNameAndPort, HostAndPort};
 
 pub mod background;
 mod endpoint;
+pub mod routes;
+pub mod static_endpoints;
 
 pub use self::endpoint::Endpoint;
+pub use self::routes::Routes;
 use config::Namespaces;
 use conditional::Conditional;
 
@@ -102,6 +119,16 @@ pub struct Metadata {
 
     /// How to verify TLS for the endpoint.
     tls_identity: Conditional<tls::Identity, tls::ReasonForNoIdentity>,
+
+    /// The endpoint's weight relative to the other endpoints of a destination.
+    ///
+    /// An endpoint with a weight of zero is not sent requests unless all of the
+    /// destination's endpoints have a weight of zero.
+    weight: u32,
+
+    /// Whether the endpoint is in the proxy's own locality, if the proxy's
+    /// locality is known.
+    locality: Option<Locality>,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -113,6 +140,43 @@ pub enum ProtocolHint {
     Http2,
 }
 
+/// Describes an endpoint's locality relative to the proxy's.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Locality {
+    /// The endpoint is in the same zone and region as the proxy.
+    Local,
+    /// The endpoint is in another zone or region.
+    Remote,
+}
+
+/// Limits the number of endpoints that resolutions use.
+#[derive(Clone, Debug)]
+pub struct EndpointLimits {
+    /// The maximum number of endpoints that a single resolution may use.
+    pub per_resolution: usize,
+
+    /// The maximum number of endpoints across all resolutions.
+    pub total: usize,
+
+    /// Identifies the proxy when choosing subsets of endpoints, so that
+    /// different proxies use different subsets.
+    pub subset_key: String,
+}
+
+/// Configures how authorities' DNS answers are used when DNS queries fail.
+#[derive(Clone, Copy, Debug)]
+pub struct DnsConfig {
+    /// How long the last answer is still used after NXDOMAIN responses.
+    pub nxdomain_grace_period: Duration,
+
+    /// How long the last answer may be used after its TTL has elapsed, if
+    /// bounded.
+    pub max_staleness: Option<Duration>,
+
+    /// The backoff between queries after consecutive errors.
+    pub error_backoff: Backoff,
+}
+
 #[derive(Debug, Clone)]
 enum Update {
     /// Indicates that an endpoint should be bound to `SocketAddr` with the
@@ -125,6 +189,9 @@ enum Update {
     Remove(SocketAddr),
 }
 
+/// The weight of endpoints that are not weighted by the Destination service.
+pub const DEFAULT_WEIGHT: u32 = 1;
+
 /// Returns a `Resolver` and a background task future.
 ///
 /// The `Resolver` is used by a listener to request resolutions, while
@@ -137,6 +204,12 @@ pub fn new(
     controller_tls: tls::ConditionalConnectionConfig<tls::ClientConfigWatch>,
     control_backoff_delay: Duration,
     concurrency_limit: usize,
+    static_endpoints: static_endpoints::Watch,
+    routes: Routes,
+    dns_config: DnsConfig,
+    dns_metrics: telemetry::dns::Registry,
+    endpoint_limits: EndpointLimits,
+    resolution_metrics: telemetry::resolution::Registry,
 ) -> (Resolver, impl Future<Item = (), Error = ()>) {
     let (request_tx, rx) = mpsc::unbounded();
     let disco = Resolver { request_tx };
@@ -148,6 +221,12 @@ pub fn new(
         controller_tls,
         control_backoff_delay,
         concurrency_limit,
+        static_endpoints,
+        routes,
+        dns_config,
+        dns_metrics,
+        endpoint_limits,
+        resolution_metrics,
     );
     (disco, bg)
 }
@@ -243,18 +322,23 @@ impl Metadata {
             // If we have no metadata on an endpoint, assume it does not support TLS.
             tls_identity:
                 Conditional::None(tls::ReasonForNoIdentity::NotProvidedByServiceDiscovery),
+            weight: DEFAULT_WEIGHT,
+            locality: None,
         }
     }
 
     pub fn new(
         labels: IndexMap<String, String>,
         protocol_hint: ProtocolHint,
-        tls_identity: Conditional<tls::Identity, tls::ReasonForNoIdentity>
+        tls_identity: Conditional<tls::Identity, tls::ReasonForNoIdentity>,
+        weight: u32,
     ) -> Self {
         Self {
             labels,
             protocol_hint,
             tls_identity,
+            weight,
+            locality: None,
         }
     }
 
@@ -263,6 +347,15 @@ impl Metadata {
         &self.labels
     }
 
+    /// Adds a label, replacing any existing value for `key`.
+    pub fn add_label<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.labels.insert(key.into(), value.into());
+    }
+
     pub fn protocol_hint(&self) -> ProtocolHint {
         self.protocol_hint
     }
@@ -270,4 +363,16 @@ impl Metadata {
     pub fn tls_identity(&self) -> Conditional<&tls::Identity, tls::ReasonForNoIdentity> {
         self.tls_identity.as_ref()
     }
+
+    pub fn weight(&self) -> u32 {
+        self.weight
+    }
+
+    pub fn locality(&self) -> Option<Locality> {
+        self.locality
+    }
+
+    pub fn set_locality(&mut self, locality: Locality) {
+        self.locality = Some(locality);
+    }
 }
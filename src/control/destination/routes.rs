@@ -0,0 +1,236 @@
+//! How the Destination service routes requests for an authority, beyond
+//! resolving its endpoints.
+//!
+//! The version of the Destination service's API that the proxy implements has
+//! no fields that describe routing, so it is described by reserved labels on
+//! the sets of endpoints that the Destination service adds for an authority:
+//!
+//! - `traffic_split`: splits the authority's traffic across backing
+//!   authorities, which are listed with their weights as they are in the
+//!   traffic split file, e.g. `web-v1.prod.svc.cluster.local:8080=95
+//!   web-v2.prod.svc.cluster.local:8080=5` (see `control::split`).
+//! - `balance_algorithm`: the algorithm that balances the authority's
+//!   endpoints, with its option as it is in the balancer algorithms file, e.g.
+//!   `hash header=x-session-id` (see `control::algorithm`).
+//!
+//! Reserved labels are not added to the endpoints' metric labels. If a set
+//! does not have a reserved label, the authority's route is reset, so the
+//! Destination service may withdraw a route by omitting its label.
+//!
+//! Routes are only known while the authority is resolved. Once requests are
+//! routed by its split, the authority itself may no longer be resolved, so
+//! its resolution is retained for as long as its route is used.
+
+use rand::Rng;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use control::algorithm::Algorithm;
+use control::split::Split;
+use transport::DnsNameAndPort;
+
+const SPLIT_LABEL: &str = "traffic_split";
+const ALGORITHM_LABEL: &str = "balance_algorithm";
+
+/// Shares the routes described by the Destination service between the
+/// discovery background task and the proxies that route requests.
+#[derive(Clone, Debug)]
+pub struct Routes {
+    routes: Arc<Mutex<HashMap<DnsNameAndPort, Route>>>,
+
+    /// How long an authority is resolved after its route was last used.
+    max_idle_age: Duration,
+}
+
+#[derive(Debug)]
+struct Route {
+    split: Option<Split>,
+    algorithm: Option<Algorithm>,
+
+    /// When `split` was last used to route a request.
+    last_used: Instant,
+}
+
+// ===== impl Routes =====
+
+impl Routes {
+    pub fn new(max_idle_age: Duration) -> Self {
+        Self {
+            routes: Arc::new(Mutex::new(HashMap::new())),
+            max_idle_age,
+        }
+    }
+
+    /// Chooses a backing authority for `logical`, if the Destination service
+    /// splits its traffic.
+    pub fn choose_backend<R: Rng>(
+        &self,
+        logical: &DnsNameAndPort,
+        rng: &mut R,
+    ) -> Option<DnsNameAndPort> {
+        let mut routes = self.routes.lock().ok()?;
+        let route = routes.get_mut(logical)?;
+        let backend = route.split.as_ref()?.choose(rng).clone();
+        route.last_used = Instant::now();
+        Some(backend)
+    }
+
+    /// Returns the algorithm that the Destination service chose to balance
+    /// `authority`'s endpoints, if it chose one.
+    pub fn algorithm(&self, authority: &DnsNameAndPort) -> Option<Algorithm> {
+        let routes = self.routes.lock().ok()?;
+        routes.get(authority)?.algorithm.clone()
+    }
+
+    /// Updates the route of `authority` from the labels of a set of endpoints
+    /// that the Destination service added, and removes the reserved labels.
+    pub(super) fn update(&self, authority: &DnsNameAndPort, labels: &mut HashMap<String, String>) {
+        let split = remove_label::<Split>(labels, SPLIT_LABEL, authority);
+        let algorithm = remove_label::<Algorithm>(labels, ALGORITHM_LABEL, authority);
+
+        if let Ok(mut routes) = self.routes.lock() {
+            if split.is_none() && algorithm.is_none() {
+                routes.remove(authority);
+                return;
+            }
+
+            trace!("route for {:?}: split={:?}; algorithm={:?}", authority, split, algorithm);
+            let route = routes.entry(authority.clone()).or_insert_with(|| Route {
+                split: None,
+                algorithm: None,
+                last_used: Instant::now(),
+            });
+            route.split = split;
+            route.algorithm = algorithm;
+        }
+    }
+
+    /// Returns true if the split of `authority` has been used recently, so
+    /// that it should remain resolved.
+    pub(super) fn is_used(&self, authority: &DnsNameAndPort, now: Instant) -> bool {
+        let routes = match self.routes.lock() {
+            Ok(routes) => routes,
+            Err(_) => return false,
+        };
+        routes
+            .get(authority)
+            .map_or(false, |route| {
+                route.split.is_some() && route.last_used + self.max_idle_age > now
+            })
+    }
+
+    /// Forgets the route of an authority that is no longer resolved.
+    pub(super) fn remove(&self, authority: &DnsNameAndPort) {
+        if let Ok(mut routes) = self.routes.lock() {
+            routes.remove(authority);
+        }
+    }
+}
+
+/// Removes a reserved label and parses its value, if it is valid.
+fn remove_label<T>(
+    labels: &mut HashMap<String, String>,
+    key: &str,
+    authority: &DnsNameAndPort,
+) -> Option<T>
+where
+    T: FromStr<Err = &'static str>,
+{
+    let value = labels.remove(key)?;
+    match value.parse() {
+        Ok(v) => Some(v),
+        Err(e) => {
+            warn!("invalid {} label for {:?}: {}", key, authority, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use convert::TryFrom;
+    use dns;
+    use rand;
+
+    fn authority(host: &str) -> DnsNameAndPort {
+        DnsNameAndPort {
+            host: dns::Name::try_from(host.as_bytes()).unwrap(),
+            port: 8080,
+        }
+    }
+
+    fn labels(split: Option<&str>) -> HashMap<String, String> {
+        let mut labels = HashMap::new();
+        labels.insert("namespace".to_owned(), "prod".to_owned());
+        if let Some(split) = split {
+            labels.insert(SPLIT_LABEL.to_owned(), split.to_owned());
+        }
+        labels
+    }
+
+    fn algorithm_labels(algorithm: &str) -> HashMap<String, String> {
+        let mut labels = labels(None);
+        labels.insert(ALGORITHM_LABEL.to_owned(), algorithm.to_owned());
+        labels
+    }
+
+    #[test]
+    fn splits_are_read_from_set_labels() {
+        let routes = Routes::new(Duration::from_secs(60));
+        let web = authority("web.prod.svc.cluster.local");
+        assert_eq!(routes.choose_backend(&web, &mut rand::thread_rng()), None);
+
+        let mut set_labels = labels(Some("web-v2.prod.svc.cluster.local:8080=1"));
+        routes.update(&web, &mut set_labels);
+        assert_eq!(set_labels, labels(None));
+        assert_eq!(
+            routes.choose_backend(&web, &mut rand::thread_rng()),
+            Some(authority("web-v2.prod.svc.cluster.local")),
+        );
+
+        // A set without a split withdraws it.
+        routes.update(&web, &mut labels(None));
+        assert_eq!(routes.choose_backend(&web, &mut rand::thread_rng()), None);
+
+        // Invalid splits are ignored.
+        routes.update(&web, &mut labels(Some("web-v2.prod.svc.cluster.local:8080=0")));
+        assert_eq!(routes.choose_backend(&web, &mut rand::thread_rng()), None);
+    }
+
+    #[test]
+    fn routes_are_used_until_idle() {
+        let routes = Routes::new(Duration::from_secs(60));
+        let web = authority("web.prod.svc.cluster.local");
+        let now = Instant::now();
+        assert!(!routes.is_used(&web, now));
+
+        routes.update(&web, &mut labels(Some("web-v2.prod.svc.cluster.local:8080=1")));
+        routes.choose_backend(&web, &mut rand::thread_rng());
+        assert!(routes.is_used(&web, now));
+        assert!(!routes.is_used(&web, Instant::now() + Duration::from_secs(60)));
+
+        routes.remove(&web);
+        assert!(!routes.is_used(&web, now));
+    }
+
+    #[test]
+    fn algorithms_are_read_from_set_labels() {
+        let routes = Routes::new(Duration::from_secs(60));
+        let web = authority("web.prod.svc.cluster.local");
+        assert_eq!(routes.algorithm(&web), None);
+
+        let mut set_labels = algorithm_labels("round-robin");
+        routes.update(&web, &mut set_labels);
+        assert_eq!(set_labels, labels(None));
+        assert_eq!(routes.algorithm(&web), Some(Algorithm::RoundRobin));
+
+        // An algorithm alone doesn't keep the authority resolved.
+        assert!(!routes.is_used(&web, Instant::now()));
+
+        routes.update(&web, &mut algorithm_labels("random"));
+        assert_eq!(routes.algorithm(&web), None);
+    }
+}
@@ -0,0 +1,297 @@
+//! Endpoints that are resolved from a local file rather than by the
+//! Destination service or DNS.
+//!
+//! This is intended for environments in which no Destination service is
+//! available. The file is watched for changes, which are published to
+//! resolutions just as the Destination service's updates are. The file is a
+//! YAML (or JSON) map from each authority to a list of its endpoints:
+//!
+//! ```yaml
+//! web.prod.example.com:8080:
+//!   - address: 10.1.1.1:8080
+//!     protocol: h2
+//!     identity: web.prod.example.com
+//!     labels:
+//!       version: v1
+//!   - address: 10.1.1.2:8080
+//!     weight: 10
+//!     labels:
+//!       version: v2
+//! cache.prod.example.com:6379: []
+//! ```
+//!
+//! If an authority has no port, port 80 is assumed. Each endpoint has an
+//! `address`, and optionally:
+//!
+//! - `protocol`: `h2` if the endpoint can receive HTTP2 messages.
+//! - `identity`: the DNS name or SPIFFE ID of the endpoint's TLS identity.
+//!   Endpoints without an identity are not sent TLS.
+//! - `weight`: the endpoint's weight relative to the authority's other
+//!   endpoints. The default is 1.
+//! - `labels`: labels that are added to the endpoint's metrics.
+//!
+//! Authorities that are not listed are resolved by the Destination service or
+//! DNS as usual.
+
+use futures_watch;
+use indexmap::IndexMap;
+use serde_yaml::{self, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use super::{Metadata, ProtocolHint, DEFAULT_WEIGHT};
+use conditional::Conditional;
+use control::split::parse_authority;
+use tls;
+use transport::DnsNameAndPort;
+
+/// Watches the current `StaticEndpoints`.
+pub type Watch = futures_watch::Watch<StaticEndpoints>;
+
+/// Maps authorities to their endpoints.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StaticEndpoints(HashMap<DnsNameAndPort, IndexMap<SocketAddr, Metadata>>);
+
+#[derive(Debug)]
+pub enum Error {
+    /// The file is not valid YAML or JSON.
+    Syntax(serde_yaml::Error),
+    /// The file is not a map of authorities to lists of endpoints.
+    NotAMap,
+    /// An authority or one of its endpoints is invalid.
+    Invalid {
+        authority: String,
+        reason: &'static str,
+    },
+}
+
+// ===== impl StaticEndpoints =====
+
+impl StaticEndpoints {
+    /// Returns the endpoints of the given authority, if it is configured.
+    pub fn get(&self, authority: &DnsNameAndPort) -> Option<&IndexMap<SocketAddr, Metadata>> {
+        self.0.get(authority)
+    }
+}
+
+impl FromStr for StaticEndpoints {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // An empty file configures no authorities.
+        if s.trim().is_empty() {
+            return Ok(StaticEndpoints::default());
+        }
+
+        let value = serde_yaml::from_str::<Value>(s).map_err(Error::Syntax)?;
+        let specs = value.as_mapping().ok_or(Error::NotAMap)?;
+
+        let mut authorities = HashMap::new();
+        for (name, specs) in specs.iter() {
+            let name = name.as_str().ok_or(Error::NotAMap)?;
+            let err = |reason| Error::Invalid { authority: name.to_owned(), reason };
+
+            let authority = parse_authority(name).ok_or_else(|| err("invalid authority"))?;
+            let specs = specs.as_sequence().ok_or(Error::NotAMap)?;
+            let mut endpoints = IndexMap::with_capacity(specs.len());
+            for spec in specs {
+                let (addr, meta) = parse_endpoint(spec).map_err(&err)?;
+                if endpoints.insert(addr, meta).is_some() {
+                    return Err(err("endpoint is listed more than once"));
+                }
+            }
+
+            if authorities.insert(authority, endpoints).is_some() {
+                return Err(err("authority is listed more than once"));
+            }
+        }
+
+        Ok(StaticEndpoints(authorities))
+    }
+}
+
+/// Parses an endpoint, as it is described in the file.
+fn parse_endpoint(spec: &Value) -> Result<(SocketAddr, Metadata), &'static str> {
+    let spec = spec.as_mapping().ok_or("endpoint is not a map")?;
+
+    let mut addr = None;
+    let mut protocol_hint = ProtocolHint::Unknown;
+    let mut tls_identity =
+        Conditional::None(tls::ReasonForNoIdentity::NotProvidedByServiceDiscovery);
+    let mut weight = DEFAULT_WEIGHT;
+    // Labels are ordered by key, as they are for the Destination service's
+    // endpoints.
+    let mut labels = BTreeMap::new();
+
+    for (field, value) in spec.iter() {
+        match field.as_str().unwrap_or("") {
+            "address" => {
+                let a = value.as_str()
+                    .and_then(|a| a.parse::<SocketAddr>().ok())
+                    .ok_or("invalid address")?;
+                addr = Some(a);
+            }
+            "protocol" => {
+                protocol_hint = match value.as_str() {
+                    Some("unknown") => ProtocolHint::Unknown,
+                    Some("h2") => ProtocolHint::Http2,
+                    _ => return Err("invalid protocol"),
+                };
+            }
+            "identity" => {
+                let identity = value.as_str()
+                    .and_then(|id| tls::Identity::parse(id).ok())
+                    .ok_or("invalid identity")?;
+                tls_identity = Conditional::Some(identity);
+            }
+            "weight" => {
+                let w = value.as_u64().ok_or("invalid weight")?;
+                if w > u64::from(u32::max_value()) {
+                    return Err("invalid weight");
+                }
+                weight = w as u32;
+            }
+            "labels" => {
+                let map = value.as_mapping().ok_or("labels are not a map")?;
+                for (k, v) in map.iter() {
+                    match (k.as_str(), v.as_str()) {
+                        (Some(k), Some(v)) => {
+                            labels.insert(k.to_owned(), v.to_owned());
+                        }
+                        _ => return Err("invalid label"),
+                    }
+                }
+            }
+            _ => return Err("unknown endpoint field"),
+        }
+    }
+
+    let addr = addr.ok_or("missing address")?;
+    let labels = labels.into_iter().collect::<IndexMap<_, _>>();
+    Ok((addr, Metadata::new(labels, protocol_hint, tls_identity, weight)))
+}
+
+// ===== impl Error =====
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Syntax(ref e) => fmt::Display::fmt(e, f),
+            Error::NotAMap => write!(f, "not a map of authorities to lists of endpoints"),
+            Error::Invalid { ref authority, reason } => write!(f, "{}: {}", authority, reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use convert::TryFrom;
+    use dns;
+
+    fn authority(host: &str, port: u16) -> DnsNameAndPort {
+        DnsNameAndPort {
+            host: dns::Name::try_from(host.as_bytes()).unwrap(),
+            port,
+        }
+    }
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_yaml() {
+        let endpoints = "
+# web
+web.prod.example.com:8080:
+  - address: 10.1.1.1:8080
+    protocol: h2
+    identity: web.prod.example.com
+    labels:
+      version: v1
+  - address: 10.1.1.2:8080
+    weight: 10
+    labels:
+      zone: a
+      version: v2
+
+cache.prod.example.com: []
+".parse::<StaticEndpoints>().unwrap();
+
+        let web = endpoints.get(&authority("web.prod.example.com", 8080)).unwrap();
+        assert_eq!(web.len(), 2);
+
+        let v1 = &web[&addr("10.1.1.1:8080")];
+        assert_eq!(v1.protocol_hint(), ProtocolHint::Http2);
+        match v1.tls_identity() {
+            Conditional::Some(identity) => {
+                assert_eq!(identity.to_string(), "web.prod.example.com");
+            }
+            Conditional::None(reason) => panic!("no identity: {:?}", reason),
+        }
+        assert_eq!(v1.weight(), DEFAULT_WEIGHT);
+
+        let v2 = &web[&addr("10.1.1.2:8080")];
+        assert_eq!(v2.protocol_hint(), ProtocolHint::Unknown);
+        assert_eq!(
+            v2.tls_identity(),
+            Conditional::None(tls::ReasonForNoIdentity::NotProvidedByServiceDiscovery),
+        );
+        assert_eq!(v2.weight(), 10);
+        assert_eq!(
+            v2.labels().iter().collect::<Vec<_>>(),
+            vec![
+                (&"version".to_owned(), &"v2".to_owned()),
+                (&"zone".to_owned(), &"a".to_owned()),
+            ],
+        );
+
+        let cache = endpoints.get(&authority("cache.prod.example.com", 80)).unwrap();
+        assert!(cache.is_empty());
+
+        assert!(endpoints.get(&authority("web.prod.example.com", 80)).is_none());
+    }
+
+    #[test]
+    fn parses_json() {
+        let endpoints = r#"{
+            "web.prod.example.com:8080": [
+                {"address": "10.1.1.1:8080", "protocol": "h2", "labels": {"version": "v1"}}
+            ]
+        }"#.parse::<StaticEndpoints>().unwrap();
+
+        let web = endpoints.get(&authority("web.prod.example.com", 8080)).unwrap();
+        let v1 = &web[&addr("10.1.1.1:8080")];
+        assert_eq!(v1.protocol_hint(), ProtocolHint::Http2);
+        assert_eq!(v1.labels()["version"], "v1");
+    }
+
+    #[test]
+    fn parses_empty_file() {
+        let endpoints = "\n".parse::<StaticEndpoints>().unwrap();
+        assert_eq!(endpoints, StaticEndpoints::default());
+    }
+
+    #[test]
+    fn rejects_invalid_endpoints() {
+        for s in &[
+            "web:8080",
+            "web:8080: [10.1.1.1:8080]",
+            "10.1.1.1:8080: []",
+            "web:8080:\n  - address: web-v1:8080",
+            "web:8080:\n  - address: 10.1.1.1:8080\n    protocol: h3",
+            "web:8080:\n  - address: 10.1.1.1:8080\n    identity: web.",
+            "web:8080:\n  - address: 10.1.1.1:8080\n    weight: -1",
+            "web:8080:\n  - address: 10.1.1.1:8080\n    weight: 4294967296",
+            "web:8080:\n  - protocol: h2",
+            "web:8080:\n  - address: 10.1.1.1:8080\n    version: v1",
+            "web:8080:\n  - address: 10.1.1.1:8080\n  - address: 10.1.1.1:8080",
+            "web: []\nweb:80: []",
+        ] {
+            assert!(s.parse::<StaticEndpoints>().is_err(), "{:?} must not parse", s);
+        }
+    }
+}
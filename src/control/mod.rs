@@ -1,3 +1,4 @@
+pub mod algorithm;
 mod cache;
 pub mod destination;
 mod fully_qualified_authority;
@@ -5,6 +6,7 @@ mod observe;
 pub mod pb;
 mod remote_stream;
 mod serve_http;
+pub mod split;
 
 pub use self::observe::Observe;
 pub use self::serve_http::serve_http;
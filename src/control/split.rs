@@ -0,0 +1,196 @@
+//! Traffic splits, which distribute the requests for a logical authority
+//! across several backing authorities by weight.
+//!
+//! Splits are read from a local file, which is watched for changes so that
+//! weights may be shifted during a rollout. Each line that is not empty or a
+//! `#` comment describes the split for one logical authority, followed by
+//! each backing authority and its weight:
+//!
+//! ```text
+//! web.prod.svc.cluster.local:8080 web-v1.prod.svc.cluster.local:8080=95 web-v2.prod.svc.cluster.local:8080=5
+//! ```
+//!
+//! The logical authority must match the authority of outbound requests. If
+//! no port is specified, port 80 is assumed.
+//!
+//! The Destination service may also split a logical authority's traffic,
+//! by labeling the logical authority's endpoints with its backing authorities
+//! (see `control::destination::routes`). A split in the file takes precedence
+//! over one from the Destination service.
+
+use futures_watch;
+use http;
+use rand::Rng;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use fs_watch::{parse_lines, LineError};
+use transport::{DnsNameAndPort, Host, HostAndPort};
+
+/// Used when an authority in a split does not specify a port.
+const DEFAULT_PORT: u16 = 80;
+
+/// Watches the current `Splits`.
+pub type Watch = futures_watch::Watch<Splits>;
+
+/// Maps logical authorities to their splits.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Splits(HashMap<DnsNameAndPort, Split>);
+
+/// Distributes requests for a logical authority across backing authorities.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Split {
+    backends: Vec<Backend>,
+    total_weight: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Backend {
+    authority: DnsNameAndPort,
+    weight: u32,
+}
+
+/// Parses an authority, using the default port if none is specified.
+///
+/// Authorities that are IP addresses are not valid.
+pub(crate) fn parse_authority(s: &str) -> Option<DnsNameAndPort> {
+    let authority = http::uri::Authority::from_str(s).ok()?;
+    match HostAndPort::normalize(&authority, Some(DEFAULT_PORT)).ok()? {
+        HostAndPort { host: Host::DnsName(host), port } => Some(DnsNameAndPort { host, port }),
+        HostAndPort { host: Host::Ip(_), .. } => None,
+    }
+}
+
+// ===== impl Splits =====
+
+impl Splits {
+    /// Returns the split for the given logical authority, if there is one.
+    pub fn get(&self, logical: &DnsNameAndPort) -> Option<&Split> {
+        self.0.get(logical)
+    }
+}
+
+impl FromStr for Splits {
+    type Err = LineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut splits = HashMap::new();
+
+        parse_lines(s, |line| {
+            let mut words = line.splitn(2, char::is_whitespace);
+            let logical = words.next()
+                .and_then(parse_authority)
+                .ok_or("invalid logical authority")?;
+            let split = words.next().unwrap_or("").parse::<Split>()?;
+            if splits.insert(logical, split).is_some() {
+                return Err("logical authority is split more than once");
+            }
+            Ok(())
+        })?;
+
+        Ok(Splits(splits))
+    }
+}
+
+// ===== impl Split =====
+
+impl Split {
+    /// Chooses a backing authority with a probability proportional to its
+    /// weight.
+    pub fn choose<R: Rng>(&self, rng: &mut R) -> &DnsNameAndPort {
+        let mut n = rng.gen_range(0, self.total_weight);
+        for backend in &self.backends {
+            if n < backend.weight {
+                return &backend.authority;
+            }
+            n -= backend.weight;
+        }
+        unreachable!("total weight must be the sum of backend weights");
+    }
+}
+
+impl FromStr for Split {
+    type Err = &'static str;
+
+    /// Parses whitespace-separated backing authorities, each with its weight.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut backends = Vec::new();
+        let mut total_weight = 0u32;
+        for word in s.split_whitespace() {
+            let mut parts = word.splitn(2, '=');
+            let authority = parts.next()
+                .and_then(parse_authority)
+                .ok_or("invalid backend authority")?;
+            let weight = parts.next()
+                .and_then(|w| w.parse::<u32>().ok())
+                .ok_or("invalid backend weight")?;
+            total_weight = total_weight.checked_add(weight)
+                .ok_or("total weight is too large")?;
+            backends.push(Backend { authority, weight });
+        }
+
+        if total_weight == 0 {
+            return Err("no backend has a weight");
+        }
+
+        Ok(Split { backends, total_weight })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use convert::TryFrom;
+    use dns;
+    use rand;
+
+    fn authority(host: &str, port: u16) -> DnsNameAndPort {
+        DnsNameAndPort {
+            host: dns::Name::try_from(host.as_bytes()).unwrap(),
+            port,
+        }
+    }
+
+    #[test]
+    fn parses_splits() {
+        let splits = "
+            # canary
+            web.prod.svc.cluster.local:8080 web-v1.prod.svc.cluster.local:8080=95 web-v2.prod.svc.cluster.local:8080=5
+
+            api.example.com api-east.example.com=1 api-west.example.com:8080=0
+        ".parse::<Splits>().unwrap();
+
+        let web = splits.get(&authority("web.prod.svc.cluster.local", 8080)).unwrap();
+        assert_eq!(web.total_weight, 100);
+        assert_eq!(web.backends, vec![
+            Backend { authority: authority("web-v1.prod.svc.cluster.local", 8080), weight: 95 },
+            Backend { authority: authority("web-v2.prod.svc.cluster.local", 8080), weight: 5 },
+        ]);
+
+        let api = splits.get(&authority("api.example.com", 80)).unwrap();
+        assert_eq!(api.backends[1].authority, authority("api-west.example.com", 8080));
+        for _ in 0..100 {
+            assert_eq!(
+                api.choose(&mut rand::thread_rng()),
+                &authority("api-east.example.com", 80),
+            );
+        }
+
+        assert!(splits.get(&authority("web.prod.svc.cluster.local", 80)).is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_splits() {
+        for s in &[
+            "web:8080",
+            "web:8080 web-v1:8080",
+            "web:8080 web-v1:8080=a",
+            "web:8080 web-v1:8080=0",
+            "10.1.1.1:8080 web-v1:8080=1",
+            "web:8080 10.1.1.1:8080=1",
+            "web:8080 web-v1:8080=1\nweb:8080 web-v2:8080=1",
+        ] {
+            assert!(s.parse::<Splits>().is_err(), "{:?} must not parse", s);
+        }
+    }
+}
@@ -47,7 +47,7 @@ pub mod test_util {
         proxy: ctx::Proxy,
         tls: ctx::transport::TlsStatus
     ) -> Arc<ctx::transport::Server> {
-        ctx::transport::Server::new(proxy, &addr(), &addr(), &Some(addr()), tls)
+        ctx::transport::Server::new(proxy, &addr(), &addr(), &Some(addr()), tls, None)
     }
 
     pub fn client(
@@ -58,7 +58,8 @@ pub mod test_util {
         let meta = destination::Metadata::new(
             labels,
             destination::ProtocolHint::Unknown,
-            Conditional::None(tls::ReasonForNoIdentity::NotProvidedByServiceDiscovery)
+            Conditional::None(tls::ReasonForNoIdentity::NotProvidedByServiceDiscovery),
+            destination::DEFAULT_WEIGHT,
         );
         ctx::transport::Client::new(proxy, &addr(), meta, tls)
     }
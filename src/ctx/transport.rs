@@ -25,6 +25,11 @@ pub struct Server {
     pub local: SocketAddr,
     pub orig_dst: Option<SocketAddr>,
     pub tls_status: TlsStatus,
+    /// The certificate the client authenticated with, if any.
+    pub peer_certificate: Option<tls::PeerCertificate>,
+    /// The identity of the client, if it authenticated with a certificate
+    /// that names one.
+    pub peer_identity: Option<tls::Identity>,
 }
 
 /// Identifies a connection from the proxy to another process.
@@ -86,18 +91,30 @@ impl Server {
         remote: &SocketAddr,
         orig_dst: &Option<SocketAddr>,
         tls_status: TlsStatus,
+        peer_certificate: Option<tls::PeerCertificate>,
     ) -> Arc<Server> {
+        let peer_identity = peer_certificate.as_ref().and_then(|c| c.identity());
         let s = Server {
             proxy,
             local: *local,
             remote: *remote,
             orig_dst: *orig_dst,
             tls_status,
+            peer_certificate,
+            peer_identity,
         };
 
         Arc::new(s)
     }
 
+    /// Returns the port to which the client connected.
+    ///
+    /// This is the port of the connection's original destination, if it has
+    /// one, since that is the port on which the application is listening.
+    pub fn port(&self) -> u16 {
+        self.orig_dst.unwrap_or(self.local).port()
+    }
+
     pub fn orig_dst_if_not_local(&self) -> Option<SocketAddr> {
         match self.orig_dst {
             None => None,
@@ -1,13 +1,16 @@
-use futures::prelude::*;
+use futures::{future, prelude::*};
+use std::cmp;
 use std::fmt;
-use std::net::IpAddr;
-use std::time::Instant;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use telemetry::dns::{Outcome, RecordType, Registry as Metrics};
 use tokio::timer::Delay;
 use transport;
 use trust_dns_resolver::{
     self,
     config::{ResolverConfig, ResolverOpts},
     error::{ResolveError, ResolveErrorKind},
+    lookup::SrvLookup,
     lookup_ip::LookupIp,
     AsyncResolver,
 };
@@ -18,6 +21,12 @@ use config::Config;
 #[derive(Clone)]
 pub struct Resolver {
     resolver: AsyncResolver,
+
+    /// Names ending in any of these suffixes are resolved by their SRV
+    /// records, rather than their A/AAAA records.
+    srv_suffixes: Vec<Name>,
+
+    metrics: Metrics,
 }
 
 pub enum IpAddrFuture {
@@ -32,9 +41,33 @@ pub enum Error {
 
 pub enum Response {
     Exists(LookupIp),
+    SrvExists(SrvAddrs),
     DoesNotExist { retry_after: Option<Instant> },
 }
 
+/// The addresses of the targets of a name's SRV records.
+///
+/// Only the records with the lowest priority are used, since the targets of
+/// records with higher priorities are only meant to be used when those
+/// targets are unreachable.
+#[derive(Debug)]
+pub struct SrvAddrs {
+    addrs: Vec<SrvAddr>,
+    valid_until: Instant,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SrvAddr {
+    pub addr: SocketAddr,
+    /// The address's share of its SRV record's weight, in units of
+    /// `1 / SRV_WEIGHT_SCALE`.
+    pub weight: u32,
+}
+
+/// SRV records' weights are scaled by this, so that they may be divided
+/// among their targets' addresses without rounding them to 0.
+const SRV_WEIGHT_SCALE: u32 = 1_000;
+
 // `Box<Future>` implements `Future` so it doesn't need to be implemented manually.
 pub type IpAddrListFuture = Box<Future<Item=Response, Error=ResolveError> + Send>;
 
@@ -53,6 +86,14 @@ impl fmt::Display for ResolveAllCtx {
     }
 }
 
+struct ResolveSrvCtx(Name);
+
+impl fmt::Display for ResolveSrvCtx {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "resolve_srv={}", self.0)
+    }
+}
+
 struct ResolveOneCtx(Name);
 
 impl fmt::Display for ResolveOneCtx {
@@ -73,36 +114,50 @@ impl Resolver {
     /// could not be parsed.
     ///
     /// TODO: Make this infallible, like it is in the `domain` crate.
-    pub fn from_system_config_and_env(env_config: &Config)
+    pub fn from_system_config_and_env(env_config: &Config, metrics: Metrics)
         -> Result<(Self, impl Future<Item = (), Error = ()> + Send), ResolveError> {
         let (config, opts) = trust_dns_resolver::system_conf::read_system_conf()?;
         let opts = env_config.configure_resolver_opts(opts);
         trace!("DNS config: {:?}", &config);
         trace!("DNS opts: {:?}", &opts);
-        Ok(Self::new(config, opts))
+        Ok(Self::new(config, opts, env_config.dns_srv_suffixes.clone(), metrics))
     }
 
 
     /// NOTE: It would be nice to be able to return a named type rather than
     ///       `impl Future` for the background future; it would be called
     ///       `Background` or `ResolverBackground` if that were possible.
-    pub fn new(config: ResolverConfig,  mut opts: ResolverOpts)
-        -> (Self, impl Future<Item = (), Error = ()> + Send)
+    pub fn new(
+        config: ResolverConfig,
+        mut opts: ResolverOpts,
+        srv_suffixes: Vec<Name>,
+        metrics: Metrics,
+    ) -> (Self, impl Future<Item = (), Error = ()> + Send)
     {
         // Disable Trust-DNS's caching.
         opts.cache_size = 0;
         let (resolver, background) = AsyncResolver::new(config, opts);
         let resolver = Resolver {
             resolver,
+            srv_suffixes,
+            metrics,
         };
         (resolver, background)
     }
 
+    /// Returns true if `host` should be resolved by its SRV records.
+    ///
+    /// This is the case if it ends in one of the configured suffixes, or if
+    /// it has the `_service._proto.name` form of an SRV name.
+    pub fn resolves_srv(&self, host: &Name) -> bool {
+        is_srv_name(host, &self.srv_suffixes)
+    }
+
     pub fn resolve_one_ip(&self, host: &transport::Host) -> IpAddrFuture {
         match *host {
             transport::Host::DnsName(ref name) => {
                 let ctx = ResolveOneCtx(name.clone());
-                let f = ::logging::context_future(ctx, self.lookup_ip(name));
+                let f = ::logging::context_future(ctx, self.lookup_ip(name.as_ref()));
                 IpAddrFuture::DNS(Box::new(f))
             }
             transport::Host::Ip(addr) => IpAddrFuture::Fixed(addr),
@@ -111,11 +166,12 @@ impl Resolver {
 
     pub fn resolve_all_ips(&self, deadline: Instant, host: &Name) -> IpAddrListFuture {
         let name = host.clone();
-        let lookup = self.lookup_ip(&name);
+        let host = host.clone();
+        let resolver = self.clone();
         let f = Delay::new(deadline)
             .then(move |_| {
                 trace!("after delay");
-                lookup
+                resolver.lookup_ip(host.as_ref())
             })
             .then(move |result| {
                 trace!("completed with {:?}", &result);
@@ -133,10 +189,125 @@ impl Resolver {
         Box::new(::logging::context_future(ResolveAllCtx(name), f))
     }
 
-    fn lookup_ip(&self, name: &Name)
+    /// Resolves the SRV records of `host`, and then the IP addresses of
+    /// their targets.
+    ///
+    /// The response is valid until the earliest of the deadlines of the
+    /// SRV records and their targets' addresses. Targets that cannot be
+    /// resolved are skipped.
+    pub fn resolve_srv(&self, deadline: Instant, host: &Name) -> IpAddrListFuture {
+        let name = host.clone();
+        let host = host.clone();
+        let resolver = self.clone();
+        let f = Delay::new(deadline)
+            .then(move |_| {
+                trace!("after delay");
+                resolver.lookup_srv(host.as_ref())
+                    .and_then(move |srv| resolver.lookup_srv_targets(srv))
+            })
+            .then(move |result| {
+                trace!("completed with {:?}", &result);
+                match result {
+                    Ok(addrs) => Ok(Response::SrvExists(addrs)),
+                    Err(e) => {
+                        if let &ResolveErrorKind::NoRecordsFound { valid_until, .. } = e.kind() {
+                            Ok(Response::DoesNotExist { retry_after: valid_until })
+                        } else {
+                            Err(e)
+                        }
+                    }
+                }
+            });
+        Box::new(::logging::context_future(ResolveSrvCtx(name), f))
+    }
+
+    fn lookup_srv_targets(&self, srv: SrvLookup)
+        -> impl Future<Item = SrvAddrs, Error = ResolveError>
+    {
+        let records = lowest_priority(srv.iter().map(|record| (record.priority(), record)));
+        let lookups = records.into_iter()
+            .map(|record| {
+                let (port, weight) = (record.port(), record.weight());
+                let target = record.target().to_string();
+                self.lookup_ip(&target).then(move |result| match result {
+                    Ok(ips) => Ok::<_, ResolveError>(Some((ips, port, weight))),
+                    Err(e) => {
+                        debug!("failed to resolve SRV target {}: {}", target, e);
+                        Ok(None)
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let valid_until = srv.valid_until();
+        future::join_all(lookups).map(move |targets| {
+            let mut srv = SrvAddrs { addrs: Vec::new(), valid_until };
+            for (ips, port, weight) in targets.into_iter().filter_map(|t| t) {
+                srv.valid_until = cmp::min(srv.valid_until, ips.valid_until());
+                let ips = ips.iter().collect::<Vec<_>>();
+                let weight = target_weight(weight, ips.len());
+                srv.addrs.extend(ips.into_iter().map(|ip| SrvAddr {
+                    addr: SocketAddr::from((ip, port)),
+                    weight,
+                }));
+            }
+            srv
+        })
+    }
+
+    fn lookup_ip(&self, name: &str)
         -> impl Future<Item = LookupIp, Error = ResolveError>
     {
-        self.resolver.lookup_ip(name.as_ref())
+        let lookup = self.resolver.lookup_ip(name);
+        self.record(name, RecordType::Ip, lookup, LookupIp::valid_until)
+    }
+
+    fn lookup_srv(&self, name: &str)
+        -> impl Future<Item = SrvLookup, Error = ResolveError>
+    {
+        let lookup = self.resolver.srv_lookup(name);
+        self.record(name, RecordType::Srv, lookup, SrvLookup::valid_until)
+    }
+
+    /// Records the outcome, latency, and TTL of a lookup of `name` once it
+    /// completes.
+    ///
+    /// The TTL reflects the configured minimum and maximum TTLs, since
+    /// Trust-DNS applies them to `valid_until`.
+    fn record<F>(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        lookup: F,
+        valid_until: fn(&F::Item) -> Instant,
+    ) -> impl Future<Item = F::Item, Error = ResolveError>
+    where
+        F: Future<Error = ResolveError>,
+    {
+        let metrics = self.metrics.clone();
+        let name = name.to_owned();
+        let started_at = Instant::now();
+        lookup.then(move |result| {
+            let now = Instant::now();
+            let outcome = match result {
+                Ok(ref answer) => {
+                    let valid_until = valid_until(answer);
+                    let ttl = if valid_until > now {
+                        valid_until - now
+                    } else {
+                        Duration::from_secs(0)
+                    };
+                    metrics.answer_ttl(record_type, ttl);
+                    Outcome::Success
+                },
+                Err(ref e) => match e.kind() {
+                    &ResolveErrorKind::NoRecordsFound { .. } => Outcome::NxDomain,
+                    _ => Outcome::Error,
+                },
+            };
+            metrics.lookup(&name, record_type, outcome, now - started_at);
+            result
+        })
     }
 }
 
@@ -150,6 +321,62 @@ impl fmt::Debug for Resolver {
     }
 }
 
+fn is_srv_name(host: &Name, srv_suffixes: &[Name]) -> bool {
+    let host = host.as_ref().trim_right_matches('.');
+    if host.starts_with('_') {
+        return true;
+    }
+    srv_suffixes.iter().any(|suffix| {
+        let suffix = suffix.as_ref().trim_right_matches('.');
+        host == suffix || (
+            host.ends_with(suffix) &&
+            host.as_bytes()[host.len() - suffix.len() - 1] == b'.'
+        )
+    })
+}
+
+/// Returns the SRV records with the lowest priority, of `records` and their
+/// priorities.
+fn lowest_priority<T, I>(records: I) -> Vec<T>
+where
+    I: IntoIterator<Item = (u16, T)>,
+{
+    let mut lowest = Vec::new();
+    let mut priority = None;
+    for (p, record) in records {
+        if priority.map_or(true, |priority| p < priority) {
+            priority = Some(p);
+            lowest.clear();
+        }
+        if priority == Some(p) {
+            lowest.push(record);
+        }
+    }
+    lowest
+}
+
+/// Returns the weight of each of an SRV record's target's `addrs`.
+///
+/// The record's weight is shared by its target's addresses, so that targets
+/// with many addresses don't receive more than their record's share of
+/// requests. RFC 2782 says that records with a weight of 0 should have a very
+/// small chance of being chosen, so they're given the smallest weight that
+/// isn't 0.
+fn target_weight(weight: u16, addrs: usize) -> u32 {
+    let weight = u32::from(weight) * SRV_WEIGHT_SCALE;
+    cmp::max(weight / cmp::max(addrs, 1) as u32, 1)
+}
+
+impl SrvAddrs {
+    pub fn addrs(&self) -> &[SrvAddr] {
+        &self.addrs
+    }
+
+    pub fn valid_until(&self) -> Instant {
+        self.valid_until
+    }
+}
+
 impl Future for IpAddrFuture {
     type Item = IpAddr;
     type Error = Error;
@@ -182,7 +409,7 @@ impl Future for IpAddrFuture {
 
 #[cfg(test)]
 mod tests {
-    use super::Name;
+    use super::{is_srv_name, lowest_priority, target_weight, Name, SRV_WEIGHT_SCALE};
 
     #[test]
     fn test_dns_name_parsing() {
@@ -230,4 +457,54 @@ mod tests {
             assert!(Name::try_from(case.as_bytes()).is_err());
         }
     }
+
+    #[test]
+    fn test_is_srv_name() {
+        use convert::TryFrom;
+
+        let name = |s: &str| Name::try_from(s.as_bytes()).unwrap();
+        let suffixes = vec![name("service.consul"), name("srv.example.com.")];
+
+        for host in &[
+            "web.service.consul",
+            "web.service.consul.",
+            "web.prod.srv.example.com",
+            "srv.example.com",
+            "_http._tcp.web.example.com",
+        ] {
+            assert!(is_srv_name(&name(host), &suffixes), "{} must be resolved by SRV", host);
+        }
+
+        for host in &[
+            "web.example.com",
+            "webservice.consul",
+            "service.consul.example.com",
+        ] {
+            assert!(!is_srv_name(&name(host), &suffixes), "{} must not be resolved by SRV", host);
+        }
+    }
+
+    #[test]
+    fn srv_records_with_the_lowest_priority_are_used() {
+        let records = vec![(10, "a"), (5, "b"), (20, "c"), (5, "d")];
+        assert_eq!(lowest_priority(records), vec!["b", "d"]);
+
+        assert_eq!(lowest_priority(vec![(0, "a"), (1, "b")]), vec!["a"]);
+        assert_eq!(lowest_priority(Vec::<(u16, &str)>::new()), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn srv_weights_are_shared_by_targets_addresses() {
+        assert_eq!(target_weight(10, 1), 10 * SRV_WEIGHT_SCALE);
+        assert_eq!(target_weight(10, 2), 5 * SRV_WEIGHT_SCALE);
+        assert_eq!(target_weight(1, 3), SRV_WEIGHT_SCALE / 3);
+        assert_eq!(target_weight(u16::max_value(), 1), 65_535 * SRV_WEIGHT_SCALE);
+    }
+
+    #[test]
+    fn srv_weights_of_0_are_small_but_positive() {
+        assert_eq!(target_weight(0, 1), 1);
+        assert_eq!(target_weight(0, 10), 1);
+        assert!(target_weight(0, 1) < target_weight(1, 100));
+    }
 }
@@ -1,6 +1,7 @@
-use std::{fs, io, cell::RefCell, path::{Path, PathBuf}, time::Duration};
+use std::{fmt, fs, io, cell::RefCell, path::{Path, PathBuf}, str::FromStr, time::Duration};
 
-use futures::Stream;
+use futures::{future, stream, Future, Stream};
+use futures_watch::Watch;
 use ring::digest::{self, Digest};
 
 use tokio_timer::{clock, Interval};
@@ -27,6 +28,86 @@ where
     }
 }
 
+/// Drives updates to a `Watch` of a file.
+pub type Publish = Box<Future<Item = (), Error = ()> + Send>;
+
+/// Describes the line of a file that could not be parsed.
+#[derive(Debug, PartialEq)]
+pub struct LineError {
+    line: usize,
+    reason: &'static str,
+}
+
+/// Returns a `Watch` of the value parsed from the file at `path`, and a task
+/// that updates it as the file changes.
+///
+/// The `Watch` initially holds the default value, and retains the last valid
+/// value if the file cannot be read or parsed. If no path is configured, the
+/// `Watch` is never updated and the task never completes.
+pub fn watch_file<T>(path: Option<PathBuf>, interval: Duration) -> (Watch<T>, Publish)
+where
+    T: FromStr + Default + Send + Sync + 'static,
+    T::Err: fmt::Display,
+{
+    let (watch, mut store) = Watch::new(T::default());
+
+    let path = match path {
+        Some(path) => path,
+        None => return (watch, Box::new(future::empty())),
+    };
+
+    // Generate one "change" immediately before starting to watch the file,
+    // so that it's loaded now if it exists.
+    let changes = stream::once(Ok(()))
+        .chain(stream_changes(Some(path.clone()), interval))
+        .filter_map(move |_| {
+            let loaded = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|s| s.parse::<T>().map_err(|e| e.to_string()));
+            match loaded {
+                Ok(value) => {
+                    debug!("loaded {:?}", path);
+                    Some(value)
+                }
+                Err(e) => {
+                    warn!("error loading {:?}: {}", path, e);
+                    None
+                }
+            }
+        });
+
+    let f = changes
+        .for_each(move |value| {
+            store.store(value)
+                .map(|_| ())
+                .map_err(|_| trace!("all file watchers dropped"))
+        })
+        .then(|_| {
+            error!("forwarding to file watch finished.");
+            Err(())
+        });
+
+    (watch, Box::new(f))
+}
+
+/// Passes each line of `s` that is not empty or a `#` comment to `parse`,
+/// trimmed.
+///
+/// If `parse` fails, the line's number is returned with the reason.
+pub fn parse_lines<F>(s: &str, mut parse: F) -> Result<(), LineError>
+where
+    F: FnMut(&str) -> Result<(), &'static str>,
+{
+    for (i, line) in s.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        parse(line).map_err(|reason| LineError { line: i + 1, reason })?;
+    }
+    Ok(())
+}
+
 /// Stream changes by polling the filesystem.
 ///
 /// This will calculate the SHA-384 hash of each of files at the paths
@@ -253,6 +334,14 @@ pub mod inotify {
 
 }
 
+// ===== impl LineError =====
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -792,4 +881,17 @@ mod tests {
     fn inotify_nonexistent_files_dont_file_delete_events() {
         Fixture::new().test_inotify(test_detects_delete_and_recreate)
     }
+
+    #[test]
+    fn parse_lines_skips_blank_lines_and_comments() {
+        let mut lines = Vec::new();
+        let s = "# a comment\n\n  first  \n\t# indented\nsecond\n";
+        assert_eq!(parse_lines(s, |l| { lines.push(l.to_owned()); Ok(()) }), Ok(()));
+        assert_eq!(lines, vec!["first", "second"]);
+
+        let err = parse_lines("ok\n\nbad\nok", |l| if l == "bad" { Err("is bad") } else { Ok(()) })
+            .unwrap_err();
+        assert_eq!(err, LineError { line: 3, reason: "is bad" });
+        assert_eq!(err.to_string(), "line 3: is bad");
+    }
 }
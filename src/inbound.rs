@@ -8,6 +8,10 @@ use tower_h2;
 
 use bind;
 use ctx;
+use proxy::authorize;
+use proxy::http::admission::{self, Admit};
+use proxy::http::authorize::Authorize;
+use proxy::http::client_id::ClientId;
 use proxy::http::router::Recognize;
 use proxy::http::orig_proto;
 
@@ -16,17 +20,27 @@ type Bind<B> = bind::Bind<ctx::Proxy, B>;
 pub struct Inbound<B> {
     default_addr: Option<SocketAddr>,
     bind: Bind<B>,
+    authorize: authorize::Authorize,
+    admission: admission::Watch,
+    max_in_flight: usize,
 }
 
-const MAX_IN_FLIGHT: usize = 10_000;
-
 // ===== impl Inbound =====
 
 impl<B> Inbound<B> {
-    pub fn new(default_addr: Option<SocketAddr>, bind: Bind<B>) -> Self {
+    pub fn new(
+        default_addr: Option<SocketAddr>,
+        bind: Bind<B>,
+        authorize: authorize::Authorize,
+        admission: admission::Watch,
+        max_in_flight: usize,
+    ) -> Self {
         Self {
             default_addr,
             bind,
+            authorize,
+            admission,
+            max_in_flight,
         }
     }
 }
@@ -39,6 +53,9 @@ where
         Self {
             bind: self.bind.clone(),
             default_addr: self.default_addr.clone(),
+            authorize: self.authorize.clone(),
+            admission: self.admission.clone(),
+            max_in_flight: self.max_in_flight,
         }
     }
 }
@@ -53,7 +70,9 @@ where
     type Response = <Self::Service as tower::Service>::Response;
     type Error = <Self::Service as tower::Service>::Error;
     type RouteError = bind::BufferSpawnError;
-    type Service = InFlightLimit<Buffer<orig_proto::Downgrade<bind::BoundService<B>>>>;
+    type Service = ClientId<Authorize<
+        Admit<InFlightLimit<Buffer<orig_proto::Downgrade<bind::BoundService<B>>>>>,
+    >>;
 
     fn recognize(&self, req: &Self::Request) -> Option<Self::Key> {
         let key = req.extensions()
@@ -74,6 +93,10 @@ where
 
     /// Builds a static service to a single endpoint.
     ///
+    /// Requests that are not authorized, or that exceed the limits configured
+    /// for admission control, are failed before they are buffered. Requests
+    /// are sent with the client's verified identity, if it has one.
+    ///
     /// # TODO
     ///
     /// Buffering does not apply timeouts.
@@ -87,9 +110,12 @@ where
 
         let log = ::logging::proxy().client("in", "local")
             .with_remote(*addr);
+        let rejections = self.bind.sensors().rejections(ctx::Proxy::Inbound);
         Buffer::new(from_orig_proto, &log.executor())
             .map(|buffer| {
-                InFlightLimit::new(buffer, MAX_IN_FLIGHT)
+                let limit = InFlightLimit::new(buffer, self.max_in_flight);
+                let admit = Admit::new(limit, self.admission.clone(), rejections);
+                ClientId::new(Authorize::new(admit, self.authorize.clone()))
             })
             .map_err(|_| bind::BufferSpawnError::Inbound)
     }
@@ -98,23 +124,36 @@ where
 #[cfg(test)]
 mod tests {
     use std::net;
+    use std::time::Duration;
 
     use http;
     use proxy::http::router::Recognize;
 
     use super::Inbound;
     use bind::{self, Bind, Host};
+    use indexmap::IndexSet;
+    use proxy::authorize;
+    use proxy::http::admission;
     use ctx;
     use conditional::Conditional;
     use tls;
 
     fn new_inbound(default: Option<net::SocketAddr>, ctx: ctx::Proxy) -> Inbound<()> {
+        let backoff = ::svc::reconnect::Backoff {
+            base: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+        };
         let bind = Bind::new(
             ::telemetry::Sensors::for_test(),
             ::transport::metrics::Registry::default(),
+            backoff,
+            ::telemetry::reconnect::Registry::default(),
             tls::ClientConfig::no_tls()
         );
-        Inbound::new(default, bind.with_ctx(ctx))
+        let (policy, _) = ::fs_watch::watch_file(None, Duration::from_secs(1));
+        let authorize = authorize::Authorize::new(IndexSet::new(), policy);
+        let (admission, _) = ::fs_watch::watch_file(None, Duration::from_secs(1));
+        Inbound::new(default, bind.with_ctx(ctx), authorize, admission, 10_000)
     }
 
     fn make_key_http1(addr: net::SocketAddr) -> (net::SocketAddr, bind::Protocol) {
@@ -140,7 +179,7 @@ mod tests {
             let inbound = new_inbound(None, ctx);
 
             let srv_ctx = ctx::transport::Server::new(
-                ctx, &local, &remote, &Some(orig_dst), TLS_DISABLED);
+                ctx, &local, &remote, &Some(orig_dst), TLS_DISABLED, None);
 
             let rec = srv_ctx.orig_dst_if_not_local().map(make_key_http1);
 
@@ -168,6 +207,7 @@ mod tests {
                     &remote,
                     &None,
                     TLS_DISABLED,
+                    None,
                 ));
 
             inbound.recognize(&req) == default.map(make_key_http1)
@@ -200,6 +240,7 @@ mod tests {
                     &remote,
                     &Some(local),
                     TLS_DISABLED,
+                    None,
                 ));
 
             inbound.recognize(&req) == default.map(make_key_http1)
@@ -4,6 +4,7 @@
 
 extern crate bytes;
 extern crate env_logger;
+extern crate fnv;
 #[macro_use]
 extern crate futures;
 extern crate futures_mpsc_lossy;
@@ -31,6 +32,7 @@ extern crate quickcheck;
 extern crate rand;
 extern crate regex;
 extern crate ring;
+extern crate serde_yaml;
 #[cfg(test)]
 extern crate tempdir;
 extern crate tokio;
@@ -60,6 +62,7 @@ use futures::*;
 use std::error::Error;
 use std::io;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, SystemTime};
 
@@ -148,24 +151,22 @@ where
 
         let tls_config_watch = tls::ConfigWatch::new(config.tls_settings.clone());
 
-        // TODO: Serve over TLS.
-        let control_listener = BoundPort::new(
-            config.control_listener.addr,
-            Conditional::None(tls::ReasonForNoIdentity::NotImplementedForTap.into()))
+        // The inbound, tap, and metrics listeners all accept TLS for the
+        // pod's identity.
+        let server_tls = config.tls_settings.as_ref().and_then(|settings| {
+            tls_config_watch.server.as_ref().map(|tls_server_config| {
+                tls::ConnectionConfig {
+                    server_identity: settings.pod_identity.clone(),
+                    config: tls_server_config.clone(),
+                }
+            })
+        });
+
+        let control_listener = BoundPort::new(config.control_listener.addr, server_tls.clone())
             .expect("controller listener bind");
 
-        let inbound_listener = {
-            let tls = config.tls_settings.as_ref().and_then(|settings| {
-                tls_config_watch.server.as_ref().map(|tls_server_config| {
-                    tls::ConnectionConfig {
-                        server_identity: settings.pod_identity.clone(),
-                        config: tls_server_config.clone(),
-                    }
-                })
-            });
-            BoundPort::new(config.inbound_listener.addr, tls)
-                .expect("public listener bind")
-        };
+        let inbound_listener = BoundPort::new(config.inbound_listener.addr, server_tls.clone())
+            .expect("public listener bind");
 
         let outbound_listener = BoundPort::new(
             config.outbound_listener.addr,
@@ -174,10 +175,7 @@ where
 
         let runtime = runtime.into();
 
-        // TODO: Serve over TLS.
-        let metrics_listener = BoundPort::new(
-            config.metrics_listener.addr,
-            Conditional::None(tls::ReasonForNoIdentity::NotImplementedForMetrics.into()))
+        let metrics_listener = BoundPort::new(config.metrics_listener.addr, server_tls)
             .expect("metrics listener bind");
 
         Main {
@@ -247,23 +245,46 @@ where
             "protocol detection disabled for outbound ports {:?}",
             config.outbound_ports_disable_protocol_detection,
         );
+        if !config.inbound_ports_require_identity.is_empty() {
+            info!(
+                "identity required for inbound ports {:?}",
+                config.inbound_ports_require_identity,
+            );
+        }
+
+        if let Some(ref identity) = config.tap_client_identity {
+            info!("tap clients must authenticate as {}", identity);
+        }
 
         let (taps, observe) = control::Observe::new(100);
         let (http_sensors, http_report) = telemetry::http::new(config.metrics_retain_idle, &taps);
 
         let (transport_registry, transport_report) = transport::metrics::new();
 
+        let (reconnect_registry, reconnect_report) = telemetry::reconnect::new();
+
         let (tls_config_sensor, tls_config_report) = telemetry::tls_config_reload::new();
 
+        let (dns_registry, dns_report) = telemetry::dns::new();
+
+        let (resolution_registry, resolution_report) = telemetry::resolution::new();
+
         let report = telemetry::Report::new(
             http_report,
             transport_report,
+            reconnect_report,
             tls_config_report,
+            dns_report,
+            resolution_report,
             telemetry::process::Report::new(start_time),
        );
 
         let tls_client_config = tls_config_watch.client.clone();
-        let tls_cfg_bg = tls_config_watch.start(tls_config_sensor);
+        let tls_session_cache = tls::SessionCache::new(
+            config.outbound_tls_session_cache_capacity,
+            transport_registry.tls_session_cache(),
+        );
+        let tls_cfg_bg = tls_config_watch.start(tls_config_sensor, tls_session_cache);
 
         let controller_tls = config.tls_settings.as_ref().and_then(|settings| {
             settings.controller_identity.as_ref().map(|controller_identity| {
@@ -274,12 +295,21 @@ where
             })
         });
 
-        let (dns_resolver, dns_bg) = dns::Resolver::from_system_config_and_env(&config)
+        let (dns_resolver, dns_bg) = dns::Resolver::from_system_config_and_env(&config, dns_registry.clone())
             .unwrap_or_else(|e| {
                 // TODO: Make DNS configuration infallible.
                 panic!("invalid DNS configuration: {:?}", e);
             });
 
+        let (static_endpoints, static_endpoints_bg) = fs_watch::watch_file::<control::destination::static_endpoints::StaticEndpoints>(
+            config.destination_static_endpoints_path.clone(),
+            Duration::from_secs(1),
+        );
+
+        // Routes described by the Destination service are resolved for as
+        // long as the outbound router would retain them.
+        let routes = control::destination::Routes::new(config.outbound_router_max_idle_age);
+
         let (resolver, resolver_bg) = control::destination::new(
             dns_resolver.clone(),
             config.namespaces.clone(),
@@ -287,13 +317,76 @@ where
             controller_tls,
             config.control_backoff_delay,
             config.destination_concurrency_limit,
+            static_endpoints,
+            routes.clone(),
+            control::destination::DnsConfig {
+                nxdomain_grace_period: config.dns_nxdomain_grace_period,
+                max_staleness: config.dns_max_staleness,
+                error_backoff: svc::reconnect::Backoff {
+                    base: config.dns_error_backoff_base,
+                    max: config.dns_error_backoff_max,
+                },
+            },
+            dns_registry,
+            control::destination::EndpointLimits {
+                per_resolution: config.destination_max_endpoints_per_resolution,
+                total: config.destination_max_endpoints,
+                // Endpoints are subset by the pod's identity. Without one, a
+                // random key is used, so that the pods' proxies still use
+                // different subsets of endpoints.
+                subset_key: match config.tls_settings {
+                    Conditional::Some(ref settings) => settings.pod_identity.to_string(),
+                    Conditional::None(_) => format!("{:016x}", rand::random::<u64>()),
+                },
+            },
+            resolution_registry,
+        );
+
+        let (splits, splits_bg) = fs_watch::watch_file::<control::split::Splits>(
+            config.traffic_split_path.clone(),
+            Duration::from_secs(1),
+        );
+
+        let (algorithms, algorithms_bg) = fs_watch::watch_file::<control::algorithm::Algorithms>(
+            config.balance_algorithms_path.clone(),
+            Duration::from_secs(1),
+        );
+
+        let (admission, admission_bg) = fs_watch::watch_file::<proxy::http::admission::Rules>(
+            config.inbound_admission_path.clone(),
+            Duration::from_secs(1),
+        );
+
+        let (authorization, authorization_bg) = fs_watch::watch_file::<proxy::authorize::Policy>(
+            config.inbound_authorization_path.clone(),
+            Duration::from_secs(1),
+        );
+        let authorize = proxy::authorize::Authorize::new(
+            config.inbound_ports_require_identity.clone(),
+            authorization,
         );
 
+        let prefer_local = if config.locality_region.is_some() || config.locality_zone.is_some() {
+            Some(proxy::http::balance::PreferLocal {
+                region: config.locality_region.clone(),
+                zone: config.locality_zone.clone(),
+                min_ready: config.locality_min_ready,
+            })
+        } else {
+            None
+        };
+
         let (drain_tx, drain_rx) = drain::channel();
 
+        let reconnect_backoff = svc::reconnect::Backoff {
+            base: config.reconnect_backoff_base,
+            max: config.reconnect_backoff_max,
+        };
         let bind = Bind::new(
             http_sensors.clone(),
             transport_registry.clone(),
+            reconnect_backoff,
+            reconnect_registry,
             tls_client_config
         );
 
@@ -306,7 +399,13 @@ where
             let default_addr = config.inbound_forward.map(|a| a.into());
 
             let router = Router::new(
-                Inbound::new(default_addr, bind),
+                Inbound::new(
+                    default_addr,
+                    bind,
+                    authorize.clone(),
+                    admission,
+                    config.inbound_max_in_flight,
+                ),
                 config.inbound_router_capacity,
                 config.inbound_router_max_idle_age,
             );
@@ -315,6 +414,7 @@ where
                 router,
                 config.inbound_connect_timeout,
                 config.inbound_ports_disable_protocol_detection,
+                Some(authorize),
                 ctx,
                 transport_registry.clone(),
                 get_original_dst.clone(),
@@ -329,7 +429,18 @@ where
             let ctx = ctx::Proxy::Outbound;
             let bind = bind.clone().with_ctx(ctx);
             let router = Router::new(
-                Outbound::new(bind, resolver, config.bind_timeout),
+                Outbound::new(
+                    bind,
+                    resolver,
+                    routes,
+                    splits,
+                    algorithms,
+                    prefer_local,
+                    config.slow_start_window,
+                    Arc::new(config.hedge_authorities.clone()),
+                    config.hedge_percentile,
+                    config.bind_timeout,
+                ),
                 config.outbound_router_capacity,
                 config.outbound_router_max_idle_age,
             );
@@ -338,6 +449,7 @@ where
                 router,
                 config.outbound_connect_timeout,
                 config.outbound_ports_disable_protocol_detection,
+                None,
                 ctx,
                 transport_registry,
                 get_original_dst,
@@ -347,6 +459,7 @@ where
 
         trace!("running");
 
+        let tap_client_identity = config.tap_client_identity.clone();
         let (_tx, admin_shutdown_signal) = futures::sync::oneshot::channel::<()>();
         {
             thread::Builder::new()
@@ -357,7 +470,11 @@ where
                     let mut rt = current_thread::Runtime::new()
                         .expect("initialize admin thread runtime");
 
-                    let tap = serve_tap(control_listener, TapServer::new(observe));
+                    let tap = serve_tap(
+                        control_listener,
+                        TapServer::new(observe),
+                        tap_client_identity,
+                    );
 
                     let metrics = control::serve_http(
                         "metrics",
@@ -374,6 +491,16 @@ where
 
                     rt.spawn(::logging::admin().bg("tls-config").future(tls_cfg_bg));
 
+                    rt.spawn(::logging::admin().bg("static-endpoints").future(static_endpoints_bg));
+
+                    rt.spawn(::logging::admin().bg("traffic-split").future(splits_bg));
+
+                    rt.spawn(::logging::admin().bg("balance-algorithms").future(algorithms_bg));
+
+                    rt.spawn(::logging::admin().bg("inbound-admission").future(admission_bg));
+
+                    rt.spawn(::logging::admin().bg("inbound-authorization").future(authorization_bg));
+
                     let shutdown = admin_shutdown_signal.then(|_| Ok::<(), ()>(()));
                     rt.block_on(shutdown).expect("admin");
                     trace!("admin shutdown finished");
@@ -404,6 +531,7 @@ fn serve<R, B, E, F, G>(
     router: Router<R>,
     tcp_connect_timeout: Duration,
     disable_protocol_detection_ports: IndexSet<u16>,
+    authorize: Option<proxy::authorize::Authorize>,
     proxy_ctx: ctx::Proxy,
     transport_registry: transport::metrics::Registry,
     get_orig_dst: G,
@@ -448,6 +576,7 @@ where
         stack,
         tcp_connect_timeout,
         disable_protocol_detection_ports,
+        authorize,
         drain_rx.clone(),
         h2::server::Builder::default(),
     );
@@ -505,9 +634,14 @@ where
     }
 }
 
+/// Serves the tap API.
+///
+/// If `client_identity` is set, connections are closed unless the client
+/// authenticated with a certificate for that identity.
 fn serve_tap<N, B>(
     bound_port: BoundPort,
     new_service: N,
+    client_identity: Option<tls::Identity>,
 ) -> impl Future<Item = (), Error = ()> + 'static
 where
     B: tower_h2::Body + Send + 'static,
@@ -535,10 +669,18 @@ where
     );
     let fut = {
         let log = log.clone();
-        // TODO: serve over TLS.
         bound_port.listen_and_fold(
             server,
             move |server, (session, remote)| {
+                if let Some(ref identity) = client_identity {
+                    let authorized = session.peer_certificate()
+                        .map_or(false, |cert| cert.is_valid_for(identity));
+                    if !authorized {
+                        debug!("closing unauthorized tap connection from {}", remote);
+                        return future::ok(server);
+                    }
+                }
+
                 let log = log.clone().with_remote(remote);
                 let serve = server.serve(session).map_err(|_| ());
 
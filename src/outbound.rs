@@ -5,36 +5,83 @@ use std::sync::Arc;
 
 use http;
 use futures::{Async, Poll};
+use indexmap::IndexSet;
+use rand;
 use tower_service as tower;
-use tower_balance::{choose, load, Balance};
 use tower_buffer::Buffer;
 use tower_discover::{Change, Discover};
-use tower_in_flight_limit::InFlightLimit;
 use tower_h2;
-use tower_h2_balance::{PendingUntilFirstData, PendingUntilFirstDataBody};
+use tower_h2_balance::PendingUntilFirstDataBody;
 
 use bind::{self, Bind, Protocol};
-use control::destination::{self, Resolution};
+use control::destination::{self, Endpoint, Resolution};
+use control::{algorithm, split};
 use ctx;
 use proxy::{self, http::h1};
+use proxy::http::balance::{self, Balancer, MakeAttributed, PreferLocal, WeightedPowerOfTwoChoices};
+use proxy::http::deadline;
+use proxy::http::hedge;
+use proxy::http::limit;
+use proxy::http::outlier;
+use proxy::http::retry::{Budget, ReplayBody, Retry};
 use proxy::http::router::Recognize;
-use svc::MakeClient;
+use svc::{Layer, MakeClient};
+use telemetry::http::RouteLabels;
 use telemetry::http::service::{ResponseBody as SensorBody};
 use timeout::Timeout;
 use transport::{DnsNameAndPort, Host, HostAndPort};
 
-type BindProtocol<B> = bind::BindProtocol<ctx::Proxy, B>;
+type BindProtocol<B> = MakeAttributed<LabelBackend<
+    outlier::Make<bind::BindProtocol<ctx::Proxy, ReplayBody<B>>>
+>>;
 
 pub struct Outbound<B> {
-    bind: Bind<ctx::Proxy, B>,
+    bind: Bind<ctx::Proxy, ReplayBody<B>>,
     discovery: destination::Resolver,
+    routes: destination::Routes,
+    splits: split::Watch,
+    algorithms: algorithm::Watch,
+    prefer_local: Option<PreferLocal>,
+    slow_start: Option<Duration>,
+    hedge_authorities: Arc<IndexSet<DnsNameAndPort>>,
+    hedge_percentile: f32,
     bind_timeout: Duration,
 }
 
-const MAX_IN_FLIGHT: usize = 10_000;
-
-/// This default is used by Finagle.
-const DEFAULT_DECAY: Duration = Duration::from_secs(10);
+/// The number of requests in flight to each destination adapts to its
+/// latency, within these bounds. The limit starts at its maximum, so that
+/// requests are only shed once the destination's latency indicates that it
+/// is overloaded.
+const CONCURRENCY_LIMIT: limit::Config = limit::Config {
+    initial: 10_000,
+    min: 10,
+    max: 10_000,
+};
+
+/// Retries may not exceed 20% of requests over the budget's TTL...
+const RETRY_RATIO: f32 = 0.2;
+
+/// ...except that 10 retries per second are always permitted.
+const MIN_RETRIES_PER_SEC: u32 = 10;
+
+const RETRY_BUDGET_TTL: Duration = Duration::from_secs(10);
+
+/// Requests with larger bodies are not retried.
+const MAX_RETRY_BODY_BYTES: usize = 64 * 1024;
+
+/// Endpoints are ejected from the balancer after consecutive failures. Each
+/// failed re-admission probe doubles the ejection, up to a maximum. No more
+/// than half of a balancer's endpoints are ejected at once.
+const OUTLIER_EJECTION: outlier::Config = outlier::Config {
+    consecutive_failures: 5,
+    base_ejection: Duration::from_secs(5),
+    max_ejection: Duration::from_secs(5 * 60),
+    max_ejected_ratio: 0.5,
+};
+
+/// Endpoints of a backing authority in a traffic split are labeled with the
+/// authority.
+const BACKEND_LABEL: &str = "backend";
 
 /// Describes a destination for HTTP requests.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -42,6 +89,13 @@ pub enum Destination {
     /// A logical, lazily-bound endpoint.
     Name(DnsNameAndPort),
 
+    /// One of the backing authorities chosen for a logical name that is
+    /// split by weight.
+    Split {
+        logical: DnsNameAndPort,
+        backend: DnsNameAndPort,
+    },
+
     /// A single, bound endpoint.
     Addr(SocketAddr),
 }
@@ -51,11 +105,25 @@ pub enum Destination {
 impl<B> Outbound<B> {
     pub fn new(bind: Bind<ctx::Proxy, B>,
                discovery: destination::Resolver,
+               routes: destination::Routes,
+               splits: split::Watch,
+               algorithms: algorithm::Watch,
+               prefer_local: Option<PreferLocal>,
+               slow_start: Option<Duration>,
+               hedge_authorities: Arc<IndexSet<DnsNameAndPort>>,
+               hedge_percentile: f32,
                bind_timeout: Duration)
                -> Outbound<B> {
         Self {
-            bind,
+            bind: bind.with_body(),
             discovery,
+            routes,
+            splits,
+            algorithms,
+            prefer_local,
+            slow_start,
+            hedge_authorities,
+            hedge_percentile,
             bind_timeout,
         }
     }
@@ -118,6 +186,75 @@ impl<B> Outbound<B> {
             }
         }
     }
+
+    /// Returns the load balancing algorithm configured for a destination by
+    /// the algorithms file or, otherwise, by the Destination service.
+    ///
+    /// A backing authority of a split uses its own algorithm, if it has one,
+    /// and otherwise the logical authority's.
+    fn algorithm(&self, dest: &Destination) -> algorithm::Algorithm {
+        let algorithms = self.algorithms.borrow();
+        let configured = |authority: &DnsNameAndPort| {
+            algorithms.get(authority)
+                .cloned()
+                .or_else(|| self.routes.algorithm(authority))
+        };
+        let algorithm = match *dest {
+            Destination::Name(ref authority) => configured(authority),
+            Destination::Split { ref logical, ref backend } => {
+                configured(backend).or_else(|| configured(logical))
+            }
+            Destination::Addr(_) => None,
+        };
+        algorithm.unwrap_or_default()
+    }
+
+    /// Returns the policy for hedging requests to `dest`, if its logical
+    /// authority has opted in.
+    fn hedge_policy(&self, dest: &Destination) -> Option<hedge::Policy> {
+        let logical = match *dest {
+            Destination::Name(ref authority) => authority,
+            Destination::Split { ref logical, .. } => logical,
+            Destination::Addr(_) => return None,
+        };
+        if !self.hedge_authorities.contains(logical) {
+            return None;
+        }
+
+        let hedges = self.bind.sensors().hedges(Self::route_labels(dest));
+        Some(hedge::Policy::new(f64::from(self.hedge_percentile), hedges))
+    }
+
+    /// Chooses a backing authority for a logical name, if its traffic is split
+    /// by the split file or, otherwise, by the Destination service.
+    fn split(&self, logical: DnsNameAndPort) -> Destination {
+        let backend = {
+            let splits = self.splits.borrow();
+            match splits.get(&logical) {
+                Some(split) => Some(split.choose(&mut rand::thread_rng()).clone()),
+                None => self.routes.choose_backend(&logical, &mut rand::thread_rng()),
+            }
+        };
+        match backend {
+            Some(backend) => {
+                trace!("split {:?} to {:?}", logical, backend);
+                Destination::Split { logical, backend }
+            }
+            None => Destination::Name(logical),
+        }
+    }
+
+    /// Labels the metrics of a route with its logical authority and, if it is
+    /// split, with its backing authority.
+    fn route_labels(dest: &Destination) -> RouteLabels {
+        let labels = RouteLabels::new(ctx::Proxy::Outbound, Dst(dest.clone()));
+        match *dest {
+            Destination::Split { ref backend, .. } => {
+                labels.with_backend(format!("{}:{}", backend.host, backend.port))
+            }
+            _ => labels,
+        }
+    }
 }
 
 impl<B> Clone for Outbound<B>
@@ -129,6 +266,13 @@ where
         Self {
             bind: self.bind.clone(),
             discovery: self.discovery.clone(),
+            routes: self.routes.clone(),
+            splits: self.splits.clone(),
+            algorithms: self.algorithms.clone(),
+            prefer_local: self.prefer_local.clone(),
+            slow_start: self.slow_start,
+            hedge_authorities: self.hedge_authorities.clone(),
+            hedge_percentile: self.hedge_percentile,
             bind_timeout: self.bind_timeout.clone(),
         }
     }
@@ -141,49 +285,90 @@ where
 {
     type Request = http::Request<B>;
     type Response = http::Response<PendingUntilFirstDataBody<
-        load::peak_ewma::Handle,
+        balance::Handle,
         SensorBody<proxy::http::Body>,
     >>;
     type Error = <Self::Service as tower::Service>::Error;
-    type Key = (Destination, Protocol);
+    type Key = (Destination, Protocol, algorithm::Algorithm);
     type RouteError = bind::BufferSpawnError;
-    type Service = InFlightLimit<Timeout<Buffer<Balance<
-        load::WithPeakEwma<Discovery<B>, PendingUntilFirstData>,
-        choose::PowerOfTwoChoices,
+    type Service = limit::Adapt<deadline::Enforce<Retry<Timeout<
+        Buffer<Balancer<Discovery<B>>>,
+        deadline::Remaining,
     >>>>;
 
     // Route the request by its destination AND PROTOCOL. This prevents HTTP/1
     // requests from being routed to HTTP/2 servers, and vice versa.
+    //
+    // If the destination's traffic is split, each request is routed to one of
+    // the backing authorities' balancers.
+    //
+    // Requests are also routed by the destination's balancing algorithm, so
+    // that when it is reconfigured, requests are sent to a new balancer, and
+    // the previous balancer is evicted once it is idle.
     fn recognize(&self, req: &Self::Request) -> Option<Self::Key> {
-        let dest = Self::destination(req)?;
+        let dest = match Self::destination(req)? {
+            Destination::Name(logical) => self.split(logical),
+            dest => dest,
+        };
         let proto = bind::Protocol::detect(req);
-        Some((dest, proto))
+        let algorithm = self.algorithm(&dest);
+        Some((dest, proto, algorithm))
     }
 
     /// Builds a dynamic, load balancing service.
     ///
     /// Resolves the authority in service discovery and initializes a service that buffers
-    /// and load balances requests across.
+    /// and load balances requests across its endpoints, using the algorithm configured for
+    /// the authority. By default, requests favor endpoints in proportion to the weights
+    /// assigned by service discovery and, if the proxy's locality is configured,
+    /// endpoints in the same locality, and newly discovered endpoints are given a slow
+    /// start.
+    ///
+    /// Failed requests are retried within a budget that is shared by all requests to the
+    /// destination. If the destination has opted into hedging, slow requests are hedged
+    /// within a budget of their own.
+    ///
+    /// Requests that carry a `grpc-timeout` or `l5d-timeout` header fail if they are
+    /// not completed before that deadline, including any retries.
+    ///
+    /// The number of requests in flight to the destination is limited by its latency;
+    /// requests that exceed the limit fail immediately. The limit starts at its maximum
+    /// and is decreased once the destination is slow.
     fn bind_service(
         &self,
         key: &Self::Key,
     ) -> Result<Self::Service, Self::RouteError> {
-        let &(ref dest, ref protocol) = key;
+        let &(ref dest, ref protocol, ref algorithm) = key;
         debug!("building outbound {:?} client to {:?}", protocol, dest);
 
         let resolve = {
-            let proto = self.bind.clone().with_protocol(protocol.clone());
+            let eject = outlier::Layer::new(
+                OUTLIER_EJECTION,
+                ctx::Proxy::Outbound,
+                self.bind.sensors().clone(),
+            );
+            let proto = eject.bind(self.bind.clone().with_protocol(protocol.clone()));
+            let label = match *dest {
+                Destination::Split { ref backend, .. } => {
+                    Some(format!("{}:{}", backend.host, backend.port))
+                }
+                _ => None,
+            };
+            let proto = LabelBackend { inner: proto, backend: label };
+            let proto = MakeAttributed::new(proto, self.prefer_local.clone());
             match *dest {
-                Destination::Name(ref authority) =>
-                    Discovery::Name(self.discovery.resolve(authority, proto)),
+                Destination::Name(ref authority) |
+                Destination::Split { backend: ref authority, .. } => {
+                    Discovery::Name(self.discovery.resolve(authority, proto))
+                }
                 Destination::Addr(addr) => Discovery::Addr(Some((addr, proto))),
             }
         };
 
         let balance = {
-            let instrument = PendingUntilFirstData::default();
-            let loaded = load::WithPeakEwma::new(resolve, DEFAULT_DECAY, instrument);
-            Balance::p2c(loaded)
+            debug!("balancing {:?} with {:?}", dest, algorithm);
+            let choose = WeightedPowerOfTwoChoices::new(self.prefer_local.as_ref());
+            Balancer::new(resolve, algorithm, choose, self.slow_start)
         };
 
         let log = ::logging::proxy().client("out", Dst(dest.clone()))
@@ -191,9 +376,23 @@ where
         let buffer = Buffer::new(balance, &log.executor())
             .map_err(|_| bind::BufferSpawnError::Outbound)?;
 
-        let timeout = Timeout::new(buffer, self.bind_timeout);
+        let timeout = Timeout::new(buffer, self.bind_timeout)
+            .with_deadline(deadline::Remaining);
+
+        let retry = {
+            let budget = Budget::new(RETRY_BUDGET_TTL, MIN_RETRIES_PER_SEC, RETRY_RATIO);
+            let retries = self.bind.sensors().retries(Self::route_labels(dest));
+            let retry = Retry::new(timeout, Arc::new(budget), retries, MAX_RETRY_BODY_BYTES);
+            match self.hedge_policy(dest) {
+                Some(policy) => retry.with_hedging(policy),
+                None => retry,
+            }
+        };
 
-        Ok(InFlightLimit::new(timeout, MAX_IN_FLIGHT))
+        let deadline = deadline::Enforce::new(retry);
+
+        let limits = self.bind.sensors().limits(Self::route_labels(dest));
+        Ok(limit::Adapt::new(deadline, CONCURRENCY_LIMIT, limits))
     }
 }
 
@@ -211,7 +410,7 @@ where
     type Request = <Self::Service as tower::Service>::Request;
     type Response = <Self::Service as tower::Service>::Response;
     type Error = <Self::Service as tower::Service>::Error;
-    type Service = bind::BoundService<B>;
+    type Service = balance::Attributed<outlier::Eject<bind::BoundService<ReplayBody<B>>>>;
     type DiscoverError = BindError;
 
     fn poll(&mut self) -> Poll<Change<Self::Key, Self::Service>, Self::DiscoverError> {
@@ -220,10 +419,9 @@ where
                 .map_err(|_| BindError::Internal),
             Discovery::Addr(ref mut opt) => {
                 // This "discovers" a single address for an external service
-                // that never has another change. This can mean it floats
-                // in the Balancer forever. However, when we finally add
-                // circuit-breaking, this should be able to take care of itself,
-                // closing down when the connection is no longer usable.
+                // that never has another change. This means it floats in the
+                // Balancer forever; however, if it fails repeatedly, it is
+                // ejected (i.e. not ready) until a probe request succeeds.
                 if let Some((addr, mut bind)) = opt.take() {
                     let svc = bind.make_client(&addr.into())
                         .map_err(|_| BindError::External { addr })?;
@@ -236,6 +434,33 @@ where
     }
 }
 
+/// Labels the endpoints of a backing authority in a traffic split, so that
+/// their metrics describe the backend that was chosen.
+#[derive(Clone, Debug)]
+pub struct LabelBackend<M> {
+    inner: M,
+    backend: Option<String>,
+}
+
+impl<M> MakeClient<Endpoint> for LabelBackend<M>
+where
+    M: MakeClient<Endpoint>,
+{
+    type Client = M::Client;
+    type Error = M::Error;
+
+    fn make_client(&self, ep: &Endpoint) -> Result<Self::Client, Self::Error> {
+        match self.backend {
+            None => self.inner.make_client(ep),
+            Some(ref backend) => {
+                let mut ep = ep.clone();
+                ep.add_label(BACKEND_LABEL, backend.clone());
+                self.inner.make_client(&ep)
+            }
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum BindError {
     External { addr: SocketAddr },
@@ -265,12 +490,14 @@ impl error::Error for BindError {
     fn cause(&self) -> Option<&error::Error> { None }
 }
 
+/// Displays a destination by its logical authority.
 struct Dst(Destination);
 
 impl fmt::Display for Dst {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.0 {
-            Destination::Name(ref name) => {
+            Destination::Name(ref name) |
+            Destination::Split { logical: ref name, .. } => {
                 write!(f, "{}:{}", name.host, name.port)
             }
             Destination::Addr(ref addr) => addr.fmt(f),
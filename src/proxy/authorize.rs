@@ -0,0 +1,246 @@
+//! Authorizes inbound connections and requests by their clients' TLS
+//! identities.
+//!
+//! Ports may be configured to require identity, so that connections to them
+//! are closed unless the client authenticated with a certificate issued by
+//! one of our trust anchors.
+//!
+//! Connections and requests are also authorized by a policy read from a local
+//! file, which is watched for changes. Each line that is not empty or a `#`
+//! comment allows or denies the connections and requests that match all of
+//! its conditions:
+//!
+//! ```text
+//! # Only the admin may use the admin API.
+//! allow port=8080 path=/admin/ identity=admin.deployment.ns1.linkerd-managed.linkerd.svc.cluster.local
+//! deny port=8080 path=/admin/
+//! # Only authenticated clients may connect to port 9090.
+//! allow port=9090 identity=*
+//! deny port=9090
+//! ```
+//!
+//! `port` matches the port to which the client connected, `path` matches HTTP
+//! requests by a prefix of their path, and `identity` matches clients with a
+//! certificate that is valid for the identity (a DNS name or SPIFFE ID) or, if
+//! it is `*`, any authenticated client. The first rule that matches applies,
+//! and connections and requests that match no rule are allowed.
+//!
+//! Rules with a `path` only match HTTP requests. Connections that are not
+//! HTTP are closed if they are denied. Denied HTTP requests are failed by
+//! `http::authorize`.
+
+use futures_watch;
+use indexmap::IndexSet;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use ctx::transport::Server as ServerCtx;
+use fs_watch::{parse_lines, LineError};
+use transport::tls;
+
+/// Watches the current `Policy`.
+pub type Watch = futures_watch::Watch<Policy>;
+
+/// Allows or denies connections and requests by the first of an ordered list
+/// of rules that they match.
+#[derive(Debug, Default)]
+pub struct Policy(Vec<Rule>);
+
+/// Authorizes the connections and requests accepted by a listener.
+#[derive(Clone, Debug)]
+pub struct Authorize {
+    require_identity_ports: Arc<IndexSet<u16>>,
+    policy: Watch,
+}
+
+#[derive(Debug)]
+struct Rule {
+    action: Action,
+    port: Option<u16>,
+    path_prefix: Option<String>,
+    client: Option<Client>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Action {
+    Allow,
+    Deny,
+}
+
+/// Describes the clients to which a rule applies.
+#[derive(Debug)]
+enum Client {
+    Authenticated,
+    Identity(tls::Identity),
+}
+
+// ===== impl Authorize =====
+
+impl Authorize {
+    pub fn new(require_identity_ports: IndexSet<u16>, policy: Watch) -> Self {
+        Self {
+            require_identity_ports: Arc::new(require_identity_ports),
+            policy,
+        }
+    }
+
+    /// Returns true if a connection may be served.
+    ///
+    /// Connections to ports that require identity must be authenticated.
+    pub fn allows_connection(&self, ctx: &ServerCtx) -> bool {
+        ctx.peer_certificate.is_some() || !self.require_identity_ports.contains(&ctx.port())
+    }
+
+    /// Returns true if the policy allows an HTTP request for `path` or, if no
+    /// path is given, a connection that is not HTTP.
+    pub fn allows(&self, ctx: &ServerCtx, path: Option<&str>) -> bool {
+        self.policy
+            .borrow()
+            .allows(ctx.port(), path, ctx.peer_certificate.as_ref())
+    }
+}
+
+// ===== impl Policy =====
+
+impl Policy {
+    fn allows(&self, port: u16, path: Option<&str>, peer: Option<&tls::PeerCertificate>) -> bool {
+        self.0.iter()
+            .find(|rule| rule.matches(port, path, peer))
+            .map_or(true, |rule| rule.action == Action::Allow)
+    }
+}
+
+impl FromStr for Policy {
+    type Err = LineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Vec::new();
+
+        parse_lines(s, |line| {
+            let mut words = line.split_whitespace();
+            let action = match words.next() {
+                Some("allow") => Action::Allow,
+                Some("deny") => Action::Deny,
+                _ => return Err("unknown action"),
+            };
+
+            let mut port = None;
+            let mut path_prefix = None;
+            let mut client = None;
+            for word in words {
+                let mut parts = word.splitn(2, '=');
+                match (parts.next().unwrap_or(""), parts.next()) {
+                    ("port", Some(p)) if port.is_none() => {
+                        let p = p.parse::<u16>().map_err(|_| "invalid port")?;
+                        port = Some(p);
+                    }
+                    ("path", Some(p)) if path_prefix.is_none() => {
+                        if !p.starts_with('/') {
+                            return Err("invalid path prefix");
+                        }
+                        path_prefix = Some(p.to_owned());
+                    }
+                    ("identity", Some("*")) if client.is_none() => {
+                        client = Some(Client::Authenticated);
+                    }
+                    ("identity", Some(id)) if client.is_none() => {
+                        let id = tls::Identity::parse(id)
+                            .map_err(|()| "invalid identity")?;
+                        client = Some(Client::Identity(id));
+                    }
+                    _ => return Err("invalid condition"),
+                }
+            }
+
+            rules.push(Rule {
+                action,
+                port,
+                path_prefix,
+                client,
+            });
+            Ok(())
+        })?;
+
+        Ok(Policy(rules))
+    }
+}
+
+// ===== impl Rule =====
+
+impl Rule {
+    fn matches(&self, port: u16, path: Option<&str>, peer: Option<&tls::PeerCertificate>)
+        -> bool
+    {
+        if self.port.map_or(false, |p| p != port) {
+            return false;
+        }
+
+        if let Some(ref prefix) = self.path_prefix {
+            if !path.map_or(false, |p| p.starts_with(prefix.as_str())) {
+                return false;
+            }
+        }
+
+        match self.client {
+            None => true,
+            Some(Client::Authenticated) => peer.is_some(),
+            Some(Client::Identity(ref id)) => peer.map_or(false, |c| c.is_valid_for(id)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use transport::tls::config_test_util::*;
+
+    fn policy(s: &str) -> Policy {
+        s.parse().expect("policy must be valid")
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        let invalid = [
+            "permit port=80",
+            "allow port=http",
+            "allow port=80 port=81",
+            "allow path=admin",
+            "allow identity=foo.",
+            "allow user=foo",
+        ];
+        for rule in &invalid {
+            assert!(rule.parse::<Policy>().is_err(), "{:?} must be invalid", rule);
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_applies() {
+        let p = policy("
+            # the admin API is restricted
+            allow port=8080 path=/admin/ identity=foo.deployment.ns1.linkerd-managed.linkerd.svc.cluster.local
+            deny port=8080 path=/admin/
+
+            allow port=9090 identity=*
+            deny port=9090
+        ");
+        let foo = FOO_NS1.peer_certificate();
+        let bar = BAR_NS1.peer_certificate();
+
+        assert!(p.allows(8080, Some("/admin/users"), Some(&foo)));
+        assert!(!p.allows(8080, Some("/admin/users"), Some(&bar)));
+        assert!(!p.allows(8080, Some("/admin/users"), None));
+        assert!(p.allows(8080, Some("/users"), None));
+        // Connections that are not HTTP don't match rules with paths.
+        assert!(p.allows(8080, None, None));
+
+        assert!(p.allows(9090, None, Some(&bar)));
+        assert!(!p.allows(9090, None, None));
+        assert!(!p.allows(9090, Some("/"), None));
+    }
+
+    #[test]
+    fn allows_by_default() {
+        assert!(Policy::default().allows(8080, Some("/"), None));
+        assert!(policy("deny port=9090").allows(8080, None, None));
+    }
+}
@@ -0,0 +1,552 @@
+//! Admission control for inbound requests.
+//!
+//! Requests are limited by rules read from a local file, which is watched for
+//! changes. Each line that is not empty or a `#` comment matches requests by
+//! their source, their authority, or a prefix of their path, followed by the
+//! limits that apply to matching requests:
+//!
+//! ```text
+//! path /api/ concurrency=100 rate=50/s burst=100
+//! authority web.prod.svc.cluster.local:8080 rate=600/m
+//! source batch.deployment.jobs.linkerd-managed.linkerd.svc.cluster.local rate=10/s
+//! source 10.1.2.3 concurrency=5
+//! ```
+//!
+//! A source is either the TLS identity (a DNS name or SPIFFE ID) for which
+//! the client's certificate is valid, or, for clients that do not
+//! authenticate, the client's IP address.
+//!
+//! A request is limited by the first rule that matches it. `concurrency`
+//! limits the number of matching requests that are awaiting responses, and
+//! `rate` limits matching requests with a token bucket that holds up to
+//! `burst` tokens (by default, one second's worth, and at least one). If no
+//! port is specified for an authority, port 80 is assumed.
+//!
+//! Requests that exceed a limit are not queued: they are rejected immediately
+//! with a `429 Too Many Requests` or, for gRPC requests, a
+//! `RESOURCE_EXHAUSTED` status. A rule's limits are shared by all of the
+//! routes it matches, and are reset when the file changes.
+
+use futures::{Async, Future, Poll};
+use futures_watch;
+use http;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use tower_service::Service;
+
+use ctx;
+use fs_watch::{parse_lines, LineError};
+use telemetry::http::{RejectReason, Rejections};
+use transport::tls;
+use super::grpc;
+use super::h1;
+
+const DEFAULT_PORT: u16 = 80;
+
+/// Watches the current `Rules`.
+pub type Watch = futures_watch::Watch<Rules>;
+
+/// Limits the requests that match each of an ordered list of rules.
+#[derive(Debug, Default)]
+pub struct Rules(Vec<Rule>);
+
+/// Rejects requests that exceed the limits of the first rule they match.
+#[derive(Clone, Debug)]
+pub struct Admit<S> {
+    inner: S,
+    rules: Watch,
+    rejections: Rejections,
+}
+
+pub enum ResponseFuture<F> {
+    Admitted {
+        inner: F,
+        /// Held until the response is received.
+        permit: Option<Permit>,
+    },
+    Rejected {
+        is_grpc: bool,
+    },
+}
+
+/// Counts a request against a rule's concurrency limit until it is dropped.
+#[derive(Debug)]
+pub struct Permit(Arc<AtomicUsize>);
+
+#[derive(Debug)]
+struct Rule {
+    matches: Match,
+    concurrency: Option<Concurrency>,
+    rate: Option<Mutex<TokenBucket>>,
+}
+
+/// Describes the requests to which a rule applies.
+#[derive(Clone, Debug, PartialEq)]
+enum Match {
+    Source(IpAddr),
+    Identity(tls::Identity),
+    Authority { host: String, port: u16 },
+    PathPrefix(String),
+}
+
+#[derive(Debug)]
+struct Concurrency {
+    max: usize,
+    in_flight: Arc<AtomicUsize>,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    per_sec: f64,
+    burst: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+// ===== impl Rules =====
+
+impl Rules {
+    fn find<B>(&self, req: &http::Request<B>) -> Option<&Rule> {
+        self.0.iter().find(|rule| rule.matches.matches(req))
+    }
+}
+
+impl FromStr for Rules {
+    type Err = LineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let now = Instant::now();
+        let mut rules = Vec::new();
+
+        parse_lines(s, |line| {
+            let mut words = line.split_whitespace();
+            let kind = words.next().unwrap_or("");
+            let value = words.next().ok_or("missing match")?;
+            let matches = match kind {
+                "source" => match value.parse::<IpAddr>() {
+                    Ok(ip) => Match::Source(ip),
+                    Err(_) => tls::Identity::parse(value)
+                        .map(Match::Identity)
+                        .map_err(|()| "invalid source")?,
+                },
+                "authority" => {
+                    let authority = http::uri::Authority::from_str(value)
+                        .map_err(|_| "invalid authority")?;
+                    Match::authority(&authority)
+                }
+                "path" => {
+                    if !value.starts_with('/') || value.contains(|c| c == '"' || c == '\\') {
+                        return Err("invalid path prefix");
+                    }
+                    Match::PathPrefix(value.to_owned())
+                }
+                _ => return Err("unknown match"),
+            };
+
+            let mut concurrency = None;
+            let mut rate = None;
+            let mut burst = None;
+            for word in words {
+                let mut parts = word.splitn(2, '=');
+                match (parts.next().unwrap_or(""), parts.next()) {
+                    ("concurrency", Some(max)) if concurrency.is_none() => {
+                        let max = max.parse::<usize>()
+                            .map_err(|_| "invalid concurrency")?;
+                        concurrency = Some(max);
+                    }
+                    ("rate", Some(r)) if rate.is_none() => {
+                        rate = Some(parse_rate(r).ok_or("invalid rate")?);
+                    }
+                    ("burst", Some(b)) if burst.is_none() => {
+                        let b = b.parse::<u32>()
+                            .ok()
+                            .filter(|&b| b > 0)
+                            .ok_or("invalid burst")?;
+                        burst = Some(b);
+                    }
+                    _ => return Err("invalid limit"),
+                }
+            }
+
+            if concurrency.is_none() && rate.is_none() {
+                return Err("missing limit");
+            }
+            if burst.is_some() && rate.is_none() {
+                return Err("burst requires a rate");
+            }
+
+            rules.push(Rule {
+                matches,
+                concurrency: concurrency.map(|max| Concurrency {
+                    max,
+                    in_flight: Arc::new(AtomicUsize::new(0)),
+                }),
+                rate: rate.map(|per_sec| {
+                    let burst = burst.map(f64::from).unwrap_or_else(|| per_sec.ceil().max(1.0));
+                    Mutex::new(TokenBucket::new(per_sec, burst, now))
+                }),
+            });
+            Ok(())
+        })?;
+
+        Ok(Rules(rules))
+    }
+}
+
+/// Parses a rate as a number of requests per second (`/s`) or per minute
+/// (`/m`), returning the number of requests per second.
+fn parse_rate(s: &str) -> Option<f64> {
+    let mut parts = s.splitn(2, '/');
+    let n = parts.next()?.parse::<u32>().ok().filter(|&n| n > 0)?;
+    match parts.next()? {
+        "s" => Some(f64::from(n)),
+        "m" => Some(f64::from(n) / 60.0),
+        _ => None,
+    }
+}
+
+// ===== impl Rule =====
+
+impl Rule {
+    /// Admits a request if it is within the rule's limits.
+    ///
+    /// If the rule limits concurrency, the request must hold the returned
+    /// `Permit` until its response is received.
+    fn admit(&self, now: Instant) -> Result<Option<Permit>, RejectReason> {
+        let permit = match self.concurrency {
+            Some(ref c) => Some(c.acquire().ok_or(RejectReason::ConcurrencyLimit)?),
+            None => None,
+        };
+
+        if let Some(ref rate) = self.rate {
+            let permitted = rate.lock()
+                .map(|mut bucket| bucket.take(now))
+                .unwrap_or(true);
+            if !permitted {
+                return Err(RejectReason::RateLimit);
+            }
+        }
+
+        Ok(permit)
+    }
+}
+
+// ===== impl Match =====
+
+impl Match {
+    fn authority(authority: &http::uri::Authority) -> Self {
+        Match::Authority {
+            host: authority.host().to_ascii_lowercase(),
+            port: authority.port().unwrap_or(DEFAULT_PORT),
+        }
+    }
+
+    fn matches<B>(&self, req: &http::Request<B>) -> bool {
+        match *self {
+            Match::Source(ref ip) => req.extensions()
+                .get::<Arc<ctx::transport::Server>>()
+                .map_or(false, |ctx| ctx.remote.ip() == *ip),
+            Match::Identity(ref id) => req.extensions()
+                .get::<Arc<ctx::transport::Server>>()
+                .and_then(|ctx| ctx.peer_certificate.as_ref())
+                .map_or(false, |cert| cert.is_valid_for(id)),
+            Match::Authority { .. } => req.uri()
+                .authority_part()
+                .cloned()
+                .or_else(|| h1::authority_from_host(req))
+                .map_or(false, |a| Match::authority(&a) == *self),
+            Match::PathPrefix(ref prefix) => req.uri().path().starts_with(prefix.as_str()),
+        }
+    }
+}
+
+impl fmt::Display for Match {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Match::Source(ref ip) => write!(f, "source:{}", ip),
+            Match::Identity(ref id) => write!(f, "source:{}", id),
+            Match::Authority { ref host, port } => write!(f, "authority:{}:{}", host, port),
+            Match::PathPrefix(ref prefix) => write!(f, "path:{}", prefix),
+        }
+    }
+}
+
+// ===== impl Concurrency =====
+
+impl Concurrency {
+    fn acquire(&self) -> Option<Permit> {
+        if self.in_flight.fetch_add(1, Ordering::AcqRel) >= self.max {
+            self.in_flight.fetch_sub(1, Ordering::AcqRel);
+            return None;
+        }
+        Some(Permit(self.in_flight.clone()))
+    }
+}
+
+// ===== impl Permit =====
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// ===== impl TokenBucket =====
+
+impl TokenBucket {
+    fn new(per_sec: f64, burst: f64, now: Instant) -> Self {
+        Self {
+            per_sec,
+            burst,
+            tokens: burst,
+            refilled: now,
+        }
+    }
+
+    /// Takes a token, if one is available.
+    fn take(&mut self, now: Instant) -> bool {
+        if now > self.refilled {
+            let elapsed = now - self.refilled;
+            let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+            self.tokens = (self.tokens + secs * self.per_sec).min(self.burst);
+            self.refilled = now;
+        }
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+// ===== impl Admit =====
+
+impl<S> Admit<S> {
+    pub fn new(inner: S, rules: Watch, rejections: Rejections) -> Self {
+        Self {
+            inner,
+            rules,
+            rejections,
+        }
+    }
+}
+
+impl<S, A, B> Service for Admit<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>>,
+    B: Default,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let admitted = {
+            let rules = self.rules.borrow();
+            match rules.find(&req) {
+                None => Ok(None),
+                Some(rule) => match rule.admit(Instant::now()) {
+                    Ok(permit) => Ok(permit),
+                    Err(reason) => {
+                        debug!("rejecting request matching {}: {:?}", rule.matches, reason);
+                        self.rejections.incr(&rule.matches, reason);
+                        Err(reason)
+                    }
+                },
+            }
+        };
+
+        match admitted {
+            Ok(permit) => ResponseFuture::Admitted {
+                inner: self.inner.call(req),
+                permit,
+            },
+            Err(_) => ResponseFuture::Rejected {
+                is_grpc: grpc::is_grpc(&req),
+            },
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+    B: Default,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            ResponseFuture::Admitted { ref mut inner, ref mut permit } => {
+                let rsp = try_ready!(inner.poll());
+                // The request no longer counts against the concurrency limit.
+                permit.take();
+                Ok(Async::Ready(rsp))
+            }
+            ResponseFuture::Rejected { is_grpc } => Ok(Async::Ready(rejection(is_grpc))),
+        }
+    }
+}
+
+fn rejection<B: Default>(is_grpc: bool) -> http::Response<B> {
+    let status = http::StatusCode::TOO_MANY_REQUESTS;
+    grpc::failure(is_grpc, status, grpc::RESOURCE_EXHAUSTED, "Too Many Requests")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use conditional::Conditional;
+    use std::time::Duration;
+    use transport::tls::config_test_util::*;
+
+    fn request(uri: &str) -> http::Request<()> {
+        http::Request::get(uri).body(()).unwrap()
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rules = "
+            # limit the API
+            path /api/ concurrency=100 rate=50/s burst=100
+            authority web.prod.svc.cluster.local rate=600/m
+
+            source 10.1.2.3 concurrency=5
+        ".parse::<Rules>().unwrap();
+
+        assert_eq!(rules.0.len(), 3);
+        assert_eq!(rules.0[0].matches, Match::PathPrefix("/api/".into()));
+        assert_eq!(rules.0[0].concurrency.as_ref().map(|c| c.max), Some(100));
+        assert_eq!(
+            rules.0[1].matches,
+            Match::Authority { host: "web.prod.svc.cluster.local".into(), port: 80 },
+        );
+        let bucket = rules.0[1].rate.as_ref().unwrap().lock().unwrap();
+        assert_eq!(bucket.per_sec, 10.0);
+        assert_eq!(bucket.burst, 10.0);
+        assert_eq!(rules.0[2].matches, Match::Source("10.1.2.3".parse().unwrap()));
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for s in &[
+            "path /api/",
+            "path api/ rate=1/s",
+            "host web rate=1/s",
+            "source web. rate=1/s",
+            "path / rate=0/s",
+            "path / rate=10",
+            "path / rate=10/h",
+            "path / concurrency=many",
+            "path / concurrency=1 concurrency=2",
+            "path / concurrency=1 burst=10",
+            "path / rate=1/s burst=0",
+        ] {
+            assert!(s.parse::<Rules>().is_err(), "{:?} must not parse", s);
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_applies() {
+        let rules = "
+            path /api/admin concurrency=1
+            path /api/ concurrency=10
+            authority web.prod.svc.cluster.local:8080 concurrency=10
+        ".parse::<Rules>().unwrap();
+
+        let find = |uri| rules.find(&request(uri)).map(|r| r.matches.to_string());
+        assert_eq!(find("http://web/api/admin/users"), Some("path:/api/admin".into()));
+        assert_eq!(find("http://web/api/users"), Some("path:/api/".into()));
+        assert_eq!(
+            find("http://WEB.prod.svc.cluster.local:8080/"),
+            Some("authority:web.prod.svc.cluster.local:8080".into()),
+        );
+        assert_eq!(find("http://web.prod.svc.cluster.local/"), None);
+    }
+
+    #[test]
+    fn sources_match_client_identities_and_addresses() {
+        let rules = format!("
+            source {} concurrency=1
+            source 10.1.2.3 concurrency=1
+        ", FOO_NS1.identity).parse::<Rules>().unwrap();
+
+        let find = |peer: Option<tls::PeerCertificate>, remote: &str| {
+            let mut req = request("http://web/");
+            req.extensions_mut().insert(ctx::transport::Server::new(
+                ctx::Proxy::Inbound,
+                &"127.0.0.1:4143".parse().unwrap(),
+                &remote.parse().unwrap(),
+                &None,
+                Conditional::Some(()),
+                peer,
+            ));
+            rules.find(&req).map(|r| r.matches.to_string())
+        };
+
+        let foo = format!("source:{}", FOO_NS1.identity);
+        assert_eq!(find(Some(FOO_NS1.peer_certificate()), "10.9.9.9:1234"), Some(foo.clone()));
+        // The first matching rule applies.
+        assert_eq!(find(Some(FOO_NS1.peer_certificate()), "10.1.2.3:1234"), Some(foo));
+        assert_eq!(find(Some(BAR_NS1.peer_certificate()), "10.9.9.9:1234"), None);
+        assert_eq!(find(None, "10.1.2.3:1234"), Some("source:10.1.2.3".into()));
+        assert_eq!(find(None, "10.9.9.9:1234"), None);
+    }
+
+    #[test]
+    fn concurrency_is_released_when_permits_drop() {
+        let rule = "path / concurrency=2".parse::<Rules>().unwrap().0.remove(0);
+        let now = Instant::now();
+
+        let a = rule.admit(now).unwrap();
+        let b = rule.admit(now).unwrap();
+        assert_eq!(rule.admit(now).unwrap_err(), RejectReason::ConcurrencyLimit);
+
+        drop(a);
+        assert!(rule.admit(now).is_ok());
+        drop(b);
+    }
+
+    #[test]
+    fn token_bucket_refills() {
+        let t0 = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, 2.0, t0);
+
+        assert!(bucket.take(t0));
+        assert!(bucket.take(t0));
+        assert!(!bucket.take(t0));
+
+        let t1 = t0 + Duration::from_millis(500);
+        assert!(bucket.take(t1));
+        assert!(!bucket.take(t1));
+
+        // Tokens do not accumulate beyond the burst.
+        let t2 = t1 + Duration::from_secs(10);
+        assert!(bucket.take(t2));
+        assert!(bucket.take(t2));
+        assert!(!bucket.take(t2));
+    }
+
+    #[test]
+    fn rejects_grpc_with_resource_exhausted() {
+        let rsp = rejection::<()>(true);
+        assert_eq!(rsp.status(), http::StatusCode::OK);
+        assert_eq!(rsp.headers()[grpc::GRPC_STATUS], grpc::RESOURCE_EXHAUSTED);
+
+        let rsp = rejection::<()>(false);
+        assert_eq!(rsp.status(), http::StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,88 @@
+//! Fails inbound requests that are not authorized by the client's identity.
+//!
+//! Requests that are denied by `proxy::authorize` are not sent: they are
+//! failed with a `403 Forbidden` or, for gRPC requests, a `PERMISSION_DENIED`
+//! status.
+
+use futures::{Future, Poll};
+use http;
+use std::sync::Arc;
+use tower_service::Service;
+
+use ctx;
+use proxy::authorize;
+use super::grpc;
+
+/// Fails requests that are not allowed by an authorization policy.
+#[derive(Clone, Debug)]
+pub struct Authorize<S> {
+    inner: S,
+    authorize: authorize::Authorize,
+}
+
+pub enum ResponseFuture<F> {
+    Allowed(F),
+    Denied { is_grpc: bool },
+}
+
+// ===== impl Authorize =====
+
+impl<S> Authorize<S> {
+    pub fn new(inner: S, authorize: authorize::Authorize) -> Self {
+        Self { inner, authorize }
+    }
+}
+
+impl<S, A, B> Service for Authorize<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>>,
+    B: Default,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        // Requests without a server context were not accepted by the proxy,
+        // so there is no client to authorize.
+        let allowed = req.extensions()
+            .get::<Arc<ctx::transport::Server>>()
+            .map_or(true, |ctx| self.authorize.allows(ctx, Some(req.uri().path())));
+
+        if !allowed {
+            debug!("request for {} is not authorized", req.uri().path());
+            return ResponseFuture::Denied {
+                is_grpc: grpc::is_grpc(&req),
+            };
+        }
+
+        ResponseFuture::Allowed(self.inner.call(req))
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+    B: Default,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            ResponseFuture::Allowed(ref mut inner) => inner.poll(),
+            ResponseFuture::Denied { is_grpc } => Ok(forbidden(is_grpc).into()),
+        }
+    }
+}
+
+fn forbidden<B: Default>(is_grpc: bool) -> http::Response<B> {
+    grpc::failure(is_grpc, http::StatusCode::FORBIDDEN, grpc::PERMISSION_DENIED, "Forbidden")
+}
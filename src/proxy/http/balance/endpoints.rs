@@ -0,0 +1,177 @@
+//! Tracks the discovered endpoints of a balancer that chooses an endpoint for
+//! each request as it is sent, rather than when the balancer is polled for
+//! readiness, so that the choice may depend on the request.
+
+use futures::{Async, Future, Poll};
+use http;
+use indexmap::IndexMap;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use tower_balance::Error;
+use tower_discover::{Change, Discover};
+use tower_service::Service;
+
+use super::Avoid;
+use super::weighted::{Attrs, Key, Replica};
+
+/// The discovered endpoints of a balancer, their attributes, and whether each
+/// is ready.
+pub(super) struct Endpoints<D: Discover> {
+    discover: D,
+    endpoints: IndexMap<SocketAddr, Endpoint<D::Service>>,
+
+    /// The number of local endpoints, whether or not they are ready.
+    local: usize,
+}
+
+pub struct ResponseFuture<F, E>(Option<F>, PhantomData<E>);
+
+struct Endpoint<S> {
+    service: S,
+    attrs: Attrs,
+    ready: bool,
+}
+
+// ===== impl Endpoints =====
+
+impl<D: Discover<Key = Key>> Endpoints<D> {
+    pub(super) fn new(discover: D) -> Self {
+        Self {
+            discover,
+            endpoints: IndexMap::new(),
+            local: 0,
+        }
+    }
+
+    /// Applies all pending changes from discovery.
+    ///
+    /// Returns true if endpoints were added or removed, in which case the
+    /// indices of endpoints may have changed.
+    pub(super) fn poll_discover(&mut self) -> Result<bool, D::DiscoverError> {
+        let mut changed = false;
+        while let Async::Ready(change) = self.discover.poll()? {
+            changed = true;
+            let prev = match change {
+                Change::Insert(key, service) => {
+                    let attrs = key.attrs();
+                    if attrs.is_local() {
+                        self.local += 1;
+                    }
+                    let ep = Endpoint {
+                        service,
+                        attrs,
+                        ready: false,
+                    };
+                    self.endpoints.insert(key.addr(), ep)
+                }
+                Change::Remove(key) => self.endpoints.remove(&key.addr()),
+            };
+            if prev.map_or(false, |ep| ep.attrs.is_local()) {
+                self.local -= 1;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Polls each endpoint that is not ready.
+    ///
+    /// Returns true if any endpoint is ready.
+    pub(super) fn poll_ready(&mut self) -> Result<bool, D::Error> {
+        let mut any_ready = false;
+        for ep in self.endpoints.values_mut() {
+            if !ep.ready {
+                ep.ready = ep.service.poll_ready()?.is_ready();
+            }
+            any_ready = any_ready || ep.ready;
+        }
+        Ok(any_ready)
+    }
+
+    pub(super) fn addrs(&self) -> Vec<SocketAddr> {
+        self.endpoints.keys().cloned().collect()
+    }
+
+    pub(super) fn addr(&self, i: usize) -> Option<SocketAddr> {
+        self.endpoints.get_index(i).map(|(addr, _)| *addr)
+    }
+
+    pub(super) fn index_of(&self, addr: &SocketAddr) -> Option<usize> {
+        self.endpoints.get_full(addr).map(|(i, _, _)| i)
+    }
+
+    /// Returns the endpoint at index `i`, as a replica for P2C.
+    pub(super) fn replica(&self, i: usize) -> Option<Replica<D::Service>> {
+        self.endpoints.get_index(i).map(|(_, ep)| Replica {
+            attrs: ep.attrs,
+            service: &ep.service,
+        })
+    }
+
+    /// Returns the number of local endpoints, whether or not they are ready.
+    pub(super) fn local(&self) -> usize {
+        self.local
+    }
+
+    pub(super) fn is_ready(&self, i: usize) -> bool {
+        self.endpoints.get_index(i).map_or(false, |(_, ep)| ep.ready)
+    }
+
+    /// Returns the indices of the ready endpoints, excluding `avoid` unless no
+    /// other endpoint is ready.
+    pub(super) fn ready(&self, avoid: Option<SocketAddr>) -> Vec<usize> {
+        let ready = (0..self.endpoints.len())
+            .filter(|&i| self.is_ready(i))
+            .collect::<Vec<_>>();
+        let others = ready.iter()
+            .cloned()
+            .filter(|&i| self.addr(i) != avoid)
+            .collect::<Vec<_>>();
+        if others.is_empty() {
+            ready
+        } else {
+            others
+        }
+    }
+}
+
+impl<D, B> Endpoints<D>
+where
+    D: Discover<Key = Key, Request = http::Request<B>>,
+{
+    /// Sends `req` to the endpoint at index `i`, if it is ready.
+    ///
+    /// The endpoint is recorded in the request's `Avoid`, if it has one.
+    pub(super) fn call(
+        &mut self,
+        i: Option<usize>,
+        req: D::Request,
+    ) -> ResponseFuture<<D::Service as Service>::Future, D::DiscoverError> {
+        let fut = i.and_then(|i| self.endpoints.get_index_mut(i)).and_then(|(addr, ep)| {
+            if !ep.ready {
+                return None;
+            }
+            trace!("sending request to {}", addr);
+            if let Some(avoid) = req.extensions().get::<Avoid>() {
+                avoid.set(*addr);
+            }
+            // The endpoint must be polled again before it's called again.
+            ep.ready = false;
+            Some(ep.service.call(req))
+        });
+        ResponseFuture(fut, PhantomData)
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F: Future, E> Future for ResponseFuture<F, E> {
+    type Item = F::Item;
+    type Error = Error<F::Error, E>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.0 {
+            Some(ref mut f) => f.poll().map_err(Error::Inner),
+            None => Err(Error::NotReady),
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! Balances requests by a consistent hash of a request header or cookie, so
+//! that requests with the same key are sent to the same endpoint.
+//!
+//! Endpoints are assigned the slots of a lookup table by Maglev hashing, so
+//! that few keys are remapped when endpoints are added or removed. If the
+//! endpoint for a key is not ready, or if the request is a retry or hedge that
+//! should avoid it, the request is sent to the endpoint of the next slot that
+//! is ready. Requests without a key are sent to the endpoint of a random slot.
+//!
+//! Keys and addresses are hashed with FNV over their bytes, so that every
+//! proxy, whatever it was built with, sends a key to the same endpoint.
+
+use futures::{Async, Poll};
+use http::{self, header};
+use fnv::FnvHasher;
+use rand::{self, Rng};
+use std::hash::Hasher;
+use std::net::{IpAddr, SocketAddr};
+use tower_balance::Error;
+use tower_discover::Discover;
+use tower_service::Service;
+
+use control::algorithm::HashKey;
+use super::Avoid;
+use super::endpoints::{Endpoints, ResponseFuture};
+use super::weighted::Key;
+
+/// The number of slots in the lookup table.
+///
+/// Maglev requires a prime that is much larger than the number of endpoints,
+/// so that each endpoint is assigned a nearly equal share of slots.
+const TABLE_SIZE: usize = 65_537;
+
+/// Marks a slot that has not been assigned an endpoint.
+const UNASSIGNED: u32 = ::std::u32::MAX;
+
+/// Balances requests by a consistent hash of their keys.
+pub struct Balance<D: Discover> {
+    endpoints: Endpoints<D>,
+    key: HashKey,
+
+    /// The addresses of the endpoints, in order.
+    addrs: Vec<SocketAddr>,
+
+    /// Maps each slot to the index of an address in `addrs`.
+    ///
+    /// Rebuilt only when the endpoints' addresses change.
+    table: Vec<u32>,
+}
+
+// ===== impl Balance =====
+
+impl<D: Discover<Key = Key>> Balance<D> {
+    pub fn new(discover: D, key: HashKey) -> Self {
+        Self {
+            endpoints: Endpoints::new(discover),
+            key,
+            addrs: Vec::new(),
+            table: Vec::new(),
+        }
+    }
+
+    /// Rebuilds the lookup table if endpoints have been added or removed.
+    fn update_table(&mut self) {
+        let mut addrs = self.endpoints.addrs();
+        addrs.sort();
+        if addrs != self.addrs {
+            trace!("rebuilding lookup table for {} endpoints", addrs.len());
+            self.table = populate(&addrs);
+            self.addrs = addrs;
+        }
+    }
+
+    /// Returns the index of the ready endpoint for `slot`, or for the nearest
+    /// slot after it.
+    ///
+    /// The endpoint at `avoid` is only chosen if no other endpoint is ready.
+    fn ready_index(&self, slot: usize, avoid: Option<SocketAddr>) -> Option<usize> {
+        if self.table.is_empty() {
+            return None;
+        }
+        let mut avoided = None;
+        for n in 0..TABLE_SIZE {
+            let addr = self.addrs[self.table[(slot + n) % TABLE_SIZE] as usize];
+            let i = match self.endpoints.index_of(&addr) {
+                Some(i) if self.endpoints.is_ready(i) => i,
+                _ => continue,
+            };
+            if Some(addr) == avoid {
+                avoided = Some(i);
+                continue;
+            }
+            return Some(i);
+        }
+        avoided
+    }
+}
+
+impl<D, B> Service for Balance<D>
+where
+    D: Discover<Key = Key, Request = http::Request<B>>,
+{
+    type Request = D::Request;
+    type Response = D::Response;
+    type Error = Error<D::Error, D::DiscoverError>;
+    type Future = ResponseFuture<<D::Service as Service>::Future, D::DiscoverError>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        if self.endpoints.poll_discover().map_err(Error::Balance)? {
+            self.update_table();
+        }
+
+        if self.endpoints.poll_ready().map_err(Error::Inner)? {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let slot = match hash_request(&self.key, &req) {
+            Some(hash) => (hash % TABLE_SIZE as u64) as usize,
+            None => rand::thread_rng().gen_range(0, TABLE_SIZE),
+        };
+        let avoid = req.extensions().get::<Avoid>().and_then(Avoid::get);
+
+        let i = self.ready_index(slot, avoid);
+        self.endpoints.call(i, req)
+    }
+}
+
+// ===== Maglev =====
+
+fn hash(seed: u8, bytes: &[u8]) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(&[seed]);
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+fn hash_addr(seed: u8, addr: &SocketAddr) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(&[seed]);
+    match addr.ip() {
+        IpAddr::V4(ip) => hasher.write(&ip.octets()),
+        IpAddr::V6(ip) => hasher.write(&ip.octets()),
+    }
+    let port = addr.port();
+    hasher.write(&[(port >> 8) as u8, port as u8]);
+    hasher.finish()
+}
+
+/// Builds a lookup table that assigns each slot to the index of one of
+/// `addrs`.
+///
+/// Each address has a permutation of the slots, determined by the address
+/// alone, and the addresses take turns claiming their next unclaimed slot.
+/// The addresses are ordered before claiming slots so that the table does not
+/// depend on the order in which endpoints were discovered.
+fn populate(addrs: &[SocketAddr]) -> Vec<u32> {
+    if addrs.is_empty() {
+        return Vec::new();
+    }
+
+    let size = TABLE_SIZE as u64;
+    let mut order = (0..addrs.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| addrs[i]);
+
+    // Each permutation is `(offset + n * skip) % size` for `n = 0, 1, 2...`.
+    // Since `size` is prime, every `skip` visits every slot.
+    let mut perms = order.iter()
+        .map(|&i| {
+            let offset = hash_addr(0, &addrs[i]) % size;
+            let skip = hash_addr(1, &addrs[i]) % (size - 1) + 1;
+            (i as u32, offset, skip, 0u64)
+        })
+        .collect::<Vec<_>>();
+
+    let mut table = vec![UNASSIGNED; TABLE_SIZE];
+    let mut assigned = 0;
+    loop {
+        for perm in perms.iter_mut() {
+            let (i, offset, skip) = (perm.0, perm.1, perm.2);
+            loop {
+                let slot = ((offset + perm.3 * skip) % size) as usize;
+                perm.3 += 1;
+                if table[slot] == UNASSIGNED {
+                    table[slot] = i;
+                    break;
+                }
+            }
+            assigned += 1;
+            if assigned == TABLE_SIZE {
+                return table;
+            }
+        }
+    }
+}
+
+/// Hashes the key of a request, if it has one.
+fn hash_request<B>(key: &HashKey, req: &http::Request<B>) -> Option<u64> {
+    match *key {
+        HashKey::Header(ref name) => {
+            let value = req.headers().get(name)?;
+            Some(hash(0, value.as_bytes()))
+        }
+        HashKey::Cookie(ref name) => {
+            let value = req.headers()
+                .get_all(header::COOKIE)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(';'))
+                .filter_map(|pair| {
+                    let mut parts = pair.trim().splitn(2, '=');
+                    match (parts.next(), parts.next()) {
+                        (Some(n), Some(v)) if n == name.as_str() => Some(v),
+                        _ => None,
+                    }
+                })
+                .next()?;
+            Some(hash(0, value.as_bytes()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_util::*;
+
+    fn addrs(n: u8) -> Vec<SocketAddr> {
+        (0..n).map(|i| SocketAddr::from(([10, 1, 1, i], 8080))).collect()
+    }
+
+    #[test]
+    fn populate_assigns_slots_evenly() {
+        let table = populate(&addrs(10));
+        let mut counts = [0usize; 10];
+        for &i in &table {
+            counts[i as usize] += 1;
+        }
+        let expected = TABLE_SIZE / 10;
+        for &count in &counts {
+            assert!(count >= expected - 1 && count <= expected + 1, "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn populate_remaps_few_slots() {
+        let before = addrs(10);
+        let mut after = before.clone();
+        after.remove(3);
+
+        let a = populate(&before);
+        let b = populate(&after);
+        let moved = (0..TABLE_SIZE)
+            .filter(|&s| before[a[s] as usize] != after[b[s] as usize])
+            .count();
+
+        // The removed endpoint's slots must move, but few others should.
+        assert!(moved < TABLE_SIZE / 10 + TABLE_SIZE / 50, "{} slots moved", moved);
+    }
+
+    #[test]
+    fn populate_ignores_discovery_order() {
+        let addrs = addrs(5);
+        let mut reversed = addrs.clone();
+        reversed.reverse();
+
+        let a = populate(&addrs);
+        let b = populate(&reversed);
+        for s in 0..TABLE_SIZE {
+            assert_eq!(addrs[a[s] as usize], reversed[b[s] as usize]);
+        }
+    }
+
+    #[test]
+    fn hashes_are_the_same_in_every_proxy() {
+        let key = HashKey::Header("x-user".parse().unwrap());
+        let mut req = http::Request::new(());
+        req.headers_mut().insert("x-user", "alice".parse().unwrap());
+        assert_eq!(hash_request(&key, &req), Some(0xf029_3953_7312_9889));
+    }
+
+    #[test]
+    fn hashes_cookie() {
+        let key = HashKey::Cookie("session".into());
+        let req = |cookie: &str| {
+            http::Request::builder()
+                .header(header::COOKIE, cookie)
+                .body(())
+                .unwrap()
+        };
+
+        let a = hash_request(&key, &req("theme=dark; session=abc"));
+        assert!(a.is_some());
+        assert_eq!(a, hash_request(&key, &req("session=abc")));
+        assert_ne!(a, hash_request(&key, &req("session=xyz")));
+        assert_eq!(hash_request(&key, &req("sessions=abc")), None);
+    }
+
+    #[test]
+    fn copies_avoid_the_last_endpoint() {
+        let endpoints = (1..6).map(|n| endpoint(n, 0)).collect();
+        let key = HashKey::Header("x-user".parse().unwrap());
+        let mut balance = Balance::new(Discovered::new(endpoints), key);
+        let keyed = |avoid: Option<&Avoid>| {
+            let mut req = match avoid {
+                Some(avoid) => copy_of(avoid),
+                None => http::Request::new(()),
+            };
+            req.headers_mut().insert("x-user", "alice".parse().unwrap());
+            req
+        };
+
+        let hashed = send(&mut balance, keyed(None));
+        assert_eq!(send(&mut balance, keyed(None)), hashed);
+
+        // A hedge or retry of the request is sent to another endpoint, and the
+        // next copy is sent back to the hashed endpoint.
+        let avoid = Avoid::default();
+        assert_eq!(send(&mut balance, keyed(Some(&avoid))), hashed);
+        let other = send(&mut balance, keyed(Some(&avoid)));
+        assert_ne!(other, hashed);
+        assert_eq!(send(&mut balance, keyed(Some(&avoid))), hashed);
+    }
+}
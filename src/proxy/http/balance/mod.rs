@@ -0,0 +1,261 @@
+//! Load balancers for outbound destinations.
+//!
+//! Each destination's endpoints are balanced by the algorithm configured for
+//! it (see `control::algorithm`). Every balancer discovers its endpoints with
+//! their attributes and instruments their responses in the same way, so that a
+//! `Balancer` has the same type regardless of its algorithm. Only the P2C
+//! algorithms use endpoints' weights and localities.
+//!
+//! Every balancer that compares endpoints' loads gives newly discovered
+//! endpoints a slow start (see `slow_start`). Round-robin balancing ignores
+//! load, and hash balancing sends each key to the same endpoint regardless.
+//!
+//! The P2C and hash balancers steer a request's retries and hedges away from
+//! the endpoint to which it was last sent (see `Avoid`). Round-robin balancing
+//! sends successive requests to different endpoints anyway.
+
+use futures::{Future, Poll};
+use http;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tower_balance::{self, choose, load};
+use tower_balance::load::{peak_ewma, pending_requests};
+use tower_discover::Discover;
+use tower_h2_balance::{PendingUntilFirstData, PendingUntilFirstDataBody};
+use tower_service::Service;
+
+use control::algorithm::Algorithm;
+
+mod endpoints;
+pub mod hash;
+pub mod p2c;
+mod slow_start;
+#[cfg(test)]
+mod test_util;
+mod weighted;
+
+pub use self::slow_start::{SlowStart, Warmth, WithSlowStart};
+
+pub use self::weighted::{
+    Attributed,
+    Key,
+    MakeAttributed,
+    PreferLocal,
+    WeightedPowerOfTwoChoices,
+    WithAttributes,
+};
+
+/// Balances requests with P2C over peak EWMA latency.
+pub type PeakEwma<D> =
+    p2c::Balance<WithSlowStart<load::WithPeakEwma<WithAttributes<D>, Instrument>>>;
+
+/// Balances requests with P2C over pending requests.
+pub type PendingRequests<D> =
+    p2c::Balance<WithSlowStart<load::WithPendingRequests<WithAttributes<D>, Instrument>>>;
+
+/// Balances requests in turn.
+///
+/// Pending requests are tracked, though not used, so that responses are
+/// instrumented like those of other balancers.
+pub type RoundRobin<D> = tower_balance::Balance<
+    load::WithPendingRequests<WithAttributes<D>, Instrument>,
+    choose::RoundRobin,
+>;
+
+/// Balances requests by a consistent hash of their keys.
+pub type Hash<D> = hash::Balance<load::WithPendingRequests<WithAttributes<D>, Instrument>>;
+
+/// Balances the endpoints discovered by `D` with any algorithm.
+pub type Balancer<D> = Balance<PeakEwma<D>, PendingRequests<D>, RoundRobin<D>, Hash<D>>;
+
+/// Balances requests with one of several algorithms.
+pub enum Balance<E, P, R, H> {
+    PeakEwma(E),
+    PendingRequests(P),
+    RoundRobin(R),
+    Hash(H),
+}
+
+pub enum ResponseFuture<E, P, R, H> {
+    PeakEwma(E),
+    PendingRequests(P),
+    RoundRobin(R),
+    Hash(H),
+}
+
+/// Instruments responses so that an endpoint is considered loaded until each
+/// of its response bodies produces data.
+///
+/// The load metric's handle is wrapped in a `Handle`, so that balancers with
+/// different load metrics produce the same type of response.
+#[derive(Clone, Debug, Default)]
+pub struct Instrument(PendingUntilFirstData);
+
+/// The handle of either load metric.
+pub enum Handle {
+    PeakEwma(peak_ewma::Handle),
+    PendingRequests(pending_requests::Handle),
+}
+
+/// Steers a request's retries and hedges away from the endpoint to which the
+/// request was last sent.
+///
+/// A request and its copies share an `Avoid`. Balancers record the endpoint
+/// to which each copy is sent, and send the next copy to another endpoint if
+/// one is ready.
+#[derive(Clone, Debug, Default)]
+pub struct Avoid(Arc<Mutex<Option<SocketAddr>>>);
+
+// ===== impl Balance =====
+
+impl<D> Balancer<D>
+where
+    D: Discover<Key = SocketAddr>,
+    WithAttributes<D>: Discover<Key = Key>,
+    load::WithPeakEwma<WithAttributes<D>, Instrument>: Discover<Key = Key>,
+    load::WithPendingRequests<WithAttributes<D>, Instrument>: Discover<Key = Key>,
+{
+    /// Balances the endpoints discovered by `discover` with `algorithm`.
+    ///
+    /// P2C algorithms choose endpoints with `choose`, and give new endpoints a
+    /// `slow_start` window.
+    pub fn new(
+        discover: D,
+        algorithm: &Algorithm,
+        choose: WeightedPowerOfTwoChoices,
+        slow_start: Option<Duration>,
+    ) -> Self {
+        let discover = WithAttributes::new(discover);
+        let instrument = Instrument::default();
+        match *algorithm {
+            Algorithm::PeakEwma { decay } => {
+                let loaded = load::WithPeakEwma::new(discover, decay, instrument);
+                let loaded = WithSlowStart::new(loaded, slow_start);
+                Balance::PeakEwma(p2c::Balance::new(loaded, choose))
+            }
+            Algorithm::PendingRequests => {
+                let loaded = load::WithPendingRequests::new(discover, instrument);
+                let loaded = WithSlowStart::new(loaded, slow_start);
+                Balance::PendingRequests(p2c::Balance::new(loaded, choose))
+            }
+            Algorithm::RoundRobin => {
+                let loaded = load::WithPendingRequests::new(discover, instrument);
+                let choose = choose::RoundRobin::default();
+                Balance::RoundRobin(tower_balance::Balance::new(loaded, choose))
+            }
+            Algorithm::Hash(ref key) => {
+                let loaded = load::WithPendingRequests::new(discover, instrument);
+                Balance::Hash(hash::Balance::new(loaded, key.clone()))
+            }
+        }
+    }
+}
+
+impl<E, P, R, H> Service for Balance<E, P, R, H>
+where
+    E: Service,
+    P: Service<Request = E::Request, Response = E::Response, Error = E::Error>,
+    R: Service<Request = E::Request, Response = E::Response, Error = E::Error>,
+    H: Service<Request = E::Request, Response = E::Response, Error = E::Error>,
+{
+    type Request = E::Request;
+    type Response = E::Response;
+    type Error = E::Error;
+    type Future = ResponseFuture<E::Future, P::Future, R::Future, H::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        match *self {
+            Balance::PeakEwma(ref mut b) => b.poll_ready(),
+            Balance::PendingRequests(ref mut b) => b.poll_ready(),
+            Balance::RoundRobin(ref mut b) => b.poll_ready(),
+            Balance::Hash(ref mut b) => b.poll_ready(),
+        }
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        match *self {
+            Balance::PeakEwma(ref mut b) => ResponseFuture::PeakEwma(b.call(req)),
+            Balance::PendingRequests(ref mut b) => ResponseFuture::PendingRequests(b.call(req)),
+            Balance::RoundRobin(ref mut b) => ResponseFuture::RoundRobin(b.call(req)),
+            Balance::Hash(ref mut b) => ResponseFuture::Hash(b.call(req)),
+        }
+    }
+}
+
+impl<E, P, R, H> Future for ResponseFuture<E, P, R, H>
+where
+    E: Future,
+    P: Future<Item = E::Item, Error = E::Error>,
+    R: Future<Item = E::Item, Error = E::Error>,
+    H: Future<Item = E::Item, Error = E::Error>,
+{
+    type Item = E::Item;
+    type Error = E::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            ResponseFuture::PeakEwma(ref mut f) => f.poll(),
+            ResponseFuture::PendingRequests(ref mut f) => f.poll(),
+            ResponseFuture::RoundRobin(ref mut f) => f.poll(),
+            ResponseFuture::Hash(ref mut f) => f.poll(),
+        }
+    }
+}
+
+// ===== impl Instrument =====
+
+impl<T, B> load::Instrument<T, http::Response<B>> for Instrument
+where
+    T: Into<Handle>,
+    PendingUntilFirstData: load::Instrument<
+        Handle,
+        http::Response<B>,
+        Output = http::Response<PendingUntilFirstDataBody<Handle, B>>,
+    >,
+{
+    type Output = http::Response<PendingUntilFirstDataBody<Handle, B>>;
+
+    fn instrument(&self, handle: T, rsp: http::Response<B>) -> Self::Output {
+        load::Instrument::instrument(&self.0, handle.into(), rsp)
+    }
+}
+
+// ===== impl Handle =====
+
+impl fmt::Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Handle::PeakEwma(_) => f.debug_tuple("PeakEwma").finish(),
+            Handle::PendingRequests(_) => f.debug_tuple("PendingRequests").finish(),
+        }
+    }
+}
+
+impl From<peak_ewma::Handle> for Handle {
+    fn from(handle: peak_ewma::Handle) -> Self {
+        Handle::PeakEwma(handle)
+    }
+}
+
+impl From<pending_requests::Handle> for Handle {
+    fn from(handle: pending_requests::Handle) -> Self {
+        Handle::PendingRequests(handle)
+    }
+}
+
+// ===== impl Avoid =====
+
+impl Avoid {
+    /// Returns the endpoint to which the request was last sent, if any.
+    fn get(&self) -> Option<SocketAddr> {
+        self.0.lock().ok().and_then(|addr| *addr)
+    }
+
+    fn set(&self, addr: SocketAddr) {
+        if let Ok(mut last) = self.0.lock() {
+            *last = Some(addr);
+        }
+    }
+}
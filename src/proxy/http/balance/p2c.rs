@@ -0,0 +1,132 @@
+//! Balances requests by the power of two choices.
+//!
+//! Unlike `tower_balance::Balance`, which chooses an endpoint when it is
+//! polled for readiness, an endpoint is chosen from those that are ready as
+//! each request is sent, so that a request's retries and hedges may avoid the
+//! endpoint to which it was last sent (see `Avoid`).
+
+use futures::{Async, Poll};
+use http;
+use tower_balance::{Error, Load};
+use tower_discover::Discover;
+use tower_service::Service;
+
+use super::Avoid;
+use super::endpoints::{Endpoints, ResponseFuture};
+use super::weighted::{Key, WeightedPowerOfTwoChoices};
+
+/// Balances requests with P2C over the load of each endpoint.
+pub struct Balance<D: Discover> {
+    endpoints: Endpoints<D>,
+    choose: WeightedPowerOfTwoChoices,
+}
+
+// ===== impl Balance =====
+
+impl<D: Discover<Key = Key>> Balance<D> {
+    pub fn new(discover: D, choose: WeightedPowerOfTwoChoices) -> Self {
+        Self {
+            endpoints: Endpoints::new(discover),
+            choose,
+        }
+    }
+}
+
+impl<D, B> Service for Balance<D>
+where
+    D: Discover<Key = Key, Request = http::Request<B>>,
+    D::Service: Load,
+    <D::Service as Load>::Metric: PartialOrd,
+{
+    type Request = D::Request;
+    type Response = D::Response;
+    type Error = Error<D::Error, D::DiscoverError>;
+    type Future = ResponseFuture<<D::Service as Service>::Future, D::DiscoverError>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.endpoints.poll_discover().map_err(Error::Balance)?;
+
+        if self.endpoints.poll_ready().map_err(Error::Inner)? {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let avoid = req.extensions().get::<Avoid>().and_then(Avoid::get);
+        let chosen = {
+            let ready = self.endpoints.ready(avoid);
+            let replicas = ready.iter()
+                .filter_map(|&i| self.endpoints.replica(i))
+                .collect::<Vec<_>>();
+            self.choose.choose(&replicas, self.endpoints.local()).map(|i| ready[i])
+        };
+        self.endpoints.call(chosen, req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_util::*;
+
+    type Mock = Balance<Discovered<Endpoint>>;
+
+    fn balance(endpoints: Vec<(Key, Endpoint)>) -> Mock {
+        let discover = Discovered::new(endpoints);
+        Balance::new(discover, WeightedPowerOfTwoChoices::new(None))
+    }
+
+    #[test]
+    fn chooses_the_less_loaded_endpoint() {
+        let mut balance = balance(vec![endpoint(1, 10), endpoint(2, 0)]);
+        for _ in 0..10 {
+            assert_eq!(send(&mut balance, http::Request::new(())), addr(2));
+        }
+    }
+
+    #[test]
+    fn copies_avoid_the_last_endpoint() {
+        let mut balance = balance(vec![endpoint(1, 10), endpoint(2, 0)]);
+        let avoid = Avoid::default();
+
+        assert_eq!(send(&mut balance, copy_of(&avoid)), addr(2));
+        assert_eq!(avoid.get(), Some(addr(2)));
+
+        // A hedge or retry of the request is sent to the other endpoint, even
+        // though it is more loaded, and the next copy avoids that endpoint.
+        assert_eq!(send(&mut balance, copy_of(&avoid)), addr(1));
+        assert_eq!(send(&mut balance, copy_of(&avoid)), addr(2));
+    }
+
+    #[test]
+    fn copies_use_the_last_endpoint_if_it_is_the_only_one() {
+        let mut balance = balance(vec![endpoint(1, 0)]);
+        let avoid = Avoid::default();
+
+        assert_eq!(send(&mut balance, copy_of(&avoid)), addr(1));
+        assert_eq!(send(&mut balance, copy_of(&avoid)), addr(1));
+    }
+
+    #[test]
+    fn sends_requests_in_proportion_to_weights() {
+        let mut balance = balance(vec![
+            weighted(1, 0, 1),
+            weighted(2, 0, 3),
+            weighted(3, 0, 0),
+        ]);
+        let mut counts = [0usize; 3];
+        for _ in 0..1_000 {
+            let sent = send(&mut balance, http::Request::new(()));
+            let i = (1..4).position(|n| sent == addr(n)).unwrap();
+            counts[i] += 1;
+        }
+
+        // The endpoints are equally loaded, so each is chosen in proportion
+        // to its weight, and the endpoint without a weight is drained.
+        assert!(counts[0] > 150 && counts[0] < 350, "{:?}", counts);
+        assert!(counts[1] > 650 && counts[1] < 850, "{:?}", counts);
+        assert_eq!(counts[2], 0, "{:?}", counts);
+    }
+}
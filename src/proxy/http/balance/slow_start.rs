@@ -0,0 +1,159 @@
+//! Gives newly discovered endpoints a slow start, so that endpoints which are
+//! still warming up are not overwhelmed.
+//!
+//! Slow start is implemented over an endpoint's `Load`, so that it applies to
+//! every balancer that compares endpoints' loads, whatever the load metric:
+//! during the slow start window, a new endpoint reports that it is more loaded
+//! than any warm endpoint with a probability that falls linearly from one to
+//! zero, so its share of requests ramps up over the window.
+
+use futures::{Async, Poll};
+use rand::{self, Rng};
+use std::time::{Duration, Instant};
+use tower_balance::Load;
+use tower_discover::{Change, Discover};
+use tower_service::Service;
+
+/// Gives the services discovered by `D` a slow start.
+#[derive(Debug)]
+pub struct WithSlowStart<D> {
+    inner: D,
+    window: Option<Duration>,
+}
+
+/// A service that is cold until its slow start window has elapsed.
+#[derive(Debug)]
+pub struct SlowStart<S> {
+    inner: S,
+    warm_at: Instant,
+    window: Duration,
+}
+
+/// The load of a `SlowStart` service.
+///
+/// A cold service is more loaded than any warm service.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub enum Warmth<M> {
+    Warm(M),
+    Cold,
+}
+
+// ===== impl WithSlowStart =====
+
+impl<D> WithSlowStart<D> {
+    pub fn new(inner: D, window: Option<Duration>) -> Self {
+        Self { inner, window }
+    }
+}
+
+impl<D: Discover> Discover for WithSlowStart<D> {
+    type Key = D::Key;
+    type Request = D::Request;
+    type Response = D::Response;
+    type Error = D::Error;
+    type Service = SlowStart<D::Service>;
+    type DiscoverError = D::DiscoverError;
+
+    fn poll(&mut self) -> Poll<Change<Self::Key, Self::Service>, Self::DiscoverError> {
+        let change = match try_ready!(self.inner.poll()) {
+            Change::Insert(key, inner) => {
+                let window = self.window.unwrap_or_else(|| Duration::from_secs(0));
+                Change::Insert(key, SlowStart::new(inner, window, Instant::now()))
+            }
+            Change::Remove(key) => Change::Remove(key),
+        };
+        Ok(Async::Ready(change))
+    }
+}
+
+// ===== impl SlowStart =====
+
+impl<S> SlowStart<S> {
+    fn new(inner: S, window: Duration, discovered: Instant) -> Self {
+        Self {
+            inner,
+            warm_at: discovered + window,
+            window,
+        }
+    }
+
+    /// Returns true if the service should be treated as cold at `now`, with a
+    /// probability proportional to the remainder of its window.
+    fn is_cold<R: Rng>(&self, rng: &mut R, now: Instant) -> bool {
+        if now >= self.warm_at {
+            return false;
+        }
+        let remaining = secs(self.warm_at - now) / secs(self.window);
+        rng.gen::<f64>() < remaining
+    }
+}
+
+impl<S: Load> Load for SlowStart<S> {
+    type Metric = Warmth<S::Metric>;
+
+    fn load(&self) -> Self::Metric {
+        if self.is_cold(&mut rand::thread_rng(), Instant::now()) {
+            Warmth::Cold
+        } else {
+            Warmth::Warm(self.inner.load())
+        }
+    }
+}
+
+impl<S: Service> Service for SlowStart<S> {
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        self.inner.call(req)
+    }
+}
+
+fn secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + f64::from(d.subsec_nanos()) / 1_000_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{prng::XorShiftRng, SeedableRng};
+
+    fn colds(svc: &SlowStart<()>, now: Instant, n: usize) -> usize {
+        let mut rng = XorShiftRng::from_seed([7; 16]);
+        (0..n).filter(|_| svc.is_cold(&mut rng, now)).count()
+    }
+
+    #[test]
+    fn cold_endpoints_are_more_loaded_than_warm_ones() {
+        assert!(Warmth::Warm(::std::u64::MAX) < Warmth::Cold);
+        assert!(Warmth::Warm(1) < Warmth::Warm(2));
+        assert!(Warmth::Cold::<u64> <= Warmth::Cold);
+    }
+
+    #[test]
+    fn ramps_up_over_the_window() {
+        let discovered = Instant::now();
+        let svc = SlowStart::new((), Duration::from_secs(10), discovered);
+
+        assert_eq!(colds(&svc, discovered, 1_000), 1_000);
+        let halfway = colds(&svc, discovered + Duration::from_secs(5), 10_000);
+        assert!(halfway > 4_700 && halfway < 5_300, "{} of 10000 cold", halfway);
+        let late = colds(&svc, discovered + Duration::from_secs(9), 10_000);
+        assert!(late > 700 && late < 1_300, "{} of 10000 cold", late);
+        assert_eq!(colds(&svc, discovered + Duration::from_secs(10), 1_000), 0);
+        assert_eq!(colds(&svc, discovered + Duration::from_secs(60), 1_000), 0);
+    }
+
+    #[test]
+    fn without_a_window_endpoints_are_warm() {
+        let discovered = Instant::now();
+        let svc = SlowStart::new((), Duration::from_secs(0), discovered);
+        assert_eq!(colds(&svc, discovered, 1_000), 0);
+    }
+}
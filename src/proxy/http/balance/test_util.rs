@@ -0,0 +1,104 @@
+//! Mock discovery and endpoints for balancer tests.
+
+use futures::{future, Async, Future, Poll};
+use http;
+use std::collections::VecDeque;
+use std::fmt;
+use std::net::SocketAddr;
+use tower_balance::Load;
+use tower_discover::{Change, Discover};
+use tower_service::Service;
+
+use super::Avoid;
+use super::weighted::{Attrs, Key};
+
+/// Discovers each of its endpoints once.
+pub struct Discovered<S>(VecDeque<(Key, S)>);
+
+/// An endpoint that is always ready and responds with its address.
+pub struct Endpoint {
+    addr: SocketAddr,
+    load: u64,
+}
+
+pub fn addr(n: u8) -> SocketAddr {
+    SocketAddr::from(([10, 1, 1, n], 8080))
+}
+
+/// Returns the endpoint at `addr(n)`, with a fixed `load`.
+pub fn endpoint(n: u8, load: u64) -> (Key, Endpoint) {
+    (Key::new(addr(n), Attrs::default()), Endpoint { addr: addr(n), load })
+}
+
+/// Returns the endpoint at `addr(n)`, with a fixed `load` and a `weight`.
+pub fn weighted(n: u8, load: u64, weight: u32) -> (Key, Endpoint) {
+    let attrs = Attrs { weight, ..Attrs::default() };
+    (Key::new(addr(n), attrs), Endpoint { addr: addr(n), load })
+}
+
+/// Sends a request through `balance`, returning the address of the endpoint
+/// that it was sent to.
+pub fn send<S>(balance: &mut S, req: http::Request<()>) -> SocketAddr
+where
+    S: Service<Request = http::Request<()>, Response = SocketAddr>,
+    S::Error: fmt::Debug,
+{
+    assert!(balance.poll_ready().unwrap().is_ready());
+    balance.call(req).wait().unwrap()
+}
+
+/// Returns a request that shares `avoid`.
+pub fn copy_of(avoid: &Avoid) -> http::Request<()> {
+    let mut req = http::Request::new(());
+    req.extensions_mut().insert(avoid.clone());
+    req
+}
+
+// ===== impl Discovered =====
+
+impl<S> Discovered<S> {
+    pub fn new(endpoints: Vec<(Key, S)>) -> Self {
+        Discovered(endpoints.into_iter().collect())
+    }
+}
+
+impl<S: Service> Discover for Discovered<S> {
+    type Key = Key;
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Service = S;
+    type DiscoverError = ();
+
+    fn poll(&mut self) -> Poll<Change<Key, S>, ()> {
+        match self.0.pop_front() {
+            Some((key, svc)) => Ok(Async::Ready(Change::Insert(key, svc))),
+            None => Ok(Async::NotReady),
+        }
+    }
+}
+
+// ===== impl Endpoint =====
+
+impl Service for Endpoint {
+    type Request = http::Request<()>;
+    type Response = SocketAddr;
+    type Error = ();
+    type Future = future::FutureResult<SocketAddr, ()>;
+
+    fn poll_ready(&mut self) -> Poll<(), ()> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, _: http::Request<()>) -> Self::Future {
+        future::ok(self.addr)
+    }
+}
+
+impl Load for Endpoint {
+    type Metric = u64;
+
+    fn load(&self) -> u64 {
+        self.load
+    }
+}
@@ -0,0 +1,594 @@
+//! Weights the endpoints of a P2C balancer and prefers local endpoints.
+//!
+//! The Destination service may assign each endpoint a weight relative to the
+//! other endpoints of a destination, so that endpoints may be drained or
+//! favored without being removed. It may also label each endpoint with its
+//! `region` and `zone`. When the proxy's own locality is configured, requests
+//! are sent to endpoints in the same locality, unless too few of them are
+//! ready (i.e. because they are failing or saturated), in which case a share
+//! of requests spills over to endpoints in other localities.
+//!
+//! An endpoint's attributes are determined as its client is made, and are
+//! then carried with its key through discovery, so that balancers learn them
+//! as endpoints are inserted.
+
+use futures::{Async, Poll};
+use indexmap::IndexMap;
+use rand::{self, Rng};
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use tower_balance::Load;
+use tower_discover::{Change, Discover};
+use tower_service::Service;
+
+use control::destination::{Endpoint, Locality, DEFAULT_WEIGHT};
+use svc::MakeClient;
+
+const REGION_LABEL: &str = "region";
+const ZONE_LABEL: &str = "zone";
+
+/// Describes the proxy's own locality, so that endpoints in the same locality
+/// may be preferred.
+#[derive(Clone, Debug)]
+pub struct PreferLocal {
+    /// The proxy's region, if known.
+    pub region: Option<String>,
+
+    /// The proxy's zone, if known.
+    pub zone: Option<String>,
+
+    /// When fewer than this fraction of local endpoints are ready, requests
+    /// spill over to other localities in proportion to the shortfall.
+    pub min_ready: f32,
+}
+
+/// Determines the attributes of each endpoint for which a client is made.
+#[derive(Clone, Debug)]
+pub struct MakeAttributed<M> {
+    inner: M,
+    prefer_local: Option<PreferLocal>,
+}
+
+/// A client, with the attributes of its endpoint.
+#[derive(Debug)]
+pub struct Attributed<S> {
+    inner: S,
+    attrs: Attrs,
+}
+
+/// Moves the attributes of discovered clients to their keys.
+#[derive(Debug)]
+pub struct WithAttributes<D> {
+    inner: D,
+}
+
+/// Identifies a discovered endpoint, and carries its attributes.
+///
+/// Keys are equal if their addresses are, since endpoints are removed by
+/// address alone.
+#[derive(Copy, Clone, Debug)]
+pub struct Key {
+    addr: SocketAddr,
+    attrs: Attrs,
+}
+
+/// Chooses two replicas at random, with probabilities proportional to their
+/// weights, and selects the less loaded of the two.
+///
+/// If no replica has a weight, replicas are chosen uniformly. If the proxy
+/// prefers local endpoints, both replicas are chosen from the same locality.
+#[derive(Clone, Debug)]
+pub struct WeightedPowerOfTwoChoices {
+    min_local_ready: Option<f32>,
+}
+
+/// A ready endpoint that a P2C balancer may choose.
+pub(super) struct Replica<'a, S: 'a> {
+    pub attrs: Attrs,
+    pub service: &'a S,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(super) struct Attrs {
+    pub weight: u32,
+    pub locality: Option<Locality>,
+}
+
+/// The replicas from which a pair may be chosen.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Subset {
+    All,
+    Local,
+    NotLocal,
+}
+
+// ===== impl PreferLocal =====
+
+impl PreferLocal {
+    /// Returns the locality of an endpoint with the given labels, unless it
+    /// has none of the locality labels that the proxy is configured with.
+    fn locality(&self, labels: &IndexMap<String, String>) -> Option<Locality> {
+        let known = (self.region.is_some() && labels.contains_key(REGION_LABEL))
+            || (self.zone.is_some() && labels.contains_key(ZONE_LABEL));
+        if !known {
+            return None;
+        }
+
+        let matches = |key: &str, value: &Option<String>| {
+            value.as_ref().map_or(true, |v| labels.get(key) == Some(v))
+        };
+        if matches(REGION_LABEL, &self.region) && matches(ZONE_LABEL, &self.zone) {
+            Some(Locality::Local)
+        } else {
+            Some(Locality::Remote)
+        }
+    }
+}
+
+// ===== impl MakeAttributed =====
+
+impl<M> MakeAttributed<M> {
+    pub fn new(inner: M, prefer_local: Option<PreferLocal>) -> Self {
+        Self {
+            inner,
+            prefer_local,
+        }
+    }
+}
+
+impl<M> MakeClient<Endpoint> for MakeAttributed<M>
+where
+    M: MakeClient<Endpoint>,
+{
+    type Client = Attributed<M::Client>;
+    type Error = M::Error;
+
+    fn make_client(&self, ep: &Endpoint) -> Result<Self::Client, Self::Error> {
+        let locality = self.prefer_local.as_ref().and_then(|p| p.locality(ep.labels()));
+        let inner = match locality {
+            None => self.inner.make_client(ep)?,
+            Some(locality) => {
+                let mut ep = ep.clone();
+                ep.set_locality(locality);
+                self.inner.make_client(&ep)?
+            }
+        };
+
+        let attrs = Attrs {
+            weight: ep.weight(),
+            locality,
+        };
+        Ok(Attributed { inner, attrs })
+    }
+}
+
+// ===== impl Attributed =====
+
+impl<S: Service> Service for Attributed<S> {
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        self.inner.call(req)
+    }
+}
+
+// ===== impl WithAttributes =====
+
+impl<D> WithAttributes<D> {
+    pub fn new(inner: D) -> Self {
+        Self { inner }
+    }
+}
+
+impl<D, S> Discover for WithAttributes<D>
+where
+    D: Discover<Key = SocketAddr, Service = Attributed<S>>,
+    S: Service<Request = D::Request, Response = D::Response, Error = D::Error>,
+{
+    type Key = Key;
+    type Request = D::Request;
+    type Response = D::Response;
+    type Error = D::Error;
+    type Service = S;
+    type DiscoverError = D::DiscoverError;
+
+    fn poll(&mut self) -> Poll<Change<Self::Key, Self::Service>, Self::DiscoverError> {
+        let change = match try_ready!(self.inner.poll()) {
+            Change::Insert(addr, Attributed { inner, attrs }) => {
+                trace!("discovered {} with {:?}", addr, attrs);
+                Change::Insert(Key::new(addr, attrs), inner)
+            }
+            Change::Remove(addr) => Change::Remove(Key::new(addr, Attrs::default())),
+        };
+        Ok(Async::Ready(change))
+    }
+}
+
+// ===== impl Key =====
+
+impl Key {
+    pub(super) fn new(addr: SocketAddr, attrs: Attrs) -> Self {
+        Self { addr, attrs }
+    }
+
+    pub(super) fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub(super) fn attrs(&self) -> Attrs {
+        self.attrs
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.addr == other.addr
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.addr.hash(state)
+    }
+}
+
+// ===== impl Attrs =====
+
+impl Attrs {
+    pub(super) fn is_local(&self) -> bool {
+        self.locality == Some(Locality::Local)
+    }
+}
+
+impl Default for Attrs {
+    fn default() -> Self {
+        Self {
+            weight: DEFAULT_WEIGHT,
+            locality: None,
+        }
+    }
+}
+
+// ===== impl Subset =====
+
+impl Subset {
+    fn contains(&self, attrs: &Attrs) -> bool {
+        match *self {
+            Subset::All => true,
+            Subset::Local => attrs.is_local(),
+            Subset::NotLocal => !attrs.is_local(),
+        }
+    }
+}
+
+// ===== impl WeightedPowerOfTwoChoices =====
+
+impl WeightedPowerOfTwoChoices {
+    pub fn new(prefer_local: Option<&PreferLocal>) -> Self {
+        Self {
+            min_local_ready: prefer_local.map(|p| p.min_ready),
+        }
+    }
+
+    /// Determines the locality from which replicas should be chosen, given
+    /// the number of `local` endpoints, whether or not they are ready.
+    fn subset<R: Rng, S>(&self, rng: &mut R, replicas: &[Replica<S>], local: usize) -> Subset {
+        let min_ready = match self.min_local_ready {
+            Some(min) => min,
+            None => return Subset::All,
+        };
+
+        let local_ready = replicas.iter()
+            .filter(|replica| replica.attrs.is_local())
+            .count();
+        if local_ready == 0 {
+            return Subset::All;
+        }
+        if local_ready == replicas.len() {
+            return Subset::Local;
+        }
+
+        let ready = local_ready as f32 / local.max(local_ready) as f32;
+        if ready >= min_ready || rng.gen::<f32>() < ready / min_ready {
+            Subset::Local
+        } else {
+            trace!("spilling over: {}/{} local endpoints are ready", local_ready, local);
+            Subset::NotLocal
+        }
+    }
+
+    /// Returns the index of the `n`th replica in `subset`.
+    fn nth<S>(replicas: &[Replica<S>], subset: Subset, n: usize) -> usize {
+        (0..replicas.len())
+            .filter(|&i| subset.contains(&replicas[i].attrs))
+            .nth(n)
+            .expect("subset must have n replicas")
+    }
+
+    /// Returns the index of the replica to which a request is sent, out of
+    /// `replicas` that are ready, or `None` if there are none.
+    ///
+    /// `local` is the number of local endpoints, whether or not they are
+    /// ready.
+    pub(super) fn choose<S>(&self, replicas: &[Replica<S>], local: usize) -> Option<usize>
+    where
+        S: Load,
+        S::Metric: PartialOrd,
+    {
+        self.choose_with(&mut rand::thread_rng(), replicas, local)
+    }
+
+    fn choose_with<R, S>(
+        &self,
+        rng: &mut R,
+        replicas: &[Replica<S>],
+        local: usize,
+    ) -> Option<usize>
+    where
+        R: Rng,
+        S: Load,
+        S::Metric: PartialOrd,
+    {
+        match replicas.len() {
+            0 => return None,
+            1 => return Some(0),
+            _ => {}
+        }
+        let subset = self.subset(rng, replicas, local);
+
+        let weights = replicas.iter()
+            .map(|replica| {
+                if subset.contains(&replica.attrs) {
+                    u64::from(replica.attrs.weight)
+                } else {
+                    0
+                }
+            })
+            .collect::<Vec<_>>();
+        let len = replicas.iter()
+            .filter(|replica| subset.contains(&replica.attrs))
+            .count();
+        if len < 2 {
+            return Some(Self::nth(replicas, subset, 0));
+        }
+
+        let total = weights.iter().sum::<u64>();
+        let (a, b) = if total == 0 {
+            // No replica has a weight, so choose a distinct pair uniformly.
+            let a = rng.gen_range(0, len);
+            let b = (a + rng.gen_range(1, len)) % len;
+            (Self::nth(replicas, subset, a), Self::nth(replicas, subset, b))
+        } else {
+            let a = sample(rng, &weights, total, None);
+            let remaining = total - weights[a];
+            if remaining == 0 {
+                // Only `a` has a weight.
+                return Some(a);
+            }
+            let b = sample(rng, &weights, remaining, Some(a));
+            (a, b)
+        };
+
+        if replicas[a].service.load() <= replicas[b].service.load() {
+            Some(a)
+        } else {
+            Some(b)
+        }
+    }
+}
+
+/// Returns the index of a replica, chosen with a probability proportional to
+/// its weight, out of those that are not `excluded`.
+///
+/// `total` must be the sum of the weights of the replicas that are not
+/// excluded, and must not be zero.
+fn sample<R: Rng>(rng: &mut R, weights: &[u64], total: u64, excluded: Option<usize>) -> usize {
+    let mut n = rng.gen_range(0, total);
+    for (i, &weight) in weights.iter().enumerate() {
+        if Some(i) == excluded {
+            continue;
+        }
+        if n < weight {
+            return i;
+        }
+        n -= weight;
+    }
+    unreachable!("total must be the sum of the replicas' weights");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{prng::XorShiftRng, SeedableRng};
+
+    fn labels(region: &str, zone: &str) -> IndexMap<String, String> {
+        let mut labels = IndexMap::new();
+        labels.insert(REGION_LABEL.to_owned(), region.to_owned());
+        labels.insert(ZONE_LABEL.to_owned(), zone.to_owned());
+        labels
+    }
+
+    #[test]
+    fn locality_matches_configured_labels() {
+        let mut prefer = PreferLocal {
+            region: Some("us-east-1".into()),
+            zone: Some("us-east-1a".into()),
+            min_ready: 0.7,
+        };
+        let local = Some(Locality::Local);
+        let remote = Some(Locality::Remote);
+        assert_eq!(prefer.locality(&labels("us-east-1", "us-east-1a")), local);
+        assert_eq!(prefer.locality(&labels("us-east-1", "us-east-1b")), remote);
+        assert_eq!(prefer.locality(&IndexMap::new()), None);
+
+        prefer.zone = None;
+        assert_eq!(prefer.locality(&labels("us-east-1", "us-east-1b")), local);
+        assert_eq!(prefer.locality(&labels("us-west-2", "us-west-2a")), remote);
+    }
+
+    #[test]
+    fn keys_are_compared_by_address() {
+        let addr = SocketAddr::from(([10, 1, 1, 1], 8080));
+        let weighted = Key::new(addr, Attrs { weight: 10, locality: Some(Locality::Local) });
+        assert_eq!(weighted, Key::new(addr, Attrs::default()));
+
+        let other = SocketAddr::from(([10, 1, 1, 2], 8080));
+        assert_ne!(weighted, Key::new(other, weighted.attrs()));
+    }
+
+    #[test]
+    fn chooses_replicas_in_proportion_to_their_weights() {
+        let services = [Loaded(0), Loaded(0), Loaded(0), Loaded(0)];
+        let replicas = replicas(&services, &[(1, None), (3, None), (0, None), (4, None)]);
+        let counts = choices(&WeightedPowerOfTwoChoices::new(None), &replicas, 0);
+
+        // Loads are equal, so the first replica of each pair is chosen, with
+        // a probability proportional to its weight.
+        assert_near(counts[0], CHOICES / 8);
+        assert_near(counts[1], CHOICES * 3 / 8);
+        assert_eq!(counts[2], 0, "replicas without a weight are drained");
+        assert_near(counts[3], CHOICES / 2);
+    }
+
+    #[test]
+    fn chooses_replicas_uniformly_without_weights() {
+        let services = [Loaded(0), Loaded(0), Loaded(0)];
+        let replicas = replicas(&services, &[(0, None), (0, None), (0, None)]);
+        let counts = choices(&WeightedPowerOfTwoChoices::new(None), &replicas, 0);
+        for &count in &counts {
+            assert_near(count, CHOICES / 3);
+        }
+    }
+
+    #[test]
+    fn chooses_the_less_loaded_of_each_pair() {
+        let services = [Loaded(10), Loaded(0), Loaded(0)];
+        let replicas = replicas(&services, &[(10, None), (1, None), (1, None)]);
+        let counts = choices(&WeightedPowerOfTwoChoices::new(None), &replicas, 0);
+
+        // The heavily weighted replica is in most pairs, but it is never
+        // chosen while it is more loaded than the other replica.
+        assert_eq!(counts[0], 0);
+        assert_near(counts[1], CHOICES / 2);
+        assert_near(counts[2], CHOICES / 2);
+    }
+
+    #[test]
+    fn chooses_local_replicas_while_enough_are_ready() {
+        let choose = WeightedPowerOfTwoChoices::new(Some(&prefer_local(0.5)));
+        let local = Some(Locality::Local);
+        let remote = Some(Locality::Remote);
+        let services = [Loaded(0), Loaded(0), Loaded(0), Loaded(0)];
+        let replicas = replicas(&services, &[(1, local), (1, local), (1, remote), (1, remote)]);
+        let mut rng = XorShiftRng::from_seed([7; 16]);
+
+        // Half of the local endpoints are ready, which meets the threshold.
+        assert_eq!(choose.subset(&mut rng, &replicas, 4), Subset::Local);
+        let counts = choices(&choose, &replicas, 4);
+        assert_near(counts[0], CHOICES / 2);
+        assert_near(counts[1], CHOICES / 2);
+        assert_eq!(counts[2] + counts[3], 0);
+
+        // Without any remote replicas, local replicas are always chosen.
+        assert_eq!(choose.subset(&mut rng, &replicas[..2], 8), Subset::Local);
+
+        // Without any ready local replicas, any replica may be chosen.
+        assert_eq!(choose.subset(&mut rng, &replicas[2..], 2), Subset::All);
+
+        // Without a preference, any replica may be chosen.
+        let choose = WeightedPowerOfTwoChoices::new(None);
+        assert_eq!(choose.subset(&mut rng, &replicas, 4), Subset::All);
+    }
+
+    #[test]
+    fn spills_over_in_proportion_to_the_shortfall() {
+        let choose = WeightedPowerOfTwoChoices::new(Some(&prefer_local(0.5)));
+        let local = Some(Locality::Local);
+        let remote = Some(Locality::Remote);
+        let services = [Loaded(0), Loaded(0), Loaded(0), Loaded(0)];
+        let replicas = replicas(&services, &[(1, local), (1, local), (1, remote), (1, remote)]);
+
+        // A quarter of the local endpoints are ready, which is half of the
+        // threshold, so half of the requests spill over.
+        let counts = choices(&choose, &replicas, 8);
+        assert_near(counts[0] + counts[1], CHOICES / 2);
+        assert_near(counts[2], CHOICES / 4);
+        assert_near(counts[3], CHOICES / 4);
+
+        // An eighth of the local endpoints are ready, so three quarters of
+        // the requests spill over.
+        let counts = choices(&choose, &replicas, 16);
+        assert_near(counts[0] + counts[1], CHOICES / 4);
+        assert_near(counts[2] + counts[3], CHOICES * 3 / 4);
+    }
+
+    const CHOICES: usize = 10_000;
+
+    fn prefer_local(min_ready: f32) -> PreferLocal {
+        PreferLocal {
+            region: Some("us-east-1".into()),
+            zone: None,
+            min_ready,
+        }
+    }
+
+    struct Loaded(u64);
+
+    impl Load for Loaded {
+        type Metric = u64;
+
+        fn load(&self) -> u64 {
+            self.0
+        }
+    }
+
+    /// Returns replicas of `services` with the given weights and localities.
+    fn replicas<'a>(
+        services: &'a [Loaded],
+        attrs: &[(u32, Option<Locality>)],
+    ) -> Vec<Replica<'a, Loaded>> {
+        services.iter()
+            .zip(attrs)
+            .map(|(service, &(weight, locality))| Replica {
+                attrs: Attrs { weight, locality },
+                service,
+            })
+            .collect()
+    }
+
+    /// Returns the number of times that each replica is chosen, of
+    /// `CHOICES` choices.
+    fn choices(
+        choose: &WeightedPowerOfTwoChoices,
+        replicas: &[Replica<Loaded>],
+        local: usize,
+    ) -> Vec<usize> {
+        let mut rng = XorShiftRng::from_seed([7; 16]);
+        let mut counts = vec![0; replicas.len()];
+        for _ in 0..CHOICES {
+            let i = choose.choose_with(&mut rng, replicas, local).unwrap();
+            counts[i] += 1;
+        }
+        counts
+    }
+
+    /// Asserts that `count` is within 3% of `CHOICES` of `expected`.
+    fn assert_near(count: usize, expected: usize) {
+        let tolerance = CHOICES * 3 / 100;
+        assert!(
+            count + tolerance >= expected && count <= expected + tolerance,
+            "chosen {} times; expected about {}",
+            count,
+            expected,
+        );
+    }
+}
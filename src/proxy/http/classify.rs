@@ -0,0 +1,52 @@
+//! Classifies the results of requests for the middleware that react to a
+//! destination failing: retries, outlier detection, and concurrency limits.
+//!
+//! Only failures that indicate that the destination could not serve a request
+//! are counted: a connection error, a server error, or a gRPC `UNAVAILABLE`
+//! status. Other non-OK gRPC statuses (e.g. `NOT_FOUND`) describe the request,
+//! so they are not failures here, even though they are reported as such in
+//! response metrics.
+
+use http;
+
+use super::grpc;
+
+/// Returns true if the request failed, or its response indicates that the
+/// destination could not serve it.
+pub(super) fn is_failure<B, E>(result: &Result<http::Response<B>, E>) -> bool {
+    let rsp = match *result {
+        Ok(ref rsp) => rsp,
+        Err(_) => return true,
+    };
+    rsp.status().is_server_error() || rsp.headers()
+        .get(grpc::GRPC_STATUS)
+        .map_or(false, |code| code == grpc::UNAVAILABLE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_errors_and_unavailable_grpc_responses_are_failures() {
+        let rsp = |status: u16, grpc_status: Option<&str>| {
+            let mut rsp = http::Response::builder();
+            rsp.status(status);
+            if let Some(code) = grpc_status {
+                rsp.header(grpc::GRPC_STATUS, code);
+            }
+            Ok::<_, ()>(rsp.body(()).unwrap())
+        };
+
+        assert!(!is_failure(&rsp(200, None)));
+        assert!(!is_failure(&rsp(404, None)));
+        assert!(!is_failure(&rsp(429, None)));
+        assert!(is_failure(&rsp(500, None)));
+        assert!(is_failure(&rsp(503, None)));
+        assert!(!is_failure(&rsp(200, Some("0"))));
+        assert!(!is_failure(&rsp(200, Some("5"))));
+        assert!(!is_failure(&rsp(200, Some(grpc::DEADLINE_EXCEEDED))));
+        assert!(is_failure(&rsp(200, Some(grpc::UNAVAILABLE))));
+        assert!(is_failure(&Err::<http::Response<()>, _>(())));
+    }
+}
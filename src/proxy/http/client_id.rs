@@ -0,0 +1,121 @@
+//! Tells the application which client sent each inbound request.
+//!
+//! The `l5d-client-id` header is set to the identity of the TLS client that
+//! sent the request, as verified by the proxy, so that the application may
+//! authorize requests without terminating TLS itself. Any copy of the header
+//! sent by the client is removed, so that the header may be trusted.
+
+use futures::Poll;
+use http;
+use http::header::HeaderValue;
+use std::sync::Arc;
+use tower_service::Service;
+
+use ctx;
+
+pub const L5D_CLIENT_ID: &str = "l5d-client-id";
+
+/// Sets the `l5d-client-id` header of requests to the client's verified
+/// identity.
+#[derive(Clone, Debug)]
+pub struct ClientId<S> {
+    inner: S,
+}
+
+// ===== impl ClientId =====
+
+impl<S> ClientId<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, B> Service for ClientId<S>
+where
+    S: Service<Request = http::Request<B>>,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Self::Request) -> Self::Future {
+        req.headers_mut().remove(L5D_CLIENT_ID);
+
+        let id = req.extensions()
+            .get::<Arc<ctx::transport::Server>>()
+            .and_then(|ctx| ctx.peer_identity.as_ref())
+            .and_then(|id| HeaderValue::from_str(id.as_ref()).ok());
+        if let Some(id) = id {
+            req.headers_mut().insert(L5D_CLIENT_ID, id);
+        }
+
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::{self, FutureResult};
+    use futures::Future;
+    use ctx::test_util::*;
+    use conditional::Conditional;
+    use tls;
+    use transport::tls::config_test_util::*;
+
+    /// Responds with the request's `l5d-client-id` header.
+    struct Echo;
+
+    impl Service for Echo {
+        type Request = http::Request<()>;
+        type Response = Option<HeaderValue>;
+        type Error = ();
+        type Future = FutureResult<Self::Response, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(().into())
+        }
+
+        fn call(&mut self, req: Self::Request) -> Self::Future {
+            future::ok(req.headers().get(L5D_CLIENT_ID).cloned())
+        }
+    }
+
+    #[test]
+    fn strips_client_supplied_id() {
+        let mut req = http::Request::builder()
+            .header(L5D_CLIENT_ID, "admin.deployment.ns1.linkerd-managed.linkerd.svc.cluster.local")
+            .body(())
+            .unwrap();
+        let tls = Conditional::None(tls::ReasonForNoTls::Disabled);
+        req.extensions_mut().insert(server(::ctx::Proxy::Inbound, tls));
+
+        let id = ClientId::new(Echo).call(req).wait().unwrap();
+        assert_eq!(id, None);
+    }
+
+    #[test]
+    fn sets_verified_client_id() {
+        let mut req = http::Request::builder()
+            .header(L5D_CLIENT_ID, "admin.deployment.ns1.linkerd-managed.linkerd.svc.cluster.local")
+            .body(())
+            .unwrap();
+        let addr = ([10, 1, 1, 1], 4143).into();
+        req.extensions_mut().insert(::ctx::transport::Server::new(
+            ::ctx::Proxy::Inbound,
+            &addr,
+            &addr,
+            &Some(addr),
+            Conditional::Some(()),
+            Some(FOO_NS1.peer_certificate()),
+        ));
+
+        let id = ClientId::new(Echo).call(req).wait().unwrap();
+        assert_eq!(id, Some(HeaderValue::from_static(FOO_NS1.identity)));
+    }
+}
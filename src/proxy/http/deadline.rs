@@ -0,0 +1,343 @@
+//! Enforces deadlines supplied by callers.
+//!
+//! A caller may bound how long a request may take with either gRPC's
+//! `grpc-timeout` header or the `l5d-timeout` header (in milliseconds). When
+//! a request is routed, its timeout is converted to a `Deadline`, which
+//! bounds every attempt to dispatch the request. Before the request is sent
+//! to an endpoint, the headers are rewritten with the time remaining so that
+//! downstream services observe the time already spent in the proxy.
+//!
+//! If the deadline passes before a response is received, the caller receives
+//! a `504 Gateway Timeout` or, for gRPC requests, a `DEADLINE_EXCEEDED`
+//! status.
+
+use futures::{Async, Future, Poll};
+use http;
+use std::time::{Duration, Instant};
+use tower_service::Service;
+
+use timeout;
+use super::grpc;
+
+const GRPC_TIMEOUT: &str = "grpc-timeout";
+const L5D_TIMEOUT: &str = "l5d-timeout";
+
+/// gRPC timeouts may not have more than 8 digits.
+const GRPC_TIMEOUT_MAX_DIGITS: usize = 8;
+
+/// The instant by which a request must be completed.
+///
+/// This is added to a request's `Extensions` by `Enforce`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Deadline(pub Instant);
+
+/// Sets a `Deadline` on requests that carry a timeout, and fails requests
+/// that do not complete before it.
+#[derive(Clone, Debug)]
+pub struct Enforce<S> {
+    inner: S,
+}
+
+pub struct ResponseFuture<F> {
+    inner: Option<F>,
+    expiry: Option<Expiry>,
+}
+
+/// Rewrites the timeout headers of requests with a `Deadline` to reflect the
+/// time remaining.
+#[derive(Copy, Clone, Debug)]
+pub struct Propagate<S> {
+    inner: S,
+}
+
+/// Limits each request's `Timeout` to the time remaining before its
+/// `Deadline`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Remaining;
+
+#[derive(Copy, Clone, Debug)]
+struct Expiry {
+    deadline: Deadline,
+    is_grpc: bool,
+}
+
+/// Returns the shortest timeout carried in the request's headers, if any.
+fn timeout(headers: &http::HeaderMap) -> Option<Duration> {
+    let grpc = headers.get(GRPC_TIMEOUT).and_then(parse_grpc_timeout);
+    let l5d = headers.get(L5D_TIMEOUT).and_then(parse_l5d_timeout);
+    match (grpc, l5d) {
+        (Some(g), Some(l)) => Some(if g < l { g } else { l }),
+        (g, l) => g.or(l),
+    }
+}
+
+/// Parses a gRPC `TimeoutValue TimeoutUnit`, e.g. `100m`.
+fn parse_grpc_timeout(value: &http::header::HeaderValue) -> Option<Duration> {
+    let s = value.to_str().ok()?;
+    if s.len() < 2 {
+        return None;
+    }
+    let (digits, unit) = s.split_at(s.len() - 1);
+    if digits.len() > GRPC_TIMEOUT_MAX_DIGITS || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let n = digits.parse::<u64>().ok()?;
+
+    let d = match unit {
+        "H" => Duration::from_secs(n * 60 * 60),
+        "M" => Duration::from_secs(n * 60),
+        "S" => Duration::from_secs(n),
+        "m" => Duration::from_millis(n),
+        "u" => Duration::from_micros(n),
+        "n" => Duration::from_nanos(n),
+        _ => return None,
+    };
+    Some(d)
+}
+
+/// Parses an `l5d-timeout` as a number of milliseconds.
+fn parse_l5d_timeout(value: &http::header::HeaderValue) -> Option<Duration> {
+    value.to_str().ok()?.trim().parse::<u64>().ok().map(Duration::from_millis)
+}
+
+/// Encodes a duration as a gRPC timeout, using the most precise unit that
+/// fits within 8 digits.
+fn encode_grpc_timeout(d: Duration) -> http::header::HeaderValue {
+    const MAX: u64 = 100_000_000;
+    let nanos = d.as_secs()
+        .saturating_mul(1_000_000_000)
+        .saturating_add(u64::from(d.subsec_nanos()));
+
+    let s = if nanos < MAX {
+        format!("{}n", nanos)
+    } else if nanos / 1_000 < MAX {
+        format!("{}u", nanos / 1_000)
+    } else if nanos / 1_000_000 < MAX {
+        format!("{}m", nanos / 1_000_000)
+    } else if d.as_secs() < MAX {
+        format!("{}S", d.as_secs())
+    } else if d.as_secs() / 60 < MAX {
+        format!("{}M", d.as_secs() / 60)
+    } else {
+        format!("{}H", ::std::cmp::min(d.as_secs() / (60 * 60), MAX - 1))
+    };
+    http::header::HeaderValue::from_str(&s).expect("timeout must be a valid header")
+}
+
+fn encode_l5d_timeout(d: Duration) -> http::header::HeaderValue {
+    let millis = d.as_secs()
+        .saturating_mul(1_000)
+        .saturating_add(u64::from(d.subsec_nanos() / 1_000_000));
+    http::header::HeaderValue::from_str(&millis.to_string())
+        .expect("timeout must be a valid header")
+}
+
+// ===== impl Deadline =====
+
+impl Deadline {
+    /// Returns the time remaining before the deadline, which is zero if it
+    /// has already passed.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        if self.0 > now {
+            self.0 - now
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+}
+
+// ===== impl Enforce =====
+
+impl<S> Enforce<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, A, B> Service for Enforce<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>>,
+    B: Default,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Self::Request) -> Self::Future {
+        let timeout = match timeout(req.headers()) {
+            Some(t) => t,
+            None => return ResponseFuture {
+                inner: Some(self.inner.call(req)),
+                expiry: None,
+            },
+        };
+
+        let expiry = Expiry {
+            deadline: Deadline(Instant::now() + timeout),
+            is_grpc: grpc::is_grpc(&req),
+        };
+        if timeout == Duration::from_secs(0) {
+            debug!("request deadline already exceeded");
+            return ResponseFuture {
+                inner: None,
+                expiry: Some(expiry),
+            };
+        }
+
+        trace!("request deadline in {:?}", timeout);
+        req.extensions_mut().insert(expiry.deadline);
+        ResponseFuture {
+            inner: Some(self.inner.call(req)),
+            expiry: Some(expiry),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+    B: Default,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Some(ref mut inner) = self.inner {
+            let now = Instant::now();
+            match inner.poll() {
+                Err(_) if self.expiry.map_or(false, |e| e.is_exceeded(now)) => {
+                    debug!("request deadline exceeded");
+                }
+                poll => return poll,
+            }
+        }
+
+        let expiry = self.expiry.expect("only expired requests are failed");
+        Ok(Async::Ready(expiry.response()))
+    }
+}
+
+// ===== impl Expiry =====
+
+impl Expiry {
+    fn is_exceeded(&self, now: Instant) -> bool {
+        self.deadline.0 <= now
+    }
+
+    fn response<B: Default>(&self) -> http::Response<B> {
+        let status = http::StatusCode::GATEWAY_TIMEOUT;
+        grpc::failure(self.is_grpc, status, grpc::DEADLINE_EXCEEDED, "Deadline Exceeded")
+    }
+}
+
+// ===== impl Propagate =====
+
+impl<S> Propagate<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, B> Service for Propagate<S>
+where
+    S: Service<Request = http::Request<B>>,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Self::Request) -> Self::Future {
+        let deadline = req.extensions().get::<Deadline>().cloned();
+        if let Some(deadline) = deadline {
+            let remaining = deadline.remaining(Instant::now());
+            let headers = req.headers_mut();
+            if headers.contains_key(GRPC_TIMEOUT) {
+                headers.insert(GRPC_TIMEOUT, encode_grpc_timeout(remaining));
+            }
+            if headers.contains_key(L5D_TIMEOUT) {
+                headers.insert(L5D_TIMEOUT, encode_l5d_timeout(remaining));
+            }
+        }
+
+        self.inner.call(req)
+    }
+}
+
+// ===== impl Remaining =====
+
+impl<B> timeout::Deadline<http::Request<B>> for Remaining {
+    fn deadline(&self, req: &http::Request<B>) -> Option<Duration> {
+        req.extensions()
+            .get::<Deadline>()
+            .map(|d| d.remaining(Instant::now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::HeaderValue;
+
+    #[test]
+    fn parses_grpc_timeouts() {
+        let parse = |s| parse_grpc_timeout(&HeaderValue::from_static(s));
+        assert_eq!(parse("2H"), Some(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(parse("3M"), Some(Duration::from_secs(3 * 60)));
+        assert_eq!(parse("10S"), Some(Duration::from_secs(10)));
+        assert_eq!(parse("100m"), Some(Duration::from_millis(100)));
+        assert_eq!(parse("250u"), Some(Duration::from_micros(250)));
+        assert_eq!(parse("99999999n"), Some(Duration::from_nanos(99_999_999)));
+
+        assert_eq!(parse("m"), None);
+        assert_eq!(parse("10"), None);
+        assert_eq!(parse("10s"), None);
+        assert_eq!(parse("-1S"), None);
+        assert_eq!(parse("123456789m"), None);
+    }
+
+    #[test]
+    fn parses_l5d_timeouts() {
+        let parse = |s| parse_l5d_timeout(&HeaderValue::from_static(s));
+        assert_eq!(parse("1500"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse("0"), Some(Duration::from_secs(0)));
+        assert_eq!(parse("1.5s"), None);
+    }
+
+    #[test]
+    fn uses_shortest_timeout() {
+        let mut headers = http::HeaderMap::new();
+        assert_eq!(timeout(&headers), None);
+
+        headers.insert(L5D_TIMEOUT, HeaderValue::from_static("500"));
+        assert_eq!(timeout(&headers), Some(Duration::from_millis(500)));
+
+        headers.insert(GRPC_TIMEOUT, HeaderValue::from_static("1S"));
+        assert_eq!(timeout(&headers), Some(Duration::from_millis(500)));
+
+        headers.insert(GRPC_TIMEOUT, HeaderValue::from_static("100m"));
+        assert_eq!(timeout(&headers), Some(Duration::from_millis(100)));
+    }
+
+    quickcheck! {
+        fn grpc_timeouts_roundtrip_within_a_unit(secs: u32, nanos: u32) -> bool {
+            let secs = u64::from(secs) % 100_000_000;
+            let d = Duration::new(secs, nanos % 1_000_000_000);
+            let encoded = encode_grpc_timeout(d);
+            let decoded = parse_grpc_timeout(&encoded).expect("must parse");
+            // Encoding may only truncate to a coarser unit.
+            decoded <= d && d - decoded < Duration::from_secs(1)
+        }
+    }
+}
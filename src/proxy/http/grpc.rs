@@ -0,0 +1,69 @@
+//! Recognizes gRPC requests, and builds the responses with which the proxy
+//! fails requests itself.
+//!
+//! A gRPC client expects a failure to be described by a `grpc-status`, so a
+//! gRPC request is failed with a `200 OK` that carries one, rather than with
+//! an HTTP error status.
+
+use http;
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+
+pub(super) const GRPC_STATUS: &str = "grpc-status";
+const GRPC_MESSAGE: &str = "grpc-message";
+
+pub(super) const DEADLINE_EXCEEDED: &str = "4";
+pub(super) const PERMISSION_DENIED: &str = "7";
+pub(super) const RESOURCE_EXHAUSTED: &str = "8";
+pub(super) const UNAVAILABLE: &str = "14";
+
+pub(super) fn is_grpc<B>(req: &http::Request<B>) -> bool {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .map(|ct| ct.starts_with("application/grpc"))
+        .unwrap_or(false)
+}
+
+/// Returns an empty response that fails a request with `status` or, if it is
+/// a gRPC request, with the gRPC status `code` and `message`.
+pub(super) fn failure<B: Default>(
+    is_grpc: bool,
+    status: http::StatusCode,
+    code: &'static str,
+    message: &'static str,
+) -> http::Response<B> {
+    let builder = &mut http::Response::builder();
+    if is_grpc {
+        builder
+            .status(http::StatusCode::OK)
+            .header(CONTENT_TYPE, "application/grpc")
+            .header(GRPC_STATUS, code)
+            .header(GRPC_MESSAGE, message);
+    } else {
+        builder.status(status);
+    }
+    builder
+        .header(CONTENT_LENGTH, "0")
+        .body(B::default())
+        .expect("failure response must be valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fails_grpc_requests_with_a_grpc_status() {
+        let status = http::StatusCode::GATEWAY_TIMEOUT;
+        let rsp: http::Response<()> = failure(true, status, DEADLINE_EXCEEDED, "Deadline Exceeded");
+        assert_eq!(rsp.status(), http::StatusCode::OK);
+        assert_eq!(rsp.headers()[CONTENT_TYPE], "application/grpc");
+        assert_eq!(rsp.headers()[GRPC_STATUS], DEADLINE_EXCEEDED);
+        assert_eq!(rsp.headers()[GRPC_MESSAGE], "Deadline Exceeded");
+
+        let rsp: http::Response<()> = failure(false, status, DEADLINE_EXCEEDED, "Deadline Exceeded");
+        assert_eq!(rsp.status(), http::StatusCode::GATEWAY_TIMEOUT);
+        assert!(rsp.headers().get(GRPC_STATUS).is_none());
+        assert_eq!(rsp.headers()[CONTENT_LENGTH], "0");
+    }
+}
@@ -0,0 +1,207 @@
+//! Hedges slow requests, within a budget.
+//!
+//! If a request has not received a response within a percentile of its
+//! destination's recent response latencies, a copy of the request is sent,
+//! which the balancer sends to another endpoint. Whichever response is
+//! received first is used, and the other request is canceled. Requests are
+//! not hedged until enough responses have been observed.
+//!
+//! Latencies are recorded in windows, and only the current and previous
+//! windows are used, so that the hedge delay adapts as the destination's
+//! latency changes. This is why the policy keeps its own histograms, with the
+//! same buckets as the `response_latency_ms` metric, rather than reading that
+//! metric: it accumulates latencies for as long as the proxy runs, and is
+//! scoped by each response's labels rather than by destination.
+//!
+//! Hedging is opt-in per destination, since it adds load to the destination's
+//! endpoints. Opting in declares that the destination's requests are safe to
+//! send twice, so they are hedged whatever their method; see `super::retry`,
+//! which sends and races the hedges.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use linkerd2_metrics::{latency, Bucket, Histogram};
+
+use telemetry::http::Hedges;
+use super::retry::{Budget, Windows};
+
+/// Hedges may not exceed 10% of requests over the budget's TTL...
+const HEDGE_RATIO: f32 = 0.1;
+
+/// ...except that 1 hedge per second is always permitted.
+const MIN_HEDGES_PER_SEC: u32 = 1;
+
+const HEDGE_BUDGET_TTL: Duration = Duration::from_secs(10);
+
+/// Requests are not hedged until the destination has this many recent
+/// responses, since percentiles of fewer latencies are not meaningful.
+const MIN_SAMPLES: u64 = 100;
+
+/// Latencies are forgotten after one to two windows of this length.
+const LATENCY_WINDOW: Duration = Duration::from_secs(10);
+
+/// Determines when a destination's requests are hedged.
+#[derive(Clone, Debug)]
+pub struct Policy {
+    percentile: f64,
+    budget: Arc<Budget>,
+    hedges: Hedges,
+    latencies: Arc<Mutex<Windows<Histogram<latency::Ms>>>>,
+}
+
+// ===== impl Policy =====
+
+impl Policy {
+    /// Hedges requests that are slower than `percentile` (between 0 and 1) of
+    /// the destination's recent responses.
+    pub fn new(percentile: f64, hedges: Hedges) -> Self {
+        let budget = Budget::new(HEDGE_BUDGET_TTL, MIN_HEDGES_PER_SEC, HEDGE_RATIO);
+        Self {
+            percentile,
+            budget: Arc::new(budget),
+            hedges,
+            latencies: Arc::new(Mutex::new(Windows::new(Instant::now()))),
+        }
+    }
+
+    /// Returns how long a request sent at `now` may wait for its response
+    /// before it is hedged.
+    ///
+    /// Returns `None` if too few responses have been observed recently.
+    pub(super) fn delay(&self, now: Instant) -> Option<Duration> {
+        let mut latencies = self.latencies.lock().ok()?;
+        latencies.rotate(now, LATENCY_WINDOW);
+
+        let buckets = (&latencies.current).into_iter()
+            .zip(&latencies.previous)
+            .map(|((&bucket, &a), (_, &b))| {
+                let (a, b): (u64, u64) = (a.into(), b.into());
+                (bucket, a + b)
+            })
+            .collect::<Vec<_>>();
+        let delay = percentile(&buckets, self.percentile, MIN_SAMPLES);
+        trace!("hedge delay: {:?}", delay);
+        delay
+    }
+
+    /// Records the latency of a response to a request that was sent at
+    /// `sent`.
+    pub(super) fn record_latency(&self, sent: Instant, now: Instant) {
+        if let Ok(mut latencies) = self.latencies.lock() {
+            latencies.rotate(now, LATENCY_WINDOW);
+            latencies.current.add(now - sent);
+        }
+    }
+
+    /// Records a request.
+    pub(super) fn deposit(&self, now: Instant) {
+        self.budget.deposit(now);
+    }
+
+    /// Records a hedge, if one is permitted by the budget.
+    ///
+    /// Returns false if the budget has been exhausted.
+    pub(super) fn issue(&mut self, now: Instant) -> bool {
+        if !self.budget.withdraw(now) {
+            return false;
+        }
+        self.hedges.issue();
+        true
+    }
+
+    /// Records that a hedge's response was used.
+    pub(super) fn win(&mut self) {
+        self.hedges.win();
+    }
+}
+
+/// Returns the latency under which `percentile` of the latencies counted in
+/// `buckets` fall.
+///
+/// The latency is the upper bound of the bucket containing the percentile. If
+/// fewer than `min_samples` latencies have been counted, or if the percentile
+/// exceeds the largest bucket, no latency is returned.
+fn percentile(buckets: &[(Bucket, u64)], percentile: f64, min_samples: u64) -> Option<Duration> {
+    let total = buckets.iter().map(|&(_, count)| count).sum::<u64>();
+    if total == 0 || total < min_samples {
+        return None;
+    }
+
+    let target = (total as f64 * percentile).ceil() as u64;
+    let mut observed = 0;
+    for &(bucket, count) in buckets {
+        observed += count;
+        if observed >= target {
+            return match bucket {
+                Bucket::Le(ms) => Some(Duration::from_millis(ms)),
+                Bucket::Inf => None,
+            };
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ctx;
+    use telemetry::http::{RouteLabels, Sensors};
+
+    fn policy(percentile: f64) -> Policy {
+        let labels = RouteLabels::new(ctx::Proxy::Outbound, "web.ns:8080");
+        let hedges = Sensors::for_test().hedges(labels);
+        Policy::new(percentile, hedges)
+    }
+
+    fn record(policy: &Policy, n: usize, latency: Duration, now: Instant) {
+        for _ in 0..n {
+            policy.record_latency(now - latency, now);
+        }
+    }
+
+    #[test]
+    fn delay_requires_min_samples() {
+        let policy = policy(0.9);
+        let now = Instant::now();
+
+        record(&policy, 99, Duration::from_millis(5), now);
+        assert_eq!(policy.delay(now), None);
+
+        record(&policy, 1, Duration::from_millis(5), now);
+        assert_eq!(policy.delay(now), Some(Duration::from_millis(5)));
+    }
+
+    #[test]
+    fn delay_is_a_percentile_of_recent_latencies() {
+        let policy = policy(0.9);
+        let t0 = Instant::now();
+
+        record(&policy, 90, Duration::from_millis(5), t0);
+        record(&policy, 10, Duration::from_millis(250), t0);
+        assert_eq!(policy.delay(t0), Some(Duration::from_millis(5)));
+
+        // Latencies from the previous window are still used.
+        let t1 = t0 + LATENCY_WINDOW;
+        record(&policy, 100, Duration::from_millis(250), t1);
+        assert_eq!(policy.delay(t1), Some(Duration::from_millis(300)));
+
+        // Latencies from earlier windows are forgotten, so the delay adapts.
+        let t2 = t1 + LATENCY_WINDOW;
+        record(&policy, 100, Duration::from_millis(40), t2);
+        assert_eq!(policy.delay(t2), Some(Duration::from_millis(300)));
+        let t3 = t2 + LATENCY_WINDOW;
+        record(&policy, 100, Duration::from_millis(40), t3);
+        assert_eq!(policy.delay(t3), Some(Duration::from_millis(40)));
+
+        // Once no latencies are recent, requests are not hedged.
+        assert_eq!(policy.delay(t3 + LATENCY_WINDOW * 2), None);
+    }
+
+    #[test]
+    fn percentile_beyond_largest_bucket() {
+        let buckets = [(Bucket::Le(10), 50), (Bucket::Inf, 50)];
+        assert_eq!(percentile(&buckets, 0.5, 1), Some(Duration::from_millis(10)));
+        assert_eq!(percentile(&buckets, 0.9, 1), None);
+    }
+}
@@ -0,0 +1,426 @@
+//! Adapts the number of requests in flight to a destination to its latency.
+//!
+//! The limit is adjusted by additive increase and multiplicative decrease
+//! (AIMD). The recent latency, a moving average that weights each response by
+//! `1 / SMOOTHING`, is compared to a baseline, the average latency of the
+//! responses received over the last one or two windows. Comparing averages,
+//! rather than each response to the lowest latency observed, keeps a
+//! destination that serves both fast and slow requests from being treated as
+//! overloaded by its slow ones. If a request fails (see `super::classify`), or
+//! the recent latency exceeds `TOLERANCE` times the baseline, the limit is
+//! multiplied by `BACKOFF_RATIO`. Only requests sent since the limit was last
+//! decreased may decrease it again, so that a burst of slow responses shrinks
+//! the limit once. Otherwise, while at least half of the limit is in use, each
+//! response increases the limit by one.
+//!
+//! A request counts against the limit until its response's headers are
+//! received, or until it fails. Response bodies are not counted, so that
+//! long-lived streams (e.g. gRPC server streams) do not hold the limit, and a
+//! response's latency is the time to its headers.
+//!
+//! Requests that would exceed the limit are not queued: they are failed
+//! immediately with a `503 Service Unavailable` or, for gRPC requests, an
+//! `UNAVAILABLE` status, so that the proxy sheds load instead of adding to a
+//! slow destination's latency.
+
+use futures::{Async, Future, Poll};
+use http;
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tower_service::Service;
+
+use telemetry::http::Limits;
+use super::{classify, grpc};
+use super::retry::Windows;
+
+/// A recent latency of this many times the baseline latency indicates that
+/// the destination is overloaded.
+const TOLERANCE: u32 = 2;
+
+/// Each response's latency contributes `1 / SMOOTHING` of the recent latency.
+const SMOOTHING: u32 = 5;
+
+/// The baseline is not used until this many responses have been received,
+/// since the average of fewer latencies is not meaningful.
+const MIN_SAMPLES: u32 = 10;
+
+const BACKOFF_RATIO: f64 = 0.9;
+
+/// The baseline is the average latency observed over the current window and
+/// the previous one, so that it adapts when a destination's latency changes.
+const BASELINE_WINDOW: Duration = Duration::from_secs(30);
+
+/// Bounds a destination's concurrency limit.
+#[derive(Copy, Clone, Debug)]
+pub struct Config {
+    pub initial: usize,
+    pub min: usize,
+    pub max: usize,
+}
+
+/// Fails requests that would exceed a concurrency limit that adapts to the
+/// latency of responses.
+#[derive(Clone, Debug)]
+pub struct Adapt<S> {
+    inner: S,
+    state: Arc<Mutex<State>>,
+}
+
+pub struct ResponseFuture<F> {
+    /// If the request was not sent, a failure is returned instead.
+    inner: Option<F>,
+    in_flight: Option<InFlight>,
+    is_grpc: bool,
+}
+
+/// Counts a request against the limit until it is dropped.
+#[derive(Debug)]
+struct InFlight {
+    state: Arc<Mutex<State>>,
+    sent: Instant,
+}
+
+#[derive(Debug)]
+struct State {
+    config: Config,
+    limit: usize,
+    in_flight: usize,
+    baseline: Windows<Latencies>,
+    recent: Option<Duration>,
+    last_decrease: Instant,
+    limits: Limits,
+}
+
+/// The latencies of the responses received in a window.
+#[derive(Copy, Clone, Debug, Default)]
+struct Latencies {
+    total: Duration,
+    count: u32,
+}
+
+// ===== impl Adapt =====
+
+impl<S> Adapt<S> {
+    pub fn new(inner: S, config: Config, mut limits: Limits) -> Self {
+        let now = Instant::now();
+        limits.set(config.initial);
+        let state = State {
+            config,
+            limit: config.initial,
+            in_flight: 0,
+            baseline: Windows::new(now),
+            recent: None,
+            last_decrease: now,
+            limits,
+        };
+        Self {
+            inner,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+}
+
+impl<S, A, B> Service for Adapt<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>>,
+    B: Default,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let acquired = self.state.lock()
+            .map(|mut state| state.acquire())
+            .unwrap_or(true);
+        if !acquired {
+            debug!("concurrency limit reached; failing request");
+            return ResponseFuture {
+                inner: None,
+                in_flight: None,
+                is_grpc: grpc::is_grpc(&req),
+            };
+        }
+
+        let in_flight = InFlight {
+            state: self.state.clone(),
+            sent: Instant::now(),
+        };
+        ResponseFuture {
+            inner: Some(self.inner.call(req)),
+            in_flight: Some(in_flight),
+            is_grpc: false,
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+    B: Default,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = match self.inner {
+            Some(ref mut inner) => match inner.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(rsp)) => Ok(rsp),
+                Err(e) => Err(e),
+            },
+            None => return Ok(Async::Ready(unavailable(self.is_grpc))),
+        };
+
+        if let Some(in_flight) = self.in_flight.take() {
+            if let Ok(mut state) = in_flight.state.lock() {
+                let is_failure = classify::is_failure(&result);
+                state.observe(in_flight.sent, Instant::now(), is_failure);
+            }
+        }
+
+        result.map(Async::Ready)
+    }
+}
+
+fn unavailable<B: Default>(is_grpc: bool) -> http::Response<B> {
+    let status = http::StatusCode::SERVICE_UNAVAILABLE;
+    grpc::failure(is_grpc, status, grpc::UNAVAILABLE, "Concurrency Limit Exceeded")
+}
+
+// ===== impl InFlight =====
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.release();
+        }
+    }
+}
+
+// ===== impl State =====
+
+impl State {
+    fn acquire(&mut self) -> bool {
+        if self.in_flight >= self.limit {
+            return false;
+        }
+        self.in_flight += 1;
+        true
+    }
+
+    fn release(&mut self) {
+        self.in_flight = self.in_flight.saturating_sub(1);
+    }
+
+    /// Adjusts the limit for a request sent at `sent` that completed at `now`.
+    fn observe(&mut self, sent: Instant, now: Instant, is_failure: bool) {
+        let latency = now - sent;
+        let recent = self.recent.map_or(latency, |recent| {
+            recent * (SMOOTHING - 1) / SMOOTHING + latency / SMOOTHING
+        });
+        self.recent = Some(recent);
+
+        self.baseline.rotate(now, BASELINE_WINDOW);
+        let is_slow = self.baseline_latency().map_or(false, |b| recent > b * TOLERANCE);
+        self.baseline.current.add(latency);
+
+        if is_failure || is_slow {
+            if sent >= self.last_decrease {
+                let limit = (self.limit as f64 * BACKOFF_RATIO) as usize;
+                self.limit = cmp::max(limit, self.config.min);
+                self.last_decrease = now;
+                debug!("decreased concurrency limit to {}", self.limit);
+            }
+        } else if self.in_flight * 2 >= self.limit {
+            self.limit = cmp::min(self.limit + 1, self.config.max);
+            trace!("increased concurrency limit to {}", self.limit);
+        }
+
+        // The gauge is updated with every response so that it is retained
+        // while the destination is in use.
+        self.limits.set(self.limit);
+    }
+
+    /// Returns the average latency of the responses received over the current
+    /// and previous windows, if there were enough of them.
+    fn baseline_latency(&self) -> Option<Duration> {
+        let total = self.baseline.current.total + self.baseline.previous.total;
+        let count = self.baseline.current.count.saturating_add(self.baseline.previous.count);
+        if count < MIN_SAMPLES {
+            return None;
+        }
+        Some(total / count)
+    }
+}
+
+// ===== impl Latencies =====
+
+impl Latencies {
+    fn add(&mut self, latency: Duration) {
+        self.total += latency;
+        self.count = self.count.saturating_add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ctx;
+    use telemetry::Sensors;
+    use telemetry::http::RouteLabels;
+
+    const CONFIG: Config = Config {
+        initial: 10,
+        min: 2,
+        max: 12,
+    };
+
+    fn state(now: Instant) -> State {
+        State {
+            config: CONFIG,
+            limit: CONFIG.initial,
+            in_flight: 0,
+            baseline: Windows::new(now),
+            recent: None,
+            last_decrease: now,
+            limits: Sensors::for_test().limits(RouteLabels::new(ctx::Proxy::Outbound, "test")),
+        }
+    }
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn increases_while_in_use() {
+        let t0 = Instant::now();
+        let mut state = state(t0);
+
+        // Little of the limit is in use, so it is not increased.
+        assert!(state.acquire());
+        state.observe(t0, t0 + ms(10), false);
+        assert_eq!(state.limit, 10);
+
+        for _ in 0..4 {
+            assert!(state.acquire());
+        }
+        state.observe(t0, t0 + ms(10), false);
+        assert_eq!(state.limit, 11);
+
+        assert!(state.acquire());
+        state.observe(t0, t0 + ms(10), false);
+        state.observe(t0, t0 + ms(10), false);
+        assert_eq!(state.limit, CONFIG.max);
+    }
+
+    #[test]
+    fn decreases_once_per_burst_of_slow_responses() {
+        let t0 = Instant::now();
+        let mut state = state(t0);
+        for _ in 0..100 {
+            state.observe(t0, t0 + ms(10), false);
+        }
+
+        // A single slow response doesn't raise the recent latency enough.
+        state.observe(t0, t0 + ms(50), false);
+        assert_eq!(state.limit, 10);
+
+        // Both of these requests were sent before the first decrease, so only
+        // one of them decreases the limit.
+        state.observe(t0, t0 + ms(50), false);
+        assert_eq!(state.limit, 9);
+        state.observe(t0, t0 + ms(60), false);
+        assert_eq!(state.limit, 9);
+
+        let t1 = t0 + ms(60);
+        state.observe(t1, t1 + ms(50), true);
+        assert_eq!(state.limit, 8);
+    }
+
+    #[test]
+    fn does_not_decrease_below_min() {
+        let t0 = Instant::now();
+        let mut state = state(t0);
+        for i in 0..100 {
+            let sent = t0 + ms(i);
+            state.observe(sent, sent, true);
+        }
+        assert_eq!(state.limit, CONFIG.min);
+
+        assert!(state.acquire());
+        assert!(state.acquire());
+        assert!(!state.acquire());
+        state.release();
+        assert!(state.acquire());
+    }
+
+    #[test]
+    fn limit_failures_are_failures() {
+        // Limits that shed load upstream of this proxy decrease its limit, too.
+        assert!(classify::is_failure(&Ok::<_, ()>(unavailable::<()>(true))));
+        assert!(classify::is_failure(&Ok::<_, ()>(unavailable::<()>(false))));
+    }
+
+    #[test]
+    fn occasional_slow_responses_are_tolerated() {
+        let t0 = Instant::now();
+        let mut state = state(t0);
+
+        // A destination whose requests usually take 1ms, but sometimes 15ms,
+        // is not overloaded by its slow requests once they are part of its
+        // baseline.
+        let observe = |state: &mut State, i: u64| {
+            let sent = t0 + ms(i * 20);
+            let latency = if i % 10 == 0 { ms(15) } else { ms(1) };
+            state.observe(sent, sent + latency, false);
+        };
+        for i in 0..100 {
+            observe(&mut state, i);
+        }
+        let limit = state.limit;
+        for i in 100..300 {
+            observe(&mut state, i);
+        }
+        assert_eq!(state.limit, limit);
+    }
+
+    #[test]
+    fn baseline_forgets_old_windows() {
+        let t0 = Instant::now();
+        let mut state = state(t0);
+        let observe = |state: &mut State, sent: Instant, latency: Duration| {
+            for _ in 0..MIN_SAMPLES / 2 {
+                state.observe(sent, sent + latency, false);
+            }
+        };
+
+        observe(&mut state, t0, ms(10));
+        assert_eq!(state.baseline_latency(), None);
+        observe(&mut state, t0, ms(20));
+        assert_eq!(state.baseline_latency(), Some(ms(15)));
+
+        // The previous window is still considered.
+        let t1 = t0 + BASELINE_WINDOW;
+        observe(&mut state, t1, ms(30));
+        assert_eq!(state.baseline_latency(), Some(ms(20)));
+
+        let t2 = t1 + BASELINE_WINDOW;
+        observe(&mut state, t2, ms(40));
+        assert_eq!(state.baseline_latency(), Some(ms(35)));
+
+        let t3 = t2 + BASELINE_WINDOW * 2;
+        observe(&mut state, t3, ms(50));
+        assert_eq!(state.baseline_latency(), None);
+        observe(&mut state, t3, ms(50));
+        assert_eq!(state.baseline_latency(), Some(ms(50)));
+    }
+}
@@ -1,8 +1,19 @@
+pub mod admission;
+pub mod authorize;
+pub mod balance;
+mod classify;
 pub mod client;
+pub mod client_id;
+pub mod deadline;
 pub(super) mod glue;
+mod grpc;
 pub mod h1;
+pub mod hedge;
+pub mod limit;
 pub mod normalize_uri;
 pub mod orig_proto;
+pub mod outlier;
+pub mod retry;
 pub mod router;
 pub mod upgrade;
 
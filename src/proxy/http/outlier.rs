@@ -0,0 +1,597 @@
+//! Ejects failing endpoints from a load balancer.
+//!
+//! An `Eject` service tracks the outcomes of the requests dispatched to an
+//! endpoint. After several consecutive failures, the endpoint is ejected: it
+//! reports that it is not ready, so that the balancer stops dispatching
+//! requests to it. Once the ejection expires, a single probe request is
+//! admitted. If the probe succeeds, the endpoint is restored; otherwise, it is
+//! ejected again for twice as long (up to a maximum).
+//!
+//! Only a fraction of a balancer's endpoints may be ejected at once, and its
+//! last endpoint is never ejected, so that a destination with few endpoints
+//! is not left with none.
+//!
+//! Failures are classified as they are for retries (see `super::classify`).
+//! Responses are classified by their headers, so gRPC failures that are only
+//! indicated in trailers are not considered.
+
+use futures::{task, Async, Future, Poll};
+use http;
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::timer::Delay;
+use tower_service::Service;
+
+use control::destination::Endpoint;
+use ctx;
+use svc::{self, MakeClient};
+use telemetry::{self, http::{Classification, Ejections}};
+use super::classify;
+
+/// Configures ejection of failing endpoints.
+#[derive(Copy, Clone, Debug)]
+pub struct Config {
+    /// The number of consecutive failures after which an endpoint is ejected.
+    pub consecutive_failures: u32,
+
+    /// How long an endpoint is ejected the first time it fails.
+    pub base_ejection: Duration,
+
+    /// The upper bound on how long an endpoint may be ejected.
+    pub max_ejection: Duration,
+
+    /// The greatest fraction of a balancer's endpoints that may be ejected
+    /// at once.
+    pub max_ejected_ratio: f64,
+}
+
+/// Layers an `Eject` middleware over the endpoint clients of a balancer.
+#[derive(Clone, Debug)]
+pub struct Layer {
+    config: Config,
+    proxy: ctx::Proxy,
+    sensors: telemetry::Sensors,
+    pool: Arc<Mutex<Pool>>,
+}
+
+/// Uses an `M`-typed `MakeClient` to build `Eject` services for endpoints.
+#[derive(Clone, Debug)]
+pub struct Make<M> {
+    inner: M,
+    layer: Layer,
+}
+
+/// Stops advertising readiness while an endpoint is failing.
+pub struct Eject<S> {
+    inner: S,
+    state: Arc<Mutex<State>>,
+    timer: Option<Delay>,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    state: Arc<Mutex<State>>,
+
+    /// Set while this is the response to an unfinished probe, so that the
+    /// probe may be retried if this future is dropped before it completes.
+    is_probe: bool,
+}
+
+/// Counts a balancer's endpoints, and how many of them are ejected.
+#[derive(Debug, Default)]
+struct Pool {
+    endpoints: usize,
+    ejected: usize,
+}
+
+/// Shares an endpoint's health between an `Eject` and its response futures.
+#[derive(Debug)]
+struct State {
+    config: Config,
+    pool: Arc<Mutex<Pool>>,
+
+    /// Set from when the endpoint is ejected until a probe succeeds, while
+    /// the endpoint is counted as ejected by `pool`.
+    is_ejected: bool,
+
+    consecutive_failures: u32,
+
+    /// How long the endpoint will be ejected the next time it fails.
+    ejection: Duration,
+
+    /// Set while the endpoint is ejected.
+    ejected_until: Option<Instant>,
+
+    probe: Probe,
+
+    /// Notified when an ejection begins or a probe completes.
+    task: Option<task::Task>,
+
+    ejections: Ejections,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Probe {
+    /// The endpoint is healthy.
+    None,
+    /// An ejection has expired (or a probe was canceled) and the next
+    /// request will be a probe.
+    Pending,
+    /// A probe request has been dispatched and its outcome is unknown.
+    InFlight,
+}
+
+// ===== impl Layer =====
+
+impl Layer {
+    pub fn new(config: Config, proxy: ctx::Proxy, sensors: telemetry::Sensors) -> Self {
+        Self {
+            config,
+            proxy,
+            sensors,
+            pool: Arc::new(Mutex::new(Pool::default())),
+        }
+    }
+}
+
+impl<M> svc::Layer<M> for Layer
+where
+    M: MakeClient<Endpoint>,
+{
+    type Bound = Make<M>;
+
+    fn bind(&self, inner: M) -> Make<M> {
+        Make {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+// ===== impl Make =====
+
+impl<M> MakeClient<Endpoint> for Make<M>
+where
+    M: MakeClient<Endpoint>,
+{
+    type Client = Eject<M::Client>;
+    type Error = M::Error;
+
+    fn make_client(&self, ep: &Endpoint) -> Result<Self::Client, Self::Error> {
+        let inner = self.inner.make_client(ep)?;
+        let ejections = self.layer.sensors.ejections(self.layer.proxy, ep);
+        Ok(Eject::new(inner, self.layer.config, self.layer.pool.clone(), ejections))
+    }
+}
+
+// ===== impl Eject =====
+
+impl<S> Eject<S> {
+    fn new(inner: S, config: Config, pool: Arc<Mutex<Pool>>, ejections: Ejections) -> Self {
+        if let Ok(mut pool) = pool.lock() {
+            pool.endpoints += 1;
+        }
+        let state = State {
+            config,
+            pool,
+            is_ejected: false,
+            consecutive_failures: 0,
+            ejection: config.base_ejection,
+            ejected_until: None,
+            probe: Probe::None,
+            task: None,
+            ejections,
+        };
+        Self {
+            inner,
+            state: Arc::new(Mutex::new(state)),
+            timer: None,
+        }
+    }
+}
+
+impl<S, A, B> Service for Eject<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>>,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        {
+            let mut state = match self.state.lock() {
+                Ok(state) => state,
+                Err(_) => return self.inner.poll_ready(),
+            };
+
+            if let Some(until) = state.ejected_until {
+                let expired = match self.timer
+                    .get_or_insert_with(|| Delay::new(until))
+                    .poll()
+                {
+                    Ok(Async::NotReady) => false,
+                    Ok(Async::Ready(())) => true,
+                    Err(e) => {
+                        error!("ejection timer failed: {}", e);
+                        true
+                    }
+                };
+                if !expired {
+                    state.task = Some(task::current());
+                    return Ok(Async::NotReady);
+                }
+
+                debug!("ejection expired; probing endpoint");
+                state.ejected_until = None;
+                state.probe = Probe::Pending;
+                self.timer = None;
+            }
+
+            // Only one probe may be in flight at a time.
+            if state.probe == Probe::InFlight {
+                state.task = Some(task::current());
+                return Ok(Async::NotReady);
+            }
+        }
+
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let mut is_probe = false;
+        if let Ok(mut state) = self.state.lock() {
+            if state.probe == Probe::Pending {
+                state.probe = Probe::InFlight;
+                is_probe = true;
+            }
+        }
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            state: self.state.clone(),
+            is_probe,
+        }
+    }
+}
+
+impl<S> Drop for Eject<S> {
+    fn drop(&mut self) {
+        // The endpoint has been removed from the balancer.
+        if let Ok(state) = self.state.lock() {
+            if let Ok(mut pool) = state.pool.lock() {
+                pool.endpoints -= 1;
+                if state.is_ejected {
+                    pool.ejected -= 1;
+                }
+            }
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = match self.inner.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(rsp)) => Ok(rsp),
+            Err(e) => Err(e),
+        };
+
+        let class = if classify::is_failure(&result) {
+            Classification::Failure
+        } else {
+            Classification::Success
+        };
+        if let Ok(mut state) = self.state.lock() {
+            state.record(class, self.is_probe, Instant::now());
+        }
+        self.is_probe = false;
+
+        result.map(Async::Ready)
+    }
+}
+
+impl<F> Drop for ResponseFuture<F> {
+    fn drop(&mut self) {
+        if !self.is_probe {
+            return;
+        }
+
+        // The probe was canceled before its outcome was known, so the next
+        // request is admitted as a probe instead.
+        if let Ok(mut state) = self.state.lock() {
+            if state.probe == Probe::InFlight {
+                debug!("probe canceled; probing again");
+                state.probe = Probe::Pending;
+                state.notify();
+            }
+        }
+    }
+}
+
+// ===== impl State =====
+
+impl State {
+    fn record(&mut self, class: Classification, is_probe: bool, now: Instant) {
+        let was_probe = is_probe && self.probe == Probe::InFlight;
+
+        if !class.is_failure() {
+            self.consecutive_failures = 0;
+            if was_probe {
+                debug!("probe succeeded; restoring endpoint");
+                self.probe = Probe::None;
+                self.ejection = self.config.base_ejection;
+                self.restore();
+                self.notify();
+            }
+            return;
+        }
+
+        self.consecutive_failures += 1;
+        if was_probe || self.consecutive_failures >= self.config.consecutive_failures {
+            self.eject(now);
+        }
+    }
+
+    fn eject(&mut self, now: Instant) {
+        // An endpoint whose probe failed is still counted as ejected.
+        if !self.is_ejected {
+            let mut pool = match self.pool.lock() {
+                Ok(pool) => pool,
+                Err(_) => return,
+            };
+            if !pool.may_eject(self.config.max_ejected_ratio) {
+                debug!(
+                    "not ejecting endpoint; {} of {} endpoints are already ejected",
+                    pool.ejected,
+                    pool.endpoints,
+                );
+                return;
+            }
+            pool.ejected += 1;
+            self.is_ejected = true;
+        }
+
+        debug!(
+            "ejecting endpoint for {:?} after {} failures",
+            self.ejection,
+            self.consecutive_failures,
+        );
+        self.ejected_until = Some(now + self.ejection);
+        self.ejection = cmp::min(self.ejection * 2, self.config.max_ejection);
+        self.consecutive_failures = 0;
+        self.probe = Probe::None;
+        self.ejections.incr();
+        self.notify();
+    }
+
+    fn restore(&mut self) {
+        if self.is_ejected {
+            self.is_ejected = false;
+            if let Ok(mut pool) = self.pool.lock() {
+                pool.ejected -= 1;
+            }
+        }
+    }
+
+    fn notify(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.notify();
+        }
+    }
+}
+
+// ===== impl Pool =====
+
+impl Pool {
+    /// Returns true if another endpoint may be ejected without ejecting more
+    /// than `max_ratio` of the endpoints, or the last endpoint.
+    fn may_eject(&self, max_ratio: f64) -> bool {
+        self.ejected + 1 < self.endpoints &&
+            (self.ejected as f64) < max_ratio * (self.endpoints as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::net::SocketAddr;
+    use telemetry::http::Sensors;
+
+    const CONFIG: Config = Config {
+        consecutive_failures: 3,
+        base_ejection: Duration::from_secs(1),
+        max_ejection: Duration::from_secs(3),
+        max_ejected_ratio: 0.5,
+    };
+
+    /// A service whose responses never complete.
+    struct NeverResponds;
+
+    impl Service for NeverResponds {
+        type Request = http::Request<()>;
+        type Response = http::Response<()>;
+        type Error = ();
+        type Future = future::Empty<http::Response<()>, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: http::Request<()>) -> Self::Future {
+            future::empty()
+        }
+    }
+
+    fn ejections() -> Ejections {
+        let ep = Endpoint::from(SocketAddr::from(([10, 1, 1, 1], 8080)));
+        Sensors::for_test().ejections(ctx::Proxy::Outbound, &ep)
+    }
+
+    fn pool(endpoints: usize) -> Arc<Mutex<Pool>> {
+        Arc::new(Mutex::new(Pool { endpoints, ejected: 0 }))
+    }
+
+    fn state() -> State {
+        state_in(pool(2))
+    }
+
+    fn state_in(pool: Arc<Mutex<Pool>>) -> State {
+        State {
+            config: CONFIG,
+            pool,
+            is_ejected: false,
+            consecutive_failures: 0,
+            ejection: CONFIG.base_ejection,
+            ejected_until: None,
+            probe: Probe::None,
+            task: None,
+            ejections: ejections(),
+        }
+    }
+
+    #[test]
+    fn ejects_after_consecutive_failures() {
+        let mut state = state();
+        let now = Instant::now();
+
+        state.record(Classification::Failure, false, now);
+        state.record(Classification::Failure, false, now);
+        state.record(Classification::Success, false, now);
+        state.record(Classification::Failure, false, now);
+        state.record(Classification::Failure, false, now);
+        assert_eq!(state.ejected_until, None);
+
+        state.record(Classification::Failure, false, now);
+        assert_eq!(state.ejected_until, Some(now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn failed_probes_back_off_exponentially() {
+        let mut state = state();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            state.record(Classification::Failure, false, now);
+        }
+        assert_eq!(state.ejected_until, Some(now + Duration::from_secs(1)));
+
+        state.ejected_until = None;
+        state.probe = Probe::InFlight;
+        state.record(Classification::Failure, true, now);
+        assert_eq!(state.ejected_until, Some(now + Duration::from_secs(2)));
+
+        state.ejected_until = None;
+        state.probe = Probe::InFlight;
+        state.record(Classification::Failure, true, now);
+        assert_eq!(state.ejected_until, Some(now + Duration::from_secs(3)));
+
+        state.ejected_until = None;
+        state.probe = Probe::InFlight;
+        state.record(Classification::Success, true, now);
+        assert_eq!(state.ejected_until, None);
+        assert_eq!(state.probe, Probe::None);
+        assert_eq!(state.ejection, CONFIG.base_ejection);
+    }
+
+    #[test]
+    fn canceled_probes_are_retried() {
+        let mut eject = Eject::new(NeverResponds, CONFIG, pool(1), ejections());
+        eject.state.lock().unwrap().probe = Probe::Pending;
+
+        let probe = eject.call(http::Request::new(()));
+        assert_eq!(eject.state.lock().unwrap().probe, Probe::InFlight);
+
+        // Other responses don't affect the probe when they're dropped.
+        drop(eject.call(http::Request::new(())));
+        assert_eq!(eject.state.lock().unwrap().probe, Probe::InFlight);
+
+        drop(probe);
+        assert_eq!(eject.state.lock().unwrap().probe, Probe::Pending);
+
+        let probe = eject.call(http::Request::new(()));
+        assert_eq!(eject.state.lock().unwrap().probe, Probe::InFlight);
+        drop(probe);
+    }
+
+    #[test]
+    fn only_probes_restore_endpoints() {
+        let mut state = state();
+        let now = Instant::now();
+
+        state.probe = Probe::InFlight;
+        state.record(Classification::Failure, false, now);
+        assert_eq!(state.ejected_until, None);
+        assert_eq!(state.probe, Probe::InFlight);
+
+        state.record(Classification::Success, false, now);
+        assert_eq!(state.probe, Probe::InFlight);
+
+        state.record(Classification::Success, true, now);
+        assert_eq!(state.probe, Probe::None);
+    }
+
+    #[test]
+    fn the_last_endpoint_is_never_ejected() {
+        let mut state = state_in(pool(1));
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            state.record(Classification::Failure, false, now);
+        }
+        assert_eq!(state.ejected_until, None);
+    }
+
+    #[test]
+    fn ejects_at_most_the_max_ratio_of_endpoints() {
+        let pool = pool(4);
+        let mut states = (0..4).map(|_| state_in(pool.clone())).collect::<Vec<_>>();
+        let now = Instant::now();
+
+        for state in &mut states {
+            for _ in 0..3 {
+                state.record(Classification::Failure, false, now);
+            }
+        }
+        let ejected = states.iter().filter(|s| s.ejected_until.is_some()).count();
+        assert_eq!(ejected, 2);
+        assert_eq!(pool.lock().unwrap().ejected, 2);
+
+        // Once an ejected endpoint is restored, another may be ejected.
+        states[0].ejected_until = None;
+        states[0].probe = Probe::InFlight;
+        states[0].record(Classification::Success, true, now);
+        assert_eq!(pool.lock().unwrap().ejected, 1);
+
+        for _ in 0..3 {
+            states[3].record(Classification::Failure, false, now);
+        }
+        assert!(states[3].ejected_until.is_some());
+        assert_eq!(pool.lock().unwrap().ejected, 2);
+    }
+
+    #[test]
+    fn dropped_endpoints_leave_the_pool() {
+        let pool = pool(1);
+        let eject = Eject::new(NeverResponds, CONFIG, pool.clone(), ejections());
+        assert_eq!(pool.lock().unwrap().endpoints, 2);
+
+        eject.state.lock().unwrap().eject(Instant::now());
+        assert_eq!(pool.lock().unwrap().ejected, 1);
+
+        drop(eject);
+        assert_eq!(pool.lock().unwrap().endpoints, 1);
+        assert_eq!(pool.lock().unwrap().ejected, 0);
+    }
+}
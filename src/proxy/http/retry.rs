@@ -0,0 +1,1002 @@
+//! Retries failed HTTP requests, within a budget.
+//!
+//! A request is retried only if it could not be served by the destination: if
+//! its connection failed, or it received a server error or a gRPC
+//! `UNAVAILABLE` status (see `super::classify`).
+//!
+//! Only requests that are safe to retry are retried: those with an idempotent
+//! method, those that have explicitly opted in with the `l5d-retryable`
+//! header, and those to destinations that have opted into hedging. Request
+//! bodies are buffered (up to a limit) as they are sent so that they may be
+//! replayed. If a request's body exceeds the limit, or if it has not been sent
+//! in its entirety when the response fails, the request is not retried.
+//!
+//! Requests to destinations that are hedged (see `super::hedge`) may also be
+//! hedged. Retries and hedges are steered away from the endpoint to which the
+//! request was last sent (see `super::balance::Avoid`).
+
+use bytes::{Buf, Bytes, IntoBuf};
+use futures::{Async, Future, Poll};
+use h2;
+use http;
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::timer::Delay;
+use tower_service::Service;
+use tower_h2;
+
+use ctx;
+use telemetry::http::Retries;
+use telemetry::http::timestamp_request_open::RequestOpen;
+use super::balance::Avoid;
+use super::classify::is_failure;
+use super::deadline::Deadline;
+use super::h1;
+use super::hedge;
+use super::upgrade::Http11Upgrade;
+
+const L5D_RETRYABLE: &str = "l5d-retryable";
+
+/// Retries requests that fail, subject to a `Budget`.
+#[derive(Debug)]
+pub struct Retry<S> {
+    inner: S,
+    budget: Arc<Budget>,
+    retries: Retries,
+    max_body_bytes: usize,
+    hedging: Option<hedge::Policy>,
+}
+
+pub struct ResponseFuture<S: Service> {
+    future: S::Future,
+    replay: Option<Replay<S>>,
+    polling_ready: bool,
+    hedge: Option<Hedge<S::Future>>,
+    /// Set if `future` is a hedge that replaced a failed request.
+    is_hedge: bool,
+}
+
+/// The state of a request's hedge.
+enum Hedge<F> {
+    /// Waiting for the hedge delay to elapse.
+    Waiting(Delay),
+    /// Waiting for the service to become ready to send the hedge.
+    PollingReady,
+    /// The hedge has been sent.
+    InFlight(F),
+}
+
+/// Limits the number of retries issued for a destination.
+///
+/// Every request deposits into the budget, and every retry withdraws from it.
+/// Retries are permitted as long as they remain below `retry_ratio` of the
+/// requests observed over the last `ttl`, plus a floor of
+/// `min_retries_per_sec` so that low-volume destinations may still retry.
+///
+/// Hedges are limited by budgets of their own.
+#[derive(Debug)]
+pub struct Budget {
+    ttl: Duration,
+    min_retries_per_sec: u32,
+    retry_ratio: f32,
+    windows: Mutex<Windows<Window>>,
+}
+
+/// A request body that records its frames so that it may be replayed.
+#[derive(Debug)]
+pub struct ReplayBody<B> {
+    inner: ReplayInner<B>,
+}
+
+#[derive(Debug)]
+enum ReplayInner<B> {
+    /// The first attempt streams the original body, buffering it as it goes.
+    Initial {
+        body: B,
+        buffer: Option<Arc<Mutex<Buffer>>>,
+        max_bytes: usize,
+    },
+    /// Subsequent attempts replay the buffered frames.
+    Replay {
+        data: VecDeque<Bytes>,
+        trailers: Option<http::HeaderMap>,
+    },
+}
+
+/// The frames of a request body that has been sent.
+#[derive(Debug, Default)]
+struct Buffer {
+    data: Vec<Bytes>,
+    len: usize,
+    trailers: Option<http::HeaderMap>,
+    /// Set once the original body has been read in its entirety.
+    is_complete: bool,
+    /// Set if the original body exceeded the buffer's limit.
+    is_overflowed: bool,
+}
+
+/// The state needed to issue a retry or hedge from a `ResponseFuture`.
+struct Replay<S> {
+    service: S,
+    head: Head,
+    buffer: Arc<Mutex<Buffer>>,
+    budget: Arc<Budget>,
+    retries: Retries,
+    hedging: Option<hedge::Policy>,
+    /// When the original request was sent, so that the latency of its
+    /// response may be recorded by the hedging policy.
+    sent: Instant,
+}
+
+/// The parts of a request that are copied into each retry.
+#[derive(Debug)]
+struct Head {
+    method: http::Method,
+    uri: http::Uri,
+    version: http::Version,
+    headers: http::HeaderMap,
+    server: Option<Arc<ctx::transport::Server>>,
+    request_open: Option<RequestOpen>,
+    deadline: Option<Deadline>,
+    avoid: Option<Avoid>,
+}
+
+/// Values recorded over the current and previous windows of a TTL.
+#[derive(Debug)]
+pub(super) struct Windows<W> {
+    epoch: Instant,
+    pub(super) current: W,
+    pub(super) previous: W,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Window {
+    requests: u64,
+    retries: u64,
+}
+
+/// Returns true if the request may be retried.
+///
+/// A destination that has opted into hedging has declared that its requests
+/// may be sent more than once, so they are retryable whatever their method
+/// (e.g. gRPC requests, which are always POSTs). HTTP/1.1 upgrades are never
+/// retried, since their connections are handed off to the application.
+fn is_retryable<B>(req: &http::Request<B>, is_hedged: bool) -> bool {
+    if h1::wants_upgrade(req) || req.extensions().get::<Http11Upgrade>().is_some() {
+        return false;
+    }
+
+    if is_hedged || req.headers().contains_key(L5D_RETRYABLE) {
+        return true;
+    }
+
+    match *req.method() {
+        http::Method::GET |
+        http::Method::HEAD |
+        http::Method::OPTIONS |
+        http::Method::TRACE |
+        http::Method::PUT |
+        http::Method::DELETE => true,
+        _ => false,
+    }
+}
+
+// ===== impl Retry =====
+
+impl<S> Retry<S> {
+    pub fn new(
+        inner: S,
+        budget: Arc<Budget>,
+        retries: Retries,
+        max_body_bytes: usize,
+    ) -> Self {
+        Self {
+            inner,
+            budget,
+            retries,
+            max_body_bytes,
+            hedging: None,
+        }
+    }
+
+    /// Hedges requests that are slow to receive responses, as determined by
+    /// `policy`.
+    pub fn with_hedging(self, policy: hedge::Policy) -> Self {
+        Self {
+            hedging: Some(policy),
+            .. self
+        }
+    }
+}
+
+impl<S, A, B> Service for Retry<S>
+where
+    S: Service<
+        Request = http::Request<ReplayBody<A>>,
+        Response = http::Response<B>,
+    > + Clone,
+    A: tower_h2::Body,
+{
+    type Request = http::Request<A>;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Self::Request) -> Self::Future {
+        let now = Instant::now();
+        self.budget.deposit(now);
+        if let Some(ref hedging) = self.hedging {
+            hedging.deposit(now);
+        }
+
+        if !is_retryable(&req, self.hedging.is_some()) {
+            let req = req.map(|body| ReplayBody::new(body, None, 0));
+            return ResponseFuture {
+                future: self.inner.call(req),
+                replay: None,
+                polling_ready: false,
+                hedge: None,
+                is_hedge: false,
+            };
+        }
+
+        let hedge = self.hedging.as_ref()
+            .and_then(|hedging| hedging.delay(now))
+            .map(|delay| Hedge::Waiting(Delay::new(now + delay)));
+
+        req.headers_mut().remove(L5D_RETRYABLE);
+        req.extensions_mut().insert(Avoid::default());
+        let head = Head::from_request(&req);
+        let buffer = Arc::new(Mutex::new(Buffer::default()));
+
+        let body_buffer = buffer.clone();
+        let max_bytes = self.max_body_bytes;
+        let req = req.map(move |body| ReplayBody::new(body, Some(body_buffer), max_bytes));
+
+        ResponseFuture {
+            future: self.inner.call(req),
+            replay: Some(Replay {
+                service: self.inner.clone(),
+                head,
+                buffer,
+                budget: self.budget.clone(),
+                retries: self.retries.clone(),
+                hedging: self.hedging.clone(),
+                sent: now,
+            }),
+            polling_ready: false,
+            hedge,
+            is_hedge: false,
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<S, A, B> Future for ResponseFuture<S>
+where
+    S: Service<
+        Request = http::Request<ReplayBody<A>>,
+        Response = http::Response<B>,
+    >,
+{
+    type Item = S::Response;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            if self.polling_ready {
+                let replay = self.replay.as_mut().expect("retry requires replay");
+                try_ready!(replay.service.poll_ready());
+                let req = replay.request();
+                self.future = replay.service.call(req);
+                self.polling_ready = false;
+                self.is_hedge = false;
+            }
+
+            if let Some(rsp) = self.poll_hedge() {
+                self.record_latency();
+                return Ok(Async::Ready(rsp));
+            }
+
+            let result = match self.future.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(rsp)) => Ok(rsp),
+                Err(e) => Err(e),
+            };
+
+            if is_failure(&result) {
+                // If a hedge is in flight, it may yet succeed, so it replaces
+                // the failed request.
+                if let Some(Hedge::InFlight(_)) = self.hedge {
+                    if let Some(Hedge::InFlight(hedge)) = self.hedge.take() {
+                        trace!("request failed; waiting for hedge");
+                        self.future = hedge;
+                        self.is_hedge = true;
+                        continue;
+                    }
+                }
+            } else if self.is_hedge {
+                if let Some(ref mut replay) = self.replay {
+                    replay.win_hedge();
+                }
+            }
+
+            let retry = match self.replay {
+                Some(ref mut replay) => replay.should_retry(&result),
+                None => false,
+            };
+            if !retry {
+                // The hedge lost, so it is canceled.
+                self.hedge = None;
+                self.record_latency();
+                return result.map(Async::Ready);
+            }
+
+            // A retry supersedes any hedge that has not yet been sent.
+            self.hedge = None;
+            self.polling_ready = true;
+        }
+    }
+}
+
+impl<S, A, B> ResponseFuture<S>
+where
+    S: Service<
+        Request = http::Request<ReplayBody<A>>,
+        Response = http::Response<B>,
+    >,
+{
+    /// Drives the request's hedge, if it has one.
+    ///
+    /// Returns the hedge's response if it succeeds before the request
+    /// does. A hedge that fails is dropped, leaving the request to complete
+    /// on its own.
+    fn poll_hedge(&mut self) -> Option<S::Response> {
+        loop {
+            let replay = match self.replay {
+                Some(ref mut replay) => replay,
+                None => return None,
+            };
+
+            match self.hedge.take() {
+                None => return None,
+                Some(Hedge::Waiting(mut delay)) => match delay.poll() {
+                    Ok(Async::NotReady) => {
+                        self.hedge = Some(Hedge::Waiting(delay));
+                        return None;
+                    }
+                    Ok(Async::Ready(())) => {
+                        if !replay.should_hedge() {
+                            return None;
+                        }
+                        self.hedge = Some(Hedge::PollingReady);
+                    }
+                    Err(e) => {
+                        warn!("hedge timer failed: {}", e);
+                        return None;
+                    }
+                },
+                Some(Hedge::PollingReady) => match replay.service.poll_ready() {
+                    Ok(Async::NotReady) => {
+                        self.hedge = Some(Hedge::PollingReady);
+                        return None;
+                    }
+                    Ok(Async::Ready(())) => {
+                        let req = replay.request();
+                        self.hedge = Some(Hedge::InFlight(replay.service.call(req)));
+                    }
+                    Err(_) => {
+                        debug!("hedge could not be sent");
+                        return None;
+                    }
+                },
+                Some(Hedge::InFlight(mut future)) => {
+                    let result = match future.poll() {
+                        Ok(Async::NotReady) => {
+                            self.hedge = Some(Hedge::InFlight(future));
+                            return None;
+                        }
+                        Ok(Async::Ready(rsp)) => Ok(rsp),
+                        Err(e) => Err(e),
+                    };
+
+                    if is_failure(&result) {
+                        trace!("hedge failed");
+                        return None;
+                    }
+                    replay.win_hedge();
+                    return result.ok();
+                }
+            }
+        }
+    }
+
+    /// Records the latency of the request's response with its hedging
+    /// policy, if it has one.
+    fn record_latency(&self) {
+        if let Some(ref replay) = self.replay {
+            if let Some(ref hedging) = replay.hedging {
+                hedging.record_latency(replay.sent, Instant::now());
+            }
+        }
+    }
+}
+
+// ===== impl Replay =====
+
+impl<S, A> Replay<S>
+where
+    S: Service<Request = http::Request<ReplayBody<A>>>,
+{
+    fn should_retry<B, E>(&mut self, result: &Result<http::Response<B>, E>) -> bool {
+        if !is_failure(result) {
+            return false;
+        }
+
+        if !self.can_replay() {
+            trace!("request body cannot be replayed; not retrying");
+            return false;
+        }
+
+        let now = Instant::now();
+        if self.is_expired(now) {
+            debug!("request deadline exceeded; not retrying");
+            return false;
+        }
+
+        if !self.budget.withdraw(now) {
+            debug!("retry budget exhausted; not retrying");
+            return false;
+        }
+
+        debug!("retrying {} {}", self.head.method, self.head.uri);
+        self.retries.incr();
+        true
+    }
+
+    fn should_hedge(&mut self) -> bool {
+        if !self.can_replay() {
+            trace!("request body cannot be replayed; not hedging");
+            return false;
+        }
+
+        let now = Instant::now();
+        if self.is_expired(now) {
+            debug!("request deadline exceeded; not hedging");
+            return false;
+        }
+
+        let issued = match self.hedging {
+            Some(ref mut hedging) => hedging.issue(now),
+            None => false,
+        };
+        if !issued {
+            debug!("hedge budget exhausted; not hedging");
+            return false;
+        }
+
+        debug!("hedging {} {}", self.head.method, self.head.uri);
+        true
+    }
+
+    fn win_hedge(&mut self) {
+        if let Some(ref mut hedging) = self.hedging {
+            hedging.win();
+        }
+    }
+
+    fn can_replay(&self) -> bool {
+        self.buffer.lock()
+            .map(|b| b.is_complete && !b.is_overflowed)
+            .unwrap_or(false)
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.head.deadline.map_or(false, |d| d.remaining(now) == Duration::from_secs(0))
+    }
+
+    fn request(&self) -> S::Request {
+        let body = match self.buffer.lock() {
+            Ok(buffer) => ReplayBody::replay(&buffer),
+            Err(_) => ReplayBody::replay(&Buffer::default()),
+        };
+        self.head.to_request(body)
+    }
+}
+
+// ===== impl Head =====
+
+impl Head {
+    fn from_request<B>(req: &http::Request<B>) -> Self {
+        Head {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            version: req.version(),
+            headers: req.headers().clone(),
+            server: req.extensions().get::<Arc<ctx::transport::Server>>().cloned(),
+            request_open: req.extensions().get::<RequestOpen>().cloned(),
+            deadline: req.extensions().get::<Deadline>().cloned(),
+            avoid: req.extensions().get::<Avoid>().cloned(),
+        }
+    }
+
+    fn to_request<B>(&self, body: B) -> http::Request<B> {
+        let mut req = http::Request::new(body);
+        *req.method_mut() = self.method.clone();
+        *req.uri_mut() = self.uri.clone();
+        *req.version_mut() = self.version;
+        *req.headers_mut() = self.headers.clone();
+        if let Some(ref server) = self.server {
+            req.extensions_mut().insert(server.clone());
+        }
+        if let Some(open) = self.request_open {
+            req.extensions_mut().insert(open);
+        }
+        if let Some(deadline) = self.deadline {
+            req.extensions_mut().insert(deadline);
+        }
+        if let Some(ref avoid) = self.avoid {
+            req.extensions_mut().insert(avoid.clone());
+        }
+        req
+    }
+}
+
+// ===== impl ReplayBody =====
+
+impl<B: tower_h2::Body> ReplayBody<B> {
+    fn new(body: B, buffer: Option<Arc<Mutex<Buffer>>>, max_bytes: usize) -> Self {
+        if body.is_end_stream() {
+            if let Some(Ok(mut buffer)) = buffer.as_ref().map(|b| b.lock()) {
+                buffer.is_complete = true;
+            }
+        }
+
+        ReplayBody {
+            inner: ReplayInner::Initial { body, buffer, max_bytes },
+        }
+    }
+}
+
+impl<B> ReplayBody<B> {
+    fn replay(buffer: &Buffer) -> Self {
+        ReplayBody {
+            inner: ReplayInner::Replay {
+                data: buffer.data.iter().cloned().collect(),
+                trailers: buffer.trailers.clone(),
+            },
+        }
+    }
+}
+
+impl<B: tower_h2::Body> tower_h2::Body for ReplayBody<B> {
+    type Data = Bytes;
+
+    fn is_end_stream(&self) -> bool {
+        match self.inner {
+            ReplayInner::Initial { ref body, .. } => body.is_end_stream(),
+            ReplayInner::Replay { ref data, ref trailers } =>
+                data.is_empty() && trailers.is_none(),
+        }
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Bytes>, h2::Error> {
+        match self.inner {
+            ReplayInner::Initial { ref mut body, ref mut buffer, max_bytes } => {
+                let data = try_ready!(body.poll_data())
+                    .map(|d| d.into_buf().collect::<Bytes>());
+
+                // Once the buffer overflows, it is no longer needed.
+                let overflowed = match buffer.as_ref().map(|b| b.lock()) {
+                    Some(Ok(mut buffer)) => {
+                        buffer.push_data(data.as_ref(), max_bytes);
+                        buffer.is_overflowed
+                    }
+                    _ => false,
+                };
+                if overflowed {
+                    trace!("request body exceeds {}B; it will not be replayed", max_bytes);
+                    buffer.take();
+                }
+
+                Ok(Async::Ready(data))
+            }
+            ReplayInner::Replay { ref mut data, .. } => Ok(Async::Ready(data.pop_front())),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, h2::Error> {
+        match self.inner {
+            ReplayInner::Initial { ref mut body, ref buffer, .. } => {
+                let trailers = try_ready!(body.poll_trailers());
+                if let Some(Ok(mut buffer)) = buffer.as_ref().map(|b| b.lock()) {
+                    buffer.trailers = trailers.clone();
+                }
+                Ok(Async::Ready(trailers))
+            }
+            ReplayInner::Replay { ref mut trailers, .. } => Ok(Async::Ready(trailers.take())),
+        }
+    }
+}
+
+// ===== impl Buffer =====
+
+impl Buffer {
+    fn push_data(&mut self, data: Option<&Bytes>, max_bytes: usize) {
+        let data = match data {
+            Some(data) => data,
+            None => {
+                self.is_complete = true;
+                return;
+            }
+        };
+
+        if self.is_overflowed {
+            return;
+        }
+
+        self.len += data.len();
+        if self.len > max_bytes {
+            self.is_overflowed = true;
+            self.data.clear();
+            return;
+        }
+
+        self.data.push(data.clone());
+    }
+}
+
+// ===== impl Budget =====
+
+impl Budget {
+    pub fn new(ttl: Duration, min_retries_per_sec: u32, retry_ratio: f32) -> Self {
+        Self {
+            ttl,
+            min_retries_per_sec,
+            retry_ratio,
+            windows: Mutex::new(Windows::new(Instant::now())),
+        }
+    }
+
+    /// Records a request.
+    pub(super) fn deposit(&self, now: Instant) {
+        if let Ok(mut windows) = self.windows.lock() {
+            windows.rotate(now, self.ttl);
+            windows.current.requests += 1;
+        }
+    }
+
+    /// Records a retry, if one is permitted by the budget.
+    ///
+    /// Returns false if the budget has been exhausted.
+    pub(super) fn withdraw(&self, now: Instant) -> bool {
+        let mut windows = match self.windows.lock() {
+            Ok(windows) => windows,
+            Err(_) => return false,
+        };
+        windows.rotate(now, self.ttl);
+
+        let requests = windows.current.requests + windows.previous.requests;
+        let retries = windows.current.retries + windows.previous.retries;
+
+        let floor = u64::from(self.min_retries_per_sec) * self.ttl.as_secs();
+        let allowed = floor + (requests as f64 * f64::from(self.retry_ratio)) as u64;
+        if retries >= allowed {
+            return false;
+        }
+
+        windows.current.retries += 1;
+        true
+    }
+}
+
+// ===== impl Windows =====
+
+impl<W: Default> Windows<W> {
+    pub(super) fn new(epoch: Instant) -> Self {
+        Self {
+            epoch,
+            current: W::default(),
+            previous: W::default(),
+        }
+    }
+
+    /// Ages out values that were recorded more than `ttl` before the current
+    /// window started.
+    pub(super) fn rotate(&mut self, now: Instant, ttl: Duration) {
+        if now < self.epoch + ttl {
+            return;
+        }
+
+        if now < self.epoch + ttl * 2 {
+            self.previous = mem::replace(&mut self.current, W::default());
+            self.epoch += ttl;
+        } else {
+            self.previous = W::default();
+            self.current = W::default();
+            self.epoch = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{future, sync::oneshot};
+    use telemetry::http::{RouteLabels, Sensors};
+    use tokio::runtime::current_thread::Runtime;
+
+    const TTL: Duration = Duration::from_secs(10);
+
+    /// The hedge delay of the mock policy.
+    const HEDGE_DELAY: Duration = Duration::from_millis(50);
+
+    /// A request body with no data.
+    struct Empty;
+
+    /// A service whose responses are sent by the test.
+    #[derive(Clone, Default)]
+    struct Mock(Arc<Mutex<Vec<Option<oneshot::Sender<http::Response<()>>>>>>);
+
+    type MockFuture = future::MapErr<
+        oneshot::Receiver<http::Response<()>>,
+        fn(oneshot::Canceled),
+    >;
+
+    impl tower_h2::Body for Empty {
+        type Data = Bytes;
+
+        fn is_end_stream(&self) -> bool {
+            true
+        }
+
+        fn poll_data(&mut self) -> Poll<Option<Bytes>, h2::Error> {
+            Ok(Async::Ready(None))
+        }
+
+        fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, h2::Error> {
+            Ok(Async::Ready(None))
+        }
+    }
+
+    impl Mock {
+        /// Returns the number of requests that have been sent.
+        fn sent(&self) -> usize {
+            self.0.lock().unwrap().len()
+        }
+
+        /// Responds to the `i`th request with `status`.
+        fn respond(&self, i: usize, status: http::StatusCode) {
+            let tx = self.0.lock().unwrap()[i].take().expect("already responded");
+            let mut rsp = http::Response::new(());
+            *rsp.status_mut() = status;
+            let _ = tx.send(rsp);
+        }
+
+        /// Returns true if the `i`th request has been canceled.
+        fn is_canceled(&self, i: usize) -> bool {
+            self.0.lock().unwrap()[i].as_ref().map_or(false, |tx| tx.is_canceled())
+        }
+    }
+
+    impl Service for Mock {
+        type Request = http::Request<ReplayBody<Empty>>;
+        type Response = http::Response<()>;
+        type Error = ();
+        type Future = MockFuture;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, req: Self::Request) -> Self::Future {
+            use tower_h2::Body;
+            fn canceled(_: oneshot::Canceled) {}
+
+            // The body is read in its entirety, so that it may be replayed.
+            let (_, mut body) = req.into_parts();
+            while let Ok(Async::Ready(Some(_))) = body.poll_data() {}
+            let _ = body.poll_trailers();
+
+            let (tx, rx) = oneshot::channel();
+            self.0.lock().unwrap().push(Some(tx));
+            rx.map_err(canceled as fn(oneshot::Canceled))
+        }
+    }
+
+    /// Returns a `Retry` that hedges requests after `HEDGE_DELAY`.
+    fn hedging(mock: &Mock) -> Retry<Mock> {
+        let sensors = Sensors::for_test();
+        let budget = Arc::new(Budget::new(TTL, 1, 0.2));
+        let labels = RouteLabels::new(ctx::Proxy::Outbound, "web.ns:8080");
+        let retries = sensors.retries(labels.clone());
+        let hedges = sensors.hedges(labels);
+
+        let policy = hedge::Policy::new(0.5, hedges);
+        let now = Instant::now();
+        for _ in 0..100 {
+            policy.record_latency(now - HEDGE_DELAY, now);
+        }
+
+        Retry::new(mock.clone(), budget, retries, 1024).with_hedging(policy)
+    }
+
+    fn request(method: http::Method) -> http::Request<Empty> {
+        let mut req = http::Request::new(Empty);
+        *req.method_mut() = method;
+        req
+    }
+
+    /// Sends `req`, and waits until it has been hedged or the hedge delay has
+    /// elapsed twice over.
+    fn send_and_wait(
+        rt: &mut Runtime,
+        retry: &mut Retry<Mock>,
+        mock: &Mock,
+        req: http::Request<Empty>,
+    ) -> ResponseFuture<Mock> {
+        let sent = mock.sent();
+        let mut rsp = rt.block_on(future::lazy(|| {
+            let mut rsp = retry.call(req);
+            assert!(rsp.poll().unwrap().is_not_ready());
+            Ok::<_, ()>(rsp)
+        })).unwrap();
+        assert_eq!(mock.sent(), sent + 1, "hedged before the delay");
+
+        {
+            let timeout = Delay::new(Instant::now() + HEDGE_DELAY * 2);
+            let hedged = future::poll_fn(|| -> Poll<(), ()> {
+                assert!(rsp.poll().unwrap().is_not_ready());
+                if mock.sent() > sent + 1 {
+                    Ok(Async::Ready(()))
+                } else {
+                    Ok(Async::NotReady)
+                }
+            });
+            let _ = rt.block_on(hedged.select(timeout.map_err(|_| ())));
+        }
+        rsp
+    }
+
+    #[test]
+    fn hedges_slow_requests() {
+        let mut rt = Runtime::new().unwrap();
+        let mock = Mock::default();
+        let mut retry = hedging(&mock);
+
+        let mut rsp = send_and_wait(&mut rt, &mut retry, &mock, request(http::Method::GET));
+        assert_eq!(mock.sent(), 2);
+
+        // The hedge responds first, so its response is used.
+        mock.respond(1, http::StatusCode::ACCEPTED);
+        let status = rt.block_on(future::poll_fn(|| rsp.poll())).unwrap().status();
+        assert_eq!(status, http::StatusCode::ACCEPTED);
+
+        // The original request is canceled with the response future.
+        assert!(!mock.is_canceled(0));
+        drop(rsp);
+        assert!(mock.is_canceled(0));
+    }
+
+    #[test]
+    fn hedges_lose_to_faster_requests() {
+        let mut rt = Runtime::new().unwrap();
+        let mock = Mock::default();
+        let mut retry = hedging(&mock);
+
+        let mut rsp = send_and_wait(&mut rt, &mut retry, &mock, request(http::Method::GET));
+        assert_eq!(mock.sent(), 2);
+
+        // The original request responds first, so the hedge is canceled.
+        mock.respond(0, http::StatusCode::OK);
+        let status = rt.block_on(future::poll_fn(|| rsp.poll())).unwrap().status();
+        assert_eq!(status, http::StatusCode::OK);
+        assert!(mock.is_canceled(1));
+    }
+
+    #[test]
+    fn hedges_grpc_requests() {
+        let mut rt = Runtime::new().unwrap();
+        let mock = Mock::default();
+        let mut retry = hedging(&mock);
+
+        let mut grpc = request(http::Method::POST);
+        grpc.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            http::header::HeaderValue::from_static("application/grpc"),
+        );
+        let _rsp = send_and_wait(&mut rt, &mut retry, &mock, grpc);
+        assert_eq!(mock.sent(), 2);
+    }
+
+    #[test]
+    fn budget_permits_floor_without_requests() {
+        let budget = Budget::new(TTL, 1, 0.2);
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            assert!(budget.withdraw(now));
+        }
+        assert!(!budget.withdraw(now));
+    }
+
+    #[test]
+    fn budget_permits_ratio_of_requests() {
+        let budget = Budget::new(TTL, 0, 0.2);
+        let now = Instant::now();
+
+        for _ in 0..100 {
+            budget.deposit(now);
+        }
+        for _ in 0..20 {
+            assert!(budget.withdraw(now));
+        }
+        assert!(!budget.withdraw(now));
+    }
+
+    #[test]
+    fn budget_forgets_old_windows() {
+        let budget = Budget::new(TTL, 0, 0.5);
+        let t0 = Instant::now();
+
+        budget.deposit(t0);
+        budget.deposit(t0);
+        assert!(budget.withdraw(t0));
+        assert!(!budget.withdraw(t0));
+
+        // Counts from the previous window are still considered.
+        let t1 = t0 + TTL;
+        assert!(!budget.withdraw(t1));
+        budget.deposit(t1);
+        budget.deposit(t1);
+        assert!(budget.withdraw(t1));
+
+        // After two windows have elapsed, nothing is retained.
+        let t2 = t1 + TTL * 2;
+        assert!(!budget.withdraw(t2));
+    }
+
+    #[test]
+    fn buffer_overflows() {
+        let mut buffer = Buffer::default();
+        buffer.push_data(Some(&Bytes::from_static(b"hello")), 8);
+        assert!(!buffer.is_overflowed);
+        buffer.push_data(Some(&Bytes::from_static(b"world")), 8);
+        assert!(buffer.is_overflowed);
+        assert!(buffer.data.is_empty());
+        buffer.push_data(None, 8);
+        assert!(buffer.is_complete);
+    }
+
+    #[test]
+    fn idempotent_methods_are_retryable() {
+        let get = http::Request::get("http://example.com").body(()).unwrap();
+        assert!(is_retryable(&get, false));
+
+        let post = http::Request::post("http://example.com").body(()).unwrap();
+        assert!(!is_retryable(&post, false));
+
+        // Requests to hedged destinations are retryable whatever their method.
+        assert!(is_retryable(&post, true));
+
+        let opt_in = http::Request::post("http://example.com")
+            .header(L5D_RETRYABLE, "true")
+            .body(())
+            .unwrap();
+        assert!(is_retryable(&opt_in, false));
+
+        let upgrade = http::Request::get("http://example.com")
+            .header(http::header::CONNECTION, "upgrade")
+            .header(http::header::UPGRADE, "websocket")
+            .body(())
+            .unwrap();
+        assert!(!is_retryable(&upgrade, true));
+    }
+}
@@ -13,6 +13,7 @@
 //! This module is intended only to store the infrastructure for building a
 //! proxy. The specific logic implemented by a proxy should live elsewhere.
 
+pub mod authorize;
 pub mod http;
 mod protocol;
 mod server;
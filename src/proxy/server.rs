@@ -16,8 +16,9 @@ use tower_h2;
 use ctx::Proxy as ProxyCtx;
 use ctx::transport::{Server as ServerCtx};
 use drain;
+use proxy::authorize::Authorize;
 use svc::{MakeClient, Service};
-use transport::{self, Connection, GetOriginalDst, Peek};
+use transport::{self, tls, Connection, GetOriginalDst, Peek};
 use proxy::http::glue::{HttpBody, HttpBodyNewSvc, HyperServerSvc};
 use proxy::protocol::Protocol;
 use proxy::tcp;
@@ -37,6 +38,7 @@ where
     B: tower_h2::Body,
     G: GetOriginalDst,
 {
+    authorize: Option<Authorize>,
     disable_protocol_detection_ports: IndexSet<u16>,
     drain_signal: drain::Watch,
     get_orig_dst: G,
@@ -75,12 +77,14 @@ where
         make_client: M,
         tcp_connect_timeout: Duration,
         disable_protocol_detection_ports: IndexSet<u16>,
+        authorize: Option<Authorize>,
         drain_signal: drain::Watch,
         h2_settings: h2::server::Builder,
     ) -> Self {
         let tcp = tcp::Forward::new(tcp_connect_timeout, transport_registry.clone());
         let log = ::logging::Server::proxy(proxy_ctx, listen_addr);
         Server {
+            authorize,
             disable_protocol_detection_ports,
             drain_signal,
             get_orig_dst,
@@ -101,10 +105,14 @@ where
 
     /// Handle a new connection.
     ///
-    /// This will peek on the connection for the first bytes to determine
-    /// what protocol the connection is speaking. From there, the connection
-    /// will be mapped into respective services, and spawned into an
-    /// executor.
+    /// If the client negotiated an HTTP version with ALPN, the connection is
+    /// served as that version. Otherwise, this will peek on the connection
+    /// for the first bytes to determine what protocol the connection is
+    /// speaking. From there, the connection will be mapped into respective
+    /// services, and spawned into an executor.
+    ///
+    /// If the server authorizes connections, unauthorized connections are
+    /// closed.
     pub fn serve(&self, connection: Connection, remote_addr: SocketAddr)
         -> impl Future<Item=(), Error=()>
     {
@@ -117,10 +125,19 @@ where
             &remote_addr,
             &orig_dst,
             connection.tls_status(),
+            connection.peer_certificate().cloned(),
         );
+        let alpn_protocol = connection.alpn_protocol();
         let log = self.log.clone()
             .with_remote(remote_addr);
 
+        let authorized = self.authorize.as_ref()
+            .map_or(true, |a| a.allows_connection(&srv_ctx));
+        if !authorized {
+            debug!("closing unauthenticated connection from {}", remote_addr);
+            return log.future(Either::B(Either::A(future::ok(()))));
+        }
+
         // record telemetry
         let io = self.transport_registry.accept(&srv_ctx, connection);
 
@@ -139,30 +156,42 @@ where
                 &self.tcp,
                 io,
                 srv_ctx,
+                self.authorize.as_ref(),
                 self.drain_signal.clone(),
             );
 
-            return log.future(Either::B(fut));
+            return log.future(Either::B(Either::B(fut)));
         }
 
-        let detect_protocol = io.peek()
-            .map_err(|e| debug!("peek error: {}", e))
-            .map(|io| {
-                let p = Protocol::detect(io.peeked());
-                (p, io)
-            });
+        let detect_protocol = match alpn_protocol {
+            Some(alpn) => Either::A({
+                trace!("negotiated {:?} with ALPN", alpn);
+                let p = match alpn {
+                    tls::AlpnProtocol::Http1 => Protocol::Http1,
+                    tls::AlpnProtocol::Http2 => Protocol::Http2,
+                };
+                future::ok((Some(p), io))
+            }),
+            None => Either::B(io.peek()
+                .map_err(|e| debug!("peek error: {}", e))
+                .map(|io| {
+                    let p = Protocol::detect(io.peeked());
+                    (p, io)
+                })),
+        };
 
         let h1 = self.h1.clone();
         let h2_settings = self.h2_settings.clone();
         let make_client = self.make_client.clone();
         let tcp = self.tcp.clone();
+        let authorize = self.authorize.clone();
         let drain_signal = self.drain_signal.clone();
         let log_clone = log.clone();
         let serve = detect_protocol
             .and_then(move |(proto, io)| match proto {
                 None => Either::A({
                     trace!("did not detect protocol; forwarding TCP");
-                    tcp_serve(&tcp, io, srv_ctx, drain_signal)
+                    tcp_serve(&tcp, io, srv_ctx, authorize.as_ref(), drain_signal)
                 }),
 
                 Some(proto) => Either::B(match proto {
@@ -215,16 +244,25 @@ where
     }
 }
 
+/// Forwards a connection that is not HTTP, if it is authorized.
+///
+/// Unauthorized connections are closed by dropping `io`.
 fn tcp_serve<T: AsyncRead + AsyncWrite + Send + 'static>(
     tcp: &tcp::Forward,
     io: T,
     srv_ctx: Arc<ServerCtx>,
+    authorize: Option<&Authorize>,
     drain_signal: drain::Watch,
 ) -> impl Future<Item=(), Error=()> + Send + 'static {
+    if !authorize.map_or(true, |a| a.allows(&srv_ctx, None)) {
+        debug!("closing unauthorized connection from {}", srv_ctx.remote);
+        return Either::A(future::ok(()));
+    }
+
     let fut = tcp.serve(io, srv_ctx);
 
     // There's nothing to do when drain is signaled, we just have to hope
     // the sockets finish soon. However, the drain signal still needs to
     // 'watch' the TCP future so that the process doesn't close early.
-    drain_signal.watch(fut, |_| ())
+    Either::B(drain_signal.watch(fut, |_| ()))
 }
@@ -1,14 +1,18 @@
-use std::fmt;
+use std::{cmp, fmt};
+use std::time::{Duration, Instant};
 
 use futures::{task, Async, Future, Poll};
+use rand::{self, Rng};
+use tokio::timer::Delay;
 use tower_reconnect;
 
+use telemetry::reconnect::Sensor;
 use super::{NewService, Service};
 
 /// Wraps `tower_reconnect`, handling errors.
 ///
 /// Ensures that the underlying service is ready and, if the underlying service
-/// fails to become ready, rebuilds the inner stack.
+/// fails to become ready, rebuilds the inner stack after backing off.
 pub struct Reconnect<T, N>
 where
     T: fmt::Debug,
@@ -23,6 +27,26 @@ where
     ///
     /// Set back to false after a connect succeeds, to log about future errors.
     mute_connect_error_log: bool,
+
+    backoff: Backoff,
+
+    /// The number of connect errors since a connect last succeeded.
+    failures: u32,
+
+    /// Set while waiting to reconnect after a connect error.
+    delay: Option<Delay>,
+
+    sensor: Sensor,
+}
+
+/// Configures jittered exponential backoff between connection attempts.
+///
+/// After the `n`th consecutive failure, the backoff is chosen randomly between
+/// half of and all of `base * 2^(n-1)`, up to `max`.
+#[derive(Copy, Clone, Debug)]
+pub struct Backoff {
+    pub base: Duration,
+    pub max: Duration,
 }
 
 pub struct ResponseFuture<N: NewService> {
@@ -37,12 +61,16 @@ where
     N: NewService,
     N::InitError: fmt::Display,
 {
-    pub fn new(target: T, new_service: N) -> Self {
+    pub fn new(target: T, new_service: N, backoff: Backoff, sensor: Sensor) -> Self {
         let inner = tower_reconnect::Reconnect::new(new_service);
         Self {
             target,
             inner,
             mute_connect_error_log: false,
+            backoff,
+            failures: 0,
+            delay: None,
+            sensor,
         }
     }
 }
@@ -59,11 +87,31 @@ where
     type Future = ResponseFuture<N>;
 
     fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        let backing_off = match self.delay {
+            None => false,
+            Some(ref mut delay) => match delay.poll() {
+                Ok(Async::NotReady) => true,
+                Ok(Async::Ready(())) => false,
+                Err(e) => {
+                    error!("reconnect timer failed: {}", e);
+                    false
+                }
+            },
+        };
+        if backing_off {
+            return Ok(Async::NotReady);
+        }
+        if self.delay.take().is_some() {
+            trace!("poll_ready: backoff elapsed, reconnecting");
+            self.sensor.backoff_ended();
+        }
+
         match self.inner.poll_ready() {
             Ok(Async::NotReady) => Ok(Async::NotReady),
             Ok(ready) => {
                 trace!("poll_ready: ready for business");
                 self.mute_connect_error_log = false;
+                self.failures = 0;
                 Ok(ready)
             }
 
@@ -86,12 +134,15 @@ where
                 }
 
                 // The inner service is now idle and will renew its internal
-                // state on the next poll. Instead of doing this immediately,
-                // the task is scheduled to be polled again only if the caller
-                // decides not to drop it.
-                //
-                // This prevents busy-looping when the connect error is
-                // instantaneous.
+                // state once the backoff elapses. The task is notified so
+                // that the backoff's timer is registered on the next poll, if
+                // the caller decides not to drop this service.
+                self.failures = self.failures.saturating_add(1);
+                let wait = self.backoff.duration(self.failures, &mut rand::thread_rng());
+                debug!("reconnecting to {:?} in {:?}", self.target, wait);
+                self.delay = Some(Delay::new(Instant::now() + wait));
+                self.sensor.backoff_started();
+
                 task::current().notify();
                 Ok(Async::NotReady)
             }
@@ -109,6 +160,26 @@ where
     }
 }
 
+// ===== impl Backoff =====
+
+impl Backoff {
+    /// Returns how long to wait after the `failures`th consecutive failure.
+    pub fn duration<R: Rng>(&self, failures: u32, rng: &mut R) -> Duration {
+        let shift = cmp::min(failures.saturating_sub(1), 31);
+        let exp = self.base
+            .checked_mul(1 << shift)
+            .map(|d| cmp::min(d, self.max))
+            .unwrap_or(self.max);
+
+        let ms = exp.as_secs() * 1_000 + u64::from(exp.subsec_millis());
+        let half = ms / 2;
+        let jitter = if half > 0 { rng.gen_range(0, half + 1) } else { 0 };
+        Duration::from_millis(ms - half + jitter)
+    }
+}
+
+// ===== impl ResponseFuture =====
+
 impl<N: NewService> Future for ResponseFuture<N> {
     type Item = N::Response;
     type Error = N::Error;
@@ -120,3 +191,42 @@ impl<N: NewService> Future for ResponseFuture<N> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    quickcheck! {
+        fn backoff_is_bounded(base_ms: u16, max_ms: u16, failures: u32) -> bool {
+            let backoff = Backoff {
+                base: Duration::from_millis(base_ms.into()),
+                max: Duration::from_millis(max_ms.into()),
+            };
+            let wait = backoff.duration(failures, &mut rand::thread_rng());
+
+            let shift = cmp::min(failures.saturating_sub(1), 31);
+            let exp = cmp::min(u64::from(base_ms) << shift, u64::from(max_ms));
+            let lower = Duration::from_millis(exp - exp / 2);
+            let upper = Duration::from_millis(exp);
+            lower <= wait && wait <= upper
+        }
+    }
+
+    #[test]
+    fn backoff_grows_exponentially() {
+        let backoff = Backoff {
+            base: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+        };
+        let mut rng = rand::thread_rng();
+
+        let first = backoff.duration(1, &mut rng);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+
+        let third = backoff.duration(3, &mut rng);
+        assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400));
+
+        let capped = backoff.duration(10, &mut rng);
+        assert!(capped >= Duration::from_millis(500) && capped <= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,421 @@
+use indexmap::IndexMap;
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use telemetry::metrics::{
+    latency,
+    Bounds,
+    Bucket,
+    Counter,
+    FmtLabels,
+    FmtMetrics,
+    Gauge,
+    Histogram,
+    Scopes,
+};
+use transport::DnsNameAndPort;
+
+metrics! {
+    dns_resolution_state: Gauge {
+        "Whether the DNS answer for an authority is fresh, stale, or expired"
+    },
+    dns_lookup_total: Counter { "Total count of DNS lookups" },
+    dns_lookup_latency_ms: Histogram<latency::Ms> { "DNS lookup latencies" },
+    dns_answer_ttl_seconds: Histogram<u64> {
+        "TTLs of positive DNS answers, after the configured minimum and maximum TTLs are applied"
+    }
+}
+
+/// The maximum value (inclusive) for each TTL bucket in seconds.
+const TTL_BOUNDS: &Bounds = &Bounds(&[
+    Bucket::Le(0),
+    Bucket::Le(1),
+    Bucket::Le(5),
+    Bucket::Le(10),
+    Bucket::Le(30),
+    Bucket::Le(60),
+    Bucket::Le(300),
+    Bucket::Le(600),
+    Bucket::Le(1_800),
+    Bucket::Le(3_600),
+    Bucket::Le(86_400),
+    // A final upper bound.
+    Bucket::Inf,
+]);
+
+/// At most this many names' lookup latencies are reported, for each record
+/// type.
+///
+/// Names are looked up as connections are established and as SRV records'
+/// targets change, not only for the authorities that have a `Sensor`, so the
+/// latencies of the names that were looked up least recently are evicted.
+const MAX_LATENCY_NAMES: usize = 1_000;
+
+/// Constructs a Registry/Report pair for DNS metrics.
+pub fn new() -> (Registry, Report) {
+    let inner = Arc::new(Mutex::new(Inner::default()));
+    (Registry(inner.clone()), Report(inner))
+}
+
+/// Creates a `Sensor` for each authority that may be resolved by DNS.
+#[derive(Clone, Debug, Default)]
+pub struct Registry(Arc<Mutex<Inner>>);
+
+/// Formats metrics for Prometheus for a corresonding `Registry`.
+#[derive(Clone, Debug, Default)]
+pub struct Report(Arc<Mutex<Inner>>);
+
+/// Tracks the state of a single authority's DNS answer.
+///
+/// When this type is dropped, the authority is no longer reported.
+#[derive(Debug)]
+pub struct Sensor {
+    inner: Arc<Mutex<Inner>>,
+    authority: DnsNameAndPort,
+}
+
+/// The type of records that a lookup queried.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum RecordType {
+    /// A and/or AAAA records.
+    Ip,
+    Srv,
+}
+
+/// Describes how a lookup completed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Outcome {
+    Success,
+    /// The name does not exist, or has no records of the queried type.
+    NxDomain,
+    Error,
+}
+
+/// Describes how current the DNS answer for an authority is.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ResolutionState {
+    /// The answer's TTL has not elapsed.
+    Fresh,
+    /// The answer's TTL has elapsed, but it's still used because queries have
+    /// failed since.
+    Stale,
+    /// No answer is used.
+    Expired,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    resolutions: IndexMap<DnsNameAndPort, ResolutionState>,
+    lookups: Scopes<(RecordType, Outcome), Counter>,
+    latencies: Scopes<(Name, RecordType), Latencies>,
+    ttls: IndexMap<RecordType, Histogram<u64>>,
+}
+
+/// The latencies of lookups of a name.
+#[derive(Debug, Default)]
+struct Latencies {
+    histogram: Histogram<latency::Ms>,
+    last_lookup: Option<Instant>,
+}
+
+/// The name that a lookup queried.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+struct Name(String);
+
+struct Labels<'a> {
+    authority: &'a DnsNameAndPort,
+    state: ResolutionState,
+}
+
+// ===== impl Registry =====
+
+impl Registry {
+    pub fn sensor(&self, authority: DnsNameAndPort) -> Sensor {
+        Sensor {
+            inner: self.0.clone(),
+            authority,
+        }
+    }
+
+    /// Records a completed lookup of `name`, which took `latency`.
+    pub fn lookup(&self, name: &str, record_type: RecordType, outcome: Outcome, latency: Duration) {
+        if let Ok(mut inner) = self.0.lock() {
+            inner.lookups.get_or_default((record_type, outcome)).incr();
+            inner.record_latency(Name(name.to_owned()), record_type, latency, Instant::now());
+        }
+    }
+
+    /// Records the TTL of a positive answer.
+    pub fn answer_ttl(&self, record_type: RecordType, ttl: Duration) {
+        if let Ok(mut inner) = self.0.lock() {
+            inner.ttls
+                .entry(record_type)
+                .or_insert_with(|| Histogram::new(TTL_BOUNDS))
+                .add(ttl.as_secs());
+        }
+    }
+}
+
+// ===== impl Sensor =====
+
+impl Sensor {
+    pub fn set_state(&mut self, state: ResolutionState) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.resolutions.insert(self.authority.clone(), state);
+        }
+    }
+
+    /// Stops reporting the authority, i.e. once it's no longer resolved by
+    /// DNS.
+    pub fn clear(&mut self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.resolutions.remove(&self.authority);
+        }
+    }
+}
+
+impl Drop for Sensor {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+// ===== impl Inner =====
+
+impl Inner {
+    fn record_latency(
+        &mut self,
+        name: Name,
+        record_type: RecordType,
+        latency: Duration,
+        now: Instant,
+    ) {
+        let key = (name, record_type);
+        if self.latencies.get(&key).is_none() {
+            let count = (&self.latencies).into_iter()
+                .filter(|&(&(_, rt), _)| rt == record_type)
+                .count();
+            if count >= MAX_LATENCY_NAMES {
+                self.evict_latencies(record_type);
+            }
+        }
+
+        let latencies = self.latencies.get_or_default(key);
+        latencies.histogram.add(latency);
+        latencies.last_lookup = Some(now);
+    }
+
+    /// Stops reporting the latencies of the name of `record_type` that was
+    /// looked up least recently.
+    fn evict_latencies(&mut self, record_type: RecordType) {
+        let oldest = (&self.latencies).into_iter()
+            .filter(|&(&(_, rt), _)| rt == record_type)
+            .min_by_key(|&(_, latencies)| latencies.last_lookup)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            trace!("evicting DNS lookup latencies for {:?}", oldest);
+            self.latencies.retain(|key, _| *key != oldest);
+        }
+    }
+}
+
+// ===== impl Report =====
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = match self.0.lock() {
+            Err(_) => return Ok(()),
+            Ok(inner) => inner,
+        };
+
+        if !inner.resolutions.is_empty() {
+            // Each state is reported for each authority, so that the states
+            // that do not apply are reported as 0.
+            let gauges = inner.resolutions.iter()
+                .flat_map(|(authority, &current)| {
+                    ResolutionState::ALL.iter().map(move |&state| {
+                        let labels = Labels { authority, state };
+                        (labels, Gauge::from(if state == current { 1 } else { 0 }))
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            dns_resolution_state.fmt_help(f)?;
+            dns_resolution_state.fmt_scopes(f, gauges.iter().map(|&(ref l, ref g)| (l, g)), |g| g)?;
+        }
+
+        if !inner.lookups.is_empty() {
+            dns_lookup_total.fmt_help(f)?;
+            dns_lookup_total.fmt_scopes(f, &inner.lookups, |c| &c)?;
+
+            dns_lookup_latency_ms.fmt_help(f)?;
+            dns_lookup_latency_ms.fmt_scopes(f, &inner.latencies, |l| &l.histogram)?;
+        }
+
+        if !inner.ttls.is_empty() {
+            dns_answer_ttl_seconds.fmt_help(f)?;
+            dns_answer_ttl_seconds.fmt_scopes(f, &inner.ttls, |h| &h)?;
+        }
+
+        Ok(())
+    }
+}
+
+// ===== impl ResolutionState =====
+
+impl ResolutionState {
+    const ALL: &'static [ResolutionState] = &[
+        ResolutionState::Fresh,
+        ResolutionState::Stale,
+        ResolutionState::Expired,
+    ];
+}
+
+impl FmtLabels for ResolutionState {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResolutionState::Fresh => f.pad("state=\"fresh\""),
+            ResolutionState::Stale => f.pad("state=\"stale\""),
+            ResolutionState::Expired => f.pad("state=\"expired\""),
+        }
+    }
+}
+
+// ===== impl RecordType =====
+
+impl FmtLabels for RecordType {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordType::Ip => f.pad("record_type=\"A/AAAA\""),
+            RecordType::Srv => f.pad("record_type=\"SRV\""),
+        }
+    }
+}
+
+// ===== impl Outcome =====
+
+impl FmtLabels for Outcome {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Success => f.pad("outcome=\"success\""),
+            Outcome::NxDomain => f.pad("outcome=\"nxdomain\""),
+            Outcome::Error => f.pad("outcome=\"error\""),
+        }
+    }
+}
+
+// ===== impl Name =====
+
+impl FmtLabels for Name {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "name=\"{}\"", self.0)
+    }
+}
+
+// ===== impl Labels =====
+
+impl<'a> FmtLabels for Labels<'a> {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "authority=\"{}:{}\",", self.authority.host, self.authority.port)?;
+        self.state.fmt_labels(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use convert::TryFrom;
+    use dns;
+
+    fn authority() -> DnsNameAndPort {
+        DnsNameAndPort {
+            host: dns::Name::try_from("web.ns.svc.cluster.local".as_bytes()).unwrap(),
+            port: 8080,
+        }
+    }
+
+    fn fmt_report(report: &Report) -> String {
+        format!("{}", report.as_display())
+    }
+
+    #[test]
+    fn formats_resolution_states() {
+        let (registry, report) = new();
+        assert_eq!(fmt_report(&report), "");
+
+        let mut sensor = registry.sensor(authority());
+        sensor.set_state(ResolutionState::Stale);
+        let out = fmt_report(&report);
+        assert!(out.contains("# TYPE dns_resolution_state gauge\n"));
+        let authority = "authority=\"web.ns.svc.cluster.local:8080\"";
+        assert!(out.contains(&format!("dns_resolution_state{{{},state=\"fresh\"}} 0\n", authority)));
+        assert!(out.contains(&format!("dns_resolution_state{{{},state=\"stale\"}} 1\n", authority)));
+        assert!(out.contains(&format!("dns_resolution_state{{{},state=\"expired\"}} 0\n", authority)));
+
+        drop(sensor);
+        assert_eq!(fmt_report(&report), "");
+    }
+
+    #[test]
+    fn formats_lookups() {
+        let (registry, report) = new();
+        let ms = Duration::from_millis;
+        registry.lookup("web.ns.svc.cluster.local", RecordType::Ip, Outcome::Success, ms(3));
+        registry.lookup("web.ns.svc.cluster.local", RecordType::Ip, Outcome::NxDomain, ms(3));
+        registry.lookup("_http._tcp.web", RecordType::Srv, Outcome::Error, ms(3));
+        registry.answer_ttl(RecordType::Srv, Duration::from_secs(30));
+
+        let out = fmt_report(&report);
+        assert!(out.contains("dns_lookup_total{record_type=\"A/AAAA\",outcome=\"success\"} 1\n"));
+        assert!(out.contains("dns_lookup_total{record_type=\"A/AAAA\",outcome=\"nxdomain\"} 1\n"));
+        assert!(out.contains("dns_lookup_total{record_type=\"SRV\",outcome=\"error\"} 1\n"));
+        assert!(out.contains(
+            "dns_lookup_latency_ms_count{name=\"web.ns.svc.cluster.local\",record_type=\"A/AAAA\"} 2\n"
+        ));
+        assert!(out.contains(
+            "dns_lookup_latency_ms_count{name=\"_http._tcp.web\",record_type=\"SRV\"} 1\n"
+        ));
+        assert!(out.contains("dns_answer_ttl_seconds_bucket{record_type=\"SRV\",le=\"10\"} 0\n"));
+        assert!(out.contains("dns_answer_ttl_seconds_bucket{record_type=\"SRV\",le=\"30\"} 1\n"));
+        assert!(out.contains("dns_answer_ttl_seconds_count{record_type=\"SRV\"} 1\n"));
+    }
+
+    #[test]
+    fn evicts_latencies_of_names_looked_up_least_recently() {
+        let (registry, report) = new();
+        let t0 = Instant::now();
+        let name = |n: usize| Name(format!("name{}.ns.svc.cluster.local", n));
+        let lookup = |n: usize, record_type, at| {
+            let mut inner = registry.0.lock().unwrap();
+            inner.lookups.get_or_default((record_type, Outcome::Success)).incr();
+            inner.record_latency(name(n), record_type, Duration::from_millis(1), at);
+        };
+
+        for n in 0..MAX_LATENCY_NAMES {
+            lookup(n, RecordType::Ip, t0 + Duration::from_secs(n as u64));
+        }
+        lookup(0, RecordType::Srv, t0);
+        // name0 is looked up again, so name1 is now the least recent.
+        let t1 = t0 + Duration::from_secs(MAX_LATENCY_NAMES as u64);
+        lookup(0, RecordType::Ip, t1);
+        lookup(MAX_LATENCY_NAMES, RecordType::Ip, t1);
+
+        {
+            let inner = registry.0.lock().unwrap();
+            assert_eq!(inner.latencies.len(), MAX_LATENCY_NAMES + 1);
+            assert!(inner.latencies.get(&(name(0), RecordType::Ip)).is_some());
+            assert!(inner.latencies.get(&(name(0), RecordType::Srv)).is_some());
+            assert!(inner.latencies.get(&(name(1), RecordType::Ip)).is_none());
+            assert!(inner.latencies.get(&(name(MAX_LATENCY_NAMES), RecordType::Ip)).is_some());
+        }
+
+        let out = fmt_report(&report);
+        assert!(out.contains(
+            "dns_lookup_latency_ms_count{name=\"name0.ns.svc.cluster.local\",record_type=\"A/AAAA\"} 2\n"
+        ));
+        assert!(!out.contains("name=\"name1.ns.svc.cluster.local\""));
+    }
+}
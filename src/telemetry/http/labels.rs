@@ -1,15 +1,19 @@
 use indexmap::IndexMap;
 use std::{
     fmt::{self, Write},
+    net::SocketAddr,
 };
 
 use http;
 
 use ctx;
 use conditional::Conditional;
+use control::destination::{Endpoint, Locality};
 use telemetry::metrics::FmtLabels;
 use transport::tls;
 
+use super::service::GRPC_STATUS;
+
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct RequestLabels {
     proxy: ctx::Proxy,
@@ -24,6 +28,10 @@ pub struct RequestLabels {
 
     /// Whether or not the request was made over TLS.
     tls_status: ctx::transport::TlsStatus,
+
+    /// Whether the request was sent to an endpoint in the proxy's locality,
+    /// if the proxy's locality is known.
+    locality: Option<Locality>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
@@ -42,6 +50,53 @@ pub struct ResponseLabels {
     classification: Classification,
 }
 
+/// Labels for destinations whose requests are retried, hedged or limited by
+/// the proxy.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct RouteLabels {
+    proxy: ctx::Proxy,
+
+    /// The logical destination to which the request was routed.
+    dst: String,
+
+    /// The backing authority to which the request was routed, if the logical
+    /// destination's traffic is split.
+    backend: Option<String>,
+}
+
+/// Labels for requests that are rejected by the proxy's admission control.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct RejectLabels {
+    proxy: ctx::Proxy,
+
+    /// Describes the rule that rejected the request.
+    rule: String,
+
+    reason: RejectReason,
+}
+
+/// Why a request was rejected by the proxy's admission control.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum RejectReason {
+    /// Too many requests matching a rule were in flight.
+    ConcurrencyLimit,
+
+    /// Requests matching a rule exceeded its rate limit.
+    RateLimit,
+}
+
+/// Labels for an individual endpoint in an outbound load balancer.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct EndpointLabels {
+    proxy: ctx::Proxy,
+
+    addr: SocketAddr,
+
+    // Additional labels identifying the destination service of the
+    // endpoint, provided by service discovery.
+    dst_labels: Option<DstLabels>,
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum Classification {
     Success,
@@ -75,6 +130,7 @@ impl RequestLabels {
             outbound_labels,
             authority: Authority(authority),
             tls_status: req.tls_status(),
+            locality: req.client.metadata.locality(),
         }
     }
 
@@ -88,7 +144,7 @@ impl FmtLabels for RequestLabels {
     fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let dst = (self.outbound_labels.as_ref(), &self.tls_status);
 
-        ((&self.authority, &self.proxy), dst).fmt_labels(f)
+        (((&self.authority, &self.proxy), dst), self.locality.as_ref()).fmt_labels(f)
     }
 }
 
@@ -156,10 +212,109 @@ impl FmtLabels for GrpcStatus {
     }
 }
 
+// ===== impl RouteLabels =====
+
+impl RouteLabels {
+    pub fn new<D: fmt::Display>(proxy: ctx::Proxy, dst: D) -> Self {
+        RouteLabels {
+            proxy,
+            dst: dst.to_string(),
+            backend: None,
+        }
+    }
+
+    pub fn with_backend<B: fmt::Display>(self, backend: B) -> Self {
+        RouteLabels {
+            backend: Some(backend.to_string()),
+            ..self
+        }
+    }
+}
+
+impl FmtLabels for RouteLabels {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "authority=\"{}\",", self.dst)?;
+        if let Some(ref backend) = self.backend {
+            write!(f, "backend=\"{}\",", backend)?;
+        }
+        self.proxy.fmt_labels(f)
+    }
+}
+
+// ===== impl RejectLabels =====
+
+impl RejectLabels {
+    pub fn new<R: fmt::Display>(proxy: ctx::Proxy, rule: R, reason: RejectReason) -> Self {
+        RejectLabels {
+            proxy,
+            rule: rule.to_string(),
+            reason,
+        }
+    }
+}
+
+impl FmtLabels for RejectLabels {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.proxy.fmt_labels(f)?;
+        write!(f, ",rule=\"{}\",", self.rule)?;
+        self.reason.fmt_labels(f)
+    }
+}
+
+impl FmtLabels for RejectReason {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RejectReason::ConcurrencyLimit => f.pad("reason=\"concurrency_limit\""),
+            RejectReason::RateLimit => f.pad("reason=\"rate_limit\""),
+        }
+    }
+}
+
+// ===== impl EndpointLabels =====
+
+impl EndpointLabels {
+    pub fn new(proxy: ctx::Proxy, endpoint: &Endpoint) -> Self {
+        EndpointLabels {
+            proxy,
+            addr: endpoint.address(),
+            dst_labels: DstLabels::new(endpoint.labels()),
+        }
+    }
+}
+
+impl FmtLabels for EndpointLabels {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.proxy.fmt_labels(f)?;
+        write!(f, ",addr=\"{}\"", self.addr)?;
+        if let Some(ref dst) = self.dst_labels {
+            f.pad(",")?;
+            dst.fmt_labels(f)?;
+        }
+        Ok(())
+    }
+}
+
 // ===== impl Classification =====
 
 impl Classification {
 
+    /// Classifies a response from its headers alone.
+    ///
+    /// Trailers-only gRPC responses carry their `grpc-status` in the
+    /// response headers; otherwise, the HTTP status is used.
+    pub fn from_response<B>(rsp: &http::Response<B>) -> Self {
+        rsp.headers()
+            .get(GRPC_STATUS)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|s| s.parse::<u32>().ok())
+            .map(Classification::grpc_status)
+            .unwrap_or_else(|| Classification::http_status(&rsp.status()))
+    }
+
+    pub fn is_failure(&self) -> bool {
+        *self == Classification::Failure
+    }
+
     fn grpc_status(code: u32) -> Self {
         if code == 0 {
             // XXX: are gRPC status codes indicating client side errors
@@ -203,6 +358,15 @@ impl FmtLabels for ctx::Proxy {
     }
 }
 
+impl FmtLabels for Locality {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Locality::Local => f.pad("locality=\"local\""),
+            Locality::Remote => f.pad("locality=\"remote\""),
+        }
+    }
+}
+
 // ===== impl DstLabels ====
 
 impl DstLabels {
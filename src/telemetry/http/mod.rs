@@ -6,6 +6,7 @@ use super::metrics::{
     latency,
     Counter,
     FmtMetrics,
+    Gauge,
     Histogram,
     Scopes,
 };
@@ -18,12 +19,27 @@ mod sensors;
 pub mod service;
 pub mod timestamp_request_open;
 
-use self::labels::{RequestLabels, ResponseLabels};
+use self::labels::{EndpointLabels, RejectLabels, RequestLabels, ResponseLabels};
 use self::record::Record;
-pub use self::sensors::Sensors;
+pub use self::labels::{Classification, RejectReason, RouteLabels};
+pub use self::sensors::{Ejections, Hedges, Limits, Rejections, Retries, Sensors};
 
 metrics! {
     request_total: Counter { "Total count of HTTP requests." },
+    retry_total: Counter { "Total count of HTTP requests retried by the proxy." },
+    hedge_total: Counter { "Total count of HTTP requests hedged by the proxy." },
+    hedge_won_total: Counter {
+        "Total count of hedged HTTP requests that responded before the original request."
+    },
+    concurrency_limit: Gauge {
+        "Maximum number of requests in flight to a destination, as adapted to its latency."
+    },
+    request_rejected_total: Counter {
+        "Total count of HTTP requests rejected by the proxy's admission control."
+    },
+    endpoint_ejection_total: Counter {
+        "Total count of endpoints ejected from a load balancer after consecutive failures."
+    },
     response_total: Counter { "Total count of HTTP responses" },
     response_latency_ms: Histogram<latency::Ms> {
         "Elapsed times between a request's headers being received \
@@ -56,7 +72,12 @@ pub struct Report(Arc<Mutex<Inner>>);
 struct Inner {
     retain_idle: Duration,
     requests: RequestScopes,
+    retries: RetryScopes,
+    hedges: HedgeScopes,
+    rejections: RejectScopes,
+    limits: LimitScopes,
     responses: ResponseScopes,
+    ejections: EjectionScopes,
 }
 
 type RequestScopes = Scopes<RequestLabels, Stamped<RequestMetrics>>;
@@ -66,6 +87,42 @@ struct RequestMetrics {
     total: Counter,
 }
 
+type RetryScopes = Scopes<RouteLabels, Stamped<RetryMetrics>>;
+
+#[derive(Debug, Default)]
+struct RetryMetrics {
+    total: Counter,
+}
+
+type HedgeScopes = Scopes<RouteLabels, Stamped<HedgeMetrics>>;
+
+#[derive(Debug, Default)]
+struct HedgeMetrics {
+    total: Counter,
+    won: Counter,
+}
+
+type RejectScopes = Scopes<RejectLabels, Stamped<RejectMetrics>>;
+
+#[derive(Debug, Default)]
+struct RejectMetrics {
+    total: Counter,
+}
+
+type LimitScopes = Scopes<RouteLabels, Stamped<LimitMetrics>>;
+
+#[derive(Debug, Default)]
+struct LimitMetrics {
+    limit: Gauge,
+}
+
+type EjectionScopes = Scopes<EndpointLabels, Stamped<EjectionMetrics>>;
+
+#[derive(Debug, Default)]
+struct EjectionMetrics {
+    total: Counter,
+}
+
 type ResponseScopes = Scopes<ResponseLabels, Stamped<ResponseMetrics>>;
 
 #[derive(Debug, Default)]
@@ -98,6 +155,51 @@ impl Registry {
         inner.requests.get_or_default(labels).stamped().end()
     }
 
+    fn retry(&mut self, labels: &RouteLabels) {
+        let mut inner = match self.0.lock() {
+            Err(_) => return,
+            Ok(lock) => lock,
+        };
+
+        inner.retries.get_or_default(labels.clone()).stamped().incr()
+    }
+
+    fn hedge(&mut self, labels: &RouteLabels) {
+        let mut inner = match self.0.lock() {
+            Err(_) => return,
+            Ok(lock) => lock,
+        };
+
+        inner.hedges.get_or_default(labels.clone()).stamped().issue()
+    }
+
+    fn hedge_won(&mut self, labels: &RouteLabels) {
+        let mut inner = match self.0.lock() {
+            Err(_) => return,
+            Ok(lock) => lock,
+        };
+
+        inner.hedges.get_or_default(labels.clone()).stamped().win()
+    }
+
+    fn reject(&mut self, labels: RejectLabels) {
+        let mut inner = match self.0.lock() {
+            Err(_) => return,
+            Ok(lock) => lock,
+        };
+
+        inner.rejections.get_or_default(labels).stamped().incr()
+    }
+
+    fn set_limit(&mut self, labels: &RouteLabels, limit: usize) {
+        let mut inner = match self.0.lock() {
+            Err(_) => return,
+            Ok(lock) => lock,
+        };
+
+        inner.limits.get_or_default(labels.clone()).stamped().set(limit)
+    }
+
     fn end_response(&mut self, labels: ResponseLabels, latency: Duration) {
         let mut inner = match self.0.lock() {
             Err(_) => return,
@@ -106,6 +208,15 @@ impl Registry {
 
         inner.responses.get_or_default(labels).stamped().end(latency)
     }
+
+    fn eject(&mut self, labels: &EndpointLabels) {
+        let mut inner = match self.0.lock() {
+            Err(_) => return,
+            Ok(lock) => lock,
+        };
+
+        inner.ejections.get_or_default(labels.clone()).stamped().incr()
+    }
 }
 
 // ===== impl Inner =====
@@ -113,7 +224,12 @@ impl Registry {
 impl Inner {
     fn retain_since(&mut self, epoch: Instant) {
         self.requests.retain(|_, v| v.stamp >= epoch);
+        self.retries.retain(|_, v| v.stamp >= epoch);
+        self.hedges.retain(|_, v| v.stamp >= epoch);
+        self.rejections.retain(|_, v| v.stamp >= epoch);
+        self.limits.retain(|_, v| v.stamp >= epoch);
         self.responses.retain(|_, v| v.stamp >= epoch);
+        self.ejections.retain(|_, v| v.stamp >= epoch);
     }
 }
 
@@ -136,6 +252,29 @@ impl FmtMetrics for Report {
             request_total.fmt_scopes(f, &inner.requests, |s| &s.total)?;
         }
 
+        if !inner.retries.is_empty() {
+            retry_total.fmt_help(f)?;
+            retry_total.fmt_scopes(f, &inner.retries, |s| &s.total)?;
+        }
+
+        if !inner.hedges.is_empty() {
+            hedge_total.fmt_help(f)?;
+            hedge_total.fmt_scopes(f, &inner.hedges, |s| &s.total)?;
+
+            hedge_won_total.fmt_help(f)?;
+            hedge_won_total.fmt_scopes(f, &inner.hedges, |s| &s.won)?;
+        }
+
+        if !inner.rejections.is_empty() {
+            request_rejected_total.fmt_help(f)?;
+            request_rejected_total.fmt_scopes(f, &inner.rejections, |s| &s.total)?;
+        }
+
+        if !inner.limits.is_empty() {
+            concurrency_limit.fmt_help(f)?;
+            concurrency_limit.fmt_scopes(f, &inner.limits, |s| &s.limit)?;
+        }
+
         if !inner.responses.is_empty() {
             response_total.fmt_help(f)?;
             response_total.fmt_scopes(f, &inner.responses, |s| &s.total)?;
@@ -144,6 +283,11 @@ impl FmtMetrics for Report {
             response_latency_ms.fmt_scopes(f, &inner.responses, |s| &s.latency)?;
         }
 
+        if !inner.ejections.is_empty() {
+            endpoint_ejection_total.fmt_help(f)?;
+            endpoint_ejection_total.fmt_scopes(f, &inner.ejections, |s| &s.total)?;
+        }
+
         Ok(())
     }
 }
@@ -161,6 +305,50 @@ impl RequestMetrics {
     }
 }
 
+// ===== impl RetryMetrics =====
+
+impl RetryMetrics {
+    pub fn incr(&mut self) {
+        self.total.incr();
+    }
+}
+
+// ===== impl HedgeMetrics =====
+
+impl HedgeMetrics {
+    pub fn issue(&mut self) {
+        self.total.incr();
+    }
+
+    pub fn win(&mut self) {
+        self.won.incr();
+    }
+}
+
+// ===== impl RejectMetrics =====
+
+impl RejectMetrics {
+    pub fn incr(&mut self) {
+        self.total.incr();
+    }
+}
+
+// ===== impl LimitMetrics =====
+
+impl LimitMetrics {
+    pub fn set(&mut self, limit: usize) {
+        self.limit = (limit as u64).into();
+    }
+}
+
+// ===== impl EjectionMetrics =====
+
+impl EjectionMetrics {
+    pub fn incr(&mut self) {
+        self.total.incr();
+    }
+}
+
 // ===== impl ResponseMetrics =====
 
 impl ResponseMetrics {
@@ -1,6 +1,6 @@
 use super::Registry;
 use super::event::Event;
-use super::labels::{RequestLabels, ResponseLabels};
+use super::labels::{EndpointLabels, RejectLabels, RequestLabels, ResponseLabels, RouteLabels};
 
 /// Tracks Prometheus metrics
 #[derive(Clone, Debug)]
@@ -20,6 +20,36 @@ impl Record {
         Self { metrics: Registry::for_test() }
     }
 
+    /// Observe that a request is being retried.
+    pub fn record_retry(&mut self, labels: &RouteLabels) {
+        self.metrics.retry(labels);
+    }
+
+    /// Observe that a request has been hedged.
+    pub fn record_hedge(&mut self, labels: &RouteLabels) {
+        self.metrics.hedge(labels);
+    }
+
+    /// Observe that a hedged request responded before the original request.
+    pub fn record_hedge_won(&mut self, labels: &RouteLabels) {
+        self.metrics.hedge_won(labels);
+    }
+
+    /// Observe that a request has been rejected by admission control.
+    pub fn record_rejection(&mut self, labels: RejectLabels) {
+        self.metrics.reject(labels);
+    }
+
+    /// Observe the current concurrency limit for a destination.
+    pub fn record_limit(&mut self, labels: &RouteLabels, limit: usize) {
+        self.metrics.set_limit(labels, limit);
+    }
+
+    /// Observe that an endpoint has been ejected from a load balancer.
+    pub fn record_ejection(&mut self, labels: &EndpointLabels) {
+        self.metrics.eject(labels);
+    }
+
     /// Observe the given event.
     pub fn record_event(&mut self, event: &Event) {
         trace!("Root::record({:?})", event);
@@ -1,13 +1,16 @@
+use std::fmt;
 use std::sync::{Arc, Mutex};
 
 use http::{Request, Response};
 use tower_service::Service;
 use tower_h2::Body;
 
+use control::destination::Endpoint;
 use ctx;
 use telemetry::{http::event, tap};
 use proxy::http::ClientError;
 
+use super::labels::{EndpointLabels, RejectLabels, RejectReason, RouteLabels};
 use super::record::Record;
 use super::service::{Http, RequestBody};
 
@@ -25,6 +28,41 @@ pub(super) struct Handle(Inner);
 #[derive(Clone, Debug)]
 pub struct Sensors(Inner);
 
+/// Records retries issued for a destination.
+#[derive(Clone, Debug)]
+pub struct Retries {
+    metrics: Record,
+    labels: RouteLabels,
+}
+
+/// Records hedges issued for a destination.
+#[derive(Clone, Debug)]
+pub struct Hedges {
+    metrics: Record,
+    labels: RouteLabels,
+}
+
+/// Records the concurrency limit of a destination.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    metrics: Record,
+    labels: RouteLabels,
+}
+
+/// Records requests rejected by admission control.
+#[derive(Clone, Debug)]
+pub struct Rejections {
+    metrics: Record,
+    proxy: ctx::Proxy,
+}
+
+/// Records ejections of an endpoint from a load balancer.
+#[derive(Clone, Debug)]
+pub struct Ejections {
+    metrics: Record,
+    labels: EndpointLabels,
+}
+
 impl Handle {
     pub fn send<F>(&mut self, mk: F)
     where
@@ -71,4 +109,73 @@ impl Sensors {
     {
         Http::new(service, Handle(self.0.clone()), client_ctx)
     }
+
+    pub fn ejections(&self, proxy: ctx::Proxy, endpoint: &Endpoint) -> Ejections {
+        Ejections {
+            metrics: self.0.metrics.clone(),
+            labels: EndpointLabels::new(proxy, endpoint),
+        }
+    }
+
+    pub fn hedges(&self, labels: RouteLabels) -> Hedges {
+        Hedges {
+            metrics: self.0.metrics.clone(),
+            labels,
+        }
+    }
+
+    pub fn limits(&self, labels: RouteLabels) -> Limits {
+        Limits {
+            metrics: self.0.metrics.clone(),
+            labels,
+        }
+    }
+
+    pub fn rejections(&self, proxy: ctx::Proxy) -> Rejections {
+        Rejections {
+            metrics: self.0.metrics.clone(),
+            proxy,
+        }
+    }
+
+    pub fn retries(&self, labels: RouteLabels) -> Retries {
+        Retries {
+            metrics: self.0.metrics.clone(),
+            labels,
+        }
+    }
+}
+
+impl Retries {
+    pub fn incr(&mut self) {
+        self.metrics.record_retry(&self.labels);
+    }
+}
+
+impl Hedges {
+    pub fn issue(&mut self) {
+        self.metrics.record_hedge(&self.labels);
+    }
+
+    pub fn win(&mut self) {
+        self.metrics.record_hedge_won(&self.labels);
+    }
+}
+
+impl Limits {
+    pub fn set(&mut self, limit: usize) {
+        self.metrics.record_limit(&self.labels, limit);
+    }
+}
+
+impl Rejections {
+    pub fn incr<R: fmt::Display>(&mut self, rule: R, reason: RejectReason) {
+        self.metrics.record_rejection(RejectLabels::new(self.proxy, rule, reason));
+    }
+}
+
+impl Ejections {
+    pub fn incr(&mut self) {
+        self.metrics.record_ejection(&self.labels);
+    }
 }
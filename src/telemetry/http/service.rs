@@ -16,7 +16,7 @@ use super::event::{self, Event};
 use super::sensors::Handle;
 use super::timestamp_request_open::RequestOpen;
 
-const GRPC_STATUS: &str = "grpc-status";
+pub(super) const GRPC_STATUS: &str = "grpc-status";
 
 /// Wraps a transport with telemetry.
 #[derive(Debug)]
@@ -1,9 +1,12 @@
 use linkerd2_metrics as metrics;
 
+pub mod dns;
 mod errno;
 pub mod http;
 pub mod process;
+pub mod reconnect;
 mod report;
+pub mod resolution;
 pub mod tap;
 pub mod tls_config_reload;
 
@@ -0,0 +1,105 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use ctx;
+use telemetry::metrics::{FmtMetrics, Gauge, Scopes};
+
+metrics! {
+    reconnect_backoff_endpoints: Gauge {
+        "Number of endpoints waiting to reconnect after a connection failure"
+    }
+}
+
+/// Constructs a Registry/Report pair for reconnect metrics.
+pub fn new() -> (Registry, Report) {
+    let inner = Arc::new(Mutex::new(Inner::default()));
+    (Registry(inner.clone()), Report(inner))
+}
+
+/// Creates a `Sensor` for each endpoint that may reconnect.
+#[derive(Clone, Debug, Default)]
+pub struct Registry(Arc<Mutex<Inner>>);
+
+/// Formats metrics for Prometheus for a corresonding `Registry`.
+#[derive(Clone, Debug, Default)]
+pub struct Report(Arc<Mutex<Inner>>);
+
+/// Tracks whether a single endpoint is backing off.
+///
+/// When this type is dropped, the endpoint is no longer considered to be
+/// backing off.
+#[derive(Debug)]
+pub struct Sensor {
+    inner: Arc<Mutex<Inner>>,
+    proxy: ctx::Proxy,
+    backing_off: bool,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    by_proxy: Scopes<ctx::Proxy, Gauge>,
+}
+
+// ===== impl Registry =====
+
+impl Registry {
+    pub fn sensor(&self, proxy: ctx::Proxy) -> Sensor {
+        Sensor {
+            inner: self.0.clone(),
+            proxy,
+            backing_off: false,
+        }
+    }
+}
+
+// ===== impl Sensor =====
+
+impl Sensor {
+    pub fn backoff_started(&mut self) {
+        if self.backing_off {
+            return;
+        }
+        self.backing_off = true;
+
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.by_proxy.get_or_default(self.proxy).incr();
+        }
+    }
+
+    pub fn backoff_ended(&mut self) {
+        if !self.backing_off {
+            return;
+        }
+        self.backing_off = false;
+
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.by_proxy.get_or_default(self.proxy).decr();
+        }
+    }
+}
+
+impl Drop for Sensor {
+    fn drop(&mut self) {
+        self.backoff_ended();
+    }
+}
+
+// ===== impl Report =====
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = match self.0.lock() {
+            Err(_) => return Ok(()),
+            Ok(inner) => inner,
+        };
+
+        if !inner.by_proxy.is_empty() {
+            reconnect_backoff_endpoints.fmt_help(f)?;
+            reconnect_backoff_endpoints.fmt_scopes(f, &inner.by_proxy, |g| &g)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 use std::fmt;
 
 use transport::metrics as transport;
-use super::{http, process, tls_config_reload};
+use super::{dns, http, process, reconnect, resolution, tls_config_reload};
 use super::metrics::FmtMetrics;
 
 /// Implements `FmtMetrics` to report runtime metrics.
@@ -9,7 +9,10 @@ use super::metrics::FmtMetrics;
 pub struct Report {
     http: http::Report,
     transports: transport::Report,
+    reconnect: reconnect::Report,
     tls_config_reload: tls_config_reload::Report,
+    dns: dns::Report,
+    resolution: resolution::Report,
     process: process::Report,
 }
 
@@ -19,13 +22,19 @@ impl Report {
     pub fn new(
         http: http::Report,
         transports: transport::Report,
+        reconnect: reconnect::Report,
         tls_config_reload: tls_config_reload::Report,
+        dns: dns::Report,
+        resolution: resolution::Report,
         process: process::Report,
     ) -> Self {
         Self {
             http,
             transports,
+            reconnect,
             tls_config_reload,
+            dns,
+            resolution,
             process,
         }
     }
@@ -37,7 +46,10 @@ impl FmtMetrics for Report {
     fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.http.fmt_metrics(f)?;
         self.transports.fmt_metrics(f)?;
+        self.reconnect.fmt_metrics(f)?;
         self.tls_config_reload.fmt_metrics(f)?;
+        self.dns.fmt_metrics(f)?;
+        self.resolution.fmt_metrics(f)?;
         self.process.fmt_metrics(f)?;
 
         Ok(())
@@ -0,0 +1,122 @@
+use indexmap::IndexMap;
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use telemetry::metrics::{FmtLabels, FmtMetrics, Gauge};
+use transport::DnsNameAndPort;
+
+metrics! {
+    resolution_endpoints: Gauge {
+        "Number of endpoints that each resolution uses"
+    },
+    resolution_truncated_endpoints: Gauge {
+        "Number of endpoints that each resolution does not use because it exceeds the endpoint limits"
+    }
+}
+
+/// Constructs a Registry/Report pair for resolution metrics.
+pub fn new() -> (Registry, Report) {
+    let inner = Arc::new(Mutex::new(Inner::default()));
+    (Registry(inner.clone()), Report(inner))
+}
+
+/// Creates a `Sensor` for each resolved authority.
+#[derive(Clone, Debug, Default)]
+pub struct Registry(Arc<Mutex<Inner>>);
+
+/// Formats metrics for Prometheus for a corresonding `Registry`.
+#[derive(Clone, Debug, Default)]
+pub struct Report(Arc<Mutex<Inner>>);
+
+/// Tracks the number of endpoints that a single authority's resolution uses
+/// and discards.
+///
+/// When this type is dropped, the authority is no longer reported.
+#[derive(Debug)]
+pub struct Sensor {
+    inner: Arc<Mutex<Inner>>,
+    authority: DnsNameAndPort,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    resolutions: IndexMap<DnsNameAndPort, Endpoints>,
+}
+
+#[derive(Debug, Default)]
+struct Endpoints {
+    used: Gauge,
+    truncated: Gauge,
+}
+
+struct Labels<'a>(&'a DnsNameAndPort);
+
+// ===== impl Registry =====
+
+impl Registry {
+    pub fn sensor(&self, authority: DnsNameAndPort) -> Sensor {
+        Sensor {
+            inner: self.0.clone(),
+            authority,
+        }
+    }
+}
+
+// ===== impl Sensor =====
+
+impl Sensor {
+    /// Records that the resolution uses `used` endpoints, and discards
+    /// `truncated` endpoints to stay within the endpoint limits.
+    pub fn set_endpoints(&mut self, used: usize, truncated: usize) {
+        if let Ok(mut inner) = self.inner.lock() {
+            let endpoints = Endpoints {
+                used: Gauge::from(used as u64),
+                truncated: Gauge::from(truncated as u64),
+            };
+            inner.resolutions.insert(self.authority.clone(), endpoints);
+        }
+    }
+}
+
+impl Drop for Sensor {
+    fn drop(&mut self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.resolutions.remove(&self.authority);
+        }
+    }
+}
+
+// ===== impl Report =====
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = match self.0.lock() {
+            Err(_) => return Ok(()),
+            Ok(inner) => inner,
+        };
+
+        if inner.resolutions.is_empty() {
+            return Ok(());
+        }
+
+        let scopes = || inner.resolutions.iter().map(|(a, e)| (Labels(a), e));
+
+        resolution_endpoints.fmt_help(f)?;
+        resolution_endpoints.fmt_scopes(f, scopes(), |e| &e.used)?;
+
+        resolution_truncated_endpoints.fmt_help(f)?;
+        resolution_truncated_endpoints.fmt_scopes(f, scopes(), |e| &e.truncated)?;
+
+        Ok(())
+    }
+}
+
+// ===== impl Labels =====
+
+impl<'a> FmtLabels for Labels<'a> {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "authority=\"{}:{}\"", self.0.host, self.0.port)
+    }
+}
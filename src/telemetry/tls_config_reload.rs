@@ -11,6 +11,7 @@ use telemetry::{
         FmtLabels,
         FmtMetrics,
         Gauge,
+        Metric,
         Scopes,
     },
     Errno,
@@ -24,6 +25,30 @@ metrics! {
     },
     tls_config_reload_total: Counter {
         "Total number of TLS configuration reloads"
+    },
+    tls_cert_not_before_seconds: Gauge {
+        "Time before which a loaded certificate is not valid \
+         (in seconds since the UNIX epoch)"
+    },
+    tls_cert_not_after_seconds: Gauge {
+        "Time after which a loaded certificate is not valid \
+         (in seconds since the UNIX epoch)"
+    },
+    tls_cert_expiry_remaining_seconds: Gauge {
+        "Time remaining until a loaded certificate expires, or zero if it \
+         has expired (in seconds)"
+    },
+    tls_revocation_list_next_update_seconds: Gauge {
+        "Time by which the loaded certificate revocation lists are due to be \
+         updated (in seconds since the UNIX epoch)"
+    },
+    tls_revocation_list_overdue_seconds: Gauge {
+        "Time since the loaded certificate revocation lists were due to be \
+         updated, or zero if they are not yet due (in seconds)"
+    },
+    tls_revoked_peer_total: Counter {
+        "Total number of TLS connections rejected because the peer's \
+         certificate was revoked"
     }
 }
 
@@ -40,6 +65,13 @@ pub fn new() -> (Sensor, Report) {
 #[derive(Debug)]
 pub struct Sensor(Arc<Mutex<Inner>>);
 
+/// Records connections that are rejected because the peer's certificate was
+/// revoked.
+///
+/// The default `RevocationSensor` records nothing.
+#[derive(Clone, Debug, Default)]
+pub struct RevocationSensor(Option<Arc<Mutex<Inner>>>);
+
 /// Formats metrics for Prometheus for a corresonding `Sensor`.
 #[derive(Clone, Debug, Default)]
 pub struct Report(Weak<Mutex<Inner>>);
@@ -48,21 +80,39 @@ pub struct Report(Weak<Mutex<Inner>>);
 struct Inner {
     last_reload: Option<Gauge>,
     by_status: Scopes<Status, Counter>,
+    certificates: Vec<tls::CertificateValidity>,
+    revocation_next_update: Option<SystemTime>,
+    revoked: Scopes<Peer, Counter>,
 }
 
+/// Labels a certificate by its role and the identity it covers.
+struct Certificate<'a>(&'a tls::CertificateValidity);
+
 #[derive(Debug, Eq, PartialEq, Hash)]
 enum Status {
     Reloaded,
     InvalidTrustAnchors,
     InvalidPrivateKey,
     InvalidEndEntityCert,
+    InvalidRevocationList,
     Io { path: PathBuf, errno: Option<Errno> },
 }
 
+/// The role of a peer whose certificate was revoked.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+enum Peer {
+    Client,
+    Server,
+}
+
 // ===== impl Sensor =====
 
 impl Sensor {
-    pub fn reloaded(&mut self) {
+    pub fn reloaded(
+        &mut self,
+        certificates: &[tls::CertificateValidity],
+        revocation_next_update: Option<SystemTime>,
+    ) {
         let t = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .expect("times must be after UNIX epoch")
@@ -71,6 +121,8 @@ impl Sensor {
         if let Ok(mut inner) = self.0.lock() {
             inner.last_reload = Some(t.into());
             inner.by_status.get_or_default(Status::Reloaded).incr();
+            inner.certificates = certificates.to_vec();
+            inner.revocation_next_update = revocation_next_update;
         }
     }
 
@@ -79,6 +131,30 @@ impl Sensor {
             inner.by_status.get_or_default(e.into()).incr();
         }
     }
+
+    pub fn revocation(&self) -> RevocationSensor {
+        RevocationSensor(Some(self.0.clone()))
+    }
+}
+
+// ===== impl RevocationSensor =====
+
+impl RevocationSensor {
+    /// Records that an inbound connection was rejected.
+    pub fn revoked_client(&self) {
+        self.revoked(Peer::Client)
+    }
+
+    /// Records that an outbound connection was rejected.
+    pub fn revoked_server(&self) {
+        self.revoked(Peer::Server)
+    }
+
+    fn revoked(&self, peer: Peer) {
+        if let Some(Ok(mut inner)) = self.0.as_ref().map(|i| i.lock()) {
+            inner.revoked.get_or_default(peer).incr();
+        }
+    }
 }
 
 // ===== impl Report =====
@@ -99,15 +175,80 @@ impl FmtMetrics for Report {
             tls_config_reload_total.fmt_scopes(f, &inner.by_status, |s| &s)?;
         }
 
+        if !inner.revoked.is_empty() {
+            tls_revoked_peer_total.fmt_help(f)?;
+            tls_revoked_peer_total.fmt_scopes(f, &inner.revoked, |c| &c)?;
+        }
+
         if let Some(timestamp) = inner.last_reload {
             tls_config_last_reload_seconds.fmt_help(f)?;
             tls_config_last_reload_seconds.fmt_metric(f, timestamp)?;
         }
 
+        if !inner.certificates.is_empty() {
+            let now = SystemTime::now();
+            let certs = &inner.certificates;
+            fmt_certificates(f, &tls_cert_not_before_seconds, certs, |c| {
+                secs_since_epoch(c.not_before)
+            })?;
+            fmt_certificates(f, &tls_cert_not_after_seconds, certs, |c| {
+                secs_since_epoch(c.not_after)
+            })?;
+            fmt_certificates(f, &tls_cert_expiry_remaining_seconds, certs, |c| {
+                c.not_after.duration_since(now).map(|d| d.as_secs()).unwrap_or(0)
+            })?;
+        }
+
+        if let Some(next_update) = inner.revocation_next_update {
+            let overdue = SystemTime::now()
+                .duration_since(next_update)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            tls_revocation_list_next_update_seconds.fmt_help(f)?;
+            tls_revocation_list_next_update_seconds
+                .fmt_metric(f, Gauge::from(secs_since_epoch(next_update)))?;
+            tls_revocation_list_overdue_seconds.fmt_help(f)?;
+            tls_revocation_list_overdue_seconds.fmt_metric(f, Gauge::from(overdue))?;
+        }
+
         Ok(())
     }
 }
 
+/// Formats a gauge for each certificate.
+fn fmt_certificates<F>(
+    f: &mut fmt::Formatter,
+    metric: &Metric<'static, Gauge>,
+    certificates: &[tls::CertificateValidity],
+    value: F,
+) -> fmt::Result
+where
+    F: Fn(&tls::CertificateValidity) -> u64,
+{
+    let gauges = certificates.iter()
+        .map(|c| (Certificate(c), Gauge::from(value(c))))
+        .collect::<Vec<_>>();
+
+    metric.fmt_help(f)?;
+    metric.fmt_scopes(f, gauges.iter().map(|&(ref c, ref g)| (c, g)), |g| g)
+}
+
+fn secs_since_epoch(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// ===== impl Certificate =====
+
+impl<'a> FmtLabels for Certificate<'a> {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let role = match self.0.role {
+            tls::CertificateRole::EndEntity => "end_entity",
+            tls::CertificateRole::TrustAnchor => "trust_anchor",
+        };
+        write!(f, "role=\"{}\",identity=\"{}\"", role, self.0.identity)
+    }
+}
+
 // ===== impl Status =====
 
 impl From<tls::ConfigError> for Status {
@@ -120,6 +261,7 @@ impl From<tls::ConfigError> for Status {
             tls::ConfigError::FailedToParseTrustAnchors(_) => Status::InvalidTrustAnchors,
             tls::ConfigError::EndEntityCertIsNotValid(_) => Status::InvalidEndEntityCert,
             tls::ConfigError::InvalidPrivateKey => Status::InvalidPrivateKey,
+            tls::ConfigError::FailedToParseRevocationList => Status::InvalidRevocationList,
         }
     }
 }
@@ -148,6 +290,18 @@ impl FmtLabels for Status {
             Status::InvalidPrivateKey => f.pad("status=\"invalid_private_key\""),
             Status::InvalidEndEntityCert => f.pad("status=\"invalid_end_entity_cert\""),
             Status::InvalidTrustAnchors => f.pad("status=\"invalid_trust_anchors\""),
+            Status::InvalidRevocationList => f.pad("status=\"invalid_revocation_list\""),
+        }
+    }
+}
+
+// ===== impl Peer =====
+
+impl FmtLabels for Peer {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Peer::Client => f.pad("peer=\"client\""),
+            Peer::Server => f.pad("peer=\"server\""),
         }
     }
 }
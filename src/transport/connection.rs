@@ -82,6 +82,12 @@ pub struct Connection {
 
     /// Whether or not the connection is secured with TLS.
     tls_status: TlsStatus,
+
+    /// The certificate a TLS client authenticated with, if any.
+    peer_certificate: Option<tls::PeerCertificate>,
+
+    /// The application protocol a TLS client negotiated with ALPN, if any.
+    alpn_protocol: Option<tls::AlpnProtocol>,
 }
 
 /// A trait describing that a type can peek bytes.
@@ -274,7 +280,14 @@ impl Future for ConditionallyUpgradeServerToTls {
                 },
                 ConditionallyUpgradeServerToTls::UpgradeToTls(upgrading) => {
                     let tls_stream = try_ready!(upgrading.poll());
-                    return Ok(Async::Ready(Connection::tls(BoxedIo::new(tls_stream))));
+                    let peer_certificate = tls_stream.peer_certificate();
+                    let alpn_protocol = tls_stream.alpn_protocol();
+                    let conn = Connection::tls(
+                        BoxedIo::new(tls_stream),
+                        peer_certificate,
+                        alpn_protocol,
+                    );
+                    return Ok(Async::Ready(conn));
                 }
             }
         }
@@ -343,7 +356,7 @@ impl Future for Connecting {
                     match upgrade.poll() {
                         Ok(Async::NotReady) => return Ok(Async::NotReady),
                         Ok(Async::Ready(tls_stream)) => {
-                            let conn = Connection::tls(BoxedIo::new(tls_stream));
+                            let conn = Connection::tls(BoxedIo::new(tls_stream), None, None);
                             return Ok(Async::Ready(conn));
                         },
                         Err(e) => {
@@ -382,14 +395,22 @@ impl Connection {
             io: BoxedIo::new(io),
             peek_buf,
             tls_status: Conditional::None(why_no_tls),
+            peer_certificate: None,
+            alpn_protocol: None,
         }
     }
 
-    fn tls(io: BoxedIo) -> Self {
+    fn tls(
+        io: BoxedIo,
+        peer_certificate: Option<tls::PeerCertificate>,
+        alpn_protocol: Option<tls::AlpnProtocol>,
+    ) -> Self {
         Connection {
             io: io,
             peek_buf: BytesMut::new(),
             tls_status: Conditional::Some(()),
+            peer_certificate,
+            alpn_protocol,
         }
     }
 
@@ -404,6 +425,14 @@ impl Connection {
     pub fn tls_status(&self) -> TlsStatus {
         self.tls_status
     }
+
+    pub fn peer_certificate(&self) -> Option<&tls::PeerCertificate> {
+        self.peer_certificate.as_ref()
+    }
+
+    pub fn alpn_protocol(&self) -> Option<tls::AlpnProtocol> {
+        self.alpn_protocol
+    }
 }
 
 impl io::Read for Connection {
@@ -31,7 +31,14 @@ metrics! {
     tcp_write_bytes_total: Counter { "Total count of bytes written to peers" },
 
     tcp_close_total: Counter { "Total count of closed connections" },
-    tcp_connection_duration_ms: Histogram<latency::Ms> { "Connection lifetimes" }
+    tcp_connection_duration_ms: Histogram<latency::Ms> { "Connection lifetimes" },
+
+    tls_session_cache_hits_total: Counter {
+        "Total count of outbound TLS sessions found in the session cache"
+    },
+    tls_session_cache_misses_total: Counter {
+        "Total count of outbound TLS sessions not found in the session cache"
+    }
 }
 
 pub fn new() -> (Registry, Report) {
@@ -104,9 +111,24 @@ struct Sensor {
 #[derive(Clone, Debug)]
 struct NewSensor(Option<Arc<Mutex<Metrics>>>);
 
+/// Records lookups in the outbound TLS session cache.
+#[derive(Clone, Debug)]
+pub struct TlsSessionCacheSensor(Arc<Mutex<Inner>>);
+
 /// Shares state between `Report` and `Registry`.
 #[derive(Debug, Default)]
-struct Inner(IndexMap<Key, Arc<Mutex<Metrics>>>);
+struct Inner {
+    transports: IndexMap<Key, Arc<Mutex<Metrics>>>,
+
+    /// Set once a TLS session cache is instrumented.
+    tls_session_cache: Option<TlsSessionCacheMetrics>,
+}
+
+#[derive(Debug, Default)]
+struct TlsSessionCacheMetrics {
+    hits: Counter,
+    misses: Counter,
+}
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 enum Peer {
@@ -120,11 +142,11 @@ enum Peer {
 
 impl Inner {
     fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.transports.is_empty()
     }
 
     fn iter(&self) -> impl Iterator<Item = (&Key, MutexGuard<Metrics>)> {
-        self.0.iter()
+        self.transports.iter()
             .filter_map(|(k, l)| l.lock().ok().map(move |m| (k, m)))
     }
 
@@ -159,7 +181,7 @@ impl Inner {
     }
 
     fn get_or_default(&mut self, k: Key) -> &Arc<Mutex<Metrics>> {
-        self.0.entry(k).or_insert_with(|| Default::default())
+        self.transports.entry(k).or_insert_with(|| Default::default())
     }
 }
 
@@ -196,6 +218,33 @@ impl Registry {
         };
         Io::new(io, Sensor::open(metrics))
     }
+
+    pub fn tls_session_cache(&self) -> TlsSessionCacheSensor {
+        if let Ok(mut inner) = self.0.lock() {
+            inner.tls_session_cache.get_or_insert_with(Default::default);
+        }
+        TlsSessionCacheSensor(self.0.clone())
+    }
+}
+
+// ===== impl TlsSessionCacheSensor =====
+
+impl TlsSessionCacheSensor {
+    pub fn hit(&self) {
+        if let Ok(mut inner) = self.0.lock() {
+            if let Some(ref mut m) = inner.tls_session_cache {
+                m.hits.incr();
+            }
+        }
+    }
+
+    pub fn miss(&self) {
+        if let Ok(mut inner) = self.0.lock() {
+            if let Some(ref mut m) = inner.tls_session_cache {
+                m.misses.incr();
+            }
+        }
+    }
 }
 
 // ===== impl Report =====
@@ -207,6 +256,14 @@ impl FmtMetrics for Report {
             Ok(lock) => lock,
         };
 
+        if let Some(ref m) = metrics.tls_session_cache {
+            tls_session_cache_hits_total.fmt_help(f)?;
+            tls_session_cache_hits_total.fmt_metric(f, m.hits)?;
+
+            tls_session_cache_misses_total.fmt_help(f)?;
+            tls_session_cache_misses_total.fmt_metric(f, m.misses)?;
+        }
+
         if metrics.is_empty() {
             return Ok(());
         }
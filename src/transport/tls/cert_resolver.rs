@@ -5,6 +5,7 @@ use std::{
 
 use super::{
     config,
+    Identity,
 
     rustls,
     untrusted,
@@ -18,6 +19,9 @@ use ring::{self, rand, signature};
 /// same certificate and private key is used for both roles.
 pub struct CertResolver {
     certified_key: Option<rustls::sign::CertifiedKey>,
+
+    /// The identity for which the certificate was verified.
+    identity: Option<Identity>,
 }
 
 impl fmt::Debug for CertResolver {
@@ -38,7 +42,7 @@ struct Signer {
 
 impl CertResolver {
     /// Returns a new `CertResolver` that has a certificate (chain) verified to
-    /// have been issued by one of the given trust anchors.
+    /// have been issued by one of the given trust anchors for `identity`.
     ///
     /// TODO: Have the caller pass in a `rustls::ServerCertVerified` as evidence
     /// that the certificate chain was validated, once Rustls's (safe) API
@@ -48,6 +52,7 @@ impl CertResolver {
     /// key.
     pub fn new(
         _certificate_was_validated: (), // TODO: `rustls::ServerCertVerified`.
+        identity: Identity,
         cert_chain: Vec<rustls::Certificate>,
         private_key: untrusted::Input)
         -> Result<Self, config::Error>
@@ -60,7 +65,10 @@ impl CertResolver {
         let signing_key = SigningKey { signer };
         let certified_key = Some(rustls::sign::CertifiedKey::new(
             cert_chain, Arc::new(Box::new(signing_key))));
-        Ok(Self { certified_key })
+        Ok(Self {
+            certified_key,
+            identity: Some(identity),
+        })
     }
 
     /// Returns a new `CertResolver` which indicates that we don't yet have
@@ -71,7 +79,10 @@ impl CertResolver {
     /// `rustls::ResolvesClientCert` and `rustls::ResolvesServerCert`, but
     /// will always returns `None`.
     pub fn empty() -> Self {
-        Self { certified_key: None, }
+        Self {
+            certified_key: None,
+            identity: None,
+        }
     }
 
     fn resolve_(&self, sigschemes: &[rustls::SignatureScheme]) -> Option<rustls::sign::CertifiedKey>
@@ -86,15 +97,6 @@ impl CertResolver {
 
 }
 
-fn parse_end_entity_cert<'a>(cert_chain: &'a[rustls::Certificate])
-    -> Result<webpki::EndEntityCert<'a>, webpki::Error>
-{
-    let cert = cert_chain.first()
-        .map(rustls::Certificate::as_ref)
-        .unwrap_or(&[]); // An empty input fill fail to parse.
-    webpki::EndEntityCert::from(untrusted::Input::from(cert))
-}
-
 impl rustls::ResolvesClientCert for CertResolver {
     fn resolve(&self, _acceptable_issuers: &[&[u8]], sigschemes: &[rustls::SignatureScheme])
         -> Option<rustls::sign::CertifiedKey>
@@ -119,10 +121,11 @@ impl rustls::ResolvesServerCert for CertResolver {
             return None;
         };
 
-        // Verify that our certificate is valid for the given SNI name.
-        if let Err(err) = parse_end_entity_cert(&self.certified_key.as_ref()?.cert)
-            .and_then(|cert| cert.verify_is_valid_for_dns_name(server_name)) {
-            debug!("our certificate is not valid for the SNI name -> no certificate: {:?}", err);
+        // Verify that the SNI name refers to the identity that our
+        // certificate was verified for.
+        let server_name: &str = server_name.into();
+        if !self.identity.as_ref()?.matches_sni(server_name.as_bytes()) {
+            debug!("SNI name does not name our identity -> no certificate");
             return None;
         }
 
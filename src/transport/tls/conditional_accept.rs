@@ -29,12 +29,8 @@ pub fn match_client_hello(input: &[u8], identity: &Identity) -> Match {
     });
     match r {
         Ok(Some(sni)) => {
-            let matches = if let Ok(sni) = Identity::from_sni_hostname(sni.as_slice_less_safe()) {
-                if sni == *identity {
-                    Match::Matched
-                } else {
-                    Match::NotMatched
-                }
+            let matches = if identity.matches_sni(sni.as_slice_less_safe()) {
+                Match::Matched
             } else {
                 Match::NotMatched
             };
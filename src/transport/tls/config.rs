@@ -4,24 +4,33 @@ use std::{
     fs::File,
     io::{self, Cursor, Read},
     path::PathBuf,
+    str::FromStr,
     sync::Arc,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 use super::{
     cert_resolver::CertResolver,
+    revocation::RevokedCertificates,
+    session_cache::{SessionCache, Tickets},
+    verifier::{ClientVerifier, IdentityVerifier},
     Identity,
 
-    rustls,
+    rustls::{self, ServerCertVerifier},
     untrusted,
     webpki,
+    x509,
 };
 use conditional::Conditional;
 use telemetry::tls_config_reload;
 
 use futures::{future, stream, Future, Stream};
 use futures_watch::{Store, Watch};
-use ring::signature;
+use ring::{digest, signature};
+use tokio_timer::{clock, Interval};
+
+/// How often the certificates in use are checked for expiry.
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
 /// Not-yet-validated settings that are used for both TLS clients and TLS
 /// servers.
@@ -46,12 +55,85 @@ pub struct CommonSettings {
     /// The private key in DER-encoded PKCS#8 form.
     pub private_key: PathBuf,
 
-    /// The identity of the pod being proxied (as opposed to the psuedo-service
-    /// exposed on the proxy's control port).
+    /// Concatenated DER-encoded certificate revocation lists, if given.
+    ///
+    /// Peers' end-entity certificates that are revoked by any of the lists
+    /// are rejected. A warning is logged while a list is past its
+    /// `nextUpdate`.
+    pub revocation_list: Option<PathBuf>,
+
+    /// The identity of the pod being proxied, which is also used by the
+    /// proxy's tap and metrics servers.
     pub pod_identity: Identity,
 
     /// The identity of the controller, if given.
     pub controller_identity: Conditional<Identity, ReasonForNoIdentity>,
+
+    /// A warning is logged while a certificate in use expires within this
+    /// window, or has expired.
+    pub expiry_warning: Duration,
+
+    /// The oldest version of TLS that may be negotiated. Every newer version
+    /// that Rustls supports may also be negotiated.
+    pub min_version: TlsVersion,
+
+    /// The cipher suites that may be negotiated, if not Rustls's defaults.
+    pub cipher_suites: Option<CipherSuites>,
+}
+
+/// A version of TLS.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum TlsVersion {
+    Tls12,
+    Tls13,
+}
+
+/// The cipher suites that may be negotiated, in order of preference.
+#[derive(Clone)]
+pub struct CipherSuites(Vec<&'static rustls::SupportedCipherSuite>);
+
+/// The IANA names of the cipher suites that Rustls supports.
+///
+/// These are listed explicitly, rather than derived from Rustls's names for
+/// them, so that the names that may be configured don't change with Rustls.
+const CIPHER_SUITE_NAMES: &[(&str, rustls::CipherSuite)] = &[
+    ("TLS_AES_128_GCM_SHA256", rustls::CipherSuite::TLS13_AES_128_GCM_SHA256),
+    ("TLS_AES_256_GCM_SHA384", rustls::CipherSuite::TLS13_AES_256_GCM_SHA384),
+    ("TLS_CHACHA20_POLY1305_SHA256", rustls::CipherSuite::TLS13_CHACHA20_POLY1305_SHA256),
+    (
+        "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+        rustls::CipherSuite::TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
+    ),
+    (
+        "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+        rustls::CipherSuite::TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+    ),
+    (
+        "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+        rustls::CipherSuite::TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
+    ),
+    (
+        "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+        rustls::CipherSuite::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+    ),
+    (
+        "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+        rustls::CipherSuite::TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384,
+    ),
+    (
+        "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+        rustls::CipherSuite::TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+    ),
+];
+
+/// An application protocol that may be negotiated with ALPN.
+///
+/// Clients offer the one protocol they will speak, so that servers may serve
+/// the connection without detecting its protocol.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AlpnProtocol {
+    Http1,
+    Http2,
 }
 
 /// Validated configuration common between TLS clients and TLS servers.
@@ -59,11 +141,57 @@ pub struct CommonSettings {
 struct CommonConfig {
     root_cert_store: rustls::RootCertStore,
     cert_resolver: Arc<CertResolver>,
+    certificates: Vec<CertificateValidity>,
+    revoked: RevokedCertificates,
+    revocation_sensor: tls_config_reload::RevocationSensor,
+    min_version: TlsVersion,
+    cipher_suites: Option<CipherSuites>,
+    session_cache: Option<SessionCache>,
+    tickets: Option<Tickets>,
 }
 
-/// Validated configuration for TLS servers.
+/// The period during which a certificate in a loaded configuration is valid.
+#[derive(Clone, Debug)]
+pub struct CertificateValidity {
+    pub role: CertificateRole,
+
+    /// The identity that the certificate covers.
+    ///
+    /// This is the pod's identity for the end-entity certificate. Trust
+    /// anchors are identified by their subject's common name or, if they
+    /// have none, by their SHA-256 fingerprint.
+    pub identity: String,
+
+    pub not_before: SystemTime,
+    pub not_after: SystemTime,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CertificateRole {
+    EndEntity,
+    TrustAnchor,
+}
+
+/// Validated configuration for TLS clients.
 #[derive(Clone)]
-pub struct ClientConfig(pub(super) Arc<rustls::ClientConfig>);
+pub struct ClientConfig {
+    /// The configuration used for connections, which offers no protocol with
+    /// ALPN unless it was selected by `with_alpn_protocol`.
+    pub(super) config: Arc<rustls::ClientConfig>,
+
+    /// Variants of the configuration that offer each protocol with ALPN.
+    ///
+    /// These are built when the configuration is loaded, so that connections
+    /// may select one without copying the configuration.
+    http1: Arc<rustls::ClientConfig>,
+    http2: Arc<rustls::ClientConfig>,
+
+    /// Used to verify each server's certificate for its expected identity.
+    revoked: RevokedCertificates,
+    revocation_sensor: tls_config_reload::RevocationSensor,
+
+    session_cache: Option<SessionCache>,
+}
 
 /// XXX: `rustls::ClientConfig` doesn't implement `Debug` yet.
 impl std::fmt::Debug for ClientConfig {
@@ -139,14 +267,6 @@ pub enum ReasonForNoIdentity {
 
     /// The proxy wasn't configured with the identity.
     NotConfigured,
-
-    /// We haven't implemented the mechanism to construct a TLs identity for
-    /// the tap psuedo-service yet.
-    NotImplementedForTap,
-
-    /// We haven't implemented the mechanism to construct a TLs identity for
-    /// the metrics psuedo-service yet.
-    NotImplementedForMetrics,
 }
 
 impl From<ReasonForNoIdentity> for ReasonForNoTls {
@@ -165,10 +285,6 @@ impl fmt::Display for ReasonForNoIdentity {
                 f.pad("not_provided_by_service_discovery"),
             ReasonForNoIdentity::Loopback => f.pad("loopback"),
             ReasonForNoIdentity::NotConfigured => f.pad("not_configured"),
-            ReasonForNoIdentity::NotImplementedForTap =>
-                f.pad("not_implemented_for_tap"),
-            ReasonForNoIdentity::NotImplementedForMetrics =>
-                f.pad("not_implemented_for_metrics"),
         }
     }
 }
@@ -182,15 +298,18 @@ pub enum Error {
     FailedToParseTrustAnchors(Option<webpki::Error>),
     EndEntityCertIsNotValid(rustls::TLSError),
     InvalidPrivateKey,
+    FailedToParseRevocationList,
 }
 
 impl CommonSettings {
-    fn paths(&self) -> [&PathBuf; 3] {
-        [
+    fn paths(&self) -> Vec<&PathBuf> {
+        let mut paths = vec![
             &self.trust_anchors,
             &self.end_entity_cert,
             &self.private_key,
-        ]
+        ];
+        paths.extend(self.revocation_list.as_ref());
+        paths
     }
 
     /// Stream changes to the files described by this `CommonSettings`.
@@ -202,11 +321,14 @@ impl CommonSettings {
         self,
         interval: Duration,
         mut sensor: tls_config_reload::Sensor,
+        mut session_cache: SessionCache,
     ) -> impl Stream<Item = CommonConfig, Error = ()>
     {
         let paths = self.paths().iter()
             .map(|&p| p.clone())
             .collect::<Vec<_>>();
+        let mut tickets = Tickets::new();
+        let mut revoked = RevokedCertificates::default();
         // Generate one "change" immediately before starting to watch
         // the files, so that we'll try to load them now if they exist.
         stream::once(Ok(()))
@@ -218,8 +340,21 @@ impl CommonSettings {
                         sensor.failed(e);
                         None
                     },
-                    Ok(cfg) => {
-                        sensor.reloaded();
+                    Ok(mut cfg) => {
+                        // Resumed sessions aren't checked for revocation
+                        // again, so sessions established before the revoked
+                        // certificates changed may not be resumed.
+                        if !cfg.revoked.revokes_same_certificates(&revoked) {
+                            debug!("revoked certificates changed; discarding TLS sessions");
+                            session_cache = session_cache.next_generation();
+                            tickets = Tickets::new();
+                            revoked = cfg.revoked.clone();
+                        }
+
+                        sensor.reloaded(&cfg.certificates, cfg.revoked.next_update());
+                        cfg.revocation_sensor = sensor.revocation();
+                        cfg.session_cache = Some(session_cache.clone());
+                        cfg.tickets = Some(tickets.clone());
                         Some(cfg)
                     }
                 }
@@ -240,27 +375,56 @@ impl CommonConfig {
     /// trust anchors file. Since filesystem operations are not atomic, we
     /// need to check for this consistency.
     fn load_from_disk(settings: &CommonSettings) -> Result<Self, Error> {
-        let root_cert_store = load_file_contents(&settings.trust_anchors)
-            .and_then(|file_contents| {
-                let mut root_cert_store = rustls::RootCertStore::empty();
-                let (added, skipped) = root_cert_store.add_pem_file(&mut Cursor::new(file_contents))
-                    .map_err(|err| {
-                        error!("error parsing trust anchors file: {:?}", err);
-                        Error::FailedToParseTrustAnchors(None)
-                    })?;
-                if skipped != 0 {
-                    warn!("skipped {} trust anchors in trust anchors file", skipped);
-                }
-                if added > 0 {
-                    Ok(root_cert_store)
-                } else {
-                    error!("no valid trust anchors in trust anchors file");
-                    Err(Error::FailedToParseTrustAnchors(None))
-                }
-            })?;
+        let trust_anchors = load_file_contents(&settings.trust_anchors)?;
+        let root_cert_store = {
+            let mut root_cert_store = rustls::RootCertStore::empty();
+            let (added, skipped) = root_cert_store.add_pem_file(&mut Cursor::new(&trust_anchors))
+                .map_err(|err| {
+                    error!("error parsing trust anchors file: {:?}", err);
+                    Error::FailedToParseTrustAnchors(None)
+                })?;
+            if skipped != 0 {
+                warn!("skipped {} trust anchors in trust anchors file", skipped);
+            }
+            if added == 0 {
+                error!("no valid trust anchors in trust anchors file");
+                return Err(Error::FailedToParseTrustAnchors(None));
+            }
+            root_cert_store
+        };
 
         let end_entity_cert = load_file_contents(&settings.end_entity_cert)?;
 
+        // Record the validity of each certificate, so that it may be
+        // monitored for expiry.
+        let certificates = {
+            let identity = settings.pod_identity.to_string();
+            let anchors = rustls::internal::pemfile::certs(&mut Cursor::new(&trust_anchors))
+                .unwrap_or_default();
+            CertificateValidity::read(CertificateRole::EndEntity, identity, &end_entity_cert)
+                .into_iter()
+                .chain(anchors.iter().filter_map(|anchor| {
+                    let identity = trust_anchor_identity(&anchor.0);
+                    CertificateValidity::read(CertificateRole::TrustAnchor, identity, &anchor.0)
+                }))
+                .collect()
+        };
+
+        let revoked = match settings.revocation_list {
+            Some(ref path) => {
+                let crls = load_file_contents(path)?;
+                let anchors = rustls::internal::pemfile::certs(&mut Cursor::new(&trust_anchors))
+                    .unwrap_or_default();
+                let revoked = RevokedCertificates::parse(&crls, &anchors).ok_or_else(|| {
+                    error!("error parsing certificate revocation list file");
+                    Error::FailedToParseRevocationList
+                })?;
+                debug!("loaded {} revoked certificates", revoked.len());
+                revoked
+            },
+            None => RevokedCertificates::default(),
+        };
+
         // XXX: Assume there are no intermediates since there is no way to load
         // them yet.
         let cert_chain = vec![rustls::Certificate(end_entity_cert)];
@@ -273,19 +437,20 @@ impl CommonConfig {
         // TLS. This assumes that server cert validation does the same or
         // more validation than client cert validation.
         //
-        // XXX: Rustls currently only provides access to a
-        // `ServerCertVerifier` through
-        // `rustls::ClientConfig::get_verifier()`.
-        //
         // XXX: Once `rustls::ServerCertVerified` is exposed in Rustls's
         // safe API, remove the `map(|_| ())` below.
         //
         // TODO: Restrict accepted signatutre algorithms.
+        let verifier = IdentityVerifier::new(
+            settings.pod_identity.clone(),
+            revoked.clone(),
+            Default::default(),
+        );
         let certificate_was_validated =
-            rustls::ClientConfig::new().get_verifier().verify_server_cert(
+            verifier.verify_server_cert(
                     &root_cert_store,
                     &cert_chain,
-                    settings.pod_identity.as_dns_name_ref(),
+                    settings.pod_identity.sni_name_ref(),
                     &[]) // No OCSP
                 .map(|_| ())
                 .map_err(|err| {
@@ -295,13 +460,26 @@ impl CommonConfig {
 
         // `CertResolver::new` is responsible for verifying that the
         // private key is the right one for the certificate.
-        let cert_resolver = CertResolver::new(certificate_was_validated, cert_chain, private_key)?;
+        let cert_resolver = CertResolver::new(
+            certificate_was_validated,
+            settings.pod_identity.clone(),
+            cert_chain,
+            private_key,
+        )?;
 
         info!("loaded TLS configuration.");
 
         Ok(Self {
             root_cert_store,
             cert_resolver: Arc::new(cert_resolver),
+            certificates,
+            revoked,
+            // Set by `CommonSettings::stream_changes`.
+            revocation_sensor: Default::default(),
+            min_version: settings.min_version,
+            cipher_suites: settings.cipher_suites.clone(),
+            session_cache: None,
+            tickets: None,
         })
     }
 
@@ -309,6 +487,13 @@ impl CommonConfig {
         Self {
             root_cert_store: rustls::RootCertStore::empty(),
             cert_resolver: Arc::new(CertResolver::empty()),
+            certificates: Vec::new(),
+            revoked: RevokedCertificates::default(),
+            revocation_sensor: Default::default(),
+            min_version: TlsVersion::Tls12,
+            cipher_suites: None,
+            session_cache: None,
+            tickets: None,
         }
     }
 
@@ -356,7 +541,15 @@ impl ConfigWatch {
     ///
     /// The returned task Future is expected to never complete. If TLS is
     /// disabled then an empty future is returned.
-    pub fn start(self, sensor: tls_config_reload::Sensor) -> PublishConfigs {
+    ///
+    /// Every client configuration shares `session_cache`, so that sessions
+    /// may be resumed across configuration reloads that don't change the
+    /// revoked certificates.
+    pub fn start(
+        self,
+        sensor: tls_config_reload::Sensor,
+        session_cache: SessionCache,
+    ) -> PublishConfigs {
         let settings = match self.settings {
             Conditional::Some(settings) => settings,
             Conditional::None(_) => {
@@ -365,24 +558,50 @@ impl ConfigWatch {
         };
 
         let (client_store, server_store) = (self.client_store, self.server_store);
+        let expiry_warning = settings.expiry_warning;
 
-        let changes = settings.stream_changes(Duration::from_secs(1), sensor);
+        enum Update {
+            Loaded(CommonConfig),
+            CheckExpiry,
+        }
+
+        let changes = settings.stream_changes(Duration::from_secs(1), sensor, session_cache)
+            .map(Update::Loaded);
+        let expiry_checks = Interval::new(clock::now() + EXPIRY_CHECK_INTERVAL, EXPIRY_CHECK_INTERVAL)
+            .map(|_| Update::CheckExpiry)
+            .map_err(|e| error!("timer error: {:?}", e));
 
         // `Store::store` will return an error iff all watchers have been dropped,
-        // so we'll use `fold` to cancel the forwarding future. Eventually, we can
-        // also use the fold to continue tracking previous states if we need to do
-        // that.
+        // so we'll use `fold` to cancel the forwarding future. The fold also
+        // tracks the certificates and revocation lists in use, so that they
+        // may be checked for expiry periodically.
         let f = changes
+            .select(expiry_checks)
             .fold(
-                (client_store, server_store),
-                |(mut client_store, mut server_store), ref config| {
-                    client_store
-                        .store(Conditional::Some(ClientConfig::from(config)))
-                        .map_err(|_| trace!("all client config watchers dropped"))?;
-                    server_store
-                        .store(ServerConfig::from(config))
-                        .map_err(|_| trace!("all server config watchers dropped"))?;
-                    Ok((client_store, server_store))
+                (client_store, server_store, Vec::new(), None),
+                move |state, update| {
+                    let (mut client_store, mut server_store, mut certificates, mut next_update) =
+                        state;
+                    if let Update::Loaded(config) = update {
+                        client_store
+                            .store(Conditional::Some(ClientConfig::from(&config)))
+                            .map_err(|_| trace!("all client config watchers dropped"))?;
+                        server_store
+                            .store(ServerConfig::from(&config))
+                            .map_err(|_| trace!("all server config watchers dropped"))?;
+                        certificates = config.certificates;
+                        next_update = config.revoked.next_update();
+                    }
+
+                    let now = SystemTime::now();
+                    for cert in &certificates {
+                        cert.warn_if_expiring(now, expiry_warning);
+                    }
+                    if let Some(next_update) = next_update {
+                        warn_if_revocation_list_is_stale(now, next_update);
+                    }
+
+                    Ok((client_store, server_store, certificates, next_update))
                 })
             .then(|_| {
                 error!("forwarding to tls config watches finished.");
@@ -397,10 +616,174 @@ impl ConfigWatch {
     }
 }
 
+impl CertificateValidity {
+    /// Reads the validity of the DER-encoded certificate `cert`.
+    fn read(role: CertificateRole, identity: String, cert: &[u8]) -> Option<Self> {
+        let validity = x509::validity(cert).or_else(|| {
+            warn!("could not read the validity of the {} certificate for {}", role, identity);
+            None
+        })?;
+        Some(Self {
+            role,
+            identity,
+            not_before: validity.not_before,
+            not_after: validity.not_after,
+        })
+    }
+
+    fn warn_if_expiring(&self, now: SystemTime, window: Duration) {
+        match self.not_after.duration_since(now) {
+            Ok(remaining) if remaining <= window => warn!(
+                "the {} certificate for {} expires in {}s",
+                self.role, self.identity, remaining.as_secs(),
+            ),
+            Ok(_) => {},
+            Err(e) => warn!(
+                "the {} certificate for {} expired {}s ago",
+                self.role, self.identity, e.duration().as_secs(),
+            ),
+        }
+    }
+}
+
+/// Warns if the certificate revocation lists should have been updated by now,
+/// since certificates revoked since they were published are accepted.
+fn warn_if_revocation_list_is_stale(now: SystemTime, next_update: SystemTime) {
+    if let Ok(overdue) = now.duration_since(next_update) {
+        warn!(
+            "the certificate revocation list was due to be updated {}s ago",
+            overdue.as_secs(),
+        );
+    }
+}
+
+impl fmt::Display for CertificateRole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CertificateRole::EndEntity => f.pad("end-entity"),
+            CertificateRole::TrustAnchor => f.pad("trust anchor"),
+        }
+    }
+}
+
+/// Identifies a trust anchor by its subject's common name or, if it has none,
+/// by its SHA-256 fingerprint.
+fn trust_anchor_identity(cert: &[u8]) -> String {
+    x509::subject_common_name(cert)
+        .and_then(|name| std::str::from_utf8(name).ok())
+        .map(String::from)
+        .unwrap_or_else(|| {
+            digest::digest(&digest::SHA256, cert)
+                .as_ref()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect()
+        })
+}
+
+impl FromStr for TlsVersion {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1.2" => Ok(TlsVersion::Tls12),
+            "1.3" => Ok(TlsVersion::Tls13),
+            _ => Err(()),
+        }
+    }
+}
+
+impl TlsVersion {
+    /// Every supported version, from newest to oldest.
+    const ALL: &'static [TlsVersion] = &[TlsVersion::Tls13, TlsVersion::Tls12];
+
+    fn as_rustls(&self) -> rustls::ProtocolVersion {
+        match self {
+            TlsVersion::Tls12 => rustls::ProtocolVersion::TLSv1_2,
+            TlsVersion::Tls13 => rustls::ProtocolVersion::TLSv1_3,
+        }
+    }
+}
+
+impl CipherSuites {
+    /// Returns true if each of the cipher suites may be used with
+    /// `min_version` or a newer version, since every version at or above the
+    /// minimum may be negotiated.
+    ///
+    /// Rustls ignores cipher suites that can't be used with the negotiated
+    /// version, so a suite that can't be used with any of them is a
+    /// configuration error.
+    pub fn supports(&self, min_version: TlsVersion) -> bool {
+        self.0.iter().all(|suite| {
+            let is_usable = TlsVersion::ALL.iter()
+                .filter(|&&version| version >= min_version)
+                .any(|version| suite.usable_for_version(version.as_rustls()));
+            if !is_usable {
+                error!("cipher suite {} may not be used with TLS {:?} or newer",
+                       cipher_suite_name(suite), min_version);
+            }
+            is_usable
+        })
+    }
+}
+
+/// Parses a comma-separated list of cipher suites by their IANA names, e.g.
+/// `TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256`.
+impl FromStr for CipherSuites {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut suites = Vec::new();
+        for name in s.split(',').map(str::trim) {
+            let suite = CIPHER_SUITE_NAMES.iter()
+                .find(|&&(n, _)| n == name)
+                .and_then(|&(_, id)| {
+                    rustls::ALL_CIPHERSUITES.iter().find(|suite| suite.suite == id)
+                })
+                .ok_or_else(|| error!("unsupported cipher suite: {:?}", name))?;
+            suites.push(*suite);
+        }
+        Ok(CipherSuites(suites))
+    }
+}
+
+impl fmt::Debug for CipherSuites {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|suite| cipher_suite_name(suite)))
+            .finish()
+    }
+}
+
+/// Returns the IANA name of a cipher suite that Rustls supports.
+fn cipher_suite_name(suite: &rustls::SupportedCipherSuite) -> &'static str {
+    CIPHER_SUITE_NAMES.iter()
+        .find(|&&(_, id)| id == suite.suite)
+        .map(|&(name, _)| name)
+        .unwrap_or("unknown")
+}
+
+impl AlpnProtocol {
+    fn name(&self) -> &'static str {
+        match self {
+            AlpnProtocol::Http1 => "http/1.1",
+            AlpnProtocol::Http2 => "h2",
+        }
+    }
+
+    pub(super) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "http/1.1" => Some(AlpnProtocol::Http1),
+            "h2" => Some(AlpnProtocol::Http2),
+            _ => None,
+        }
+    }
+}
+
 impl ClientConfig {
     fn from(common: &CommonConfig) -> Self {
         let mut config = rustls::ClientConfig::new();
-        set_common_settings(&mut config.versions);
+        set_common_settings(common, &mut config.versions, &mut config.ciphersuites);
 
         // XXX: Rustls's built-in verifiers don't let us tweak things as fully
         // as we'd like (e.g. controlling the set of trusted signature
@@ -409,15 +792,70 @@ impl ClientConfig {
         // TODO: Change Rustls's API to Avoid needing to clone `root_cert_store`.
         config.root_store = common.root_cert_store.clone();
 
-        // Disable session resumption for the time-being until resumption is
-        // more tested.
-        config.enable_tickets = false;
+        // Servers' certificates are verified for the identity of each
+        // connection's server, and sessions are resumed from the cache that's
+        // shared by all client configurations; see `for_identity`. Sessions
+        // aren't resumed once the revoked certificates change; see
+        // `SessionCache`.
+        config.enable_tickets = true;
 
         // Enable client authentication if and only if we were configured for
         // it.
         config.client_auth_cert_resolver = common.cert_resolver.clone();
 
-        ClientConfig(Arc::new(config))
+        let (http1, http2) = {
+            let offering = |protocol: AlpnProtocol| {
+                let mut config = config.clone();
+                config.set_protocols(&[protocol.name().to_owned()]);
+                Arc::new(config)
+            };
+            (offering(AlpnProtocol::Http1), offering(AlpnProtocol::Http2))
+        };
+
+        ClientConfig {
+            config: Arc::new(config),
+            http1,
+            http2,
+            revoked: common.revoked.clone(),
+            revocation_sensor: common.revocation_sensor.clone(),
+            session_cache: common.session_cache.clone(),
+        }
+    }
+
+    /// Returns a configuration that offers `protocol` with ALPN.
+    pub fn with_alpn_protocol(&self, protocol: AlpnProtocol) -> Self {
+        let config = match protocol {
+            AlpnProtocol::Http1 => self.http1.clone(),
+            AlpnProtocol::Http2 => self.http2.clone(),
+        };
+        ClientConfig {
+            config,
+            .. self.clone()
+        }
+    }
+
+    /// Returns the configuration for a connection to a server with the given
+    /// identity.
+    ///
+    /// The server's certificate is verified for the identity, whether it is a
+    /// DNS name or a SPIFFE ID. Sessions are only resumed with servers of the
+    /// same identity, since a resumed session's certificate isn't verified
+    /// again and servers with different SPIFFE IDs share an SNI name.
+    pub(super) fn for_identity(&self, identity: &Identity) -> Arc<rustls::ClientConfig> {
+        let mut config = (*self.config).clone();
+
+        let verifier = IdentityVerifier::new(
+            identity.clone(),
+            self.revoked.clone(),
+            self.revocation_sensor.clone(),
+        );
+        config.dangerous().set_certificate_verifier(Arc::new(verifier));
+
+        if let Some(ref cache) = self.session_cache {
+            config.session_persistence = Arc::new(cache.for_identity(identity));
+        }
+
+        Arc::new(config)
     }
 
     /// Some tests aren't set up to do TLS yet, but we require a
@@ -434,9 +872,10 @@ impl ServerConfig {
         // Ask TLS clients for a certificate and accept any certificate issued
         // by our trusted CA(s).
         //
-        // Initially, also allow TLS clients that don't have a client
-        // certificate too, to minimize risk. TODO: Use
-        // `AllowAnyAuthenticatedClient` to require a valid certificate.
+        // Also allow TLS clients that don't have a client certificate, since
+        // the same configuration is used for every inbound port. Ports that
+        // require a client certificate close unauthenticated connections
+        // after the handshake; see `proxy::authorize`.
         //
         // XXX: Rustls's built-in verifiers don't let us tweak things as fully
         // as we'd like (e.g. controlling the set of trusted signature
@@ -444,11 +883,28 @@ impl ServerConfig {
         // TODO: lock down the verification further.
         //
         // TODO: Change Rustls's API to Avoid needing to clone `root_cert_store`.
-        let client_cert_verifier =
-            rustls::AllowAnyAnonymousOrAuthenticatedClient::new(common.root_cert_store.clone());
+        let client_cert_verifier = ClientVerifier::new(
+            rustls::AllowAnyAnonymousOrAuthenticatedClient::new(common.root_cert_store.clone()),
+            common.revoked.clone(),
+            common.revocation_sensor.clone(),
+        );
+
+        let mut config = rustls::ServerConfig::new(Arc::new(client_cert_verifier));
+        set_common_settings(common, &mut config.versions, &mut config.ciphersuites);
+
+        // Issue session tickets so that clients may resume sessions. No
+        // sessions are stored, so sessions may not be resumed by session ID.
+        if let Some(ref tickets) = common.tickets {
+            config.ticketer = tickets.ticketer();
+        }
+
+        // Accept either version of HTTP, preferring HTTP/2, so that clients
+        // may tell us which they will speak.
+        config.set_protocols(&[
+            AlpnProtocol::Http2.name().to_owned(),
+            AlpnProtocol::Http1.name().to_owned(),
+        ]);
 
-        let mut config = rustls::ServerConfig::new(client_cert_verifier);
-        set_common_settings(&mut config.versions);
         config.cert_resolver = common.cert_resolver.clone();
         ServerConfig(Arc::new(config))
     }
@@ -483,14 +939,20 @@ fn load_file_contents(path: &PathBuf) -> Result<Vec<u8>, Error> {
         })
 }
 
-fn set_common_settings(versions: &mut Vec<rustls::ProtocolVersion>) {
-    // Only enable TLS 1.2 until TLS 1.3 is stable.
-    *versions = vec![rustls::ProtocolVersion::TLSv1_2]
-
-    // XXX: Rustls doesn't provide a good way to customize the cipher suite
-    // support, so just use its defaults, which are still pretty good.
-    // TODO: Expand Rustls's API to allow us to clearly whitelist the cipher
-    // suites we want to enable.
+fn set_common_settings(
+    common: &CommonConfig,
+    versions: &mut Vec<rustls::ProtocolVersion>,
+    ciphersuites: &mut Vec<&'static rustls::SupportedCipherSuite>,
+) {
+    *versions = TlsVersion::ALL.iter()
+        .filter(|&&version| version >= common.min_version)
+        .map(TlsVersion::as_rustls)
+        .collect();
+
+    // Otherwise, use Rustls's default cipher suites, which are pretty good.
+    if let Some(ref suites) = common.cipher_suites {
+        *ciphersuites = suites.0.clone();
+    }
 }
 
 // Keep these in sync.
@@ -506,7 +968,7 @@ pub mod test_util {
     use super::*;
     use std::path::PathBuf;
     use conditional::Conditional;
-    use tls::Identity;
+    use tls::{Identity, PeerCertificate};
 
     pub struct Strings {
         pub identity: &'static str,
@@ -538,9 +1000,21 @@ pub mod test_util {
                 trust_anchors: dir.join(self.trust_anchors),
                 end_entity_cert: dir.join(self.end_entity_cert),
                 private_key: dir.join(self.private_key),
+                revocation_list: None,
+                expiry_warning: Duration::from_secs(0),
+                min_version: TlsVersion::Tls12,
+                cipher_suites: None,
             }
         }
 
+        /// Returns the end-entity certificate as it would be presented by a
+        /// TLS peer.
+        pub fn peer_certificate(&self) -> PeerCertificate {
+            let settings = self.to_settings();
+            let der = load_file_contents(&settings.end_entity_cert).unwrap();
+            PeerCertificate(rustls::Certificate(der))
+        }
+
         // Returns a `ConnectionConfig<ClientConfigWatch>` preloaded with a
         // valid client TLS configuration.
         pub fn client(&self, server_identity: Identity) -> ConnectionConfig<ClientConfigWatch>
@@ -578,8 +1052,20 @@ pub mod test_util {
 
 #[cfg(test)]
 mod tests {
-    use tls::{ClientConfig, ServerConfig};
-    use super::{CommonConfig, Error, test_util::*};
+    use std::sync::Arc;
+    use tls::{ClientConfig, Identity, ServerConfig};
+    use super::{
+        rustls::{self, Session},
+        AlpnProtocol,
+        CertificateRole,
+        CipherSuites,
+        CommonConfig,
+        CommonSettings,
+        Error,
+        Tickets,
+        TlsVersion,
+        test_util::*,
+    };
 
     #[test]
     fn can_construct_client_and_server_config_from_valid_settings() {
@@ -589,6 +1075,21 @@ mod tests {
         let _: ServerConfig = ServerConfig::from(&common); // infallible
     }
 
+    #[test]
+    fn records_validity_of_loaded_certificates() {
+        let settings = FOO_NS1.to_settings();
+        let common = CommonConfig::load_from_disk(&settings).unwrap();
+        let roles = common.certificates.iter()
+            .map(|c| (c.role, c.identity.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(roles.len(), 2);
+        assert_eq!(roles[0], (CertificateRole::EndEntity, FOO_NS1.identity));
+        assert_eq!(roles[1].0, CertificateRole::TrustAnchor);
+        for cert in &common.certificates {
+            assert!(cert.not_before < cert.not_after);
+        }
+    }
+
     #[test]
     fn recognize_ca_did_not_issue_cert() {
         let settings = Strings {
@@ -614,6 +1115,163 @@ mod tests {
         }
     }
 
+    #[test]
+    fn recognize_cert_is_revoked() {
+        let crl = Some("src/transport/tls/testdata/ca1.crl".into());
+        let settings = CommonSettings {
+            revocation_list: crl.clone(),
+            ..FOO_NS1.to_settings()
+        };
+        match CommonConfig::load_from_disk(&settings) {
+            Err(Error::EndEntityCertIsNotValid(_)) => (),
+            r => unreachable!("CommonConfig::load_from_disk returned {:?}", r),
+        }
+
+        let settings = CommonSettings {
+            revocation_list: crl,
+            ..BAR_NS1.to_settings()
+        };
+        let common = CommonConfig::load_from_disk(&settings).unwrap();
+        assert_eq!(common.revoked.len(), 1);
+    }
+
+    #[test]
+    fn enables_versions_at_or_above_minimum() {
+        use super::rustls::ProtocolVersion::{TLSv1_2, TLSv1_3};
+
+        let settings = FOO_NS1.to_settings();
+        let common = CommonConfig::load_from_disk(&settings).unwrap();
+        assert_eq!(ClientConfig::from(&common).config.versions, vec![TLSv1_3, TLSv1_2]);
+        assert_eq!(ServerConfig::from(&common).0.versions, vec![TLSv1_3, TLSv1_2]);
+
+        let settings = CommonSettings {
+            min_version: TlsVersion::Tls13,
+            ..settings
+        };
+        let common = CommonConfig::load_from_disk(&settings).unwrap();
+        assert_eq!(ClientConfig::from(&common).config.versions, vec![TLSv1_3]);
+        assert_eq!(ServerConfig::from(&common).0.versions, vec![TLSv1_3]);
+    }
+
+    /// Completes a handshake between sessions in memory.
+    fn handshake(
+        client: &mut rustls::ClientSession,
+        server: &mut rustls::ServerSession,
+    ) -> Result<(), rustls::TLSError> {
+        fn transfer(from: &mut Session, to: &mut Session) -> Result<(), rustls::TLSError> {
+            let mut buf = Vec::new();
+            while from.wants_write() {
+                from.write_tls(&mut buf).unwrap();
+            }
+            let mut rd = &buf[..];
+            while !rd.is_empty() {
+                to.read_tls(&mut rd).unwrap();
+            }
+            to.process_new_packets()
+        }
+
+        while client.is_handshaking() || server.is_handshaking() {
+            transfer(client, server)?;
+            transfer(server, client)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn negotiates_alpn_protocols() {
+        let settings = FOO_NS1.to_settings();
+        let common = CommonConfig::load_from_disk(&settings).unwrap();
+        let client = ClientConfig::from(&common);
+        let server = ServerConfig::from(&common);
+        let identity = &settings.pod_identity;
+        let name = identity.sni_name_ref();
+
+        for &protocol in &[AlpnProtocol::Http1, AlpnProtocol::Http2] {
+            let config = client.with_alpn_protocol(protocol).for_identity(identity);
+            let mut client = rustls::ClientSession::new(&config, name);
+            let mut server = rustls::ServerSession::new(&server.0);
+            handshake(&mut client, &mut server).unwrap();
+            assert_eq!(server.get_alpn_protocol().and_then(AlpnProtocol::from_name), Some(protocol));
+            assert_eq!(client.get_alpn_protocol().and_then(AlpnProtocol::from_name), Some(protocol));
+        }
+
+        // Clients that don't offer a protocol don't negotiate one.
+        let mut client = rustls::ClientSession::new(&client.for_identity(identity), name);
+        let mut server = rustls::ServerSession::new(&server.0);
+        handshake(&mut client, &mut server).unwrap();
+        assert_eq!(server.get_alpn_protocol(), None);
+    }
+
+    #[test]
+    fn verifies_server_identity() {
+        let common = CommonConfig::load_from_disk(&FOO_NS1.to_settings()).unwrap();
+        let client = ClientConfig::from(&common);
+        let server = ServerConfig::from(&common);
+        let foo = Identity::parse(FOO_NS1.identity).unwrap();
+        let bar = Identity::parse(BAR_NS1.identity).unwrap();
+
+        let config = client.for_identity(&foo);
+        let mut client_session = rustls::ClientSession::new(&config, foo.sni_name_ref());
+        let mut server_session = rustls::ServerSession::new(&server.0);
+        assert!(handshake(&mut client_session, &mut server_session).is_ok());
+
+        // The server's certificate is verified for the expected identity, not
+        // for the name in SNI.
+        let config = client.for_identity(&bar);
+        let mut client_session = rustls::ClientSession::new(&config, foo.sni_name_ref());
+        let mut server_session = rustls::ServerSession::new(&server.0);
+        assert!(handshake(&mut client_session, &mut server_session).is_err());
+    }
+
+    #[test]
+    fn reloads_share_a_ticketer() {
+        let tickets = Tickets::new();
+        let load = || {
+            let mut common = CommonConfig::load_from_disk(&FOO_NS1.to_settings()).unwrap();
+            common.tickets = Some(tickets.clone());
+            ServerConfig::from(&common)
+        };
+        let (a, b) = (load(), load());
+        assert!(a.0.ticketer.enabled());
+        assert!(Arc::ptr_eq(&a.0.ticketer, &b.0.ticketer));
+    }
+
+    #[test]
+    fn alpn_configs_are_shared() {
+        let common = CommonConfig::load_from_disk(&FOO_NS1.to_settings()).unwrap();
+        let client = ClientConfig::from(&common);
+        let a = client.with_alpn_protocol(AlpnProtocol::Http2);
+        let b = client.with_alpn_protocol(AlpnProtocol::Http2).with_alpn_protocol(AlpnProtocol::Http2);
+        assert!(Arc::ptr_eq(&a.config, &b.config));
+        assert!(!Arc::ptr_eq(&a.config, &client.config));
+        assert!(!Arc::ptr_eq(&a.config, &client.with_alpn_protocol(AlpnProtocol::Http1).config));
+    }
+
+    #[test]
+    fn parses_cipher_suites() {
+        let suites = "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256, \
+                      TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256"
+            .parse::<CipherSuites>()
+            .unwrap();
+        assert_eq!(suites.0.len(), 2);
+        assert!(suites.supports(TlsVersion::Tls12));
+        assert!(!suites.supports(TlsVersion::Tls13));
+
+        // TLS 1.3 suites may be used when TLS 1.2 is the minimum, since newer
+        // versions are also enabled, but not every suite is usable at 1.3.
+        let mixed = "TLS_AES_128_GCM_SHA256,TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"
+            .parse::<CipherSuites>()
+            .unwrap();
+        assert!(mixed.supports(TlsVersion::Tls12));
+        assert!(!mixed.supports(TlsVersion::Tls13));
+        assert!("TLS_AES_256_GCM_SHA384".parse::<CipherSuites>().unwrap()
+            .supports(TlsVersion::Tls13));
+
+        assert!("TLS_RSA_WITH_RC4_128_MD5".parse::<CipherSuites>().is_err());
+        // Rustls's names for TLS 1.3 suites are not their IANA names.
+        assert!("TLS13_AES_128_GCM_SHA256".parse::<CipherSuites>().is_err());
+    }
+
     // XXX: The check that this tests hasn't been implemented yet.
     #[test]
     #[should_panic]
@@ -9,10 +9,11 @@ use tokio::net::TcpStream;
 use transport::{AddrInfo, io::internal::Io, prefixed::Prefixed};
 
 use super::{
-    identity::Identity,
+    identity::{Identity, PeerCertificate},
     rustls,
     tokio_rustls::{self, ClientConfigExt, ServerConfigExt, TlsStream},
 
+    AlpnProtocol,
     ClientConfig,
     ServerConfig,
 };
@@ -55,10 +56,11 @@ pub type UpgradeServerToTls =
         tokio_rustls::AcceptAsync<Prefixed<TcpStream>>>;
 
 impl Connection<TcpStream, rustls::ClientSession> {
-    pub fn connect(socket: TcpStream, identity: &Identity, ClientConfig(config): ClientConfig)
+    pub fn connect(socket: TcpStream, identity: &Identity, config: ClientConfig)
         -> UpgradeClientToTls
     {
-        UpgradeToTls(config.connect_async(identity.as_dns_name_ref(), socket))
+        let config = config.for_identity(identity);
+        UpgradeToTls(config.connect_async(identity.sni_name_ref(), socket))
     }
 }
 
@@ -68,6 +70,21 @@ impl Connection<Prefixed<TcpStream>, rustls::ServerSession> {
     {
         UpgradeToTls(config.accept_async(Prefixed::new(prefix, socket)))
     }
+
+    /// Returns the certificate the client authenticated with, if any.
+    pub fn peer_certificate(&self) -> Option<PeerCertificate> {
+        self.0.get_ref().1
+            .get_peer_certificates()
+            .and_then(|certs| certs.into_iter().next())
+            .map(PeerCertificate)
+    }
+
+    /// Returns the application protocol negotiated with ALPN, if any.
+    pub fn alpn_protocol(&self) -> Option<AlpnProtocol> {
+        self.0.get_ref().1
+            .get_alpn_protocol()
+            .and_then(AlpnProtocol::from_name)
+    }
 }
 
 impl<S, C> io::Read for Connection<S, C>
@@ -1,11 +1,37 @@
 use linkerd2_proxy_api;
 use convert::TryFrom;
-use super::{DnsName, InvalidDnsName, webpki};
+use super::{DnsName, InvalidDnsName, rustls, untrusted, webpki};
+use super::x509::{self, GeneralName};
+use std::fmt;
 use std::sync::Arc;
 
+const SPIFFE_SCHEME: &str = "spiffe://";
+
 /// An endpoint's identity.
+///
+/// An identity is either a DNS name or a SPIFFE ID, which certificates name in
+/// DNS-ID and URI-ID subjectAltNames, respectively.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Identity(Arc<Name>);
+
+/// The end-entity certificate presented by a TLS peer.
+///
+/// The certificate has already been verified to have been issued by one of
+/// our trust anchors during the handshake.
+#[derive(Clone, Debug)]
+pub struct PeerCertificate(pub(super) rustls::Certificate);
+
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Identity(pub(super) Arc<DnsName>);
+enum Name {
+    Dns(DnsName),
+
+    /// A SPIFFE ID.
+    ///
+    /// SNI can only carry DNS names, so a SPIFFE ID is named in SNI by its
+    /// trust domain. Certificates are verified for the ID itself, in their
+    /// URI-ID subjectAltNames.
+    Spiffe { id: String, trust_domain: DnsName },
+}
 
 impl Identity {
     /// Parses the given TLS identity, if provided.
@@ -29,25 +55,210 @@ impl Identity {
                 if controller_namespace != Some(i.controller_ns.as_ref()) {
                     return Ok(None);
                 }
-                Self::from_sni_hostname(i.pod_identity.as_bytes()).map(Some)
+                Self::parse(&i.pod_identity).map(Some)
             },
             None => Ok(None), // No TLS.
         }
     }
 
+    /// Parses an identity that is either a SPIFFE ID or a DNS name.
+    ///
+    /// In the event of an error, the error is logged.
+    pub fn parse(s: &str) -> Result<Self, ()> {
+        if s.starts_with(SPIFFE_SCHEME) {
+            Self::from_spiffe_id(s)
+        } else {
+            Self::from_sni_hostname(s.as_bytes())
+        }
+    }
+
     pub fn from_sni_hostname(hostname: &[u8]) -> Result<Self, ()> {
         if hostname.last() == Some(&b'.') {
             return Err(()); // SNI hostnames are implicitly absolute.
         }
         DnsName::try_from(hostname)
-            .map(|name| Identity(Arc::new(name)))
+            .map(|name| Identity(Arc::new(Name::Dns(name))))
             .map_err(|InvalidDnsName| {
                 error!("Invalid DNS name: {:?}", hostname);
                 ()
             })
     }
 
-    pub(super) fn as_dns_name_ref(&self) -> webpki::DNSNameRef {
-        (self.0).0.as_ref()
+    /// Parses a SPIFFE ID of the form `spiffe://trust-domain/path`.
+    pub fn from_spiffe_id(id: &str) -> Result<Self, ()> {
+        let trust_domain = spiffe_trust_domain(id).ok_or_else(|| {
+            error!("Invalid SPIFFE ID: {:?}", id);
+        })?;
+        Ok(Identity(Arc::new(Name::Spiffe {
+            id: id.to_owned(),
+            trust_domain,
+        })))
+    }
+
+    /// Returns the name that identifies this identity in SNI.
+    ///
+    /// This is the DNS name itself, or a SPIFFE ID's trust domain.
+    pub(super) fn sni_name(&self) -> &DnsName {
+        match *self.0 {
+            Name::Dns(ref name) => name,
+            Name::Spiffe { ref trust_domain, .. } => trust_domain,
+        }
+    }
+
+    pub(super) fn sni_name_ref(&self) -> webpki::DNSNameRef {
+        self.sni_name().0.as_ref()
+    }
+
+    /// Returns true if the SNI extension of a ClientHello names this identity.
+    pub(super) fn matches_sni(&self, sni: &[u8]) -> bool {
+        if sni.last() == Some(&b'.') {
+            return false; // SNI hostnames are implicitly absolute.
+        }
+        DnsName::try_from(sni).map_or(false, |name| name == *self.sni_name())
+    }
+}
+
+impl AsRef<str> for Identity {
+    fn as_ref(&self) -> &str {
+        match *self.0 {
+            Name::Dns(ref name) => name.as_ref(),
+            Name::Spiffe { ref id, .. } => id,
+        }
+    }
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_ref())
+    }
+}
+
+impl PeerCertificate {
+    /// Returns true if the certificate is valid for `identity`.
+    pub fn is_valid_for(&self, identity: &Identity) -> bool {
+        is_valid_for(&(self.0).0, identity)
+    }
+
+    /// Returns the identity that the certificate was issued for.
+    ///
+    /// This is the first DNS name or SPIFFE ID in the certificate's
+    /// subjectAltName extension for which the certificate is valid.
+    pub fn identity(&self) -> Option<Identity> {
+        x509::subject_alt_names(&(self.0).0)?
+            .into_iter()
+            .filter_map(|name| match name {
+                GeneralName::DnsName(name) => Identity::from_sni_hostname(name).ok(),
+                GeneralName::Uri(uri) => ::std::str::from_utf8(uri)
+                    .ok()
+                    .filter(|uri| uri.starts_with(SPIFFE_SCHEME))
+                    .and_then(|uri| Identity::from_spiffe_id(uri).ok()),
+                GeneralName::Other => None,
+            })
+            .find(|id| self.is_valid_for(id))
+    }
+}
+
+/// Returns true if the DER-encoded certificate `cert` is valid for
+/// `identity`: for a DNS name, in a DNS-ID subjectAltName (as verified by
+/// webpki), or for a SPIFFE ID, in a URI-ID subjectAltName.
+pub(super) fn is_valid_for(cert: &[u8], identity: &Identity) -> bool {
+    match *identity.0 {
+        Name::Dns(ref name) => is_valid_for_dns_name(cert, name.0.as_ref()),
+        Name::Spiffe { ref id, .. } => spiffe_ids(cert).any(|i| i == id.as_bytes()),
+    }
+}
+
+fn is_valid_for_dns_name(cert: &[u8], name: webpki::DNSNameRef) -> bool {
+    webpki::EndEntityCert::from(untrusted::Input::from(cert))
+        .and_then(|cert| cert.verify_is_valid_for_dns_name(name))
+        .is_ok()
+}
+
+/// Returns the SPIFFE IDs in the certificate's URI-ID subjectAltNames.
+fn spiffe_ids<'a>(cert: &'a [u8]) -> impl Iterator<Item = &'a [u8]> + 'a {
+    x509::subject_alt_names(cert)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|name| match name {
+            GeneralName::Uri(uri) if uri.starts_with(SPIFFE_SCHEME.as_bytes()) => Some(uri),
+            _ => None,
+        })
+}
+
+/// Validates a SPIFFE ID of the form `spiffe://trust-domain/path`, returning
+/// its trust domain.
+fn spiffe_trust_domain(id: &str) -> Option<DnsName> {
+    if !id.starts_with(SPIFFE_SCHEME) {
+        return None;
+    }
+    let rest = &id[SPIFFE_SCHEME.len()..];
+    let slash = rest.find('/')?;
+    let (trust_domain, path) = (&rest[..slash], &rest[slash + 1..]);
+
+    let is_valid_segment = |s: &str| {
+        !s.is_empty() && s != "." && s != ".." && s.bytes().all(|b| {
+            b.is_ascii_alphanumeric() || b == b'-' || b == b'.' || b == b'_'
+        })
+    };
+    if !path.split('/').all(is_valid_segment) {
+        return None;
+    }
+
+    // Trust domains are lowercase DNS names.
+    if trust_domain.is_empty()
+        || trust_domain.ends_with('.')
+        || trust_domain.bytes().any(|b| b.is_ascii_uppercase())
+    {
+        return None;
+    }
+    DnsName::try_from(trust_domain.as_bytes()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use tls::config_test_util::*;
+    use super::*;
+
+    #[test]
+    fn peer_certificate_is_valid_for_its_identity() {
+        let cert = FOO_NS1.peer_certificate();
+        let foo = Identity::from_sni_hostname(FOO_NS1.identity.as_bytes()).unwrap();
+        let bar = Identity::from_sni_hostname(BAR_NS1.identity.as_bytes()).unwrap();
+        assert!(cert.is_valid_for(&foo));
+        assert!(!cert.is_valid_for(&bar));
+    }
+
+    #[test]
+    fn peer_certificate_identity() {
+        let foo = Identity::from_sni_hostname(FOO_NS1.identity.as_bytes()).unwrap();
+        assert_eq!(FOO_NS1.peer_certificate().identity(), Some(foo));
+    }
+
+    #[test]
+    fn parses_spiffe_ids() {
+        let id = Identity::parse("spiffe://example.com/ns/web/sa/default").unwrap();
+        assert_eq!(id.as_ref(), "spiffe://example.com/ns/web/sa/default");
+        assert_eq!(id.sni_name().as_ref(), "example.com");
+        assert!(id.matches_sni(b"example.com"));
+        assert!(!id.matches_sni(b"web.example.com"));
+
+        let invalid = [
+            "spiffe://example.com",
+            "spiffe://example.com/",
+            "spiffe://Example.com/ns/web",
+            "spiffe://example.com/ns//web",
+            "spiffe://example.com/ns/../web",
+            "spiffe://example.com/ns/web?sa=default",
+        ];
+        for id in &invalid {
+            assert!(Identity::parse(id).is_err(), "{:?} must be invalid", id);
+        }
+    }
+
+    #[test]
+    fn parses_dns_names() {
+        let id = Identity::parse(FOO_NS1.identity).unwrap();
+        assert_eq!(id, Identity::from_sni_hostname(FOO_NS1.identity.as_bytes()).unwrap());
+        assert!(id.matches_sni(FOO_NS1.identity.as_bytes()));
     }
 }
@@ -10,9 +10,17 @@ mod cert_resolver;
 mod connection;
 mod dns_name;
 mod identity;
+mod revocation;
+mod session_cache;
+mod verifier;
+mod x509;
 
 pub use self::{
     config::{
+        AlpnProtocol,
+        CertificateRole,
+        CertificateValidity,
+        CipherSuites,
         ClientConfig,
         ClientConfigWatch,
         CommonSettings,
@@ -25,6 +33,7 @@ pub use self::{
         ReasonForNoIdentity,
         ServerConfig,
         ServerConfigWatch,
+        TlsVersion,
     },
     connection::{
         Connection,
@@ -33,7 +42,8 @@ pub use self::{
         UpgradeServerToTls
     },
     dns_name::{DnsName, InvalidDnsName},
-    identity::Identity,
+    identity::{Identity, PeerCertificate},
+    session_cache::SessionCache,
     rustls::TLSError as Error,
 };
 
@@ -0,0 +1,154 @@
+use std::cmp;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::{rustls, untrusted, webpki, x509};
+use super::verifier::SIGNATURE_ALGORITHMS;
+
+/// The certificates revoked by the loaded certificate revocation lists.
+///
+/// Certificates are identified by their issuer and serial number. Each CRL
+/// must be signed by the trust anchor that issued it. Since CRLs are only
+/// accepted from trust anchors and no intermediates are loaded, only peers'
+/// end-entity certificates are checked.
+///
+/// CRLs that are past their `nextUpdate` are still used, since a stale list
+/// revokes no fewer certificates than it did; the time is recorded so that
+/// stale lists may be reported (see `next_update`).
+#[derive(Clone, Debug, Default)]
+pub struct RevokedCertificates {
+    revoked: Arc<HashSet<(Vec<u8>, Vec<u8>)>>,
+    next_update: Option<SystemTime>,
+}
+
+impl RevokedCertificates {
+    /// Reads a sequence of concatenated DER-encoded CRLs.
+    ///
+    /// Returns `None` if any CRL is invalid or isn't signed by one of the
+    /// `trust_anchors`.
+    pub fn parse(mut der: &[u8], trust_anchors: &[rustls::Certificate]) -> Option<Self> {
+        let mut revoked = HashSet::new();
+        let mut next_update: Option<SystemTime> = None;
+        while !der.is_empty() {
+            let (crl, rest) = x509::read_revocation_list(der)?;
+            if !is_signed_by_trust_anchor(&crl, trust_anchors) {
+                error!("certificate revocation list is not signed by a trust anchor");
+                return None;
+            }
+            for serial in crl.revoked_serials {
+                revoked.insert((crl.issuer.to_vec(), serial.to_vec()));
+            }
+            if let Some(t) = crl.next_update {
+                next_update = Some(next_update.map_or(t, |n| cmp::min(n, t)));
+            }
+            der = rest;
+        }
+        Some(RevokedCertificates {
+            revoked: Arc::new(revoked),
+            next_update,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.revoked.len()
+    }
+
+    /// Returns true if `other` revokes exactly the certificates that these
+    /// lists revoke.
+    pub fn revokes_same_certificates(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.revoked, &other.revoked) || self.revoked == other.revoked
+    }
+
+    /// Returns the earliest time at which one of the lists' issuers will
+    /// publish a new list, if any of them said.
+    pub fn next_update(&self) -> Option<SystemTime> {
+        self.next_update
+    }
+
+    /// Returns true if the DER-encoded end-entity certificate `cert` has been
+    /// revoked.
+    pub fn contains(&self, cert: &[u8]) -> bool {
+        if self.revoked.is_empty() {
+            return false;
+        }
+        x509::issuer_and_serial(cert).map_or(false, |(issuer, serial)| {
+            self.revoked.contains(&(issuer.to_vec(), serial.to_vec()))
+        })
+    }
+}
+
+/// Returns true if `crl` was signed by the trust anchor that is its issuer.
+fn is_signed_by_trust_anchor(
+    crl: &x509::RevocationList,
+    trust_anchors: &[rustls::Certificate],
+) -> bool {
+    trust_anchors.iter()
+        .filter(|anchor| x509::subject(&anchor.0) == Some(crl.issuer))
+        .filter_map(|anchor| webpki::EndEntityCert::from(untrusted::Input::from(&anchor.0)).ok())
+        .any(|anchor| SIGNATURE_ALGORITHMS.iter().any(|alg| {
+            anchor.verify_signature(
+                alg,
+                untrusted::Input::from(crl.signed_data),
+                untrusted::Input::from(crl.signature),
+            ).is_ok()
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Cursor;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tls::config_test_util::*;
+
+    fn trust_anchors(pem: &str) -> Vec<rustls::Certificate> {
+        let pem = fs::read(format!("src/transport/tls/testdata/{}", pem)).unwrap();
+        rustls::internal::pemfile::certs(&mut Cursor::new(pem)).unwrap()
+    }
+
+    #[test]
+    fn contains_revoked_certificates() {
+        let crl = fs::read("src/transport/tls/testdata/ca1.crl").unwrap();
+        let revoked = RevokedCertificates::parse(&crl, &trust_anchors("ca1.pem")).unwrap();
+        assert_eq!(revoked.len(), 1);
+        assert!(revoked.contains(&(FOO_NS1.peer_certificate().0).0));
+        assert!(!revoked.contains(&(BAR_NS1.peer_certificate().0).0));
+
+        assert!(revoked.revokes_same_certificates(&revoked.clone()));
+        assert!(!revoked.revokes_same_certificates(&RevokedCertificates::default()));
+    }
+
+    #[test]
+    fn reads_next_update() {
+        let crl = fs::read("src/transport/tls/testdata/ca1.crl").unwrap();
+        let revoked = RevokedCertificates::parse(&crl, &trust_anchors("ca1.pem")).unwrap();
+        // Oct 15 02:12:10 2036 GMT.
+        let next_update = UNIX_EPOCH + Duration::from_secs(2_107_649_530);
+        assert_eq!(revoked.next_update(), Some(next_update));
+        assert_eq!(RevokedCertificates::default().next_update(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_lists() {
+        let ca1 = trust_anchors("ca1.pem");
+        let crl = fs::read("src/transport/tls/testdata/ca1.crl").unwrap();
+        assert!(RevokedCertificates::parse(&crl[..crl.len() - 1], &ca1).is_none());
+        assert_eq!(RevokedCertificates::parse(&[], &ca1).map(|r| r.len()), Some(0));
+    }
+
+    #[test]
+    fn rejects_lists_not_signed_by_a_trust_anchor() {
+        let crl = fs::read("src/transport/tls/testdata/ca1.crl").unwrap();
+
+        // ca2 has the same name as ca1, but a different key pair.
+        assert!(RevokedCertificates::parse(&crl, &trust_anchors("ca2.pem")).is_none());
+
+        // The signature must match the list's contents.
+        let mut tampered = crl.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0xff;
+        assert!(RevokedCertificates::parse(&tampered, &trust_anchors("ca1.pem")).is_none());
+    }
+}
@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use super::{rustls::{self, StoresClientSessions}, Identity};
+use transport::metrics::TlsSessionCacheSensor;
+
+/// Rustls prefixes the keys of sessions with this, to distinguish them from
+/// the other values it stores, i.e. key exchange hints.
+const SESSION_KEY_PREFIX: &[u8] = b"session";
+
+/// A bounded cache of TLS client sessions, keyed by the server's identity.
+///
+/// The cache is shared by every outbound `ClientConfig`, so that sessions may
+/// be resumed across reconnects and TLS configuration reloads.
+///
+/// A resumed session's certificate isn't checked for revocation again, so
+/// sessions are also keyed by a generation that changes when the revoked
+/// certificates do (see `next_generation`). Sessions of older generations are
+/// never resumed, and are evicted as the cache fills.
+#[derive(Clone)]
+pub struct SessionCache {
+    sessions: Arc<rustls::ClientSessionMemoryCache>,
+    generation: u64,
+    sensor: TlsSessionCacheSensor,
+}
+
+/// The sessions in a `SessionCache` with servers of a single identity.
+///
+/// Rustls keys sessions by the server's SNI name, but servers with different
+/// SPIFFE IDs in a trust domain share an SNI name. A resumed session's
+/// certificate isn't verified again, so sessions are also keyed by the
+/// identity that the server's certificate was verified for.
+pub(super) struct IdentitySessions {
+    cache: SessionCache,
+    identity: Identity,
+}
+
+/// Issues the session tickets of every inbound `ServerConfig`.
+///
+/// Servers store no sessions, so sessions may only be resumed with tickets.
+/// The ticketer is shared across TLS configuration reloads, so that a reload
+/// doesn't invalidate outstanding tickets; rustls rotates its keys
+/// periodically. A new ticketer is created when the revoked certificates
+/// change, since a resumed session's client certificate isn't checked for
+/// revocation again.
+#[derive(Clone)]
+pub(super) struct Tickets(Arc<rustls::ProducesTickets>);
+
+impl SessionCache {
+    pub fn new(capacity: usize, sensor: TlsSessionCacheSensor) -> Self {
+        Self {
+            sessions: rustls::ClientSessionMemoryCache::new(capacity),
+            generation: 0,
+            sensor,
+        }
+    }
+
+    /// Returns a cache that shares this cache's capacity, but resumes none of
+    /// the sessions that were stored in it.
+    pub(super) fn next_generation(&self) -> Self {
+        Self {
+            generation: self.generation.wrapping_add(1),
+            .. self.clone()
+        }
+    }
+
+    pub(super) fn for_identity(&self, identity: &Identity) -> IdentitySessions {
+        IdentitySessions {
+            cache: self.clone(),
+            identity: identity.clone(),
+        }
+    }
+}
+
+impl IdentitySessions {
+    fn key(&self, key: &[u8]) -> Vec<u8> {
+        let generation = self.cache.generation.to_string();
+        let identity: &str = self.identity.as_ref();
+        let mut k = Vec::with_capacity(generation.len() + identity.len() + 2 + key.len());
+        k.extend_from_slice(generation.as_bytes());
+        k.push(0);
+        k.extend_from_slice(identity.as_bytes());
+        // Identities never contain a NUL, so keys can't be ambiguous.
+        k.push(0);
+        k.extend_from_slice(key);
+        k
+    }
+}
+
+impl StoresClientSessions for IdentitySessions {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        self.cache.sessions.put(self.key(&key), value)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let value = self.cache.sessions.get(&self.key(key));
+        if key.starts_with(SESSION_KEY_PREFIX) {
+            if value.is_some() {
+                self.cache.sensor.hit();
+            } else {
+                self.cache.sensor.miss();
+            }
+        }
+        value
+    }
+}
+
+/// XXX: `rustls::ClientSessionMemoryCache` doesn't implement `Debug`.
+impl ::std::fmt::Debug for SessionCache {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("SessionCache")
+            .finish()
+    }
+}
+
+impl Tickets {
+    pub(super) fn new() -> Self {
+        Tickets(rustls::Ticketer::new())
+    }
+
+    pub(super) fn ticketer(&self) -> Arc<rustls::ProducesTickets> {
+        self.0.clone()
+    }
+}
+
+/// XXX: `rustls::ProducesTickets` doesn't implement `Debug`.
+impl ::std::fmt::Debug for Tickets {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("Tickets")
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd2_metrics::FmtMetrics;
+    use transport::metrics;
+
+    fn identity(s: &str) -> Identity {
+        Identity::parse(s).unwrap()
+    }
+
+    #[test]
+    fn sessions_are_scoped_to_identities() {
+        let (registry, _) = metrics::new();
+        let cache = SessionCache::new(10, registry.tls_session_cache());
+        let foo = cache.for_identity(&identity("foo.ns1.example.com"));
+        let bar = cache.for_identity(&identity("bar.ns1.example.com"));
+
+        assert!(foo.put(b"session-a".to_vec(), b"foo".to_vec()));
+        assert_eq!(foo.get(b"session-a"), Some(b"foo".to_vec()));
+        assert_eq!(bar.get(b"session-a"), None);
+
+        // Each identity's session for the same key is kept separately.
+        assert!(bar.put(b"session-a".to_vec(), b"bar".to_vec()));
+        assert_eq!(foo.get(b"session-a"), Some(b"foo".to_vec()));
+        assert_eq!(bar.get(b"session-a"), Some(b"bar".to_vec()));
+    }
+
+    #[test]
+    fn sessions_are_scoped_to_generations() {
+        let (registry, _) = metrics::new();
+        let cache = SessionCache::new(10, registry.tls_session_cache());
+        let foo = identity("foo.ns1.example.com");
+
+        let old = cache.for_identity(&foo);
+        assert!(old.put(b"session-a".to_vec(), b"old".to_vec()));
+
+        // Sessions stored before the revoked certificates changed aren't
+        // resumed.
+        let cache = cache.next_generation();
+        let new = cache.for_identity(&foo);
+        assert_eq!(new.get(b"session-a"), None);
+        assert!(new.put(b"session-a".to_vec(), b"new".to_vec()));
+        assert_eq!(new.get(b"session-a"), Some(b"new".to_vec()));
+        assert_eq!(old.get(b"session-a"), Some(b"old".to_vec()));
+    }
+
+    #[test]
+    fn counts_hits_and_misses_of_sessions() {
+        let (registry, report) = metrics::new();
+        let cache = SessionCache::new(10, registry.tls_session_cache());
+        let foo = cache.for_identity(&identity("foo.ns1.example.com"));
+
+        foo.put(b"session-a".to_vec(), b"a".to_vec());
+        foo.put(b"kx-hint".to_vec(), b"x25519".to_vec());
+        assert!(foo.get(b"session-a").is_some());
+        assert!(foo.get(b"session-b").is_none());
+
+        // Other values, i.e. key exchange hints, aren't sessions.
+        assert!(foo.get(b"kx-hint").is_some());
+        assert!(foo.get(b"kx-other").is_none());
+
+        let report = format!("{}", report.as_display());
+        assert!(report.contains("tls_session_cache_hits_total 1\n"), "{}", report);
+        assert!(report.contains("tls_session_cache_misses_total 1\n"), "{}", report);
+    }
+}
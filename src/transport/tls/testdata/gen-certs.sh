@@ -52,6 +52,25 @@ ee() {
   echo "  private-key.p8: ${p8}" >> "${ee_name}-${ca_name}-secret.yml"
 }
 
+# Revokes an end-entity certificate in a DER-encoded CRL.
+crl() {
+  ca_name=$1
+  revoked=$2
+
+  dir=`mktemp -d`
+  touch ${dir}/index.txt
+  echo 01 > ${dir}/crlnumber
+  printf '[ca]\ndefault_ca = crl_ca\n[crl_ca]\ndatabase = %s\ncrlnumber = %s\ndefault_md = sha256\ndefault_crl_days = 3650\n' \
+    ${dir}/index.txt ${dir}/crlnumber > ${dir}/ca.cnf
+  openssl x509 -inform der -in ${revoked}.crt -out ${dir}/revoked.pem
+  openssl ca -config ${dir}/ca.cnf -keyfile ${ca_name}-key.pem -cert ${ca_name}.pem \
+    -revoke ${dir}/revoked.pem
+  openssl ca -config ${dir}/ca.cnf -keyfile ${ca_name}-key.pem -cert ${ca_name}.pem \
+    -gencrl -out ${dir}/crl.pem
+  openssl crl -in ${dir}/crl.pem -outform der -out ${ca_name}.crl
+  rm -r ${dir}
+}
+
 ca "Cluster-local CA 1" ca1
 ca "Cluster-local CA 1" ca2 # Same name, different key pair.
 
@@ -61,3 +80,5 @@ ee ca1 controller linkerd linkerd
 ee ca1 foo ns1 linkerd
 ee ca2 foo ns1 linkerd # Same, but different CA
 ee ca1 bar ns1 linkerd # Different service.
+
+crl ca1 foo-ns1-ca1 # Revokes foo, but not bar.
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::{identity, rustls, untrusted, webpki, Identity};
+use super::revocation::RevokedCertificates;
+use telemetry::tls_config_reload::RevocationSensor;
+
+/// The signature algorithms that servers' certificate chains and revocation
+/// lists may use.
+///
+/// These are the algorithms that Rustls's `WebPKIVerifier` supports.
+pub(super) static SIGNATURE_ALGORITHMS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+/// Verifies that a server's certificate was issued by one of our trust
+/// anchors for an expected identity, whether that identity is a DNS name or a
+/// SPIFFE ID, and that it has not been revoked.
+///
+/// Rustls's `WebPKIVerifier` only verifies the DNS name that SNI names, but a
+/// SPIFFE ID can't be named in SNI, so the certificate is verified for the
+/// identity itself instead.
+pub struct IdentityVerifier {
+    identity: Identity,
+    revoked: RevokedCertificates,
+    sensor: RevocationSensor,
+}
+
+/// Verifies clients' certificates with Rustls's verifier, and that they have
+/// not been revoked.
+pub struct ClientVerifier {
+    inner: Arc<rustls::ClientCertVerifier>,
+    revoked: RevokedCertificates,
+    sensor: RevocationSensor,
+}
+
+// ===== impl IdentityVerifier =====
+
+impl IdentityVerifier {
+    pub fn new(
+        identity: Identity,
+        revoked: RevokedCertificates,
+        sensor: RevocationSensor,
+    ) -> Self {
+        IdentityVerifier {
+            identity,
+            revoked,
+            sensor,
+        }
+    }
+}
+
+impl rustls::ServerCertVerifier for IdentityVerifier {
+    fn verify_server_cert(
+        &self,
+        roots: &rustls::RootCertStore,
+        presented_certs: &[rustls::Certificate],
+        _sni_name: webpki::DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
+        let (cert, intermediates) = presented_certs.split_first()
+            .ok_or(rustls::TLSError::NoCertificatesPresented)?;
+        let cert = cert.as_ref();
+
+        let end_entity = webpki::EndEntityCert::from(untrusted::Input::from(cert))
+            .map_err(rustls::TLSError::WebPKIError)?;
+        let trust_anchors = roots.roots.iter()
+            .map(|root| root.to_trust_anchor())
+            .collect::<Vec<_>>();
+        let intermediates = intermediates.iter()
+            .map(|c| untrusted::Input::from(c.as_ref()))
+            .collect::<Vec<_>>();
+        let now = webpki::Time::try_from(SystemTime::now())
+            .map_err(|_| rustls::TLSError::FailedToGetCurrentTime)?;
+        end_entity
+            .verify_is_valid_tls_server_cert(
+                SIGNATURE_ALGORITHMS,
+                &webpki::TLSServerTrustAnchors(&trust_anchors),
+                &intermediates,
+                now,
+            )
+            .map_err(rustls::TLSError::WebPKIError)?;
+
+        if !identity::is_valid_for(cert, &self.identity) {
+            debug!("server certificate is not valid for {}", self.identity);
+            return Err(rustls::TLSError::WebPKIError(webpki::Error::CertNotValidForName));
+        }
+
+        if self.revoked.contains(cert) {
+            self.sensor.revoked_server();
+            return Err(revoked());
+        }
+
+        Ok(rustls::ServerCertVerified::assertion())
+    }
+}
+
+// ===== impl ClientVerifier =====
+
+impl ClientVerifier {
+    pub fn new(
+        inner: Arc<rustls::ClientCertVerifier>,
+        revoked: RevokedCertificates,
+        sensor: RevocationSensor,
+    ) -> Self {
+        ClientVerifier {
+            inner,
+            revoked,
+            sensor,
+        }
+    }
+}
+
+impl rustls::ClientCertVerifier for ClientVerifier {
+    fn client_auth_mandatory(&self) -> bool {
+        self.inner.client_auth_mandatory()
+    }
+
+    fn client_auth_root_subjects(&self)
+        -> rustls::internal::msgs::handshake::DistinguishedNames
+    {
+        self.inner.client_auth_root_subjects()
+    }
+
+    fn verify_client_cert(
+        &self,
+        presented_certs: &[rustls::Certificate],
+    ) -> Result<rustls::ClientCertVerified, rustls::TLSError> {
+        let verified = self.inner.verify_client_cert(presented_certs)?;
+
+        let cert = presented_certs.first()
+            .map(rustls::Certificate::as_ref)
+            .unwrap_or(&[]);
+        if self.revoked.contains(cert) {
+            self.sensor.revoked_client();
+            return Err(revoked());
+        }
+
+        Ok(verified)
+    }
+}
+
+fn revoked() -> rustls::TLSError {
+    rustls::TLSError::General("certificate has been revoked".into())
+}
@@ -0,0 +1,410 @@
+//! Reads the fields of X.509 certificates that webpki doesn't expose.
+//!
+//! Certificates are verified by rustls and webpki before they are read here,
+//! so this only needs to find fields in well-formed DER; anything unexpected
+//! is treated as the field being absent.
+
+use std::str;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// DER encoding of the subjectAltName extension's OID, 2.5.29.17.
+const SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+/// DER encoding of the commonName attribute's OID, 2.5.4.3.
+const COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const OID: u8 = 0x06;
+const BOOLEAN: u8 = 0x01;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+/// The context-specific tag of TBSCertificate's version.
+const VERSION: u8 = 0xa0;
+/// The context-specific tag of TBSCertificate's extensions.
+const EXTENSIONS: u8 = 0xa3;
+/// The context-specific tags of a GeneralName's dNSName and
+/// uniformResourceIdentifier.
+const DNS_NAME: u8 = 0x82;
+const URI: u8 = 0x86;
+
+/// A name in a certificate's subjectAltName extension.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum GeneralName<'a> {
+    DnsName(&'a [u8]),
+    Uri(&'a [u8]),
+    Other,
+}
+
+/// The certificates revoked by a certificate revocation list.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct RevocationList<'a> {
+    /// The DER-encoded contents of the CRL issuer's Name.
+    pub issuer: &'a [u8],
+    /// The serial numbers of the revoked certificates.
+    pub revoked_serials: Vec<&'a [u8]>,
+    /// When the issuer will publish the next CRL, if it says.
+    pub next_update: Option<SystemTime>,
+    /// The DER-encoded TBSCertList, which the issuer signed.
+    pub signed_data: &'a [u8],
+    /// The issuer's signature of `signed_data`.
+    pub signature: &'a [u8],
+}
+
+/// Returns the names in the subjectAltName extension of the DER-encoded
+/// certificate `cert`.
+///
+/// Returns `None` if the certificate has no such extension.
+pub(super) fn subject_alt_names(cert: &[u8]) -> Option<Vec<GeneralName>> {
+    let names = find_extension(cert, SUBJECT_ALT_NAME)?;
+    let (tag, mut names, _) = read_tlv(names)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+
+    let mut result = Vec::new();
+    while !names.is_empty() {
+        let (tag, name, rest) = read_tlv(names)?;
+        result.push(match tag {
+            DNS_NAME => GeneralName::DnsName(name),
+            URI => GeneralName::Uri(name),
+            _ => GeneralName::Other,
+        });
+        names = rest;
+    }
+    Some(result)
+}
+
+/// The period during which a certificate is valid.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(super) struct Validity {
+    pub not_before: SystemTime,
+    pub not_after: SystemTime,
+}
+
+/// Returns the validity period of the DER-encoded certificate `cert`.
+pub(super) fn validity(cert: &[u8]) -> Option<Validity> {
+    let (tag, validity) = tbs_field(cert, 3)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    let (tag, not_before, rest) = read_tlv(validity)?;
+    let not_before = read_time(tag, not_before)?;
+    let (tag, not_after, _) = read_tlv(rest)?;
+    let not_after = read_time(tag, not_after)?;
+    Some(Validity { not_before, not_after })
+}
+
+/// Returns the value of the first commonName attribute of the DER-encoded
+/// certificate's subject, if it has one.
+pub(super) fn subject_common_name(cert: &[u8]) -> Option<&[u8]> {
+    let (tag, mut rdns) = tbs_field(cert, 4)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    while !rdns.is_empty() {
+        let (tag, mut attributes, rest) = read_tlv(rdns)?;
+        rdns = rest;
+        if tag != SET {
+            return None;
+        }
+        while !attributes.is_empty() {
+            let (_, attribute, rest) = read_tlv(attributes)?;
+            attributes = rest;
+
+            let (tag, id, value) = read_tlv(attribute)?;
+            if tag == OID && id == COMMON_NAME {
+                let (_, value, _) = read_tlv(value)?;
+                return Some(value);
+            }
+        }
+    }
+    None
+}
+
+/// Returns the DER-encoded contents of the certificate's issuer Name and its
+/// serial number, which together identify the certificate in a CRL.
+pub(super) fn issuer_and_serial(cert: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (tag, serial) = tbs_field(cert, 0)?;
+    if tag != INTEGER {
+        return None;
+    }
+    let (tag, issuer) = tbs_field(cert, 2)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    Some((issuer, serial))
+}
+
+/// Returns the DER-encoded contents of the certificate's subject Name.
+pub(super) fn subject(cert: &[u8]) -> Option<&[u8]> {
+    match tbs_field(cert, 4)? {
+        (SEQUENCE, subject) => Some(subject),
+        _ => None,
+    }
+}
+
+/// Reads a DER-encoded CRL from the front of `der`, returning it and the
+/// bytes that follow it.
+///
+/// The CRL's signature is not verified here; see `RevokedCertificates`.
+pub(super) fn read_revocation_list(der: &[u8]) -> Option<(RevocationList, &[u8])> {
+    let (tag, crl, rest) = read_tlv(der)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    let (tag, mut fields, after) = read_tlv(crl)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    let signed_data = &crl[..crl.len() - after.len()];
+
+    // Skip the signature algorithm, which is repeated in the TBSCertList.
+    let (tag, _, after) = read_tlv(after)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    // The signature is a BIT STRING with no unused bits.
+    let signature = match read_tlv(after)? {
+        (BIT_STRING, value, _) if value.first() == Some(&0) => &value[1..],
+        _ => return None,
+    };
+
+    // Skip the version, if present, and the signature algorithm.
+    let (tag, _, after) = read_tlv(fields)?;
+    if tag == INTEGER {
+        fields = after;
+    }
+    let (_, _, after) = read_tlv(fields)?;
+    fields = after;
+
+    let (tag, issuer, after) = read_tlv(fields)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    fields = after;
+
+    // Skip thisUpdate, and read nextUpdate if it is present.
+    let (_, _, after) = read_tlv(fields)?;
+    fields = after;
+    let mut next_update = None;
+    if let Some((tag, value, after)) = read_tlv(fields) {
+        if tag == UTC_TIME || tag == GENERALIZED_TIME {
+            next_update = Some(read_time(tag, value)?);
+            fields = after;
+        }
+    }
+
+    let mut revoked_serials = Vec::new();
+    if let Some((SEQUENCE, mut entries, _)) = read_tlv(fields) {
+        while !entries.is_empty() {
+            let (_, entry, after) = read_tlv(entries)?;
+            entries = after;
+            let (tag, serial, _) = read_tlv(entry)?;
+            if tag != INTEGER {
+                return None;
+            }
+            revoked_serials.push(serial);
+        }
+    }
+
+    let crl = RevocationList {
+        issuer,
+        revoked_serials,
+        next_update,
+        signed_data,
+        signature,
+    };
+    Some((crl, rest))
+}
+
+/// Returns the tag and contents of the `n`th field of the certificate's
+/// TBSCertificate, not counting its version, e.g. the serialNumber is field 0.
+fn tbs_field(cert: &[u8], n: usize) -> Option<(u8, &[u8])> {
+    let mut fields = tbs_certificate(cert)?;
+    let (tag, _, rest) = read_tlv(fields)?;
+    if tag == VERSION {
+        fields = rest;
+    }
+    for _ in 0..n {
+        let (_, _, rest) = read_tlv(fields)?;
+        fields = rest;
+    }
+    let (tag, value, _) = read_tlv(fields)?;
+    Some((tag, value))
+}
+
+/// Reads a UTCTime or GeneralizedTime in the forms allowed by RFC 5280,
+/// `YYMMDDHHMMSSZ` and `YYYYMMDDHHMMSSZ`, respectively.
+fn read_time(tag: u8, value: &[u8]) -> Option<SystemTime> {
+    let value = str::from_utf8(value).ok()?;
+    if !value.ends_with('Z') {
+        return None;
+    }
+    let digits = &value[..value.len() - 1];
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let (year, rest) = match tag {
+        UTC_TIME if digits.len() == 12 => {
+            let year = digits[..2].parse::<u64>().ok()?;
+            // UTCTime represents years from 1950 through 2049.
+            (if year >= 50 { 1900 + year } else { 2000 + year }, &digits[2..])
+        }
+        GENERALIZED_TIME if digits.len() == 14 => {
+            (digits[..4].parse::<u64>().ok()?, &digits[4..])
+        }
+        _ => return None,
+    };
+    let field = |i: usize| rest[i..i + 2].parse::<u64>().ok();
+    let (month, day) = (field(0)?, field(2)?);
+    let (hour, minute, second) = (field(4)?, field(6)?, field(8)?);
+    let is_valid = year >= 1970
+        && month >= 1 && month <= 12
+        && day >= 1 && day <= 31
+        && hour < 24 && minute < 60 && second < 60;
+    if !is_valid {
+        return None;
+    }
+
+    let days = days_since_epoch(year, month, day);
+    let secs = days * 86_400 + hour * 3_600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Returns the number of days from 1970-01-01 to the given date in the
+/// proleptic Gregorian calendar.
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    // Count years from March, so that leap days fall at the end of the year.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    // 719,468 days separate 0000-03-01 and 1970-01-01.
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the contents of the certificate's TBSCertificate.
+fn tbs_certificate(cert: &[u8]) -> Option<&[u8]> {
+    let (tag, cert, _) = read_tlv(cert)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    let (tag, tbs, _) = read_tlv(cert)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    Some(tbs)
+}
+
+/// Returns the value of the extension identified by `oid`.
+fn find_extension<'a>(cert: &'a [u8], oid: &[u8]) -> Option<&'a [u8]> {
+    let mut fields = tbs_certificate(cert)?;
+    while !fields.is_empty() {
+        let (tag, value, rest) = read_tlv(fields)?;
+        fields = rest;
+        if tag != EXTENSIONS {
+            continue;
+        }
+
+        let (tag, mut extensions, _) = read_tlv(value)?;
+        if tag != SEQUENCE {
+            return None;
+        }
+        while !extensions.is_empty() {
+            let (_, extension, rest) = read_tlv(extensions)?;
+            extensions = rest;
+
+            let (tag, id, extension) = read_tlv(extension)?;
+            if tag != OID || id != oid {
+                continue;
+            }
+            // Skip the `critical` flag, if present.
+            let (mut tag, mut value, rest) = read_tlv(extension)?;
+            if tag == BOOLEAN {
+                let (t, v, _) = read_tlv(rest)?;
+                tag = t;
+                value = v;
+            }
+            if tag != OCTET_STRING {
+                return None;
+            }
+            return Some(value);
+        }
+        return None;
+    }
+    None
+}
+
+/// Reads a DER-encoded value from the front of `der`, returning its tag, its
+/// contents, and the bytes that follow it.
+fn read_tlv(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first & 0x80 == 0 {
+        (first as usize, rest)
+    } else {
+        // The length is encoded in the next `n` bytes.
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 4 || rest.len() < n {
+            return None;
+        }
+        let len = rest[..n].iter().fold(0, |len, &b| (len << 8) | b as usize);
+        (len, &rest[n..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn reads_dns_names() {
+        let cert = fs::read("src/transport/tls/testdata/foo-ns1-ca1.crt").unwrap();
+        let names = subject_alt_names(&cert).unwrap();
+        let foo = &b"foo.deployment.ns1.linkerd-managed.linkerd.svc.cluster.local"[..];
+        assert!(names.contains(&GeneralName::DnsName(foo)), "{:?}", names);
+    }
+
+    #[test]
+    fn reads_validity() {
+        let cert = fs::read("src/transport/tls/testdata/foo-ns1-ca1.crt").unwrap();
+        let validity = validity(&cert).unwrap();
+        // Jul 17 05:35:00 2018 GMT through Jul 17 05:35:00 2019 GMT.
+        assert_eq!(validity.not_before, UNIX_EPOCH + Duration::from_secs(1_531_805_700));
+        assert_eq!(validity.not_after, UNIX_EPOCH + Duration::from_secs(1_563_341_700));
+    }
+
+    #[test]
+    fn reads_times() {
+        let secs = |t: SystemTime| t.duration_since(UNIX_EPOCH).unwrap().as_secs();
+        assert_eq!(read_time(UTC_TIME, b"700101000000Z").map(secs), Some(0));
+        assert_eq!(read_time(UTC_TIME, b"491231235959Z").map(secs), Some(2_524_607_999));
+        assert_eq!(read_time(GENERALIZED_TIME, b"20000229120000Z").map(secs), Some(951_825_600));
+        assert_eq!(read_time(UTC_TIME, b"20000229120000Z"), None);
+        assert_eq!(read_time(UTC_TIME, b"000229120000+0000"), None);
+        assert_eq!(read_time(GENERALIZED_TIME, b"20001301000000Z"), None);
+    }
+
+    #[test]
+    fn reads_long_lengths() {
+        let mut der = vec![0x04, 0x82, 0x01, 0x00];
+        der.extend(vec![0; 256]);
+        der.push(0x05);
+        let (tag, value, rest) = read_tlv(&der).unwrap();
+        assert_eq!(tag, 0x04);
+        assert_eq!(value.len(), 256);
+        assert_eq!(rest, &[0x05]);
+
+        assert_eq!(read_tlv(&der[..100]), None);
+    }
+}